@@ -0,0 +1,177 @@
+//! Throughput of the response assembler and of TLS record processing, over in-memory byte
+//! streams rather than a proxy, so buffer management changes can be measured natively.
+//!
+//! Run with `cargo bench`. `test/public/bench.html` measures the same paths in a browser,
+//! through the wsproxy of the test server.
+
+use std::{
+    io::{Read, Write},
+    sync::Arc,
+};
+
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion, Throughput};
+use rustls::{
+    pki_types::{CertificateDer, PrivatePkcs8KeyDer, ServerName},
+    ClientConfig, RootCertStore, ServerConfig, ServerConnection,
+};
+use socketguard::bench::{ResponseAssembler, TlsSession};
+
+/// Size of the body of the large responses.
+const BODY_SIZE: usize = 1024 * 1024;
+
+/// Size of the messages the body is received in, as sent by the proxy.
+const MESSAGE_SIZE: usize = 16 * 1024;
+
+/// Small response, as sent to API requests.
+const SMALL_RESPONSE: &[u8] = b"HTTP/1.1 200 OK\r\nContent-Type: application/json\r\n\
+    Content-Length: 17\r\nCache-Control: no-store\r\n\r\n{\"status\":\"ok\"}\r\n";
+
+/// Build a response with a body of `BODY_SIZE` bytes, framed by `Content-Length` or chunked.
+fn large_response(chunked: bool) -> Vec<u8> {
+    let body = vec![b'x'; BODY_SIZE];
+    if !chunked {
+        let head = format!("HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n", BODY_SIZE);
+        return [head.as_bytes(), &body].concat();
+    }
+    let mut response = b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n".to_vec();
+    for chunk in body.chunks(8 * 1024) {
+        write!(response, "{:x}\r\n", chunk.len()).unwrap();
+        response.extend_from_slice(chunk);
+        response.extend_from_slice(b"\r\n");
+    }
+    response.extend_from_slice(b"0\r\n\r\n");
+    response
+}
+
+/// Push a response to an assembler in messages of `MESSAGE_SIZE` bytes.
+fn assemble(assembler: &mut ResponseAssembler, response: &[u8]) {
+    let mut complete = None;
+    for message in response.chunks(MESSAGE_SIZE) {
+        complete = assembler.push(message).unwrap();
+    }
+    assert!(complete.is_some());
+}
+
+fn assembler(c: &mut Criterion) {
+    let mut group = c.benchmark_group("assembler");
+
+    group.throughput(Throughput::Elements(1));
+    group.bench_function("small responses", |b| {
+        let mut assembler = ResponseAssembler::new();
+        b.iter(|| assert!(assembler.push(SMALL_RESPONSE).unwrap().is_some()));
+    });
+
+    group.throughput(Throughput::Bytes(BODY_SIZE as u64));
+    for (name, chunked) in [("content-length body", false), ("chunked body", true)] {
+        let response = large_response(chunked);
+        group.bench_function(name, |b| {
+            let mut assembler = ResponseAssembler::new();
+            b.iter(|| assemble(&mut assembler, &response));
+        });
+    }
+    group.finish();
+}
+
+/// Configs of a client and of a server it trusts, with a certificate for `localhost`.
+fn tls_configs() -> (Arc<ClientConfig>, Arc<ServerConfig>) {
+    let mut ca_params = rcgen::CertificateParams::new(Vec::new());
+    ca_params.is_ca = rcgen::IsCa::Ca(rcgen::BasicConstraints::Unconstrained);
+    let ca = rcgen::Certificate::from_params(ca_params).unwrap();
+    let leaf = rcgen::Certificate::from_params(rcgen::CertificateParams::new(vec![
+        "localhost".to_string()
+    ]))
+    .unwrap();
+
+    let mut roots = RootCertStore::empty();
+    roots
+        .add(CertificateDer::from(ca.serialize_der().unwrap()))
+        .unwrap();
+    let client = ClientConfig::builder()
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+    let chain = vec![CertificateDer::from(
+        leaf.serialize_der_with_signer(&ca).unwrap(),
+    )];
+    let key = PrivatePkcs8KeyDer::from(leaf.serialize_private_key_der());
+    let server = ServerConfig::builder()
+        .with_no_client_auth()
+        .with_single_cert(chain, key.into())
+        .unwrap();
+    (Arc::new(client), Arc::new(server))
+}
+
+/// Take the records a server wants to send.
+fn server_records(server: &mut ServerConnection) -> Vec<u8> {
+    let mut records = Vec::new();
+    while server.wants_write() {
+        server.write_tls(&mut records).unwrap();
+    }
+    records
+}
+
+/// Pass records to a server, taking the plaintext they carry.
+fn server_read(server: &mut ServerConnection, mut records: &[u8]) -> Vec<u8> {
+    let mut plaintext = Vec::new();
+    while !records.is_empty() {
+        server.read_tls(&mut records).unwrap();
+        server.process_new_packets().unwrap();
+        // Fails once the plaintext received so far is taken
+        let _ = server.reader().read_to_end(&mut plaintext);
+    }
+    plaintext
+}
+
+/// Run the handshake of a client session with a server, over memory.
+fn handshake(
+    client: &Arc<ClientConfig>,
+    server: &Arc<ServerConfig>,
+) -> (TlsSession, ServerConnection) {
+    let name = ServerName::try_from("localhost").unwrap();
+    let mut session = TlsSession::new(client.clone(), name).unwrap();
+    let mut server = ServerConnection::new(server.clone()).unwrap();
+    server.set_buffer_limit(None);
+    let mut output = session.write(&[]).unwrap();
+    while output.handshaking || !output.tls.is_empty() {
+        server_read(&mut server, &output.tls);
+        output = session.read(&server_records(&mut server)).unwrap();
+    }
+    (session, server)
+}
+
+fn tls(c: &mut Criterion) {
+    let (client, server) = tls_configs();
+    let mut group = c.benchmark_group("tls");
+
+    group.throughput(Throughput::Elements(1));
+    group.bench_function("handshakes", |b| b.iter(|| handshake(&client, &server)));
+
+    group.throughput(Throughput::Bytes(BODY_SIZE as u64));
+    let (mut session, mut server) = handshake(&client, &server);
+    let body = vec![b'x'; BODY_SIZE];
+    group.bench_function("read records", |b| {
+        b.iter_batched(
+            || {
+                server.writer().write_all(&body).unwrap();
+                server_records(&mut server)
+            },
+            |records| {
+                let read: usize = records
+                    .chunks(MESSAGE_SIZE)
+                    .map(|message| session.read(message).unwrap().plaintext.len())
+                    .sum();
+                assert_eq!(read, BODY_SIZE);
+            },
+            BatchSize::LargeInput,
+        );
+    });
+    group.bench_function("write records", |b| {
+        b.iter(|| {
+            let output = session.write(&body).unwrap();
+            assert_eq!(server_read(&mut server, &output.tls).len(), BODY_SIZE);
+        });
+    });
+    group.finish();
+}
+
+criterion_group!(benches, assembler, tls);
+criterion_main!(benches);
@@ -0,0 +1,109 @@
+use std::{cell::RefCell, collections::HashMap, rc::Rc};
+
+use wasm_bindgen_futures::js_sys;
+
+use crate::{connection_apis::http::HttpConnectionResponse, retry::parse_retry_after};
+
+/// Spacing between requests to a host after its first rate limit response, in ms.
+const MIN_SPACING: f64 = 250.0;
+/// Longest spacing between requests to a host, in ms.
+const MAX_SPACING: f64 = 60_000.0;
+
+/// Pace of the requests to a host.
+#[derive(Debug, Default)]
+struct HostPace {
+    /// Time between the starts of requests, in ms, doubled by every rate limit response
+    /// and halved by every other response
+    spacing: f64,
+    /// Time the next request may start at, in ms since the epoch
+    next: f64,
+}
+
+/// Adaptive throttling of the requests to hosts answering with rate limits.
+///
+/// A `429 Too Many Requests` or `503 Service Unavailable` response slows down the following
+/// requests to its host, before the server starts dropping connections, while successful
+/// responses speed them back up. `Retry-After` holds back every request to the host until
+/// the time asked.
+#[derive(Clone, Debug, Default)]
+pub struct HostBackoff {
+    /// Pace of the hosts that sent a rate limit response, by address
+    hosts: Rc<RefCell<HashMap<String, HostPace>>>,
+}
+
+impl HostBackoff {
+    /// Reserve the start of a request to a host.
+    ///
+    /// # Arguments
+    ///
+    /// * `addr` - Address of the host
+    ///
+    /// # Returns
+    ///
+    /// The time to wait before starting the request, in ms.
+    pub fn reserve(&self, addr: &str) -> f64 {
+        let mut hosts = self.hosts.borrow_mut();
+        let pace = match hosts.get_mut(addr) {
+            Some(pace) => pace,
+            None => return 0.0,
+        };
+        let now = js_sys::Date::now();
+        let start = pace.next.max(now);
+        pace.next = start + pace.spacing;
+        start - now
+    }
+
+    /// Adapt the pace of a host to a response it sent.
+    ///
+    /// # Arguments
+    ///
+    /// * `addr` - Address of the host
+    /// * `response` - Response sent by the host
+    pub fn record(&self, addr: &str, response: &HttpConnectionResponse) {
+        let mut hosts = self.hosts.borrow_mut();
+        if !matches!(response.code, 429 | 503) {
+            if let Some(pace) = hosts.get_mut(addr) {
+                pace.spacing /= 2.0;
+                if pace.spacing < MIN_SPACING {
+                    hosts.remove(addr);
+                }
+            }
+            return;
+        }
+
+        let pace = hosts.entry(addr.to_string()).or_default();
+        pace.spacing = (pace.spacing * 2.0).clamp(MIN_SPACING, MAX_SPACING);
+        let retry_after = response
+            .get_header("Retry-After")
+            .and_then(|value| parse_retry_after(&value));
+        if let Some(wait) = retry_after {
+            pace.next = pace.next.max(js_sys::Date::now() + wait.min(MAX_SPACING));
+        }
+    }
+
+    /// Get the time between the starts of requests to a host, in ms, 0 if not slowed down.
+    pub fn spacing(&self, addr: &str) -> f64 {
+        self.hosts
+            .borrow()
+            .get(addr)
+            .map_or(0.0, |pace| pace.spacing)
+    }
+
+    /// Get the pace of every slowed down host, as its address, spacing in ms and time the
+    /// next request may start at in ms since the epoch.
+    pub fn paces(&self) -> Vec<(String, f64, f64)> {
+        self.hosts
+            .borrow()
+            .iter()
+            .map(|(addr, pace)| (addr.clone(), pace.spacing, pace.next))
+            .collect()
+    }
+
+    /// Set the pace of a host, as returned by `paces`.
+    pub fn set_pace(&self, addr: String, spacing: f64, next: f64) {
+        let spacing = spacing.clamp(MIN_SPACING, MAX_SPACING);
+        self.hosts
+            .borrow_mut()
+            .insert(addr, HostPace { spacing, next });
+    }
+}
@@ -0,0 +1,473 @@
+use std::{
+    cell::{Cell, RefCell},
+    collections::VecDeque,
+    rc::Rc,
+};
+
+use wasm_bindgen::{convert::TryFromJsValue, prelude::*};
+use wasm_bindgen_futures::{
+    future_to_promise,
+    js_sys::{Array, Object, Promise, Reflect},
+    JsFuture,
+};
+use web_sys::{AddEventListenerOptions, CloseEvent, WebSocket};
+
+use crate::{
+    backoff::HostBackoff,
+    client::Client,
+    connection::{Connection, ConnectionError, ConnectionSettings, SocketAddr},
+    connection_apis::{
+        http::{ForwardProxy, HttpConnectionApi, HttpConnectionRequest, HttpConnectionResponse},
+        https::HttpsConnectionApi,
+    },
+    id::ConnIdFactory,
+    limiter::{ConnectionLimiter, Priority},
+    middleware::Pipeline,
+    pending::ConnectionClosed,
+    redirect::RedirectPolicy,
+    retry::RetryPolicy,
+    set_timeout,
+    tls::TlsVersions,
+    worker::TlsWorker,
+    SocketCapability,
+};
+
+/// Default number of requests of a batch in flight at once.
+pub const DEFAULT_BATCH_CONCURRENCY: usize = 6;
+
+/// Request of a batch, waiting for its connection.
+pub struct BatchItem {
+    /// Position of the request in the batch
+    pub index: usize,
+    /// ID of the connection of the request, reused by the connections of its retries
+    pub id: u64,
+    /// Protocol of the connection of the request
+    pub protocol: SocketCapability,
+    /// Address the request is sent to
+    pub addr: String,
+    /// Open a connection for the request, once per attempt, failing if the host policy
+    /// refuses it
+    pub open: Rc<dyn Fn() -> Result<Connection, ConnectionError>>,
+    /// Request to send
+    pub request: HttpConnectionRequest,
+}
+
+impl BatchItem {
+    /// Get the url the request is sent to.
+    fn url(&self) -> String {
+        let scheme = match self.protocol {
+            SocketCapability::HTTPS(_) => "https",
+            _ => "http",
+        };
+        format!("{}://{}{}", scheme, self.addr, self.request.path)
+    }
+}
+
+/// Handles of a client needed to prepare requests over new connections, so redirects can be
+/// followed without borrowing the client.
+#[derive(Clone)]
+pub struct Dialer {
+    /// Settings of the new connections
+    pub settings: ConnectionSettings,
+    /// Upstream HTTP proxy plain HTTP requests are routed through, if any
+    pub http_proxy: Option<ForwardProxy>,
+    /// Factory for the connection IDs of the client
+    pub factory: Rc<RefCell<ConnIdFactory>>,
+    /// Protocol of `http://` urls, if a capability of the client
+    pub http: Option<SocketCapability>,
+    /// Protocol of `https://` urls, if a capability of the client
+    pub https: Option<SocketCapability>,
+}
+
+impl Dialer {
+    /// Prepare a request to send over a new connection once the connection limit allows.
+    ///
+    /// # Arguments
+    ///
+    /// * `index` - Position of the request in its batch
+    /// * `protocol` - Protocol of the connection
+    /// * `addr` - Address to send the request to
+    /// * `request` - Request to send
+    pub fn item(
+        &self,
+        index: usize,
+        protocol: SocketCapability,
+        addr: String,
+        request: HttpConnectionRequest,
+    ) -> BatchItem {
+        let id = self.factory.borrow_mut().generate(protocol);
+        let query = Client::protocol_query(protocol);
+        let settings = self.settings.clone();
+        let http_proxy = self.http_proxy.clone();
+        let target = addr.clone();
+        let open: Rc<dyn Fn() -> Result<Connection, ConnectionError>> =
+            Rc::new(move || match protocol {
+                SocketCapability::HTTP => Client::new_http_connection(
+                    &settings,
+                    http_proxy.as_ref(),
+                    target.clone(),
+                    id,
+                    &query,
+                ),
+                _ => Connection::new_with_settings(&settings, protocol, target.clone(), id, &query),
+            });
+        BatchItem {
+            index,
+            id: id.into(),
+            protocol,
+            addr,
+            open,
+            request,
+        }
+    }
+
+    /// Prepare a request to send to an `http://` or `https://` url, e.g. that of a redirect.
+    ///
+    /// # Returns
+    ///
+    /// The request, or an error if the scheme is not a capability of the client or the host
+    /// policy does not allow the address.
+    pub fn item_for_url(
+        &self,
+        index: usize,
+        url: &str,
+        request: HttpConnectionRequest,
+    ) -> Result<BatchItem, ConnectionError> {
+        let (scheme, rest) = url.split_once("://").unwrap_or(("", url));
+        let scheme = scheme.to_ascii_lowercase();
+        let protocol = match scheme.as_str() {
+            "http" => self.http,
+            "https" => self.https,
+            _ => None,
+        }
+        .ok_or_else(|| ConnectionError {
+            message: format!("Protocol not supported by this client: {}", url),
+        })?;
+        let authority = rest.split(['/', '?']).next().unwrap_or(rest);
+        let addr = SocketAddr::split_addr(protocol, format!("{}://{}", scheme, authority))
+            .ok_or_else(|| ConnectionError {
+                message: "Invalid address".to_string(),
+            })?;
+        self.settings.host_policy.check(protocol, &addr)?;
+        Ok(self.item(index, protocol, addr, request))
+    }
+}
+
+/// Handles of a client needed to send requests over new connections, so they are sent
+/// without borrowing the client.
+#[derive(Clone)]
+pub struct Batch {
+    /// Limit of the number of concurrent connections of the client
+    pub limiter: ConnectionLimiter,
+    /// Connections of the client
+    pub connections: Rc<RefCell<Vec<Connection>>>,
+    /// Middlewares of the client
+    pub pipeline: Pipeline,
+    /// Worker to run the TLS state machine in, if any
+    pub tls_worker: Option<TlsWorker>,
+    /// Priority of the connections of the batch while waiting for a free slot
+    pub priority: Priority,
+    /// When to retry requests, if ever
+    pub retry: Option<RetryPolicy>,
+    /// Pace of the requests to hosts answering with rate limits, if adapted
+    pub backoff: Option<HostBackoff>,
+    /// How redirects are followed, if ever
+    pub redirect: Option<RedirectPolicy>,
+    /// Prepares the requests of the redirects followed
+    pub dialer: Dialer,
+}
+
+impl Batch {
+    /// Send the requests of a batch, at most `concurrency` at once.
+    ///
+    /// # Returns
+    ///
+    /// A promise resolving to the outcome of every request, in the order of the batch.
+    pub fn run(self, items: Vec<BatchItem>, concurrency: usize) -> Promise {
+        let results = Array::new_with_length(items.len() as u32);
+        let workers = concurrency.clamp(1, items.len().max(1));
+        let queue = Rc::new(RefCell::new(VecDeque::from(items)));
+
+        let workers: Array = (0..workers)
+            .map(|_| {
+                let batch = self.clone();
+                let queue = queue.clone();
+                let results = results.clone();
+                JsValue::from(future_to_promise(async move {
+                    loop {
+                        // Released before awaiting, so other workers can take requests
+                        let item = queue.borrow_mut().pop_front();
+                        let item = match item {
+                            Some(item) => item,
+                            None => return Ok(JsValue::UNDEFINED),
+                        };
+                        let index = item.index as u32;
+                        let outcome = match batch.fetch(item, None).await {
+                            Ok(response) => {
+                                object(&[("status", "fulfilled".into()), ("value", response)])
+                            }
+                            Err(reason) => {
+                                object(&[("status", "rejected".into()), ("reason", reason)])
+                            }
+                        };
+                        results.set(index, outcome);
+                    }
+                }))
+            })
+            .collect();
+
+        future_to_promise(async move {
+            JsFuture::from(Promise::all(&workers)).await?;
+            Ok(results.into())
+        })
+    }
+
+    /// Send a single request.
+    ///
+    /// # Arguments
+    ///
+    /// * `item` - Request to send
+    /// * `connection` - Connection already added to the client to send the first attempt
+    ///   over, e.g. one opened ahead of time, or `None` to open one once the limit allows
+    ///
+    /// # Returns
+    ///
+    /// A promise resolving to the response, or rejecting with a `ConnectionError`.
+    pub fn fetch_one(self, item: BatchItem, connection: Option<Connection>) -> Promise {
+        future_to_promise(async move { self.fetch(item, connection).await })
+    }
+
+    /// Send a request, following its redirects as the redirect policy allows.
+    async fn fetch(
+        &self,
+        mut item: BatchItem,
+        mut connection: Option<Connection>,
+    ) -> Result<JsValue, JsValue> {
+        let mut redirects = 0;
+        loop {
+            let result = self.fetch_with_retries(&item, connection.take()).await;
+            let policy = match &self.redirect {
+                Some(policy) => policy,
+                None => return result,
+            };
+            let response = HttpConnectionResponse::try_from_js_value(result?)?;
+            let (url, request) = match policy.follow(&item.url(), &item.request, &response) {
+                Some(next) => next,
+                None => return Ok(response.into()),
+            };
+            if redirects == policy.get_max_redirects() {
+                return Err(ConnectionError {
+                    message: format!("Too many redirects: {}", url),
+                }
+                .into());
+            }
+            redirects += 1;
+            item = self.dialer.item_for_url(item.index, &url, request)?;
+        }
+    }
+
+    /// Send a request, retrying it as the retry policy allows. Every attempt is sent over
+    /// its own connection, closed once the response is received.
+    async fn fetch_with_retries(
+        &self,
+        item: &BatchItem,
+        mut connection: Option<Connection>,
+    ) -> Result<JsValue, JsValue> {
+        let mut retry = 0;
+        let mut fell_back = false;
+        loop {
+            if let Some(backoff) = &self.backoff {
+                sleep(backoff.reserve(&item.addr)).await?;
+            }
+            let attempt = match connection.take() {
+                Some(connection) => connection,
+                None => self.open(item).await?,
+            };
+            let id: u64 = attempt.get_id().into();
+            let result = self
+                .send(&attempt, item.protocol, item.request.clone())
+                .await;
+            // Closed once the response is received, freeing its slot
+            attempt.close();
+            self.connections
+                .borrow_mut()
+                .retain(|c| Into::<u64>::into(c.get_id()) != id);
+
+            let rejected = attempt.tls.as_ref().is_some_and(TlsVersions::fell_back);
+            if result.is_err() && rejected && !fell_back {
+                // Retried once on a new connection, offering the TLS version fallen back to
+                fell_back = true;
+                continue;
+            }
+            if self.retry.is_none() && self.backoff.is_none() {
+                return result;
+            }
+            let response = HttpConnectionResponse::try_from_js_value(result?)?;
+            if let Some(backoff) = &self.backoff {
+                backoff.record(&item.addr, &response);
+            }
+            let policy = match &self.retry {
+                Some(policy) => policy,
+                None => return Ok(response.into()),
+            };
+            retry += 1;
+            let wait = match policy.delay(&item.request.method, &response, retry) {
+                Some(wait) => wait,
+                None => return Ok(response.into()),
+            };
+            policy.notify(retry, wait, response.code);
+            sleep(wait).await?;
+        }
+    }
+
+    /// Open a connection for a request once the limit allows.
+    async fn open(&self, item: &BatchItem) -> Result<Connection, JsValue> {
+        let (mut resolve, mut reject) = (None, None);
+        let slot = Promise::new(&mut |res, rej| {
+            resolve = Some(res);
+            reject = Some(rej);
+        });
+        let open = item.open.clone();
+        self.limiter.enqueue(
+            Box::new(move || open()),
+            resolve.unwrap_throw(),
+            reject.unwrap_throw(),
+            self.priority,
+        );
+        JsFuture::from(slot).await?;
+
+        let connection = self
+            .connections
+            .borrow()
+            .iter()
+            .find(|c| Into::<u64>::into(c.get_id()) == item.id)
+            .cloned();
+        connection.ok_or_else(|| {
+            ConnectionError {
+                message: "Connection was removed from its client".to_string(),
+            }
+            .into()
+        })
+    }
+
+    /// Send a request once its connection is open.
+    ///
+    /// # Returns
+    ///
+    /// The response, or an error if the connection fails or closes before the response.
+    async fn send(
+        &self,
+        connection: &Connection,
+        protocol: SocketCapability,
+        request: HttpConnectionRequest,
+    ) -> Result<JsValue, JsValue> {
+        if connection.socket.ready_state() != 1 {
+            JsFuture::from(settle_on(
+                &connection.socket,
+                Some("open"),
+                "Connection failed to open",
+            ))
+            .await?;
+        }
+
+        let mut settle = None;
+        let response = Promise::new(&mut |res, rej| settle = Some((res, rej)));
+        let (resolve, reject) = settle.unwrap_throw();
+        let callback = Closure::once_into_js(move |response: JsValue, error: JsValue| {
+            let _ = match error.is_undefined() {
+                true => resolve.call1(&JsValue::null(), &response),
+                false => reject.call1(&JsValue::null(), &error),
+            };
+        });
+        let closed = settle_on(
+            &connection.socket,
+            None,
+            "Connection closed before the response was received",
+        );
+
+        match protocol {
+            SocketCapability::HTTPS(_) => HttpsConnectionApi::new(
+                connection.clone(),
+                self.tls_worker.clone(),
+                self.pipeline.clone(),
+            )
+            .send(request, callback.unchecked_into())?,
+            _ => HttpConnectionApi::new(connection.clone(), self.pipeline.clone())
+                .send(request, callback.unchecked_into())?,
+        }
+        JsFuture::from(Promise::race(&Array::of2(&response, &closed))).await
+    }
+}
+
+/// Create a promise settled by the events of a socket.
+///
+/// # Arguments
+///
+/// * `socket` - Socket to listen to
+/// * `event` - Event resolving the promise, if any
+/// * `error` - Message of the error the promise rejects with on `error`, as it rejects with a
+///   `ConnectionClosed` on `close`
+pub(crate) fn settle_on(socket: &WebSocket, event: Option<&str>, error: &str) -> Promise {
+    Promise::new(&mut |resolve, reject| {
+        // Settled by whichever event comes first
+        let settled = Rc::new(Cell::new(false));
+        let fail = {
+            let settled = settled.clone();
+            Rc::new(move |error: JsValue| {
+                if !settled.replace(true) {
+                    let _ = reject.call1(&JsValue::null(), &error);
+                }
+            })
+        };
+
+        let mut listeners = vec![];
+        if let Some(event) = event {
+            let settled = settled.clone();
+            let listener = Closure::once_into_js(move || {
+                if !settled.replace(true) {
+                    let _ = resolve.call0(&JsValue::null());
+                }
+            });
+            listeners.push((event, listener));
+        }
+        let onerror = {
+            let fail = fail.clone();
+            let error = error.to_string();
+            Closure::once_into_js(move || fail(ConnectionError { message: error }.into()))
+        };
+        let onclose = Closure::once_into_js(move |event: CloseEvent| {
+            fail(ConnectionClosed::from_event(&event).into())
+        });
+        listeners.push(("error", onerror));
+        listeners.push(("close", onclose));
+        for (event, listener) in listeners {
+            socket
+                .add_event_listener_with_callback_and_add_event_listener_options(
+                    event,
+                    listener.unchecked_ref(),
+                    AddEventListenerOptions::new().once(true),
+                )
+                .unwrap_throw();
+        }
+    })
+}
+
+/// Wait for some time, in ms.
+pub(crate) async fn sleep(millis: f64) -> Result<JsValue, JsValue> {
+    if millis <= 0.0 {
+        return Ok(JsValue::UNDEFINED);
+    }
+    JsFuture::from(Promise::new(&mut |resolve, _| {
+        set_timeout(&resolve, millis);
+    }))
+    .await
+}
+
+/// Create a JS object from its properties.
+pub(crate) fn object(properties: &[(&str, JsValue)]) -> JsValue {
+    let object = Object::new();
+    for (key, value) in properties {
+        Reflect::set(&object, &(*key).into(), value).unwrap_throw();
+    }
+    object.into()
+}
@@ -0,0 +1,135 @@
+use wasm_bindgen::prelude::*;
+use wasm_bindgen_futures::js_sys;
+
+#[wasm_bindgen(typescript_custom_section)]
+const CALLBACK_TYPES: &'static str = r#"
+/** Called with the response to a HTTP or HTTPS request, or with `null` and an error. */
+export type HttpResponseCallback = (
+    response: HttpConnectionResponse | null,
+    error?: ConnectionError,
+) => void;
+/** Called with the data received in response to a TCP send. */
+export type TcpResponseCallback = (response: TcpConnectionResponse) => void;
+/** Called with every datagram received by a UDP connection. */
+export type DatagramCallback = (datagram: Uint8Array) => void;
+/** Called with every message received by an IRC connection. */
+export type IrcMessageCallback = (message: IrcMessage) => void;
+/** Called once a connection is open. */
+export type ReadyCallback = (event: Event) => void;
+/** Called with the progress of a transfer or handshake. */
+export type ProgressCallback = (progress: Progress) => void;
+/** Called with every milestone of the TLS handshake of a request. */
+export type HandshakeCallback = (event: HandshakeEvent) => void;
+/** Called with the ID of every connection closed for being idle. */
+export type ReapCallback = (id: bigint) => void;
+/** Called with every outgoing request, returning the request to send in its place. */
+export type RequestMiddleware = (request: HttpConnectionRequest) => HttpConnectionRequest;
+/** Called with every incoming response, returning the response to deliver in its place. */
+export type ResponseMiddleware = (response: HttpConnectionResponse) => HttpConnectionResponse;
+/** Called with the API of a tunneled connection once established, or with `null` and an error. */
+export type TunnelReadyCallback = (
+    api: TcpConnectionApi | HttpsConnectionApi | null,
+    error?: ConnectionError,
+) => void;
+/** Called with the ID of a connection of a shared client once it is open, or `null`. */
+export type ConnectionIdCallback = (id: bigint | null) => void;
+/** Called before waiting to retry a request, with the retry number, wait in ms and response code. */
+export type RetryCallback = (retry: number, wait: number, code: number) => void;
+/** Called before following a redirect, returning `false` to stop or the request to send instead. */
+export type RedirectCallback = (entry: RedirectEntry) => HttpConnectionRequest | boolean | void;
+/** API of a connection of any protocol. */
+export type ConnectionApi = TcpConnectionApi | UdpConnectionApi | HttpConnectionApi | HttpsConnectionApi;
+/** Request of a batch, sent over a new connection to `url`. */
+export type BatchRequest = { url: string, request: HttpConnectionRequest };
+/** Rule matching hosts, a glob pattern like `*.example.com` or `*:443`, or a regular expression. */
+export type HostRule = string | RegExp;
+"#;
+
+#[wasm_bindgen]
+extern "C" {
+    /// JS function called with the response to a HTTP or HTTPS request, or with `null` and an
+    /// error.
+    #[wasm_bindgen(extends = js_sys::Function, typescript_type = "HttpResponseCallback")]
+    #[derive(Clone, Debug)]
+    pub type HttpResponseCallback;
+
+    /// JS function called with the data received in response to a TCP send.
+    #[wasm_bindgen(extends = js_sys::Function, typescript_type = "TcpResponseCallback")]
+    #[derive(Clone, Debug)]
+    pub type TcpResponseCallback;
+
+    /// JS function called with every datagram received by a UDP connection.
+    #[wasm_bindgen(extends = js_sys::Function, typescript_type = "DatagramCallback")]
+    #[derive(Clone, Debug)]
+    pub type DatagramCallback;
+
+    /// JS function called with every message received by an IRC connection.
+    #[wasm_bindgen(extends = js_sys::Function, typescript_type = "IrcMessageCallback")]
+    #[derive(Clone, Debug)]
+    pub type IrcMessageCallback;
+
+    /// JS function called once a connection is open.
+    #[wasm_bindgen(extends = js_sys::Function, typescript_type = "ReadyCallback")]
+    #[derive(Clone, Debug)]
+    pub type ReadyCallback;
+
+    /// JS function called with the progress of a transfer or handshake.
+    #[wasm_bindgen(extends = js_sys::Function, typescript_type = "ProgressCallback")]
+    #[derive(Clone, Debug)]
+    pub type ProgressCallback;
+
+    /// JS function called with every milestone of the TLS handshake of a request.
+    #[wasm_bindgen(extends = js_sys::Function, typescript_type = "HandshakeCallback")]
+    #[derive(Clone, Debug)]
+    pub type HandshakeCallback;
+
+    /// JS function called with the ID of every connection closed for being idle.
+    #[wasm_bindgen(extends = js_sys::Function, typescript_type = "ReapCallback")]
+    #[derive(Clone, Debug)]
+    pub type ReapCallback;
+
+    /// JS function called with every outgoing request, returning the request to send.
+    #[wasm_bindgen(extends = js_sys::Function, typescript_type = "RequestMiddleware")]
+    #[derive(Clone, Debug)]
+    pub type RequestMiddleware;
+
+    /// JS function called with every incoming response, returning the response to deliver.
+    #[wasm_bindgen(extends = js_sys::Function, typescript_type = "ResponseMiddleware")]
+    #[derive(Clone, Debug)]
+    pub type ResponseMiddleware;
+
+    /// JS function called with the API of a tunneled connection once established.
+    #[wasm_bindgen(extends = js_sys::Function, typescript_type = "TunnelReadyCallback")]
+    #[derive(Clone, Debug)]
+    pub type TunnelReadyCallback;
+
+    /// JS function called with the ID of a connection of a shared client once it is open.
+    #[wasm_bindgen(extends = js_sys::Function, typescript_type = "ConnectionIdCallback")]
+    #[derive(Clone, Debug)]
+    pub type ConnectionIdCallback;
+
+    /// JS function called before waiting to retry a request.
+    #[wasm_bindgen(extends = js_sys::Function, typescript_type = "RetryCallback")]
+    #[derive(Clone, Debug)]
+    pub type RetryCallback;
+
+    /// JS function called before following a redirect.
+    #[wasm_bindgen(extends = js_sys::Function, typescript_type = "RedirectCallback")]
+    #[derive(Clone, Debug)]
+    pub type RedirectCallback;
+
+    /// API of a connection of any protocol.
+    #[wasm_bindgen(typescript_type = "ConnectionApi")]
+    #[derive(Clone, Debug)]
+    pub type ConnectionApi;
+
+    /// Requests of a batch, each sent over a new connection.
+    #[wasm_bindgen(extends = js_sys::Array, typescript_type = "BatchRequest[]")]
+    #[derive(Clone, Debug)]
+    pub type BatchRequests;
+
+    /// Rules matching hosts, each a glob pattern or a regular expression.
+    #[wasm_bindgen(extends = js_sys::Array, typescript_type = "HostRule[]")]
+    #[derive(Clone, Debug)]
+    pub type HostRules;
+}
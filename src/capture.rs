@@ -0,0 +1,298 @@
+use std::{cell::RefCell, collections::VecDeque, rc::Rc};
+
+use wasm_bindgen::prelude::*;
+use wasm_bindgen_futures::js_sys;
+
+use crate::SocketCapability;
+
+/// Default number of payload bytes kept by a capture.
+pub const DEFAULT_CAPTURE_CAPACITY: usize = 1024 * 1024;
+
+/// Largest payload put in a single fake packet, so it fits in an IPv4 packet.
+const MAX_SEGMENT: usize = 65535 - 20 - 20;
+
+/// Fake address of the local end of a captured connection.
+const LOCAL_ADDR: [u8; 4] = [10, 0, 0, 1];
+/// Fake address of the remote end of a captured connection.
+const REMOTE_ADDR: [u8; 4] = [10, 0, 0, 2];
+/// Fake port of the local end of a captured connection.
+const LOCAL_PORT: u16 = 49152;
+
+/// Direction of captured bytes.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Direction {
+    /// Sent to the remote end
+    Outgoing,
+    /// Received from the remote end
+    Incoming,
+}
+
+/// Bytes captured at once.
+#[derive(Debug)]
+struct Record {
+    /// Time of the capture, in ms since the epoch
+    time: f64,
+    /// Direction of the bytes
+    direction: Direction,
+    /// Offset of the bytes in the stream of their direction
+    offset: u32,
+    /// Captured bytes
+    data: Vec<u8>,
+}
+
+/// Bounded buffer of the records of a capture.
+#[derive(Debug)]
+struct CaptureBuffer {
+    /// Records, oldest first
+    records: VecDeque<Record>,
+    /// Payload bytes currently kept
+    size: usize,
+    /// Maximum payload bytes kept before the oldest records are dropped
+    capacity: usize,
+    /// Bytes captured so far in each direction, outgoing first
+    offsets: [u32; 2],
+    /// Time the capture started at, in ms since the epoch
+    started: f64,
+}
+
+/// TCP header fields of a fake packet.
+struct Segment {
+    /// Direction of the packet
+    direction: Direction,
+    /// TCP flags
+    flags: u8,
+    /// Sequence number
+    seq: u32,
+    /// Acknowledgment number
+    ack: u32,
+}
+
+impl Segment {
+    /// Create the header fields of a fake packet.
+    fn new(direction: Direction, flags: u8, seq: u32, ack: u32) -> Self {
+        Self {
+            direction,
+            flags,
+            seq,
+            ack,
+        }
+    }
+}
+
+/// Capture of the raw bytes of a connection in both directions.
+///
+/// The capture is a ring buffer: once its capacity is reached, the oldest records are
+/// dropped. It can be exported as pcapng, with the bytes wrapped in fake IPv4 packets of a
+/// single TCP (or UDP) stream, for analysis in Wireshark.
+#[wasm_bindgen]
+#[derive(Clone, Debug)]
+pub struct Capture {
+    /// Captured records
+    buffer: Rc<RefCell<CaptureBuffer>>,
+    /// Protocol of the captured connection
+    protocol: SocketCapability,
+    /// Port of the remote end of the captured connection
+    port: u16,
+}
+
+impl Capture {
+    /// Create a new empty capture.
+    ///
+    /// # Arguments
+    ///
+    /// * `protocol` - Protocol of the captured connection
+    /// * `addr` - Address of the captured connection
+    /// * `capacity` - Maximum payload bytes kept
+    pub fn new(protocol: SocketCapability, addr: &str, capacity: usize) -> Self {
+        let port = addr
+            .rsplit(':')
+            .next()
+            .and_then(|port| port.parse().ok())
+            .unwrap_or(match protocol {
+                SocketCapability::HTTPS(_) => 443,
+                _ => 80,
+            });
+        Self {
+            buffer: Rc::new(RefCell::new(CaptureBuffer {
+                records: VecDeque::new(),
+                size: 0,
+                capacity,
+                offsets: [0; 2],
+                started: js_sys::Date::now(),
+            })),
+            protocol,
+            port,
+        }
+    }
+
+    /// Record bytes sent or received.
+    ///
+    /// # Arguments
+    ///
+    /// * `direction` - Direction of the bytes
+    /// * `data` - Bytes to record
+    pub fn record(&self, direction: Direction, data: &[u8]) {
+        let mut buffer = self.buffer.borrow_mut();
+        let offset = buffer.offsets[direction as usize];
+        buffer.offsets[direction as usize] = offset.wrapping_add(data.len() as u32);
+
+        // Keep only the tail of records larger than the whole buffer
+        let skip = data.len().saturating_sub(buffer.capacity);
+        let data = &data[skip..];
+        buffer.size += data.len();
+        buffer.records.push_back(Record {
+            time: js_sys::Date::now(),
+            direction,
+            offset: offset.wrapping_add(skip as u32),
+            data: data.to_vec(),
+        });
+
+        while buffer.size > buffer.capacity {
+            let dropped = buffer.records.pop_front().unwrap_throw();
+            buffer.size -= dropped.data.len();
+        }
+    }
+
+    /// Write a fake IPv4 packet carrying the given payload as an enhanced packet block.
+    fn write_packet(&self, out: &mut Vec<u8>, time: f64, segment: &Segment, payload: &[u8]) {
+        let tcp = !matches!(self.protocol, SocketCapability::UDP);
+        let (src, dst, sport, dport) = match segment.direction {
+            Direction::Outgoing => (LOCAL_ADDR, REMOTE_ADDR, LOCAL_PORT, self.port),
+            Direction::Incoming => (REMOTE_ADDR, LOCAL_ADDR, self.port, LOCAL_PORT),
+        };
+
+        let transport_len = if tcp { 20 } else { 8 };
+        let total_len = 20 + transport_len + payload.len();
+        let mut packet = Vec::with_capacity(total_len);
+
+        packet.extend_from_slice(&[0x45, 0]);
+        packet.extend_from_slice(&(total_len as u16).to_be_bytes());
+        packet.extend_from_slice(&[0, 0, 0x40, 0, 64, if tcp { 6 } else { 17 }, 0, 0]);
+        packet.extend_from_slice(&src);
+        packet.extend_from_slice(&dst);
+        let checksum = ipv4_checksum(&packet);
+        packet[10..12].copy_from_slice(&checksum.to_be_bytes());
+
+        packet.extend_from_slice(&sport.to_be_bytes());
+        packet.extend_from_slice(&dport.to_be_bytes());
+        if tcp {
+            // Checksum left at 0, Wireshark does not validate it by default
+            packet.extend_from_slice(&segment.seq.to_be_bytes());
+            packet.extend_from_slice(&segment.ack.to_be_bytes());
+            packet.extend_from_slice(&[0x50, segment.flags, 0xFF, 0xFF, 0, 0, 0, 0]);
+        } else {
+            packet.extend_from_slice(&((8 + payload.len()) as u16).to_be_bytes());
+            packet.extend_from_slice(&[0, 0]);
+        }
+        packet.extend_from_slice(payload);
+
+        let micros = (time * 1000.0) as u64;
+        write_block(out, 0x0000_0006, |body| {
+            body.extend_from_slice(&0u32.to_le_bytes());
+            body.extend_from_slice(&((micros >> 32) as u32).to_le_bytes());
+            body.extend_from_slice(&(micros as u32).to_le_bytes());
+            body.extend_from_slice(&(packet.len() as u32).to_le_bytes());
+            body.extend_from_slice(&(packet.len() as u32).to_le_bytes());
+            body.extend_from_slice(&packet);
+            body.resize(body.len().next_multiple_of(4), 0);
+        });
+    }
+}
+
+#[wasm_bindgen]
+impl Capture {
+    /// Get the number of records kept.
+    #[wasm_bindgen]
+    pub fn len(&self) -> usize {
+        self.buffer.borrow().records.len()
+    }
+
+    /// Check if no record is kept.
+    #[wasm_bindgen]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Get the number of payload bytes kept.
+    #[wasm_bindgen]
+    pub fn size(&self) -> usize {
+        self.buffer.borrow().size
+    }
+
+    /// Discard all records kept.
+    #[wasm_bindgen]
+    pub fn clear(&self) {
+        let mut buffer = self.buffer.borrow_mut();
+        buffer.records.clear();
+        buffer.size = 0;
+    }
+
+    /// Export the records kept as a pcapng file.
+    #[wasm_bindgen]
+    pub fn export_pcapng(&self) -> Vec<u8> {
+        let buffer = self.buffer.borrow();
+        let mut out = Vec::with_capacity(64 + buffer.size * 2);
+
+        // Section header block
+        write_block(&mut out, 0x0A0D_0D0A, |body| {
+            body.extend_from_slice(&0x1A2B_3C4Du32.to_le_bytes());
+            body.extend_from_slice(&1u16.to_le_bytes());
+            body.extend_from_slice(&0u16.to_le_bytes());
+            body.extend_from_slice(&(-1i64).to_le_bytes());
+        });
+        // Interface description block, raw IP link type
+        write_block(&mut out, 0x0000_0001, |body| {
+            body.extend_from_slice(&101u16.to_le_bytes());
+            body.extend_from_slice(&0u16.to_le_bytes());
+            body.extend_from_slice(&0u32.to_le_bytes());
+        });
+
+        let tcp = !matches!(self.protocol, SocketCapability::UDP);
+        if tcp {
+            // Fake handshake, so the stream is decoded from its start
+            let syn = Segment::new(Direction::Outgoing, 0x02, u32::MAX, 0);
+            let syn_ack = Segment::new(Direction::Incoming, 0x12, u32::MAX, 0);
+            let ack = Segment::new(Direction::Outgoing, 0x10, 0, 0);
+            for segment in [syn, syn_ack, ack] {
+                self.write_packet(&mut out, buffer.started, &segment, &[]);
+            }
+        }
+
+        // End of the stream written so far in each direction, acknowledged by the other
+        let mut ends = [0u32; 2];
+        for record in &buffer.records {
+            let ack = ends[1 - record.direction as usize];
+            for (i, chunk) in record.data.chunks(MAX_SEGMENT).enumerate() {
+                let seq = record.offset.wrapping_add((i * MAX_SEGMENT) as u32);
+                let segment = Segment::new(record.direction, 0x18, seq, ack);
+                self.write_packet(&mut out, record.time, &segment, chunk);
+            }
+            ends[record.direction as usize] = record.offset.wrapping_add(record.data.len() as u32);
+        }
+
+        out
+    }
+}
+
+/// Write a pcapng block, framing the body written by `write_body` with its type and length.
+fn write_block(out: &mut Vec<u8>, block_type: u32, write_body: impl FnOnce(&mut Vec<u8>)) {
+    let start = out.len();
+    out.extend_from_slice(&block_type.to_le_bytes());
+    out.extend_from_slice(&[0; 4]);
+    write_body(out);
+    let len = (out.len() - start + 4) as u32;
+    out[start + 4..start + 8].copy_from_slice(&len.to_le_bytes());
+    out.extend_from_slice(&len.to_le_bytes());
+}
+
+/// Compute the checksum of an IPv4 header.
+fn ipv4_checksum(header: &[u8]) -> u16 {
+    let mut sum: u32 = header
+        .chunks(2)
+        .map(|pair| u32::from(u16::from_be_bytes([pair[0], pair[1]])))
+        .sum();
+    while sum > 0xFFFF {
+        sum = (sum & 0xFFFF) + (sum >> 16);
+    }
+    !(sum as u16)
+}
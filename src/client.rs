@@ -1,24 +1,124 @@
-use wasm_bindgen::prelude::*;
-use wasm_bindgen_futures::js_sys;
+use std::{
+    cell::{Cell, RefCell},
+    rc::Rc,
+};
+
+use wasm_bindgen::{convert::TryFromJsValue, prelude::*};
+use wasm_bindgen_futures::{future_to_promise, js_sys, JsFuture};
+use web_sys::{AddEventListenerOptions, CloseEvent};
 
+#[cfg(feature = "http3")]
+use crate::connection_apis::http3::Http3ConnectionApi;
 use crate::{
-    connection::{Connection, SocketAddr},
-    connection_apis::{http::HttpConnectionApi, https::HttpsConnectionApi, tcp::TcpConnectionApi},
+    backoff::HostBackoff,
+    batch::{Batch, Dialer, DEFAULT_BATCH_CONCURRENCY},
+    callbacks::{
+        BatchRequests, ConnectionApi, HostRules, IrcMessageCallback, ReadyCallback, ReapCallback,
+        RequestMiddleware, ResponseMiddleware, TunnelReadyCallback,
+    },
+    capture::{Capture, DEFAULT_CAPTURE_CAPACITY},
+    compat::{ProxyCompat, ProxyProfile},
+    conditions::NetworkConditions,
+    connection::{Connection, ConnectionError, ConnectionInfo, ConnectionSettings, SocketAddr},
+    connection_apis::{
+        ftp::{ftp_addr, FtpConnectionApi},
+        http::{
+            validate_method, ForwardProxy, HttpConnectionApi, HttpConnectionRequest, HttpHeader,
+            TimeoutPolicy, METHOD_DELETE, METHOD_GET, METHOD_POST, METHOD_PUT,
+        },
+        https::HttpsConnectionApi,
+        irc::{irc_addr, IrcConnectionApi, IrcRegistration},
+        tcp::{TcpConnectionApi, TcpDialer, TcpOptions},
+        tunnel,
+        udp::UdpConnectionApi,
+    },
+    cors::CorsPolicy,
+    debug,
+    framing::Framing,
     get_capabilities,
-    id::ConnIdFactory,
+    handle::ConnectionHandle,
+    har::HarRecorder,
+    id::{ConnId, ConnIdFactory, ConnIdParts, ConnIdScheme},
+    limiter::{ConnectionLimiter, Priority},
+    middleware::{
+        HopByHopMiddleware, JsMiddleware, Middleware, Pipeline, RequestIdMiddleware, ViaMiddleware,
+        DEFAULT_REQUEST_ID_HEADER,
+    },
+    options::{parse_tls_version, ConnectionOptions},
+    pending::ConnectionClosed,
+    policy::{HostPolicy, BAD_PORTS},
+    reaper::IdleReaper,
+    redirect::RedirectPolicy,
+    retry::RetryPolicy,
+    set_timeout,
+    snapshot::ClientSnapshot,
+    stats::{ClientStats, StatsRecorder},
+    tls::{
+        check_names, cipher_suite_names, kx_group_names, supported_versions, TlsDowngrades,
+        TlsOptions, TlsProfile, TlsVersions,
+    },
+    usage::BufferUsage,
+    whois,
+    worker::TlsWorker,
     SocketCapability, TLSVersion,
 };
 
 #[wasm_bindgen]
 pub struct Client {
     /// Factory for connection IDs.
-    factory: ConnIdFactory,
+    factory: Rc<RefCell<ConnIdFactory>>,
     /// Base socket address of this client.
     addr: String,
     /// Connections belonging to this client.
-    connections: Vec<Connection>,
+    connections: Rc<RefCell<Vec<Connection>>>,
     /// Capabilities of this client.
     capabilities: Vec<SocketCapability>,
+    /// Worker to run TLS sessions in, if any.
+    tls_worker: Option<TlsWorker>,
+    /// Middlewares run on HTTP and HTTPS requests and responses.
+    pipeline: Pipeline,
+    /// Capacity of the raw traffic capture of new connections, if enabled.
+    capture_capacity: Option<usize>,
+    /// Upload and download rate limits of new connections, in bytes per second.
+    throttle_limits: (Option<f64>, Option<f64>),
+    /// Network conditions simulated on new connections, if any.
+    network_conditions: Option<NetworkConditions>,
+    /// Limit of the number of concurrent connections.
+    limiter: ConnectionLimiter,
+    /// Check closing idle connections, if enabled.
+    reaper: Option<IdleReaper>,
+    /// Upstream HTTP proxy plain HTTP requests are routed through, if any.
+    http_proxy: Option<ForwardProxy>,
+    /// When to retry requests sent by `get`, `post`, `put`, `delete` and `fetch_all`, if ever.
+    retry: Option<RetryPolicy>,
+    /// How requests sent by `get`, `post`, `put`, `delete` and `fetch_all` follow redirects,
+    /// if ever.
+    redirect: Option<RedirectPolicy>,
+    /// Pace of the requests sent by `get`, `post`, `put`, `delete` and `fetch_all` to hosts
+    /// answering with rate limits, if adapted.
+    backoff: Option<HostBackoff>,
+    /// How bytes are carried in the frames exchanged with the proxy.
+    framing: Framing,
+    /// Totals across all connections of this client.
+    stats: StatsRecorder,
+    /// Name of this client, if any.
+    name: Option<String>,
+    /// Lowest TLS version HTTPS connections may fall back to, if falling back is enabled.
+    tls_fallback_floor: Option<TLSVersion>,
+    /// TLS versions HTTPS connections fell back to, by address.
+    tls_downgrades: TlsDowngrades,
+    /// Cipher suites and key exchange groups of new HTTPS connections.
+    tls_options: TlsOptions,
+    /// What happens to new connections when a request times out.
+    timeout_policy: TimeoutPolicy,
+    /// Connections opened ahead of time by `preconnect`, not handed out yet.
+    preconnected: Vec<Connection>,
+    /// Hosts and ports new connections may be created to.
+    host_policy: HostPolicy,
+    /// Preset handling of the proxy applied, if any.
+    proxy_profile: Option<ProxyProfile>,
+    /// How new connections reach their target through the proxy.
+    proxy: ProxyCompat,
 }
 
 #[wasm_bindgen]
@@ -26,11 +126,35 @@ impl Client {
     /// Create a new client using the given socket address, and the default capabilities.
     #[wasm_bindgen(constructor)]
     pub fn new(addr: String) -> Self {
+        let connections = Rc::new(RefCell::new(Vec::new()));
+        debug::register(&addr, &connections);
         Client {
-            factory: ConnIdFactory::new(),
+            factory: Rc::new(RefCell::new(ConnIdFactory::new())),
             addr,
-            connections: Vec::new(),
+            limiter: ConnectionLimiter::new(connections.clone()),
+            connections,
             capabilities: get_capabilities(),
+            tls_worker: None,
+            pipeline: Pipeline::default(),
+            capture_capacity: None,
+            throttle_limits: (None, None),
+            network_conditions: None,
+            reaper: None,
+            http_proxy: None,
+            retry: None,
+            redirect: None,
+            backoff: None,
+            framing: Framing::default(),
+            stats: StatsRecorder::default(),
+            name: None,
+            tls_fallback_floor: None,
+            tls_downgrades: TlsDowngrades::default(),
+            tls_options: TlsOptions::default(),
+            timeout_policy: TimeoutPolicy::default(),
+            preconnected: Vec::new(),
+            host_policy: HostPolicy::default(),
+            proxy_profile: None,
+            proxy: ProxyCompat::default(),
         }
     }
     /// Create a new client using the given socket address, and the given capabilities.
@@ -40,23 +164,338 @@ impl Client {
             .iter()
             .filter_map(|s| SocketCapability::from_string(s.to_lowercase().as_str()))
             .collect();
+        let connections = Rc::new(RefCell::new(Vec::new()));
+        debug::register(&addr, &connections);
         Client {
-            factory: ConnIdFactory::new(),
+            factory: Rc::new(RefCell::new(ConnIdFactory::new())),
             addr,
-            connections: Vec::new(),
+            limiter: ConnectionLimiter::new(connections.clone()),
+            connections,
             capabilities,
+            tls_worker: None,
+            pipeline: Pipeline::default(),
+            capture_capacity: None,
+            throttle_limits: (None, None),
+            network_conditions: None,
+            reaper: None,
+            http_proxy: None,
+            retry: None,
+            redirect: None,
+            backoff: None,
+            framing: Framing::default(),
+            stats: StatsRecorder::default(),
+            name: None,
+            tls_fallback_floor: None,
+            tls_downgrades: TlsDowngrades::default(),
+            tls_options: TlsOptions::default(),
+            timeout_policy: TimeoutPolicy::default(),
+            preconnected: Vec::new(),
+            host_policy: HostPolicy::default(),
+            proxy_profile: None,
+            proxy: ProxyCompat::default(),
+        }
+    }
+    /// Save the configuration of this client, and the pace of the hosts slowed down by
+    /// adaptive throttling, so a new client can resume with it after a page reload or
+    /// worker restart.
+    ///
+    /// Live connections are not saved, nor is anything holding a JS function, such as
+    /// middlewares, callbacks, the idle timeout or the TLS worker, nor the credentials of the
+    /// upstream HTTP proxy. These must be set again on the restored client.
+    /// # Returns
+    /// The snapshot, as JSON.
+    #[wasm_bindgen]
+    pub fn serialize(&self) -> Result<String, ConnectionError> {
+        ClientSnapshot {
+            addr: self.addr.clone(),
+            capabilities: self.get_capabilities(),
+            name: self.name.clone(),
+            framing: self.framing,
+            id_scheme: self.get_id_scheme(),
+            proxy_profile: self.proxy_profile,
+            proxy_url_template: Some(self.proxy.url_template.clone()),
+            timeout_policy: self.timeout_policy,
+            tls_fallback: self.tls_fallback_floor.map(|v| v.to_string()),
+            tls_options: self.tls_options.clone(),
+            capture_capacity: self.capture_capacity,
+            throttle_limits: self.throttle_limits,
+            network_conditions: self.network_conditions,
+            max_connections: self.limiter.max(),
+            http_proxy: self.http_proxy.as_ref().map(|proxy| proxy.addr.clone()),
+            retry: self.retry.clone(),
+            max_redirects: self
+                .redirect
+                .as_ref()
+                .map(RedirectPolicy::get_max_redirects),
+            backoff: self.backoff.clone(),
         }
+        .to_json()
+    }
+    /// Create a new client from a snapshot saved by `serialize`.
+    /// # Arguments
+    /// * `snapshot` - Snapshot of a client, as JSON
+    #[wasm_bindgen]
+    pub fn restore(snapshot: &str) -> Result<Client, ConnectionError> {
+        let snapshot = ClientSnapshot::from_json(snapshot)?;
+        let mut client = Client::new_with_capabilities(snapshot.addr, snapshot.capabilities);
+        client.set_name(snapshot.name);
+        client.set_proxy_profile(snapshot.proxy_profile);
+        client.set_proxy_url_template(snapshot.proxy_url_template)?;
+        client.framing = snapshot.framing;
+        client.set_id_scheme(snapshot.id_scheme);
+        client.timeout_policy = snapshot.timeout_policy;
+        client.set_tls_fallback(snapshot.tls_fallback)?;
+        client.set_tls_cipher_suites(snapshot.tls_options.cipher_suites)?;
+        client.set_tls_kx_groups(snapshot.tls_options.kx_groups)?;
+        client.tls_options.min_version = snapshot.tls_options.min_version;
+        client.capture_capacity = snapshot.capture_capacity;
+        client.throttle_limits = snapshot.throttle_limits;
+        client.network_conditions = snapshot.network_conditions;
+        client.limiter.set_max(snapshot.max_connections);
+        client.set_http_proxy(snapshot.http_proxy, None);
+        client.retry = snapshot.retry;
+        client.redirect = snapshot
+            .max_redirects
+            .map(|max| RedirectPolicy::new(Some(max)));
+        client.backoff = snapshot.backoff;
+        Ok(client)
     }
     /// Get the base wsproxy url of this client.
     #[wasm_bindgen]
     pub fn get_addr(&self) -> String {
         self.addr.clone()
     }
+    /// Name this client, so apps using several clients can tell their connections apart.
+    ///
+    /// The name appears in logs, stats, HAR exports and `get_connections`, for connections
+    /// created after it is set.
+    /// # Arguments
+    /// * `name` - Name of this client, or `None` to remove it
+    #[wasm_bindgen]
+    pub fn set_name(&mut self, name: Option<String>) {
+        debug::rename(&self.connections, name.clone());
+        self.name = name;
+    }
+    /// Get the name of this client, if any.
+    #[wasm_bindgen]
+    pub fn get_name(&self) -> Option<String> {
+        self.name.clone()
+    }
+    /// Get a description of every connection of this client.
+    #[wasm_bindgen]
+    pub fn get_connections(&self) -> Vec<ConnectionInfo> {
+        self.connections
+            .borrow()
+            .iter()
+            .map(Connection::info)
+            .collect()
+    }
+    /// Replace the tags of the connection with the given ID.
+    ///
+    /// Stats only count the tags connections were created with.
+    /// # Arguments
+    /// * `id` - ID of the connection
+    /// * `tags` - Labels to attach to the connection
+    /// # Returns
+    /// Whether the connection exists.
+    #[wasm_bindgen]
+    pub fn set_connection_tags(&self, id: u64, tags: Vec<String>) -> bool {
+        self.connections
+            .borrow()
+            .iter()
+            .find(|c| Into::<u64>::into(c.get_id()) == id)
+            .map(|c| c.set_tags(tags))
+            .is_some()
+    }
+    /// Get the tags of the connection with the given ID, if it exists.
+    #[wasm_bindgen]
+    pub fn get_connection_tags(&self, id: u64) -> Option<Vec<String>> {
+        self.connections
+            .borrow()
+            .iter()
+            .find(|c| Into::<u64>::into(c.get_id()) == id)
+            .map(Connection::get_tags)
+    }
+    /// Decode a connection ID into the time it was made at, the type of the connection and
+    /// its sequence number, e.g. to render it in a dashboard.
+    ///
+    /// The connection does not need to exist. If it is a connection of this client, its UUID
+    /// is included as well.
+    /// # Arguments
+    /// * `id` - ID of the connection
+    #[wasm_bindgen]
+    pub fn decode_id(&self, id: u64) -> ConnIdParts {
+        self.connections
+            .borrow()
+            .iter()
+            .map(Connection::get_id)
+            .find(|c| Into::<u64>::into(*c) == id)
+            .unwrap_or_else(|| ConnId::from(id))
+            .into()
+    }
     /// Get the capabilities of this client.
     #[wasm_bindgen]
     pub fn get_capabilities(&self) -> Vec<String> {
         self.capabilities.iter().map(|c| c.to_string()).collect()
     }
+    /// Check if this client has a capability.
+    /// # Arguments
+    /// * `capability` - Name of the capability, e.g. `tcp` or `https_tls1_3`, or `https` for
+    ///   any TLS version
+    #[wasm_bindgen]
+    pub fn supports_capability(&self, capability: &str) -> bool {
+        let capability = capability.to_lowercase();
+        self.capabilities.iter().any(|c| match c {
+            SocketCapability::HTTPS(_) if capability == "https" => true,
+            c => c.to_string() == capability,
+        })
+    }
+    /// Set what happens to new connections when a request times out.
+    ///
+    /// By default the connection is closed, so the rest of the late response is never read
+    /// as the response of the next request.
+    /// # Arguments
+    /// * `policy` - Policy applied to connections created after this call
+    #[wasm_bindgen]
+    pub fn set_timeout_policy(&mut self, policy: TimeoutPolicy) {
+        self.timeout_policy = policy;
+    }
+    /// Only allow new connections to hosts matching at least one of the given rules.
+    ///
+    /// Rules are glob patterns, where `*` matches any run of characters and `?` any single
+    /// one, or regular expressions. A rule matches a host if it matches its name, e.g.
+    /// `*.example.com`, or its address with the port, e.g. `example.com:443`. The policy is
+    /// checked before the proxy is dialed, creating a connection to any other host fails.
+    /// # Arguments
+    /// * `rules` - Rules of the hosts allowed, or `None` to allow every host
+    #[wasm_bindgen]
+    pub fn set_host_allowlist(&mut self, rules: Option<HostRules>) -> Result<(), ConnectionError> {
+        self.host_policy.set_allow(rules)
+    }
+    /// Forbid new connections to hosts matching any of the given rules, even if allowed by
+    /// `set_host_allowlist`. Rules are matched like those of `set_host_allowlist`, e.g. `*:25`
+    /// to forbid SMTP.
+    /// # Arguments
+    /// * `rules` - Rules of the hosts forbidden, or `None` to forbid no host
+    #[wasm_bindgen]
+    pub fn set_host_denylist(&mut self, rules: Option<HostRules>) -> Result<(), ConnectionError> {
+        self.host_policy.set_deny(rules)
+    }
+    /// Check if the allowlist and denylist allow new connections to an address, regardless
+    /// of `set_block_bad_ports`.
+    /// # Arguments
+    /// * `addr` - Address to connect to, e.g. `example.com:443`
+    #[wasm_bindgen]
+    pub fn is_host_allowed(&self, addr: String) -> bool {
+        self.host_policy.check(SocketCapability::TCP, &addr).is_ok()
+    }
+    /// Forbid new HTTP and HTTPS connections to the ports browsers block, as listed by the
+    /// Fetch standard, e.g. 25 (SMTP) or 6000 (X11).
+    ///
+    /// Creating such a connection fails with a `ConnectionError`, and requests to them or
+    /// redirects to them are rejected, unless the connection is created with
+    /// `create_connection_with_options` and `ConnectionOptions.set_allow_bad_port`. TCP and
+    /// UDP connections are never affected.
+    /// # Arguments
+    /// * `block` - Whether to forbid the connections
+    #[wasm_bindgen]
+    pub fn set_block_bad_ports(&mut self, block: bool) {
+        self.host_policy.set_block_bad_ports(block);
+    }
+    /// Check if a port is blocked by `set_block_bad_ports`.
+    #[wasm_bindgen]
+    pub fn is_bad_port(port: u16) -> bool {
+        BAD_PORTS.contains(&port)
+    }
+    /// Fall back to the next lower TLS version this client allows when a server rejects the
+    /// offered ones, never going below `floor`.
+    ///
+    /// The request whose handshake was rejected fails and its connection is closed, as the
+    /// handshake cannot be retried on the same socket. New connections to the same address
+    /// then offer the lower version, and requests sent by `get`, `post`, `put`, `delete` and
+    /// `fetch_all` are retried once over one. Only affects connections created after this
+    /// call. The version finally negotiated is reported by `get_tls_version` on the
+    /// connection API.
+    /// # Arguments
+    /// * `floor` - Lowest TLS version to fall back to, e.g. `tls1_2`, or `None` to never fall back
+    #[wasm_bindgen]
+    pub fn set_tls_fallback(&mut self, floor: Option<String>) -> Result<(), ConnectionError> {
+        self.tls_fallback_floor = floor.as_deref().map(parse_tls_version).transpose()?;
+        Ok(())
+    }
+    /// Restrict the cipher suites offered by new HTTPS connections, e.g. for compliance
+    /// requirements or servers mishandling some suites.
+    ///
+    /// The handshake fails if none of the suites can be used with the TLS versions offered.
+    /// # Arguments
+    /// * `suites` - Names of the suites, as returned by `get_supported_cipher_suites`, or `None`
+    ///   to offer every supported suite
+    #[wasm_bindgen]
+    pub fn set_tls_cipher_suites(
+        &mut self,
+        suites: Option<Vec<String>>,
+    ) -> Result<(), ConnectionError> {
+        if let Some(suites) = &suites {
+            check_names("cipher suite", suites, &cipher_suite_names())?;
+        }
+        self.tls_options.cipher_suites = suites;
+        Ok(())
+    }
+    /// Restrict the key exchange groups offered by new HTTPS connections.
+    /// # Arguments
+    /// * `groups` - Names of the groups, as returned by `get_supported_kx_groups`, or `None` to
+    ///   offer every supported group
+    #[wasm_bindgen]
+    pub fn set_tls_kx_groups(
+        &mut self,
+        groups: Option<Vec<String>>,
+    ) -> Result<(), ConnectionError> {
+        if let Some(groups) = &groups {
+            check_names("key exchange group", groups, &kx_group_names())?;
+        }
+        self.tls_options.kx_groups = groups;
+        Ok(())
+    }
+    /// Apply a preset of TLS settings to new HTTPS connections, replacing their cipher suites,
+    /// key exchange groups and fallback.
+    ///
+    /// `Strict` only offers TLS 1.3 with AEAD suites and modern groups, and never falls back,
+    /// so the client needs the `https_tls1_3` capability. `Compat` offers everything supported
+    /// and falls back down to TLS 1.2. Certificate transparency and stapled OCSP responses are
+    /// not checked by either profile, as rustls does not verify them.
+    /// # Arguments
+    /// * `profile` - Preset to apply, or `None` to restore the defaults
+    #[wasm_bindgen]
+    pub fn set_tls_profile(&mut self, profile: Option<TlsProfile>) -> Result<(), ConnectionError> {
+        if profile == Some(TlsProfile::Strict) && !self.supports_capability("https_tls1_3") {
+            return Err(ConnectionError {
+                message: "The strict TLS profile needs the https_tls1_3 capability".to_string(),
+            });
+        }
+        self.tls_options = profile.map(TlsProfile::options).unwrap_or_default();
+        self.tls_fallback_floor = profile.and_then(TlsProfile::fallback_floor);
+        Ok(())
+    }
+    /// Get the names of the cipher suites supported by HTTPS connections, in order of
+    /// preference, e.g. `TLS13_AES_256_GCM_SHA384`.
+    #[wasm_bindgen]
+    pub fn get_supported_cipher_suites() -> Vec<String> {
+        cipher_suite_names()
+    }
+    /// Get the names of the key exchange groups supported by HTTPS connections, in order of
+    /// preference, e.g. `X25519`.
+    #[wasm_bindgen]
+    pub fn get_supported_kx_groups() -> Vec<String> {
+        kx_group_names()
+    }
+    /// Get the highest TLS version of this client, e.g. `tls1_2`, for feature detection
+    /// before creating a HTTPS connection.
+    /// # Returns
+    /// The TLS version, or nothing if this client has no HTTPS capability.
+    #[wasm_bindgen(js_name = get_highest_tls_version)]
+    pub fn highest_tls_version(&self) -> Option<String> {
+        self.max_tls_version().map(|v| v.to_string())
+    }
     /// Get the capabilities of this implementation.
     #[wasm_bindgen]
     pub fn get_impl_capabilities() -> Vec<String> {
@@ -65,152 +504,1962 @@ impl Client {
             .map(|c| c.to_string())
             .collect()
     }
+    /// Run the TLS state machine of HTTPS connections in a dedicated worker.
+    ///
+    /// Only affects connections created after this call.
+    /// # Arguments
+    /// * `worker` - Worker that has called `start_tls_worker`
+    #[wasm_bindgen]
+    pub fn set_tls_worker(&mut self, worker: web_sys::Worker) {
+        self.tls_worker = Some(TlsWorker::new(worker));
+    }
+    /// Add a middleware run on the HTTP and HTTPS requests and responses of this client.
+    ///
+    /// Each function is called with the request or response and must return the object to
+    /// use in its place. Requests go through middlewares in the order they were added, and
+    /// responses in reverse order. Only affects connection APIs created after this call.
+    /// # Arguments
+    /// * `on_request` - Function called with outgoing requests
+    /// * `on_response` - Function called with incoming responses
+    #[wasm_bindgen]
+    pub fn add_middleware(
+        &mut self,
+        on_request: Option<RequestMiddleware>,
+        on_response: Option<ResponseMiddleware>,
+    ) {
+        self.use_middleware(JsMiddleware::new(
+            on_request.map(Into::into),
+            on_response.map(Into::into),
+        ));
+    }
+    /// Attach a unique correlation ID to every HTTP and HTTPS request of this client.
+    ///
+    /// The ID is sent in the given header, prefixed to the log lines and errors of the
+    /// request, and returned by `get_request_id` on the response. Only affects connection
+    /// APIs created after this call.
+    /// # Arguments
+    /// * `header` - Header carrying the ID, `X-Request-ID` if not given
+    #[wasm_bindgen]
+    pub fn enable_request_id(&mut self, header: Option<String>) {
+        let header = header.unwrap_or_else(|| DEFAULT_REQUEST_ID_HEADER.to_string());
+        self.use_middleware(RequestIdMiddleware::new(header));
+    }
+    /// Remove hop-by-hop headers, such as `Connection`, `Keep-Alive`, `Transfer-Encoding` and
+    /// `Upgrade`, from the HTTP and HTTPS requests and responses of this client, per RFC 7230.
+    ///
+    /// Meant for clients forwarding messages of another HTTP layer, e.g. a fetch shim in a
+    /// service worker, so the headers of that hop don't leak into the tunneled request or
+    /// back into the forwarded response. Headers named by the `Connection` header are removed
+    /// as well. Only affects connection APIs created after this call.
+    #[wasm_bindgen]
+    pub fn enable_hop_by_hop_stripping(&mut self) {
+        self.use_middleware(HopByHopMiddleware);
+    }
+    /// Identify this client as a hop in the `Via` header of its HTTP and HTTPS requests, e.g.
+    /// `Via: 1.1 socketguard`, so servers can tell tunneled traffic apart when debugging.
+    ///
+    /// `X-Forwarded-*` headers are only sent for the values given. `X-Forwarded-For` is
+    /// appended to, while `X-Forwarded-Host` and `X-Forwarded-Proto` set by the request are
+    /// kept. Only affects connection APIs created after this call.
+    /// # Arguments
+    /// * `pseudonym` - Name of the hop, the name of the client or `socketguard` if not given
+    /// * `forwarded_for` - Address of the original client, for `X-Forwarded-For`
+    /// * `forwarded_host` - Original `Host` of the requests, for `X-Forwarded-Host`
+    /// * `forwarded_proto` - Original protocol of the requests, e.g. `https`, for
+    ///   `X-Forwarded-Proto`
+    #[wasm_bindgen]
+    pub fn enable_via(
+        &mut self,
+        pseudonym: Option<String>,
+        forwarded_for: Option<String>,
+        forwarded_host: Option<String>,
+        forwarded_proto: Option<String>,
+    ) -> Result<(), ConnectionError> {
+        let middleware =
+            ViaMiddleware::new(pseudonym, forwarded_for, forwarded_host, forwarded_proto)?;
+        self.use_middleware(middleware);
+        Ok(())
+    }
+    /// Check the HTTP and HTTPS requests of this client like browsers do for a page of the
+    /// given origin, instead of bypassing the same-origin policy as tunneled requests do.
+    ///
+    /// Requests to other origins carry an `Origin` header, and their responses are rejected
+    /// unless their `Access-Control-Allow-*` headers allow the origin and the credentials,
+    /// the callback of the request being called with the error instead. Allowed responses
+    /// only keep the headers browsers expose, besides `Location` and `Retry-After`, and their
+    /// timing only keeps its start and duration unless `Timing-Allow-Origin` allows the
+    /// origin.
+    ///
+    /// Preflights are not supported, so cross-origin requests that would need one, e.g. `PUT`
+    /// requests or requests with a `Content-Type` of `application/json`, fail to be sent.
+    /// Requests without a correlation ID are assigned one, to match them with their response.
+    /// Middlewares added later see requests once the `Origin` header is set, and responses
+    /// before they are checked, so this is best enabled last. Only affects connection APIs
+    /// created after this call.
+    /// # Arguments
+    /// * `origin` - Origin of the page or service worker, e.g. `https://app.example.com`
+    /// * `credentials` - Whether cross-origin requests include their `Cookie` header, which
+    ///   is removed otherwise, and require `Access-Control-Allow-Credentials`
+    #[wasm_bindgen]
+    pub fn enable_cors(
+        &mut self,
+        origin: String,
+        credentials: Option<bool>,
+    ) -> Result<(), ConnectionError> {
+        let policy = CorsPolicy::new(&origin, credentials.unwrap_or(false))?;
+        self.use_middleware(policy);
+        Ok(())
+    }
+    /// Start recording the HTTP and HTTPS traffic of this client.
+    ///
+    /// Only affects connection APIs created after this call.
+    /// # Returns
+    /// The recorder, which exports the traffic as an HTTP Archive (HAR).
+    #[wasm_bindgen]
+    pub fn record_har(&mut self) -> HarRecorder {
+        let recorder = HarRecorder::new();
+        self.use_middleware(recorder.clone());
+        recorder
+    }
+    /// Capture the raw bytes sent and received by new connections of this client.
+    ///
+    /// Each connection keeps its own bounded capture, retrieved with `get_capture`.
+    /// # Arguments
+    /// * `capacity` - Maximum payload bytes kept per connection, 1 MiB if not given
+    #[wasm_bindgen]
+    pub fn enable_capture(&mut self, capacity: Option<usize>) {
+        self.capture_capacity = Some(capacity.unwrap_or(DEFAULT_CAPTURE_CAPACITY));
+    }
+    /// Stop capturing the raw bytes of new connections of this client.
+    ///
+    /// Connections already capturing keep doing so.
+    #[wasm_bindgen]
+    pub fn disable_capture(&mut self) {
+        self.capture_capacity = None;
+    }
+    /// Get the raw traffic capture of the connection with the given ID.
+    /// # Returns
+    /// The capture, or `None` if the connection does not exist or was not captured.
+    #[wasm_bindgen]
+    pub fn get_capture(&self, id: u64) -> Option<Capture> {
+        self.connections
+            .borrow()
+            .iter()
+            .find(|c| Into::<u64>::into(c.get_id()) == id)
+            .and_then(|c| c.capture.clone())
+    }
+    /// Limit the rate of the bytes sent and received by new connections of this client.
+    /// # Arguments
+    /// * `upload` - Bytes sent per second, or `None` for no limit
+    /// * `download` - Bytes received per second, or `None` for no limit
+    #[wasm_bindgen]
+    pub fn set_default_throttle(&mut self, upload: Option<f64>, download: Option<f64>) {
+        self.throttle_limits = (upload, download);
+    }
+    /// Limit the rate of the bytes sent and received by the connection with the given ID.
+    ///
+    /// Bytes over the limit are delayed, not dropped.
+    /// # Arguments
+    /// * `id` - ID of the connection
+    /// * `upload` - Bytes sent per second, or `None` for no limit
+    /// * `download` - Bytes received per second, or `None` for no limit
+    /// # Returns
+    /// Whether the connection exists.
+    #[wasm_bindgen]
+    pub fn set_throttle(&self, id: u64, upload: Option<f64>, download: Option<f64>) -> bool {
+        self.connections
+            .borrow()
+            .iter()
+            .find(|c| Into::<u64>::into(c.get_id()) == id)
+            .map(|c| c.throttle.set_limits(upload, download))
+            .is_some()
+    }
+    /// Simulate bad network conditions on the messages received by new connections.
+    ///
+    /// Meant for development only.
+    /// # Arguments
+    /// * `conditions` - Latency, jitter and drop rate to simulate
+    #[wasm_bindgen]
+    pub fn simulate_network(&mut self, conditions: &NetworkConditions) {
+        self.network_conditions = Some(*conditions);
+    }
+    /// Stop simulating network conditions on new connections.
+    #[wasm_bindgen]
+    pub fn stop_network_simulation(&mut self) {
+        self.network_conditions = None;
+    }
+    /// Get totals across all connections of this client: connections opened and closed by
+    /// protocol, bytes sent and received, requests, errors, average response time, and
+    /// percentiles of the connect time and time to first byte.
+    #[wasm_bindgen]
+    pub fn get_stats(&self) -> ClientStats {
+        self.stats.snapshot(self.name.clone())
+    }
+    /// Get the memory currently held by the buffers of this client: responses being
+    /// assembled, TLS sessions in progress and traffic captures, along with the number of idle
+    /// and preconnected connections, to detect leaks or decide when to trim connections.
+    #[wasm_bindgen]
+    pub fn get_buffer_usage(&self) -> BufferUsage {
+        let mut usage = BufferUsage::default();
+        for connection in self.connections.borrow().iter() {
+            connection.pending.measure(&mut usage);
+            let idle = connection.socket.ready_state() == 1 && connection.pending.len() == 0;
+            let capture_bytes = connection.capture.as_ref().map_or(0, Capture::size);
+            usage.add_connection(idle, capture_bytes);
+        }
+        let preconnected = self
+            .preconnected
+            .iter()
+            .filter(|c| c.socket.ready_state() <= 1)
+            .count();
+        usage.set_preconnected(preconnected);
+        usage
+    }
+    /// Set how bytes are carried in the frames exchanged with the proxy by new connections.
+    ///
+    /// Proxies that only relay text frames need `Framing.Base64`. With `Framing.Negotiate`,
+    /// binary frames are used unless the proxy selects the `base64` subprotocol.
+    /// # Arguments
+    /// * `framing` - Framing of new connections
+    #[wasm_bindgen]
+    pub fn set_framing(&mut self, framing: Framing) {
+        self.framing = framing;
+    }
+    /// Get how bytes are carried in the frames exchanged with the proxy by new connections.
+    #[wasm_bindgen]
+    pub fn get_framing(&self) -> Framing {
+        self.framing
+    }
+    /// Set how the IDs of new connections are made.
+    ///
+    /// With `ConnIdScheme.UuidV7`, connections also get a time-ordered UUID, returned as a
+    /// string by `get_uuid`, for external systems expecting UUIDs. Connections are still
+    /// looked up by their numeric ID.
+    /// # Arguments
+    /// * `scheme` - Scheme of the IDs of new connections
+    #[wasm_bindgen]
+    pub fn set_id_scheme(&mut self, scheme: ConnIdScheme) {
+        self.factory.borrow_mut().set_scheme(scheme);
+    }
+    /// Get how the IDs of new connections are made.
+    #[wasm_bindgen]
+    pub fn get_id_scheme(&self) -> ConnIdScheme {
+        self.factory.borrow().scheme()
+    }
+    /// Apply a preset handling of a proxy implementation to new connections: the url
+    /// template, framing, subprotocols and protocols it relays.
+    ///
+    /// `Wsproxy` is the default. `Websockify` passes the target as the `token` query
+    /// parameter and negotiates the framing. `Epoxy` offers no subprotocol, for the wsproxy
+    /// endpoints of Wisp servers. UDP connections fail with the latter two, which only relay
+    /// TCP. The framing and url template can be changed afterwards.
+    /// # Arguments
+    /// * `profile` - Preset to apply, or `None` to restore the defaults
+    #[wasm_bindgen]
+    pub fn set_proxy_profile(&mut self, profile: Option<ProxyProfile>) {
+        self.proxy_profile = profile;
+        self.proxy = profile.map(ProxyProfile::compat).unwrap_or_default();
+        self.framing = profile.map(ProxyProfile::framing).unwrap_or_default();
+    }
+    /// Get the preset handling of the proxy applied by `set_proxy_profile`, if any.
+    #[wasm_bindgen]
+    pub fn get_proxy_profile(&self) -> Option<ProxyProfile> {
+        self.proxy_profile
+    }
+    /// Set the template of the urls new connections open to the proxy, for custom relays.
+    ///
+    /// `{base}` is replaced by the base url of the client without its query, `{addr}` by the
+    /// target as `host:port`, `{host}` and `{port}` by its parts, and `{protocol}` by `tcp`
+    /// or `udp`, e.g. `{base}/connect/{host}/{port}`. Query parameters of the base url and of
+    /// connections are appended.
+    /// # Arguments
+    /// * `template` - Template naming `{addr}` or `{host}`, or `None` to restore the one of
+    ///   the profile
+    #[wasm_bindgen]
+    pub fn set_proxy_url_template(
+        &mut self,
+        template: Option<String>,
+    ) -> Result<(), ConnectionError> {
+        let template = match template {
+            Some(template) => {
+                ProxyCompat::check_template(&template)?;
+                template
+            }
+            None => {
+                self.proxy_profile
+                    .map(ProxyProfile::compat)
+                    .unwrap_or_default()
+                    .url_template
+            }
+        };
+        self.proxy.url_template = template;
+        Ok(())
+    }
+    /// Get the template of the urls new connections open to the proxy.
+    #[wasm_bindgen]
+    pub fn get_proxy_url_template(&self) -> String {
+        self.proxy.url_template.clone()
+    }
+    /// Limit the number of concurrent connections of this client.
+    ///
+    /// A connection counts until its socket is closed. Once the limit is reached, the
+    /// `create_*_connection` functions return nothing, and `create_connection_queued` waits
+    /// for a free slot.
+    /// # Arguments
+    /// * `max` - Maximum number of concurrent connections, or `None` for no limit
+    #[wasm_bindgen]
+    pub fn set_max_connections(&mut self, max: Option<usize>) {
+        self.limiter.set_max(max);
+    }
+    /// Retry requests sent by `get`, `post`, `put`, `delete` and `fetch_all` answered with a
+    /// rate limit or server error, honoring `Retry-After`.
+    /// # Arguments
+    /// * `policy` - When and how long to wait before retrying, or `None` to never retry
+    #[wasm_bindgen]
+    pub fn set_retry_policy(&mut self, policy: Option<RetryPolicy>) {
+        self.retry = policy;
+    }
+    /// Follow the redirects answered to requests sent by `get`, `post`, `put`, `delete` and
+    /// `fetch_all`, each over a new connection held to the host policy.
+    ///
+    /// Retries apply to every request of the redirect chain. Once the policy allows no more
+    /// redirects, the request fails with a `ConnectionError`.
+    /// # Arguments
+    /// * `policy` - How redirects are followed, or `None` to deliver redirect responses as is
+    #[wasm_bindgen]
+    pub fn set_redirect_policy(&mut self, policy: Option<RedirectPolicy>) {
+        self.redirect = policy;
+    }
+    /// Slow down the requests sent by `get`, `post`, `put`, `delete` and `fetch_all` to hosts
+    /// answering with `429` or `503`, and speed them back up as they recover.
+    ///
+    /// Requests to such a host are spaced out, doubling the spacing with every rate limit
+    /// response and halving it with every other response, and held back until the time
+    /// asked by `Retry-After`.
+    /// # Arguments
+    /// * `enabled` - Whether to adapt the pace of requests, forgetting that of every host if not
+    #[wasm_bindgen]
+    pub fn set_adaptive_throttling(&mut self, enabled: bool) {
+        self.backoff = enabled.then(HostBackoff::default);
+    }
+    /// Get the time between the starts of requests to a host in ms, 0 if not slowed down.
+    /// # Arguments
+    /// * `addr` - Address of the host as in the url of the requests, e.g. `example.com`
+    #[wasm_bindgen]
+    pub fn get_host_spacing(&self, addr: &str) -> f64 {
+        self.backoff
+            .as_ref()
+            .map_or(0.0, |backoff| backoff.spacing(addr))
+    }
+    /// Close the connections of this client left idle for too long.
+    ///
+    /// A connection is idle while no byte is sent or received on it. Closed connections are
+    /// removed from this client, freeing their slot.
+    /// # Arguments
+    /// * `timeout` - Idle time after which a connection is closed in ms, or `None` to never close them
+    /// * `onreap` - Callback called with the ID of every connection closed
+    #[wasm_bindgen]
+    pub fn set_idle_timeout(&mut self, timeout: Option<f64>, onreap: Option<ReapCallback>) {
+        self.reaper = timeout.map(|timeout| {
+            IdleReaper::start(self.connections.clone(), timeout, onreap.map(Into::into))
+        });
+    }
+    /// Shut this client down, letting the requests in flight complete first, e.g. so uploads
+    /// are not truncated when navigating away.
+    ///
+    /// New connections and requests are refused from now on, and queued connection creations
+    /// fail. Connections opened by `preconnect` and not used yet are closed right away, the
+    /// others once the HTTP, HTTPS and TCP requests in flight on every connection completed,
+    /// or the timeout passed.
+    /// # Arguments
+    /// * `timeout` - Time to wait for the requests in flight in ms, or `None` to wait forever
+    /// # Returns
+    /// A promise resolving once the connections are closed, to `true` if every request in
+    /// flight completed, or `false` if the timeout passed first.
+    #[wasm_bindgen]
+    pub fn shutdown(&mut self, timeout: Option<f64>) -> js_sys::Promise {
+        self.limiter.shut_down();
+        self.preconnected.clear();
+        let connections = self.connections.clone();
+        let idle: js_sys::Array = connections
+            .borrow()
+            .iter()
+            .map(|c| {
+                c.pending.refuse_new();
+                JsValue::from(c.pending.idle())
+            })
+            .collect();
+
+        future_to_promise(async move {
+            let idle = js_sys::Promise::all(&idle);
+            let waited = match timeout {
+                Some(timeout) => {
+                    let expired = js_sys::Promise::new(&mut |resolve, _| {
+                        set_timeout(&resolve, timeout);
+                    });
+                    js_sys::Promise::race(&js_sys::Array::of2(&idle, &expired))
+                }
+                None => idle,
+            };
+            JsFuture::from(waited).await?;
+
+            let drained = connections.borrow().iter().all(|c| c.pending.len() == 0);
+            // Taken first, as closing a connection may call back into the client
+            let remaining: Vec<Connection> = connections.borrow_mut().drain(..).collect();
+            for connection in remaining {
+                connection.close();
+            }
+            Ok(drained.into())
+        })
+    }
+    /// Get the number of connection creations waiting for a free slot.
+    #[wasm_bindgen]
+    pub fn get_queued_connections(&self) -> usize {
+        self.limiter.queued()
+    }
+    /// Create a new connection once the number of concurrent connections allows it.
+    ///
+    /// The connection is created with the settings of this client at the time of this call.
+    /// Its API is then retrieved with the `get_*_connection_api` function of its protocol.
+    /// Queued creations of higher priority are served more often, but lower priorities are
+    /// never starved.
+    /// # Arguments
+    /// * `protocol` - Protocol of the connection (`tcp`, `udp`, `http`, `https` or `https_tls1_x`)
+    /// * `addr` - Address to connect to
+    /// * `priority` - Priority of the creation while queued, `Normal` by default
+    /// # Returns
+    /// A promise resolving to the ID of the connection once created, or rejecting with a
+    /// `ConnectionError` if the client shuts down first.
+    #[wasm_bindgen]
+    pub fn create_connection_queued(
+        &mut self,
+        protocol: String,
+        addr: String,
+        priority: Option<Priority>,
+    ) -> Result<js_sys::Promise, ConnectionError> {
+        let protocol = self.parse_protocol(&protocol)?;
+        let id = self.factory.borrow_mut().generate(protocol);
+        let addr = SocketAddr::split_addr(protocol, addr).ok_or_else(|| ConnectionError {
+            message: "Invalid address".to_string(),
+        })?;
+        self.host_policy.check(protocol, &addr)?;
+        let query = Self::protocol_query(protocol);
+        let settings = self.settings();
+        let open =
+            Box::new(move || Connection::new_with_settings(&settings, protocol, addr, id, &query));
+
+        let (mut resolve, mut reject) = (None, None);
+        let promise = js_sys::Promise::new(&mut |res, rej| {
+            resolve = Some(res);
+            reject = Some(rej);
+        });
+        self.limiter.enqueue(
+            open,
+            resolve.unwrap_throw(),
+            reject.unwrap_throw(),
+            priority.unwrap_or_default(),
+        );
+        Ok(promise)
+    }
+    /// Create a new connection, attaching extra query parameters to its proxy url.
+    ///
+    /// Meant for proxies keying behavior off query strings, e.g. auth tokens or target
+    /// metadata. The API of the connection is then obtained from its ID with the
+    /// `get_*_connection_api` function of its protocol.
+    /// # Arguments
+    /// * `protocol` - Protocol of the connection, e.g. `tcp` or `https`
+    /// * `addr` - Address to connect to
+    /// * `query` - Object mapping query parameter names to values
+    /// # Returns
+    /// The ID of the connection, or nothing if the connection limit is reached.
+    #[wasm_bindgen]
+    pub fn create_connection_with_query(
+        &mut self,
+        protocol: String,
+        addr: String,
+        query: &js_sys::Object,
+    ) -> Result<Option<u64>, ConnectionError> {
+        let protocol = self.parse_protocol(&protocol)?;
+        let addr = SocketAddr::split_addr(protocol, addr).ok_or_else(|| ConnectionError {
+            message: "Invalid address".to_string(),
+        })?;
+        let mut params = Self::protocol_query(protocol);
+        for entry in js_sys::Object::entries(query).iter() {
+            let entry = js_sys::Array::from(&entry);
+            let name = entry.get(0).as_string().unwrap_or_default();
+            let value = entry.get(1);
+            let value = value.as_string().ok_or_else(|| ConnectionError {
+                message: format!("Query parameter {} is not a string", name),
+            })?;
+            params.push((name, value));
+        }
+        let settings = self.settings();
+        Ok(self
+            .open_connection(&settings, protocol, addr, &params)?
+            .map(|c| c.get_id().into()))
+    }
+    /// Create a new connection with labels attached to it.
+    ///
+    /// The tags appear in logs, stats, HAR exports and `get_connections`. The API of the
+    /// connection is then obtained from its ID with the `get_*_connection_api` function of
+    /// its protocol.
+    /// # Arguments
+    /// * `protocol` - Protocol of the connection, e.g. `tcp` or `https`
+    /// * `addr` - Address to connect to
+    /// * `tags` - Labels to attach to the connection
+    /// # Returns
+    /// The ID of the connection, or nothing if the connection limit is reached.
+    #[wasm_bindgen]
+    pub fn create_tagged_connection(
+        &mut self,
+        protocol: String,
+        addr: String,
+        tags: Vec<String>,
+    ) -> Result<Option<u64>, ConnectionError> {
+        let protocol = self.parse_protocol(&protocol)?;
+        let addr = SocketAddr::split_addr(protocol, addr).ok_or_else(|| ConnectionError {
+            message: "Invalid address".to_string(),
+        })?;
+        let params = Self::protocol_query(protocol);
+        let settings = ConnectionSettings {
+            tags,
+            ..self.settings()
+        };
+        Ok(self
+            .open_connection(&settings, protocol, addr, &params)?
+            .map(|c| c.get_id().into()))
+    }
+    /// Create a new connection of a protocol picked at runtime.
+    /// # Arguments
+    /// * `protocol` - Protocol of the connection (`tcp`, `udp`, `http`, `https` or `https_tls1_x`)
+    /// * `addr` - Address to connect to
+    /// # Returns
+    /// A handle from which the API of the protocol is requested, or nothing if the
+    /// connection limit is reached.
+    #[wasm_bindgen]
+    pub fn create_connection(
+        &mut self,
+        protocol: String,
+        addr: String,
+    ) -> Result<Option<ConnectionHandle>, ConnectionError> {
+        let protocol = self.parse_protocol(&protocol)?;
+        let addr = SocketAddr::split_addr(protocol, addr).ok_or_else(|| ConnectionError {
+            message: "Invalid address".to_string(),
+        })?;
+        let query = Self::protocol_query(protocol);
+        let settings = self.settings();
+        Ok(self
+            .open_connection(&settings, protocol, addr, &query)?
+            .map(|connection| {
+                ConnectionHandle::new(
+                    &connection,
+                    &self.connections,
+                    self.pipeline.clone(),
+                    self.tls_worker.clone(),
+                )
+            }))
+    }
+    /// Create a new connection from a url, its scheme selecting the protocol and default port.
+    ///
+    /// Supported schemes are `tcp://`, `udp://`, `http://` and `https://`, with `ws://` and
+    /// `wss://` opening HTTP and HTTPS connections respectively. Any path is ignored.
+    /// # Arguments
+    /// * `url` - Url to connect to, e.g. `https://example.com` or `tcp://example.com:6379`
+    /// # Returns
+    /// The API of the protocol, or nothing if the connection limit is reached. Fails if the
+    /// scheme is unknown or not a capability of this client, or the host policy refuses the
+    /// address.
+    #[wasm_bindgen]
+    pub fn connect(&mut self, url: String) -> Result<Option<ConnectionApi>, ConnectionError> {
+        let (protocol, addr) = self.parse_url(&url)?;
+        // Preconnected connections may predate the policy, e.g. `set_block_bad_ports`
+        self.host_policy.check(protocol, &addr)?;
+        if let Some(connection) = self.take_preconnected(protocol, &addr) {
+            return Ok(Some(self.connection_api(connection).unchecked_into()));
+        }
+        let query = Self::protocol_query(protocol);
+        let settings = self.settings();
+        Ok(self
+            .open_connection(&settings, protocol, addr, &query)?
+            .map(|connection| self.connection_api(connection).unchecked_into()))
+    }
+    /// Open a connection to a url ahead of time, like `<link rel=preconnect>`, so the first
+    /// request to it does not wait for the proxy connection to open.
+    ///
+    /// The connection is handed out by the next `connect`, `create_http_connection` or
+    /// `create_https_connection` call for the same protocol and address. TLS sessions are
+    /// started with each request, so for `https://` only the proxy connection is opened early.
+    /// # Arguments
+    /// * `url` - Url to connect to, e.g. `https://example.com`, as accepted by `connect`
+    /// * `timeout` - Time to wait for the connection to open in ms, or `None` to wait forever
+    /// # Returns
+    /// A promise resolving once the connection is open, or rejecting with a `ConnectionError`
+    /// if it fails to open in time or the connection limit is reached.
+    #[wasm_bindgen]
+    pub fn preconnect(
+        &mut self,
+        url: String,
+        timeout: Option<f64>,
+    ) -> Result<js_sys::Promise, ConnectionError> {
+        let (protocol, addr) = self.parse_url(&url)?;
+        let query = Self::protocol_query(protocol);
+        let settings = self.settings();
+        let opened = match self.open_connection(&settings, protocol, addr, &query)? {
+            Some(connection) => {
+                // Pooled until handed out, as dropping its last clone closes a connection
+                self.preconnected.push(connection.clone());
+                Some((connection, JsValue::UNDEFINED))
+            }
+            None => None,
+        };
+        Ok(self.resolve_on_open(opened, timeout))
+    }
+    /// Send many HTTP or HTTPS requests, each over a new connection, with at most
+    /// `concurrency` of them in flight at once. Connections are also held to the connection
+    /// limit of the client, and closed once their response is received.
+    /// # Arguments
+    /// * `requests` - Requests to send, each with the `http://` or `https://` url to send it to
+    /// * `concurrency` - Number of requests in flight at once, defaults to 6
+    /// * `priority` - Priority of the connections while waiting for a free slot, `Normal` by
+    ///   default, e.g. `Low` for bulk downloads not to delay interactive requests
+    /// # Returns
+    /// A promise resolving, once every request has settled, to an array of the outcomes in
+    /// the order of `requests`, shaped like the results of `Promise.allSettled`.
+    #[wasm_bindgen]
+    pub fn fetch_all(
+        &mut self,
+        requests: BatchRequests,
+        concurrency: Option<usize>,
+        priority: Option<Priority>,
+    ) -> Result<js_sys::Promise, ConnectionError> {
+        let dialer = self.dialer();
+        let mut items = vec![];
+        for (index, entry) in requests.iter().enumerate() {
+            let url = js_sys::Reflect::get(&entry, &"url".into())
+                .ok()
+                .and_then(|url| url.as_string())
+                .ok_or_else(|| ConnectionError {
+                    message: format!("Missing url in batch request {}", index),
+                })?;
+            let (protocol, addr) = self.parse_url(&url)?;
+            if !matches!(
+                protocol,
+                SocketCapability::HTTP | SocketCapability::HTTPS(_)
+            ) {
+                return Err(ConnectionError {
+                    message: format!("Batch requests must be HTTP or HTTPS: {}", url),
+                });
+            }
+            self.host_policy.check(protocol, &addr)?;
+            let request = js_sys::Reflect::get(&entry, &"request".into())
+                .ok()
+                .and_then(|request| HttpConnectionRequest::try_from_js_value(request).ok())
+                .ok_or_else(|| ConnectionError {
+                    message: format!("Missing request in batch request {}", index),
+                })?;
+            validate_method(&request.method)?;
+
+            items.push(dialer.item(index, protocol, addr, request));
+        }
+
+        let batch = self.batch(priority.unwrap_or_default());
+        Ok(batch.run(items, concurrency.unwrap_or(DEFAULT_BATCH_CONCURRENCY)))
+    }
+    /// Send a `GET` request to a url, over a connection opened ahead of time by `preconnect`
+    /// if there is one, or a new connection once the connection limit allows.
+    /// # Arguments
+    /// * `url` - `http://` or `https://` url to send the request to
+    /// * `headers` - Object mapping header names to values, `Host` being set from the url
+    /// # Returns
+    /// A promise resolving to the response, or rejecting with a `ConnectionError`.
+    #[wasm_bindgen]
+    pub fn get(
+        &mut self,
+        url: String,
+        headers: Option<js_sys::Object>,
+    ) -> Result<js_sys::Promise, ConnectionError> {
+        self.fetch(METHOD_GET, url, headers, None)
+    }
+    /// Send a `POST` request to a url, like `get`.
+    /// # Arguments
+    /// * `url` - `http://` or `https://` url to send the request to
+    /// * `body` - Request body
+    /// * `headers` - Object mapping header names to values, `Host` being set from the url
+    /// # Returns
+    /// A promise resolving to the response, or rejecting with a `ConnectionError`.
+    #[wasm_bindgen]
+    pub fn post(
+        &mut self,
+        url: String,
+        body: Option<Vec<u8>>,
+        headers: Option<js_sys::Object>,
+    ) -> Result<js_sys::Promise, ConnectionError> {
+        self.fetch(METHOD_POST, url, headers, body)
+    }
+    /// Send a `PUT` request to a url, like `get`.
+    /// # Arguments
+    /// * `url` - `http://` or `https://` url to send the request to
+    /// * `body` - Request body
+    /// * `headers` - Object mapping header names to values, `Host` being set from the url
+    /// # Returns
+    /// A promise resolving to the response, or rejecting with a `ConnectionError`.
+    #[wasm_bindgen]
+    pub fn put(
+        &mut self,
+        url: String,
+        body: Option<Vec<u8>>,
+        headers: Option<js_sys::Object>,
+    ) -> Result<js_sys::Promise, ConnectionError> {
+        self.fetch(METHOD_PUT, url, headers, body)
+    }
+    /// Send a `DELETE` request to a url, like `get`.
+    /// # Arguments
+    /// * `url` - `http://` or `https://` url to send the request to
+    /// * `headers` - Object mapping header names to values, `Host` being set from the url
+    /// # Returns
+    /// A promise resolving to the response, or rejecting with a `ConnectionError`.
+    #[wasm_bindgen]
+    pub fn delete(
+        &mut self,
+        url: String,
+        headers: Option<js_sys::Object>,
+    ) -> Result<js_sys::Promise, ConnectionError> {
+        self.fetch(METHOD_DELETE, url, headers, None)
+    }
+    /// Create a new connection of any protocol, with options left unset keeping the
+    /// behavior of the `create_*_connection` function of that protocol.
+    /// # Arguments
+    /// * `protocol` - Protocol of the connection, e.g. `tcp` or `https`
+    /// * `addr` - Address to connect to
+    /// * `options` - Options of the connection, or `None` for the defaults
+    /// # Returns
+    /// A promise resolving to the API of the protocol once the connection is open, or
+    /// rejecting with a `ConnectionError` if it fails to open in time or the connection
+    /// limit is reached.
+    #[wasm_bindgen]
+    pub fn create_connection_with_options(
+        &mut self,
+        protocol: String,
+        addr: String,
+        options: Option<ConnectionOptions>,
+    ) -> Result<js_sys::Promise, ConnectionError> {
+        let options = options.unwrap_or_default();
+        let mut min_tls_version = None;
+        let protocol = match (self.parse_protocol(&protocol)?, options.tls_versions()) {
+            (SocketCapability::HTTPS(_), Some((min, max))) => {
+                self.check_tls_versions(min, max)?;
+                min_tls_version = Some(min);
+                SocketCapability::HTTPS(max)
+            }
+            (_, Some(_)) => {
+                return Err(ConnectionError {
+                    message: "TLS version set for a protocol without TLS".to_string(),
+                })
+            }
+            (protocol, None) => protocol,
+        };
+        let addr = SocketAddr::split_addr(protocol, addr).ok_or_else(|| ConnectionError {
+            message: "Invalid address".to_string(),
+        })?;
+        let mut query = Self::protocol_query(protocol);
+        query.extend(options.query());
+        let mut settings = ConnectionSettings {
+            tags: options.tags().to_vec(),
+            subprotocols: options.subprotocols().to_vec(),
+            min_tls_version,
+            ..self.settings()
+        };
+        if let Some(framing) = options.framing() {
+            settings.framing = framing;
+        }
+        if options.allow_bad_port() {
+            settings.host_policy.set_block_bad_ports(false);
+        }
+
+        let connection = self.open_connection(&settings, protocol, addr, &query)?;
+        if let (Some(connection), Some(header)) = (
+            &connection,
+            options.tcp().and_then(TcpOptions::proxy_header),
+        ) {
+            let sender = connection.sender();
+            let header = header.as_bytes().to_vec();
+            let onready = Closure::once_into_js(move || {
+                sender.send(&header).unwrap_throw();
+            });
+            connection.set_onready(onready.unchecked_into(), Some(true));
+        }
+        let opened = connection.map(|c| (c.clone(), self.connection_api(c)));
+        Ok(self.resolve_on_open(opened, options.timeout()))
+    }
+    /// Create a new http connection to the given address, once open.
+    /// # Arguments
+    /// * `addr` - Address to connect to
+    /// * `timeout` - Time to wait for the connection to open in ms, or `None` to wait forever
+    /// # Returns
+    /// A promise resolving to a `HttpConnectionApi` once the connection is open, or rejecting
+    /// with a `ConnectionError` if the host policy refuses the address, it fails to open in
+    /// time or the connection limit is reached.
+    #[wasm_bindgen]
+    pub fn create_http_connection_async(
+        &mut self,
+        addr: String,
+        timeout: Option<f64>,
+    ) -> js_sys::Promise {
+        match self.create_http_connection(addr) {
+            Ok(api) => {
+                let opened = api.map(|api| (api.connection().clone(), JsValue::from(api)));
+                self.resolve_on_open(opened, timeout)
+            }
+            Err(e) => js_sys::Promise::reject(&e.into()),
+        }
+    }
+    /// Create a new https connection to the given address, once open.
+    /// # Arguments
+    /// * `addr` - Address to connect to
+    /// * `timeout` - Time to wait for the connection to open in ms, or `None` to wait forever
+    /// # Returns
+    /// A promise resolving to a `HttpsConnectionApi` once the connection is open, or rejecting
+    /// with a `ConnectionError` if the host policy refuses the address, it fails to open in
+    /// time or the connection limit is reached.
+    #[wasm_bindgen]
+    pub fn create_https_connection_async(
+        &mut self,
+        addr: String,
+        timeout: Option<f64>,
+    ) -> js_sys::Promise {
+        match self.create_https_connection(addr) {
+            Ok(api) => {
+                let opened = api.map(|api| (api.connection().clone(), JsValue::from(api)));
+                self.resolve_on_open(opened, timeout)
+            }
+            Err(e) => js_sys::Promise::reject(&e.into()),
+        }
+    }
+    /// Create a new tcp connection to the given address, once open.
+    /// # Arguments
+    /// * `addr` - Address to connect to
+    /// * `timeout` - Time to wait for the connection to open in ms, or `None` to wait forever
+    /// # Returns
+    /// A promise resolving to a `TcpConnectionApi` once the connection is open, or rejecting
+    /// with a `ConnectionError` if the host policy refuses the address, it fails to open in
+    /// time or the connection limit is reached.
+    #[wasm_bindgen]
+    pub fn create_tcp_connection_async(
+        &mut self,
+        addr: String,
+        timeout: Option<f64>,
+    ) -> js_sys::Promise {
+        match self.create_tcp_connection(addr) {
+            Ok(api) => {
+                let opened = api.map(|api| (api.connection().clone(), JsValue::from(api)));
+                self.resolve_on_open(opened, timeout)
+            }
+            Err(e) => js_sys::Promise::reject(&e.into()),
+        }
+    }
+    /// Create a new udp connection to the given address, once open.
+    /// # Arguments
+    /// * `addr` - Address to connect to
+    /// * `timeout` - Time to wait for the connection to open in ms, or `None` to wait forever
+    /// # Returns
+    /// A promise resolving to a `UdpConnectionApi` once the connection is open, or rejecting
+    /// with a `ConnectionError` if the host policy refuses the address, it fails to open in
+    /// time or the connection limit is reached.
+    #[wasm_bindgen]
+    pub fn create_udp_connection_async(
+        &mut self,
+        addr: String,
+        timeout: Option<f64>,
+    ) -> js_sys::Promise {
+        match self.create_udp_connection(addr) {
+            Ok(api) => {
+                let opened = api.map(|api| (api.connection().clone(), JsValue::from(api)));
+                self.resolve_on_open(opened, timeout)
+            }
+            Err(e) => js_sys::Promise::reject(&e.into()),
+        }
+    }
     /// Create a new http connection to the given address.
     /// # Arguments
     /// * `addr` - Address to connect to
+    /// # Returns
+    /// The connection API, or nothing if the connection limit is reached. Fails if the address
+    /// is invalid or refused by the host policy.
     #[wasm_bindgen]
-    pub fn create_http_connection(&mut self, addr: String) -> Option<HttpConnectionApi> {
+    pub fn create_http_connection(
+        &mut self,
+        addr: String,
+    ) -> Result<Option<HttpConnectionApi>, ConnectionError> {
         let protocol = SocketCapability::HTTP;
-        let id = self.factory.generate(protocol);
-        let addr = SocketAddr::split_addr(protocol, addr).unwrap();
-        let connection = Connection::new(self, protocol, addr, id).unwrap();
-        self.connections.push(connection.clone());
-        Some(HttpConnectionApi::new(connection))
+        let addr = SocketAddr::split_addr(protocol, addr).ok_or_else(|| ConnectionError {
+            message: "Invalid address".to_string(),
+        })?;
+        // Preconnected connections may predate the policy, e.g. `set_block_bad_ports`
+        self.host_policy.check(protocol, &addr)?;
+        if let Some(connection) = self.take_preconnected(protocol, &addr) {
+            return Ok(Some(HttpConnectionApi::new(
+                connection,
+                self.pipeline.clone(),
+            )));
+        }
+        if !self.limiter.try_acquire() {
+            return Ok(None);
+        }
+        let id = self.factory.borrow_mut().generate(protocol);
+        let connection =
+            Self::new_http_connection(&self.settings(), self.http_proxy.as_ref(), addr, id, &[])
+                .inspect_err(|_| self.limiter.release())?;
+        self.limiter.track(&connection);
+        self.connections.borrow_mut().push(connection.clone());
+        Ok(Some(HttpConnectionApi::new(
+            connection,
+            self.pipeline.clone(),
+        )))
     }
 
     /// Create a new http connection to the given address with an onready callback.
     /// # Arguments
     /// * `addr` - Address to connect to
     /// * `callback` - Callback to call when the connection is ready
+    /// # Returns
+    /// The connection API, or nothing if the connection limit is reached. Fails if the address
+    /// is invalid or refused by the host policy.
     #[wasm_bindgen]
     pub fn create_http_connection_with_onready(
         &mut self,
         addr: String,
-        callback: js_sys::Function,
-    ) -> Option<HttpConnectionApi> {
+        callback: ReadyCallback,
+    ) -> Result<Option<HttpConnectionApi>, ConnectionError> {
         let protocol = SocketCapability::HTTP;
-        let id = self.factory.generate(protocol);
-        let addr = SocketAddr::split_addr(protocol, addr).unwrap();
-        let connection = Connection::new(self, protocol, addr, id).unwrap();
-        connection.set_onready(callback, None);
-        self.connections.push(connection.clone());
-        Some(HttpConnectionApi::new(connection))
+        let addr = SocketAddr::split_addr(protocol, addr).ok_or_else(|| ConnectionError {
+            message: "Invalid address".to_string(),
+        })?;
+        if !self.limiter.try_acquire() {
+            return Ok(None);
+        }
+        let id = self.factory.borrow_mut().generate(protocol);
+        let connection =
+            Self::new_http_connection(&self.settings(), self.http_proxy.as_ref(), addr, id, &[])
+                .inspect_err(|_| self.limiter.release())?;
+        connection.set_onready(callback.into(), None);
+        self.limiter.track(&connection);
+        self.connections.borrow_mut().push(connection.clone());
+        Ok(Some(HttpConnectionApi::new(
+            connection,
+            self.pipeline.clone(),
+        )))
     }
 
     /// Get a http connection API for the given connection.
     #[wasm_bindgen]
     pub fn get_http_connection_api(&self, id: u64) -> HttpConnectionApi {
         self.connections
+            .borrow()
             .iter()
             .find(|c| Into::<u64>::into(c.get_id()) == id)
-            .map(|c| HttpConnectionApi::new(c.clone()))
+            .map(|c| HttpConnectionApi::new(c.clone(), self.pipeline.clone()))
             .unwrap()
     }
 
     /// Create a new http connection to the given address.
     /// # Arguments
     /// * `addr` - Address to connect to
+    /// # Returns
+    /// The connection API, or nothing if the connection limit is reached. Fails if the address
+    /// is invalid or refused by the host policy, or if this client has no HTTPS capability.
     #[wasm_bindgen]
-    pub fn create_https_connection(&mut self, addr: String) -> Option<HttpsConnectionApi> {
-        let protocol = SocketCapability::HTTPS(self.get_highest_tls_version());
-        let id = self.factory.generate(protocol);
-        let addr = SocketAddr::split_addr(protocol, addr).unwrap();
-        let connection = Connection::new(self, protocol, addr, id).unwrap();
-        self.connections.push(connection.clone());
-        Some(HttpsConnectionApi::new(connection))
+    pub fn create_https_connection(
+        &mut self,
+        addr: String,
+    ) -> Result<Option<HttpsConnectionApi>, ConnectionError> {
+        let protocol = SocketCapability::HTTPS(self.require_tls_version()?);
+        let addr = SocketAddr::split_addr(protocol, addr).ok_or_else(|| ConnectionError {
+            message: "Invalid address".to_string(),
+        })?;
+        // Preconnected connections may predate the policy, e.g. `set_block_bad_ports`
+        self.host_policy.check(protocol, &addr)?;
+        if let Some(connection) = self.take_preconnected(protocol, &addr) {
+            return Ok(Some(HttpsConnectionApi::new(
+                connection,
+                self.tls_worker.clone(),
+                self.pipeline.clone(),
+            )));
+        }
+        if !self.limiter.try_acquire() {
+            return Ok(None);
+        }
+        let id = self.factory.borrow_mut().generate(protocol);
+        let connection =
+            Connection::new(self, protocol, addr, id).inspect_err(|_| self.limiter.release())?;
+        self.limiter.track(&connection);
+        self.connections.borrow_mut().push(connection.clone());
+        Ok(Some(HttpsConnectionApi::new(
+            connection,
+            self.tls_worker.clone(),
+            self.pipeline.clone(),
+        )))
     }
 
     /// Create a new http connection to the given address with an onready callback.
     /// # Arguments
     /// * `addr` - Address to connect to
     /// * `callback` - Callback to call when the connection is ready
+    /// # Returns
+    /// The connection API, or nothing if the connection limit is reached. Fails if the address
+    /// is invalid or refused by the host policy, or if this client has no HTTPS capability.
     #[wasm_bindgen]
     pub fn create_https_connection_with_onready(
         &mut self,
         addr: String,
-        callback: js_sys::Function,
-    ) -> Option<HttpsConnectionApi> {
-        let protocol = SocketCapability::HTTPS(self.get_highest_tls_version());
-        let id = self.factory.generate(protocol);
-        let addr = SocketAddr::split_addr(protocol, addr).unwrap();
-        let connection = Connection::new(self, protocol, addr, id).unwrap();
-        connection.set_onready(callback, None);
-        self.connections.push(connection.clone());
-        Some(HttpsConnectionApi::new(connection))
+        callback: ReadyCallback,
+    ) -> Result<Option<HttpsConnectionApi>, ConnectionError> {
+        let protocol = SocketCapability::HTTPS(self.require_tls_version()?);
+        let addr = SocketAddr::split_addr(protocol, addr).ok_or_else(|| ConnectionError {
+            message: "Invalid address".to_string(),
+        })?;
+        if !self.limiter.try_acquire() {
+            return Ok(None);
+        }
+        let id = self.factory.borrow_mut().generate(protocol);
+        let connection =
+            Connection::new(self, protocol, addr, id).inspect_err(|_| self.limiter.release())?;
+        connection.set_onready(callback.into(), None);
+        self.limiter.track(&connection);
+        self.connections.borrow_mut().push(connection.clone());
+        Ok(Some(HttpsConnectionApi::new(
+            connection,
+            self.tls_worker.clone(),
+            self.pipeline.clone(),
+        )))
+    }
+
+    /// Create a new https connection to the given address, offering a range of TLS versions.
+    /// # Arguments
+    /// * `addr` - Address to connect to
+    /// * `min_version` - Lowest TLS version to offer, e.g. `tls1_2`
+    /// * `max_version` - Highest TLS version to offer, or `None` to only offer `min_version`
+    /// # Returns
+    /// The connection API, or nothing if the connection limit is reached. Fails if a version
+    /// is not a capability of this client, or no version of the range is supported.
+    #[wasm_bindgen]
+    pub fn create_https_connection_with_tls_version(
+        &mut self,
+        addr: String,
+        min_version: String,
+        max_version: Option<String>,
+    ) -> Result<Option<HttpsConnectionApi>, ConnectionError> {
+        let min = parse_tls_version(&min_version)?;
+        let max = match max_version {
+            Some(max_version) => parse_tls_version(&max_version)?,
+            None => min,
+        };
+        self.check_tls_versions(min, max)?;
+        let protocol = SocketCapability::HTTPS(max);
+        let addr = SocketAddr::split_addr(protocol, addr).ok_or_else(|| ConnectionError {
+            message: "Invalid address".to_string(),
+        })?;
+        let settings = ConnectionSettings {
+            min_tls_version: Some(min),
+            ..self.settings()
+        };
+        Ok(self
+            .open_connection(&settings, protocol, addr, &[])?
+            .map(|connection| {
+                HttpsConnectionApi::new(connection, self.tls_worker.clone(), self.pipeline.clone())
+            }))
     }
 
     /// Get a http connection API for the given connection.
     #[wasm_bindgen]
     pub fn get_https_connection_api(&self, id: u64) -> HttpsConnectionApi {
         self.connections
+            .borrow()
             .iter()
             .find(|c| Into::<u64>::into(c.get_id()) == id)
-            .map(|c| HttpsConnectionApi::new(c.clone()))
+            .map(|c| {
+                HttpsConnectionApi::new(c.clone(), self.tls_worker.clone(), self.pipeline.clone())
+            })
             .unwrap()
     }
 
     /// Create a new tcp connection to the given address.
     /// # Arguments
     /// * `addr` - Address to connect to
+    /// # Returns
+    /// The connection API, or nothing if the connection limit is reached. Fails if the address
+    /// is invalid or refused by the host policy.
     #[wasm_bindgen]
-    pub fn create_tcp_connection(&mut self, addr: String) -> Option<TcpConnectionApi> {
+    pub fn create_tcp_connection(
+        &mut self,
+        addr: String,
+    ) -> Result<Option<TcpConnectionApi>, ConnectionError> {
         let protocol = SocketCapability::TCP;
-        let id = self.factory.generate(protocol);
-        let addr = SocketAddr::split_addr(protocol, addr).unwrap();
-        let connection = Connection::new(self, protocol, addr, id).unwrap();
-        self.connections.push(connection.clone());
-        Some(TcpConnectionApi::new(connection))
+        let addr = SocketAddr::split_addr(protocol, addr).ok_or_else(|| ConnectionError {
+            message: "Invalid address".to_string(),
+        })?;
+        if !self.limiter.try_acquire() {
+            return Ok(None);
+        }
+        let id = self.factory.borrow_mut().generate(protocol);
+        let connection =
+            Connection::new(self, protocol, addr, id).inspect_err(|_| self.limiter.release())?;
+        self.limiter.track(&connection);
+        self.connections.borrow_mut().push(connection.clone());
+        Ok(Some(TcpConnectionApi::new(connection)))
     }
 
     /// Create a new tcp connection to the given address with an onready callback.
     /// # Arguments
     /// * `addr` - Address to connect to
     /// * `callback` - Callback to call when the connection is ready
+    /// # Returns
+    /// The connection API, or nothing if the connection limit is reached. Fails if the address
+    /// is invalid or refused by the host policy.
     #[wasm_bindgen]
     pub fn create_tcp_connection_with_onready(
         &mut self,
         addr: String,
-        callback: js_sys::Function,
-    ) -> Option<TcpConnectionApi> {
+        callback: ReadyCallback,
+    ) -> Result<Option<TcpConnectionApi>, ConnectionError> {
         let protocol = SocketCapability::TCP;
-        let id = self.factory.generate(protocol);
-        let addr = SocketAddr::split_addr(protocol, addr).unwrap();
-        let connection = Connection::new(self, protocol, addr, id).unwrap();
-        connection.set_onready(callback, None);
-        self.connections.push(connection.clone());
-        Some(TcpConnectionApi::new(connection))
+        let addr = SocketAddr::split_addr(protocol, addr).ok_or_else(|| ConnectionError {
+            message: "Invalid address".to_string(),
+        })?;
+        if !self.limiter.try_acquire() {
+            return Ok(None);
+        }
+        let id = self.factory.borrow_mut().generate(protocol);
+        let connection =
+            Connection::new(self, protocol, addr, id).inspect_err(|_| self.limiter.release())?;
+        connection.set_onready(callback.into(), None);
+        self.limiter.track(&connection);
+        self.connections.borrow_mut().push(connection.clone());
+        Ok(Some(TcpConnectionApi::new(connection)))
+    }
+
+    /// Create a new tcp connection to the given address with socket options.
+    /// # Arguments
+    /// * `addr` - Address to connect to
+    /// * `options` - Socket options for the remote end
+    /// * `callback` - Callback to call when the connection is ready
+    /// # Returns
+    /// The connection API, or nothing if the connection limit is reached. Fails if the address
+    /// is invalid or refused by the host policy.
+    #[wasm_bindgen]
+    pub fn create_tcp_connection_with_options(
+        &mut self,
+        addr: String,
+        options: &TcpOptions,
+        callback: Option<ReadyCallback>,
+    ) -> Result<Option<TcpConnectionApi>, ConnectionError> {
+        let protocol = SocketCapability::TCP;
+        let addr = SocketAddr::split_addr(protocol, addr).ok_or_else(|| ConnectionError {
+            message: "Invalid address".to_string(),
+        })?;
+        if !self.limiter.try_acquire() {
+            return Ok(None);
+        }
+        let id = self.factory.borrow_mut().generate(protocol);
+        let connection = Connection::new_with_query(self, protocol, addr, id, &options.to_query())
+            .inspect_err(|_| self.limiter.release())?;
+        // Registered first, so the header is sent before the callback can send anything
+        if let Some(header) = options.proxy_header() {
+            let sender = connection.sender();
+            let header = header.as_bytes().to_vec();
+            let onready = Closure::once_into_js(move || {
+                sender.send(&header).unwrap_throw();
+            });
+            connection.set_onready(onready.unchecked_into(), Some(true));
+        }
+        if let Some(callback) = callback {
+            connection.set_onready(callback.into(), None);
+        }
+        self.limiter.track(&connection);
+        self.connections.borrow_mut().push(connection.clone());
+        Ok(Some(TcpConnectionApi::new(connection)))
+    }
+
+    /// Create tcp connections to many addresses with the same socket options, with at most
+    /// `concurrency` of them opening at once, e.g. to check the health of many services.
+    /// # Arguments
+    /// * `addrs` - Addresses to connect to
+    /// * `options` - Socket options for the remote end of every connection
+    /// * `timeout` - Time each connection has to open in ms, or `None` to wait forever
+    /// * `concurrency` - Number of connections opening at once, defaults to 6
+    /// # Returns
+    /// A promise resolving, once every connection has opened or failed, to an array of the
+    /// outcomes in the order of `addrs`, each an object with the `addr`, a `status` of
+    /// `connected`, `refused`, `timeout` or `error`, the `elapsed` time in ms, and either the
+    /// `connection` API or an `error` message. Connections that fail are removed from the
+    /// client, and ones refused by the host policy or connection limit are an `error`.
+    #[wasm_bindgen]
+    pub fn create_tcp_connections(
+        &mut self,
+        addrs: Vec<String>,
+        options: &TcpOptions,
+        timeout: Option<f64>,
+        concurrency: Option<usize>,
+    ) -> Result<js_sys::Promise, ConnectionError> {
+        let addrs = addrs
+            .into_iter()
+            .map(|addr| {
+                SocketAddr::split_addr(SocketCapability::TCP, addr.clone()).ok_or(ConnectionError {
+                    message: format!("Invalid address: {}", addr),
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let concurrency = concurrency.unwrap_or(DEFAULT_BATCH_CONCURRENCY);
+        Ok(self
+            .tcp_dialer()
+            .open_all(addrs, options.clone(), timeout, concurrency))
+    }
+
+    /// Route the plain HTTP requests of new connections through an upstream HTTP proxy.
+    ///
+    /// Each HTTP connection is made to the proxy, and its requests are sent with their
+    /// absolute URL. HTTPS connections are not affected, see `create_tunnel_connection`.
+    /// # Arguments
+    /// * `proxy_addr` - Address of the HTTP proxy, or `None` to connect directly
+    /// * `proxy_authorization` - Value of the `Proxy-Authorization` header, if any
+    #[wasm_bindgen]
+    pub fn set_http_proxy(
+        &mut self,
+        proxy_addr: Option<String>,
+        proxy_authorization: Option<String>,
+    ) {
+        self.http_proxy = proxy_addr.map(|addr| ForwardProxy {
+            addr: SocketAddr::split_addr(SocketCapability::HTTP, addr).unwrap(),
+            authorization: proxy_authorization,
+        });
+    }
+    /// Create a new connection tunneled through an HTTP proxy with a CONNECT request.
+    ///
+    /// The proxy is reached through the wsproxy like any TCP address, so proxies can be chained.
+    /// # Arguments
+    /// * `proxy_addr` - Address of the HTTP proxy
+    /// * `target` - Address to reach through the proxy, as `host:port`
+    /// * `tls` - Whether to speak HTTPS with the target, instead of raw TCP
+    /// * `proxy_authorization` - Value of the `Proxy-Authorization` header, if any
+    /// * `callback` - Callback called with a `TcpConnectionApi` (or an `HttpsConnectionApi` if
+    ///   `tls` is set) once the tunnel is established, or with `null` and an error
+    #[wasm_bindgen]
+    pub fn create_tunnel_connection(
+        &mut self,
+        proxy_addr: String,
+        target: String,
+        tls: bool,
+        proxy_authorization: Option<String>,
+        callback: TunnelReadyCallback,
+    ) -> Result<(), ConnectionError> {
+        let version = match tls {
+            true => Some(self.require_tls_version()?),
+            false => None,
+        };
+        let target_protocol = version.map_or(SocketCapability::TCP, SocketCapability::HTTPS);
+        self.host_policy.check(target_protocol, &target)?;
+        let protocol = SocketCapability::TCP;
+        let addr = SocketAddr::split_addr(protocol, proxy_addr).ok_or_else(|| ConnectionError {
+            message: "Invalid address".to_string(),
+        })?;
+        if !self.limiter.try_acquire() {
+            return Err(ConnectionError {
+                message: "Too many connections".to_string(),
+            });
+        }
+        let id = self.factory.borrow_mut().generate(protocol);
+        let connection =
+            Connection::new(self, protocol, addr, id).inspect_err(|_| self.limiter.release())?;
+
+        let api: JsValue = if let Some(version) = version {
+            let mut tunneled = connection.clone();
+            tunneled.addr = target.clone();
+            tunneled.protocol = SocketCapability::HTTPS(version);
+            tunneled.tls = Some(
+                TlsVersions::new(version, version, &self.tls_fallback())
+                    .with_downgrades(&self.tls_downgrades, &target),
+            );
+            HttpsConnectionApi::new(tunneled, self.tls_worker.clone(), self.pipeline.clone()).into()
+        } else {
+            TcpConnectionApi::new(connection.clone()).into()
+        };
+        tunnel::establish(
+            &connection.socket,
+            connection.sender(),
+            target,
+            proxy_authorization,
+            Box::new(move |result| {
+                let this = JsValue::null();
+                let _ = match result {
+                    Ok(()) => callback.call1(&this, &api),
+                    Err(e) => callback.call2(&this, &JsValue::null(), &e.into()),
+                };
+            }),
+        );
+
+        self.limiter.track(&connection);
+        self.connections.borrow_mut().push(connection);
+        Ok(())
     }
 
     /// Get a tcp connection API for the given connection.
     #[wasm_bindgen]
     pub fn get_tcp_connection_api(&self, id: u64) -> TcpConnectionApi {
         self.connections
+            .borrow()
             .iter()
             .find(|c| Into::<u64>::into(c.get_id()) == id)
             .map(|c| TcpConnectionApi::new(c.clone()))
             .unwrap()
     }
 
+    /// Create a new udp connection to the given address.
+    ///
+    /// The proxy is asked for a UDP association with the `protocol=udp` query parameter.
+    /// # Arguments
+    /// * `addr` - Address to connect to
+    /// # Returns
+    /// The connection API, or nothing if the connection limit is reached. Fails if the address
+    /// is invalid or refused by the host policy.
+    #[wasm_bindgen]
+    pub fn create_udp_connection(
+        &mut self,
+        addr: String,
+    ) -> Result<Option<UdpConnectionApi>, ConnectionError> {
+        let protocol = SocketCapability::UDP;
+        let addr = SocketAddr::split_addr(protocol, addr).ok_or_else(|| ConnectionError {
+            message: "Invalid address".to_string(),
+        })?;
+        if !self.limiter.try_acquire() {
+            return Ok(None);
+        }
+        let id = self.factory.borrow_mut().generate(protocol);
+        let query = [("protocol".to_string(), "udp".to_string())];
+        let connection = Connection::new_with_query(self, protocol, addr, id, &query)
+            .inspect_err(|_| self.limiter.release())?;
+        self.limiter.track(&connection);
+        self.connections.borrow_mut().push(connection.clone());
+        Ok(Some(UdpConnectionApi::new(connection)))
+    }
+
+    /// Create a new udp connection to the given address with an onready callback.
+    /// # Arguments
+    /// * `addr` - Address to connect to
+    /// * `callback` - Callback to call when the connection is ready
+    /// # Returns
+    /// The connection API, or nothing if the connection limit is reached. Fails if the address
+    /// is invalid or refused by the host policy.
+    #[wasm_bindgen]
+    pub fn create_udp_connection_with_onready(
+        &mut self,
+        addr: String,
+        callback: ReadyCallback,
+    ) -> Result<Option<UdpConnectionApi>, ConnectionError> {
+        let api = self.create_udp_connection(addr)?;
+        if let Some(api) = &api {
+            api.connection().set_onready(callback.into(), None);
+        }
+        Ok(api)
+    }
+
+    /// Get a udp connection API for the given connection.
+    #[wasm_bindgen]
+    pub fn get_udp_connection_api(&self, id: u64) -> UdpConnectionApi {
+        self.connections
+            .borrow()
+            .iter()
+            .find(|c| Into::<u64>::into(c.get_id()) == id)
+            .map(|c| UdpConnectionApi::new(c.clone()))
+            .unwrap()
+    }
+
+    /// Create a new FTP connection to the given address.
+    ///
+    /// Transfers use passive mode: each one opens a TCP connection through the proxy to the
+    /// port the server gives in reply to `PASV`, held to the host policy and the connection
+    /// limit like any other.
+    /// # Arguments
+    /// * `addr` - Address to connect to, port 21 if none
+    /// # Returns
+    /// The connection API, or nothing if the connection limit is reached.
+    #[wasm_bindgen]
+    pub fn create_ftp_connection(
+        &mut self,
+        addr: String,
+    ) -> Result<Option<FtpConnectionApi>, ConnectionError> {
+        let addr = ftp_addr(&addr).ok_or_else(|| ConnectionError {
+            message: format!("Invalid FTP address: {}", addr),
+        })?;
+        let settings = self.settings();
+        let connection = match self.open_connection(&settings, SocketCapability::TCP, addr, &[])? {
+            Some(connection) => connection,
+            None => return Ok(None),
+        };
+        Ok(Some(FtpConnectionApi::new(connection, self.tcp_dialer())))
+    }
+
+    /// Create a new IRC connection to the given address, registering once it is open.
+    ///
+    /// `PING` from the server is answered, and a taken nick is retried with `_` appended.
+    /// # Arguments
+    /// * `addr` - Address to connect to, port 6697 with TLS or 6667 without if none
+    /// * `tls` - Whether to speak TLS with the server
+    /// * `registration` - Nick and names to register with
+    /// * `callback` - Callback to call with every message received
+    /// # Returns
+    /// The connection API, or nothing if the connection limit is reached.
+    #[wasm_bindgen]
+    pub fn create_irc_connection(
+        &mut self,
+        addr: String,
+        tls: bool,
+        registration: &IrcRegistration,
+        callback: Option<IrcMessageCallback>,
+    ) -> Result<Option<IrcConnectionApi>, ConnectionError> {
+        registration.check()?;
+        let settings = self.settings();
+        let addr = irc_addr(&addr, tls);
+        let versions = match tls {
+            true => {
+                let max = self.require_tls_version()?;
+                let min = settings.min_tls_version.unwrap_or(max).min(max);
+                Some(TlsVersions::new(min, max, &[]))
+            }
+            false => None,
+        };
+        let mut connection =
+            match self.open_connection(&settings, SocketCapability::TCP, addr, &[])? {
+                Some(connection) => connection,
+                None => return Ok(None),
+            };
+        connection.tls = versions;
+        let callback = callback.map(Into::into);
+        IrcConnectionApi::new(connection, self.tls_worker.clone(), registration, callback).map(Some)
+    }
+
+    /// Look up a domain or IP address with WHOIS.
+    ///
+    /// The query is sent to IANA, then to the server it refers to, e.g. the registry of the
+    /// TLD, and to the registrar that server refers to, if any. Every server is reached over a
+    /// TCP connection through the proxy, held to the host policy and the connection limit.
+    /// # Arguments
+    /// * `query` - Domain or IP address to look up
+    /// # Returns
+    /// A promise resolving to the text of the last server answering, or rejecting with a
+    /// `ConnectionError`.
+    #[wasm_bindgen]
+    pub fn whois(&self, query: String) -> js_sys::Promise {
+        let query = query.trim().to_string();
+        if query.is_empty() || query.contains(['\r', '\n']) {
+            return js_sys::Promise::reject(&JsValue::from(ConnectionError {
+                message: format!("Invalid WHOIS query: {:?}", query),
+            }));
+        }
+        future_to_promise(whois::whois(self.tcp_dialer(), query))
+    }
+
+    /// Create a new HTTP/3 connection to the given address, carried by QUIC over a UDP
+    /// connection through the proxy.
+    ///
+    /// Experimental, requires the `http3` feature and a proxy relaying UDP. The QUIC
+    /// handshake starts once the UDP connection is open.
+    /// # Arguments
+    /// * `addr` - Address to connect to, port 443 if given as a `https://` url without port
+    /// # Returns
+    /// The connection API, or nothing if the connection limit is reached.
+    #[cfg(feature = "http3")]
+    #[wasm_bindgen]
+    pub fn create_http3_connection(
+        &mut self,
+        addr: String,
+    ) -> Result<Option<Http3ConnectionApi>, ConnectionError> {
+        let protocol = SocketCapability::UDP;
+        let addr = SocketAddr::split_addr(SocketCapability::HTTPS(TLSVersion::TLSv1_3), addr)
+            .ok_or_else(|| ConnectionError {
+                message: "Invalid address".to_string(),
+            })?;
+        if !self.limiter.try_acquire() {
+            return Ok(None);
+        }
+        let id = self.factory.borrow_mut().generate(protocol);
+        let query = [("protocol".to_string(), "udp".to_string())];
+        let connection = Connection::new_with_query(self, protocol, addr, id, &query)
+            .inspect_err(|_| self.limiter.release())?;
+        self.limiter.track(&connection);
+        self.connections.borrow_mut().push(connection.clone());
+        Http3ConnectionApi::new(connection, self.pipeline.clone()).map(Some)
+    }
+
     /// Generate a new connection ID.
+    ///
+    /// Fails on an unknown connection type rather than panicking.
     #[wasm_bindgen]
-    pub fn generate_id(&mut self, conn_type: String) -> u64 {
-        let conn_type = SocketCapability::from_string(conn_type.to_lowercase().as_str()).unwrap();
-        let id = self.factory.generate(conn_type);
-        Into::<u64>::into(id)
+    pub fn generate_id(&mut self, conn_type: String) -> Result<u64, ConnectionError> {
+        let conn_type = SocketCapability::from_string(conn_type.to_lowercase().as_str())
+            .ok_or_else(|| ConnectionError {
+                message: format!("Unknown connection type: {}", conn_type),
+            })?;
+        let id = self.factory.borrow_mut().generate(conn_type);
+        Ok(Into::<u64>::into(id))
     }
 }
 
 impl Client {
-    /// Get the highest supported TLS version.
-    pub fn get_highest_tls_version(&self) -> TLSVersion {
-        *self
-            .capabilities
+    /// Add a middleware run on the HTTP and HTTPS requests and responses of this client.
+    pub fn use_middleware(&mut self, middleware: impl Middleware + 'static) {
+        self.pipeline.push(Rc::new(middleware));
+    }
+
+    /// Wait for a connection created by this client to open.
+    ///
+    /// If it fails to open in time, the connection is closed and removed from this client.
+    ///
+    /// # Arguments
+    ///
+    /// * `opened` - Connection created with its API, or `None` if the limit refused it
+    /// * `timeout` - Time to wait for the connection to open in ms, if limited
+    ///
+    /// # Returns
+    ///
+    /// A promise resolving to the API once the connection is open.
+    fn resolve_on_open(
+        &self,
+        opened: Option<(Connection, JsValue)>,
+        timeout: Option<f64>,
+    ) -> js_sys::Promise {
+        let (connection, api) = match opened {
+            Some(opened) => opened,
+            None => {
+                return js_sys::Promise::reject(&JsValue::from(ConnectionError {
+                    message: "Too many connections".to_string(),
+                }))
+            }
+        };
+        let socket = connection.socket.clone();
+        let id = u64::from(connection.get_id());
+        let connections = self.connections.clone();
+
+        js_sys::Promise::new(&mut |resolve, reject| {
+            // Settled by whichever of open, error, close or timeout comes first
+            let settled = Rc::new(Cell::new(false));
+            let fail = {
+                let settled = settled.clone();
+                let socket = socket.clone();
+                let connections = connections.clone();
+                Rc::new(move |error: JsValue| {
+                    if settled.replace(true) {
+                        return;
+                    }
+                    let _ = socket.close();
+                    connections
+                        .borrow_mut()
+                        .retain(|c| u64::from(c.get_id()) != id);
+                    let _ = reject.call1(&JsValue::null(), &error);
+                })
+            };
+
+            let onopen = {
+                let settled = settled.clone();
+                let api = api.clone();
+                Closure::once_into_js(move || {
+                    if !settled.replace(true) {
+                        let _ = resolve.call1(&JsValue::null(), &api);
+                    }
+                })
+            };
+            let error = |message: &str| {
+                JsValue::from(ConnectionError {
+                    message: message.to_string(),
+                })
+            };
+            let onerror = {
+                let fail = fail.clone();
+                Closure::once_into_js(move || fail(error("Connection failed to open")))
+            };
+            let onclose = {
+                let fail = fail.clone();
+                Closure::once_into_js(move |event: CloseEvent| {
+                    fail(ConnectionClosed::from_event(&event).into())
+                })
+            };
+            for (event, listener) in [("open", onopen), ("error", onerror), ("close", onclose)] {
+                socket
+                    .add_event_listener_with_callback_and_add_event_listener_options(
+                        event,
+                        listener.unchecked_ref(),
+                        AddEventListenerOptions::new().once(true),
+                    )
+                    .unwrap_throw();
+            }
+
+            if let Some(timeout) = timeout {
+                let fail = fail.clone();
+                set_timeout(
+                    &Closure::once_into_js(move || {
+                        fail(error("Connection timed out while opening"))
+                    }),
+                    timeout,
+                );
+            }
+        })
+    }
+
+    /// Parse a url of `connect` into the protocol and address of its connection.
+    fn parse_url(&self, url: &str) -> Result<(SocketCapability, String), ConnectionError> {
+        let (scheme, host) = match url.split_once("://") {
+            Some((scheme, rest)) => (
+                scheme.to_lowercase(),
+                rest.split('/').next().unwrap_or(rest),
+            ),
+            None => {
+                return Err(ConnectionError {
+                    message: format!("Missing scheme in url: {}", url),
+                })
+            }
+        };
+        let name = match scheme.as_str() {
+            "tcp" | "udp" | "http" | "https" => scheme.as_str(),
+            "ws" => "http",
+            "wss" => "https",
+            _ => {
+                return Err(ConnectionError {
+                    message: format!("Unknown scheme: {}", scheme),
+                })
+            }
+        };
+        if !self.supports_capability(name) {
+            return Err(ConnectionError {
+                message: format!("Protocol not supported by this client: {}", name),
+            });
+        }
+        let protocol = self.parse_protocol(name)?;
+        // Only the host and port are kept, the path of the url is ignored
+        let addr = SocketAddr::split_addr(protocol, format!("{}://{}", scheme, host)).ok_or_else(
+            || ConnectionError {
+                message: "Invalid address".to_string(),
+            },
+        )?;
+        Ok((protocol, addr))
+    }
+
+    /// Take the connection opened by `preconnect` for a protocol and address, if it is still
+    /// connecting or open.
+    fn take_preconnected(&mut self, protocol: SocketCapability, addr: &str) -> Option<Connection> {
+        // Connections closed or removed from this client since are forgotten
+        let connections = self.connections.borrow();
+        self.preconnected.retain(|p| {
+            p.socket.ready_state() <= 1
+                && connections
+                    .iter()
+                    .any(|c| Into::<u64>::into(c.get_id()) == Into::<u64>::into(p.get_id()))
+        });
+        drop(connections);
+        let index = self
+            .preconnected
+            .iter()
+            .position(|c| c.addr == addr && c.protocol.to_string() == protocol.to_string())?;
+        Some(self.preconnected.remove(index))
+    }
+
+    /// Open a connection of any protocol and add it to this client, if the limit allows.
+    ///
+    /// # Returns
+    ///
+    /// The connection, or `None` if the connection limit is reached. Fails if the host policy
+    /// does not allow the address.
+    fn open_connection(
+        &mut self,
+        settings: &ConnectionSettings,
+        protocol: SocketCapability,
+        addr: String,
+        query: &[(String, String)],
+    ) -> Result<Option<Connection>, ConnectionError> {
+        settings.host_policy.check(protocol, &addr)?;
+        if !self.limiter.try_acquire() {
+            return Ok(None);
+        }
+        let id = self.factory.borrow_mut().generate(protocol);
+        let connection = match protocol {
+            SocketCapability::HTTP => {
+                Self::new_http_connection(settings, self.http_proxy.as_ref(), addr, id, query)
+            }
+            _ => Connection::new_with_settings(settings, protocol, addr, id, query),
+        }
+        .inspect_err(|_| self.limiter.release())?;
+        self.limiter.track(&connection);
+        self.connections.borrow_mut().push(connection.clone());
+        Ok(Some(connection))
+    }
+
+    /// Send a request to a url, for `get`, `post`, `put` and `delete`.
+    fn fetch(
+        &mut self,
+        method: &str,
+        url: String,
+        headers: Option<js_sys::Object>,
+        body: Option<Vec<u8>>,
+    ) -> Result<js_sys::Promise, ConnectionError> {
+        let (protocol, addr) = self.parse_url(&url)?;
+        if !matches!(
+            protocol,
+            SocketCapability::HTTP | SocketCapability::HTTPS(_)
+        ) {
+            return Err(ConnectionError {
+                message: format!("Requests must be HTTP or HTTPS: {}", url),
+            });
+        }
+        self.host_policy.check(protocol, &addr)?;
+        // The authority and path follow the scheme, which `parse_url` checked
+        let rest = url.split_once("://").map_or("", |(_, rest)| rest);
+        let (host, path) = match rest.find(['/', '?']) {
+            Some(i) if rest[i..].starts_with('/') => (&rest[..i], rest[i..].to_string()),
+            Some(i) => (&rest[..i], format!("/{}", &rest[i..])),
+            None => (rest, "/".to_string()),
+        };
+        let mut request = match &headers {
+            Some(headers) => {
+                HttpConnectionRequest::with_header_object(method.to_string(), path, headers, body)
+            }
+            None => HttpConnectionRequest::new(method.to_string(), path, Vec::new(), body),
+        };
+        if !request
+            .headers
+            .iter()
+            .any(|h| h.name.eq_ignore_ascii_case("Host"))
+        {
+            request.headers.insert(0, HttpHeader::new("Host", host));
+        }
+
+        let connection = self.take_preconnected(protocol, &addr);
+        let item = self.dialer().item(0, protocol, addr, request);
+        Ok(self.batch(Priority::default()).fetch_one(item, connection))
+    }
+
+    /// Get the handles of this client needed to send requests outside of a borrow of it.
+    fn batch(&self, priority: Priority) -> Batch {
+        Batch {
+            limiter: self.limiter.clone(),
+            connections: self.connections.clone(),
+            pipeline: self.pipeline.clone(),
+            tls_worker: self.tls_worker.clone(),
+            priority,
+            retry: self.retry.clone(),
+            backoff: self.backoff.clone(),
+            redirect: self.redirect.clone(),
+            dialer: self.dialer(),
+        }
+    }
+
+    /// Get the handles of this client needed to prepare requests outside of a borrow of it.
+    fn dialer(&self) -> Dialer {
+        let capability = |name: &str| {
+            self.parse_protocol(name)
+                .ok()
+                .filter(|_| self.supports_capability(name))
+        };
+        Dialer {
+            settings: self.settings(),
+            http_proxy: self.http_proxy.clone(),
+            factory: self.factory.clone(),
+            http: capability("http"),
+            https: capability("https"),
+        }
+    }
+
+    /// Get the handles of this client needed to open TCP connections outside of a borrow of it.
+    fn tcp_dialer(&self) -> TcpDialer {
+        TcpDialer {
+            settings: self.settings(),
+            factory: self.factory.clone(),
+            limiter: self.limiter.clone(),
+            connections: self.connections.clone(),
+        }
+    }
+
+    /// Get the API of a connection, for its protocol.
+    fn connection_api(&self, connection: Connection) -> JsValue {
+        match connection.protocol {
+            SocketCapability::TCP => TcpConnectionApi::new(connection).into(),
+            SocketCapability::UDP => UdpConnectionApi::new(connection).into(),
+            SocketCapability::HTTP => {
+                HttpConnectionApi::new(connection, self.pipeline.clone()).into()
+            }
+            SocketCapability::HTTPS(_) => {
+                HttpsConnectionApi::new(connection, self.tls_worker.clone(), self.pipeline.clone())
+                    .into()
+            }
+        }
+    }
+
+    /// Open a plain HTTP connection, through the upstream HTTP proxy if one is set.
+    ///
+    /// The client does not need to be borrowed, so the connection can be opened later.
+    pub(crate) fn new_http_connection(
+        settings: &ConnectionSettings,
+        http_proxy: Option<&ForwardProxy>,
+        addr: String,
+        id: ConnId,
+        query: &[(String, String)],
+    ) -> Result<Connection, ConnectionError> {
+        let protocol = SocketCapability::HTTP;
+        match http_proxy {
+            Some(proxy) => {
+                settings.host_policy.check(protocol, &addr)?;
+                // The proxy is set by the app rather than the url, so it is not held to the policy
+                let settings = ConnectionSettings {
+                    host_policy: HostPolicy::default(),
+                    ..settings.clone()
+                };
+                let mut connection = Connection::new_with_settings(
+                    &settings,
+                    protocol,
+                    proxy.addr.clone(),
+                    id,
+                    query,
+                )?;
+                connection.addr = addr;
+                connection.forward_proxy = Some(proxy.clone());
+                Ok(connection)
+            }
+            None => Connection::new_with_settings(settings, protocol, addr, id, query),
+        }
+    }
+
+    /// Parse the name of a protocol.
+    fn parse_protocol(&self, protocol: &str) -> Result<SocketCapability, ConnectionError> {
+        match protocol.to_lowercase().as_str() {
+            "https" => Ok(SocketCapability::HTTPS(self.require_tls_version()?)),
+            protocol => SocketCapability::from_string(protocol).ok_or_else(|| ConnectionError {
+                message: format!("Unknown protocol: {}", protocol),
+            }),
+        }
+    }
+
+    /// Check that a range of TLS versions is valid, allowed by the capabilities of this
+    /// client, and that at least one of its versions is supported.
+    fn check_tls_versions(&self, min: TLSVersion, max: TLSVersion) -> Result<(), ConnectionError> {
+        if min > max {
+            return Err(ConnectionError {
+                message: format!("Invalid TLS version range: {:?} to {:?}", min, max),
+            });
+        }
+        for version in [min, max] {
+            let allowed = self
+                .capabilities
+                .iter()
+                .any(|c| matches!(c, SocketCapability::HTTPS(v) if *v == version));
+            if !allowed {
+                return Err(ConnectionError {
+                    message: format!("TLS version not allowed by this client: {:?}", version),
+                });
+            }
+        }
+        supported_versions(min, max).map(|_| ())
+    }
+
+    /// Get the TLS versions HTTPS connections may fall back to, allowed by the capabilities of
+    /// this client, supported, and not below the fallback floor.
+    fn tls_fallback(&self) -> Vec<TLSVersion> {
+        let floor = match self.tls_fallback_floor {
+            Some(floor) => floor,
+            None => return Vec::new(),
+        };
+        self.capabilities
+            .iter()
+            .filter_map(|c| match c {
+                SocketCapability::HTTPS(v) if *v >= floor => Some(*v),
+                _ => None,
+            })
+            .filter(|v| supported_versions(*v, *v).is_ok())
+            .collect()
+    }
+
+    /// Get the query parameters the proxy needs to open a connection of the given protocol.
+    pub(crate) fn protocol_query(protocol: SocketCapability) -> Vec<(String, String)> {
+        match protocol {
+            SocketCapability::UDP => vec![("protocol".to_string(), "udp".to_string())],
+            _ => Vec::new(),
+        }
+    }
+
+    /// Get the settings applied to new connections.
+    pub fn settings(&self) -> ConnectionSettings {
+        ConnectionSettings {
+            base: self.addr.clone(),
+            capture_capacity: self.capture_capacity,
+            throttle_limits: self.throttle_limits,
+            network_conditions: self.network_conditions,
+            framing: self.framing,
+            stats: self.stats.clone(),
+            client_name: self.name.clone(),
+            tags: Vec::new(),
+            subprotocols: Vec::new(),
+            min_tls_version: None,
+            tls_fallback: self.tls_fallback(),
+            tls_downgrades: self.tls_downgrades.clone(),
+            tls_options: self.tls_options.clone(),
+            timeout_policy: self.timeout_policy,
+            host_policy: self.host_policy.clone(),
+            proxy: self.proxy.clone(),
+        }
+    }
+
+    /// Get the capacity of the raw traffic capture of new connections, if enabled.
+    pub fn get_capture_capacity(&self) -> Option<usize> {
+        self.capture_capacity
+    }
+
+    /// Get the upload and download rate limits of new connections, in bytes per second.
+    pub fn get_throttle_limits(&self) -> (Option<f64>, Option<f64>) {
+        self.throttle_limits
+    }
+
+    /// Get the network conditions simulated on new connections, if any.
+    pub fn get_network_conditions(&self) -> Option<NetworkConditions> {
+        self.network_conditions
+    }
+
+    /// Get the protocol of the connection with the given ID, if it exists.
+    pub fn get_connection_protocol(&self, id: u64) -> Option<SocketCapability> {
+        self.connections
+            .borrow()
+            .iter()
+            .find(|c| Into::<u64>::into(c.get_id()) == id)
+            .map(|c| c.get_protocol())
+    }
+
+    /// Remove the connection with the given ID, closing it.
+    pub fn remove_connection(&mut self, id: u64) {
+        // Taken first, as closing a connection may call back into the client
+        let removed: Vec<Connection> = {
+            let mut connections = self.connections.borrow_mut();
+            let (removed, kept) = connections
+                .drain(..)
+                .partition(|c| Into::<u64>::into(c.get_id()) == id);
+            *connections = kept;
+            removed
+        };
+        for connection in removed {
+            connection.close();
+        }
+    }
+
+    /// Get the highest TLS version of this client.
+    /// # Returns
+    /// The TLS version, or nothing if this client has no HTTPS capability.
+    fn max_tls_version(&self) -> Option<TLSVersion> {
+        self.capabilities
             .iter()
             .filter_map(|c| match c {
-                SocketCapability::HTTPS(v) => Some(v),
+                SocketCapability::HTTPS(v) => Some(*v),
                 _ => None,
             })
             .max()
-            .unwrap()
+    }
+
+    /// Get the highest TLS version of this client, failing if it has no HTTPS capability.
+    fn require_tls_version(&self) -> Result<TLSVersion, ConnectionError> {
+        self.max_tls_version().ok_or_else(|| ConnectionError {
+            message: "HTTPS is not supported by this client".to_string(),
+        })
     }
 }
@@ -0,0 +1,160 @@
+use wasm_bindgen::prelude::*;
+use wasm_bindgen_futures::js_sys;
+
+use crate::{connection::ConnectionError, framing::Framing, SocketCapability};
+
+/// Url template of wsproxy, the target address as the path of the base url.
+pub const WSPROXY_URL_TEMPLATE: &str = "{base}/{addr}";
+/// Url template of websockify, the target address as the token of its token plugins.
+pub const WEBSOCKIFY_URL_TEMPLATE: &str = "{base}?token={addr}";
+
+/// Preset handling of a proxy implementation, see `Client::set_proxy_profile`.
+#[wasm_bindgen]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ProxyProfile {
+    /// wsproxy and wsproxy-ng: target address in the path, raw bytes in binary frames with
+    /// the `binary` subprotocol, UDP selected by a `protocol=udp` query parameter
+    Wsproxy,
+    /// websockify: target address as the `token` query parameter, for a token plugin to map
+    /// it to a target, binary or base64 frames as the proxy selects, TCP only
+    Websockify,
+    /// wsproxy endpoints of epoxy-server and other Wisp servers: target address in the path,
+    /// binary frames without subprotocol, as these endpoints select none, TCP only
+    Epoxy,
+}
+
+impl ProxyProfile {
+    /// Get the handling of this profile.
+    pub fn compat(self) -> ProxyCompat {
+        match self {
+            ProxyProfile::Wsproxy => ProxyCompat::default(),
+            ProxyProfile::Websockify => ProxyCompat {
+                url_template: WEBSOCKIFY_URL_TEMPLATE.to_string(),
+                offer_subprotocols: true,
+                udp: false,
+            },
+            ProxyProfile::Epoxy => ProxyCompat {
+                url_template: WSPROXY_URL_TEMPLATE.to_string(),
+                offer_subprotocols: false,
+                udp: false,
+            },
+        }
+    }
+
+    /// Get the framing of this profile.
+    pub fn framing(self) -> Framing {
+        match self {
+            ProxyProfile::Wsproxy | ProxyProfile::Epoxy => Framing::Binary,
+            ProxyProfile::Websockify => Framing::Negotiate,
+        }
+    }
+
+    /// Get the name of this profile in snapshots.
+    pub fn name(self) -> &'static str {
+        match self {
+            ProxyProfile::Wsproxy => "wsproxy",
+            ProxyProfile::Websockify => "websockify",
+            ProxyProfile::Epoxy => "epoxy",
+        }
+    }
+
+    /// Parse the name of a profile in snapshots.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "wsproxy" => Some(ProxyProfile::Wsproxy),
+            "websockify" => Some(ProxyProfile::Websockify),
+            "epoxy" => Some(ProxyProfile::Epoxy),
+            _ => None,
+        }
+    }
+}
+
+/// How connections reach their target through the proxy.
+#[derive(Clone, Debug)]
+pub struct ProxyCompat {
+    /// Template of the url of connections, see `Client::set_proxy_url_template`
+    pub url_template: String,
+    /// Whether the subprotocols of the framing are offered to the proxy
+    pub offer_subprotocols: bool,
+    /// Whether the proxy relays UDP, selected by a `protocol=udp` query parameter
+    pub udp: bool,
+}
+
+impl Default for ProxyCompat {
+    fn default() -> Self {
+        Self {
+            url_template: WSPROXY_URL_TEMPLATE.to_string(),
+            offer_subprotocols: true,
+            udp: true,
+        }
+    }
+}
+
+impl ProxyCompat {
+    /// Check that a url template names the target of connections.
+    pub fn check_template(template: &str) -> Result<(), ConnectionError> {
+        if !template.contains("{addr}") && !template.contains("{host}") {
+            return Err(ConnectionError {
+                message: format!(
+                    "Proxy url template has no {{addr}} or {{host}}: {}",
+                    template
+                ),
+            });
+        }
+        Ok(())
+    }
+
+    /// Build the url of a connection.
+    ///
+    /// Query parameters of the base url are kept, before those of the connection.
+    ///
+    /// # Arguments
+    ///
+    /// * `base` - Base url of the proxy
+    /// * `protocol` - Protocol of the connection
+    /// * `addr` - Address of the target, as `host:port`
+    /// * `query` - Query parameters of the connection
+    pub fn url(
+        &self,
+        base: &str,
+        protocol: SocketCapability,
+        addr: &str,
+        query: &[(String, String)],
+    ) -> Result<String, ConnectionError> {
+        if matches!(protocol, SocketCapability::UDP) && !self.udp {
+            return Err(ConnectionError {
+                message: "The proxy does not relay UDP".to_string(),
+            });
+        }
+        let (base, base_query) = match base.split_once('?') {
+            Some((base, base_query)) => (base, Some(base_query)),
+            None => (base, None),
+        };
+        let (host, port) = match addr.rsplit_once(':') {
+            Some((host, port)) if !host.ends_with(':') => (host, port),
+            _ => (addr, ""),
+        };
+        let protocol = match protocol {
+            SocketCapability::UDP => "udp",
+            _ => "tcp",
+        };
+        let mut url = self
+            .url_template
+            .replace("{base}", base)
+            .replace("{addr}", addr)
+            .replace("{host}", host)
+            .replace("{port}", port)
+            .replace("{protocol}", protocol);
+        if let Some(base_query) = base_query.filter(|q| !q.is_empty()) {
+            url.push(if url.contains('?') { '&' } else { '?' });
+            url.push_str(base_query);
+        }
+        for (name, value) in query {
+            url.push(if url.contains('?') { '&' } else { '?' });
+            url.push_str(&String::from(js_sys::encode_uri_component(name)));
+            url.push('=');
+            url.push_str(&String::from(js_sys::encode_uri_component(value)));
+        }
+        Ok(url)
+    }
+}
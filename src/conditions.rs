@@ -0,0 +1,76 @@
+use wasm_bindgen::prelude::*;
+use wasm_bindgen_futures::js_sys;
+
+/// Simulated network conditions applied to the messages received by connections.
+///
+/// Meant for development, to test web apps against bad networks without external tooling.
+#[wasm_bindgen]
+#[derive(Copy, Clone, Debug, Default)]
+pub struct NetworkConditions {
+    /// Delay added to every message, in ms
+    latency: f64,
+    /// Maximum random delay added on top of the latency, in ms
+    jitter: f64,
+    /// Probability of a message being dropped, between 0 and 1
+    drop_rate: f64,
+}
+
+#[wasm_bindgen]
+impl NetworkConditions {
+    /// Create new network conditions, without any latency or loss.
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the delay added to every message, in ms.
+    #[wasm_bindgen]
+    pub fn set_latency(&mut self, millis: f64) {
+        self.latency = millis.max(0.0);
+    }
+
+    /// Set the maximum random delay added on top of the latency, in ms.
+    #[wasm_bindgen]
+    pub fn set_jitter(&mut self, millis: f64) {
+        self.jitter = millis.max(0.0);
+    }
+
+    /// Set the probability of a message being dropped, between 0 and 1.
+    ///
+    /// Dropping a message of a stream protocol such as TCP corrupts the stream, which is
+    /// useful to test error handling.
+    #[wasm_bindgen]
+    pub fn set_drop_rate(&mut self, rate: f64) {
+        self.drop_rate = rate.clamp(0.0, 1.0);
+    }
+
+    /// Get the delay added to every message, in ms.
+    #[wasm_bindgen]
+    pub fn get_latency(&self) -> f64 {
+        self.latency
+    }
+
+    /// Get the maximum random delay added on top of the latency, in ms.
+    #[wasm_bindgen]
+    pub fn get_jitter(&self) -> f64 {
+        self.jitter
+    }
+
+    /// Get the probability of a message being dropped, between 0 and 1.
+    #[wasm_bindgen]
+    pub fn get_drop_rate(&self) -> f64 {
+        self.drop_rate
+    }
+}
+
+impl NetworkConditions {
+    /// Draw the delay of the next message, in ms.
+    pub fn delay(&self) -> f64 {
+        self.latency + js_sys::Math::random() * self.jitter
+    }
+
+    /// Draw whether the next message is dropped.
+    pub fn should_drop(&self) -> bool {
+        self.drop_rate > 0.0 && js_sys::Math::random() < self.drop_rate
+    }
+}
@@ -1,16 +1,42 @@
 use std::{
+    cell::{Cell, RefCell},
+    collections::VecDeque,
     error,
     fmt::{self},
+    rc::Rc,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
 };
 
 use wasm_bindgen::prelude::*;
 
-use wasm_bindgen_futures::js_sys::{self};
-use web_sys::{
-    AddEventListenerOptions, WebSocket,
+use wasm_bindgen_futures::{
+    js_sys::{self, ArrayBuffer, Promise, Uint8Array},
+    JsFuture,
 };
+use web_sys::{AddEventListenerOptions, Blob, MessageEvent, MessageEventInit, WebSocket};
 
-use crate::{client::Client, id::ConnId, SocketCapability};
+use crate::{
+    capture::{Capture, Direction},
+    client::Client,
+    compat::ProxyCompat,
+    conditions::NetworkConditions,
+    connection_apis::http::{ForwardProxy, TimeoutPolicy},
+    console_log,
+    framing::{self, Framing},
+    id::ConnId,
+    pending::{ConnectionClosed, PendingRequests, CLOSE_NORMAL},
+    policy::HostPolicy,
+    progress::ProgressReporter,
+    set_timeout,
+    stats::StatsRecorder,
+    throttle::Throttle,
+    timing::ConnectionSetup,
+    tls::{TlsDowngrades, TlsOptions, TlsVersions},
+    SocketCapability, TLSVersion,
+};
 
 #[derive(Clone, Debug)]
 pub struct Connection {
@@ -22,13 +48,259 @@ pub struct Connection {
     pub(crate) protocol: SocketCapability,
     /// ID of this connection
     pub(crate) id: ConnId,
+    /// Whether the write side of this connection has been shut down
+    pub(crate) write_shutdown: Arc<AtomicBool>,
+    /// Capture of the raw bytes of this connection, if enabled on the client
+    pub(crate) capture: Option<Capture>,
+    /// Rate limits of this connection
+    pub(crate) throttle: Rc<Throttle>,
+    /// Time of the last byte sent or received, in ms since the epoch
+    pub(crate) last_activity: Rc<Cell<f64>>,
+    /// Idle time after which the server closes this connection, in ms, if hinted
+    pub(crate) keep_alive_timeout: Rc<Cell<Option<f64>>>,
+    /// Upstream HTTP proxy this connection is made to, if any, in which case `addr` is the
+    /// address requests are forwarded to
+    pub(crate) forward_proxy: Option<ForwardProxy>,
+    /// How bytes are carried in the frames of the socket
+    pub(crate) framing: Framing,
+    /// Callback receiving the progress of requests, responses and handshakes
+    pub(crate) progress: ProgressReporter,
+    /// Statistics of the client owning this connection
+    pub(crate) stats: StatsRecorder,
+    /// Name of the client owning this connection, if any
+    pub(crate) client_name: Option<String>,
+    /// Labels attached to this connection
+    pub(crate) tags: Rc<RefCell<Vec<String>>>,
+    /// TLS versions of this connection, if it is a HTTPS connection
+    pub(crate) tls: Option<TlsVersions>,
+    /// Cipher suites and key exchange groups of this connection, if it is a HTTPS connection
+    pub(crate) tls_options: TlsOptions,
+    /// What happens to this connection when a request times out
+    pub(crate) timeout_policy: TimeoutPolicy,
+    /// Time spent setting up this connection, charged to its first request
+    pub(crate) setup: ConnectionSetup,
+    /// Requests in flight on this connection, cancelled once it closes
+    pub(crate) pending: PendingRequests,
+    /// Closes this connection once its last clone is dropped
+    closer: Rc<Closer>,
+}
+
+/// Part of a connection shared by its clones, closing it once the last of them is dropped.
+#[derive(Debug)]
+struct Closer {
+    /// WebSocket connection
+    socket: WebSocket,
+    /// Requests in flight on the connection
+    pending: PendingRequests,
+}
+
+impl Closer {
+    /// Close the socket, cancelling the requests in flight right away.
+    fn close(&self) {
+        self.pending.cancel_all(ConnectionClosed::new(
+            CLOSE_NORMAL,
+            "Connection closed by the client",
+        ));
+        let _ = self.socket.close();
+    }
+}
+
+impl Drop for Closer {
+    fn drop(&mut self) {
+        self.close();
+    }
+}
+
+/// Description of a connection of a client, as of the time it was taken.
+#[wasm_bindgen]
+#[derive(Clone, Debug)]
+pub struct ConnectionInfo {
+    /// ID of the connection
+    id: u64,
+    /// UUID of the connection, if made with `ConnIdScheme::UuidV7`
+    uuid: Option<String>,
+    /// Protocol of the connection
+    protocol: String,
+    /// Address of the connection
+    addr: String,
+    /// Name of the client owning the connection, if any
+    client_name: Option<String>,
+    /// Labels attached to the connection
+    tags: Vec<String>,
+}
+
+#[wasm_bindgen]
+impl ConnectionInfo {
+    /// Get the ID of the connection.
+    #[wasm_bindgen]
+    pub fn get_id(&self) -> u64 {
+        self.id
+    }
+
+    /// Get the UUID of the connection, if made with `ConnIdScheme.UuidV7`.
+    #[wasm_bindgen]
+    pub fn get_uuid(&self) -> Option<String> {
+        self.uuid.clone()
+    }
+
+    /// Get the protocol of the connection, e.g. `tcp` or `https_tls1_2`.
+    #[wasm_bindgen]
+    pub fn get_protocol(&self) -> String {
+        self.protocol.clone()
+    }
+
+    /// Get the address of the connection.
+    #[wasm_bindgen]
+    pub fn get_addr(&self) -> String {
+        self.addr.clone()
+    }
+
+    /// Get the name of the client owning the connection, if any.
+    #[wasm_bindgen]
+    pub fn get_client_name(&self) -> Option<String> {
+        self.client_name.clone()
+    }
+
+    /// Get the labels attached to the connection.
+    #[wasm_bindgen]
+    pub fn get_tags(&self) -> Vec<String> {
+        self.tags.clone()
+    }
+}
+
+/// Handle applying the keep-alive semantics of HTTP responses to a connection.
+#[derive(Clone, Debug)]
+pub struct KeepAlive {
+    /// WebSocket connection
+    pub(crate) socket: WebSocket,
+    /// Idle time after which the server closes the connection, in ms, if hinted
+    pub(crate) timeout: Rc<Cell<Option<f64>>>,
+}
+
+/// Handle sending bytes on a connection, capturing and throttling them.
+#[derive(Clone, Debug)]
+pub struct Sender {
+    /// WebSocket connection
+    socket: WebSocket,
+    /// Capture of the raw bytes of the connection, if enabled
+    capture: Option<Capture>,
+    /// Rate limits of the connection
+    throttle: Rc<Throttle>,
+    /// Time of the last byte sent or received, in ms since the epoch
+    last_activity: Rc<Cell<f64>>,
+    /// How bytes are carried in the frames of the socket
+    framing: Framing,
+    /// Statistics of the client owning the connection
+    stats: StatsRecorder,
+}
+
+impl Sender {
+    /// Send bytes, delaying them if over the upload limit.
+    pub fn send(&self, data: &[u8]) -> Result<(), JsValue> {
+        self.last_activity.set(js_sys::Date::now());
+        self.stats.record_bytes_out(data.len());
+        if let Some(capture) = &self.capture {
+            capture.record(Direction::Outgoing, data);
+        }
+        let delay = self.throttle.upload_delay(data.len());
+        if delay <= 0.0 {
+            return Self::transmit(&self.socket, self.framing, data);
+        }
+        let socket = self.socket.clone();
+        let framing = self.framing;
+        let data = data.to_vec();
+        set_timeout(
+            &Closure::once_into_js(move || {
+                if let Err(e) = Self::transmit(&socket, framing, &data) {
+                    console_log!("Failed to send delayed data: {:?}", e);
+                }
+            }),
+            delay,
+        );
+        Ok(())
+    }
+
+    /// Send a JS buffer, delaying it if over the upload limit.
+    ///
+    /// The buffer is only copied when captured or delayed.
+    pub fn send_array(&self, data: &Uint8Array) -> Result<(), JsValue> {
+        if self.framing.is_text(&self.socket) {
+            return self.send(&data.to_vec());
+        }
+        self.last_activity.set(js_sys::Date::now());
+        self.stats.record_bytes_out(data.length() as usize);
+        if let Some(capture) = &self.capture {
+            capture.record(Direction::Outgoing, &data.to_vec());
+        }
+        let delay = self.throttle.upload_delay(data.length() as usize);
+        if delay <= 0.0 {
+            return self.socket.send_with_array_buffer_view(data);
+        }
+        let socket = self.socket.clone();
+        // Copied, so later changes to the buffer do not affect the delayed send
+        let data = data.slice(0, data.length());
+        set_timeout(
+            &Closure::once_into_js(move || {
+                if let Err(e) = socket.send_with_array_buffer_view(&data) {
+                    console_log!("Failed to send delayed data: {:?}", e);
+                }
+            }),
+            delay,
+        );
+        Ok(())
+    }
+
+    /// Send bytes in a single frame of the socket, encoded as the framing requires.
+    fn transmit(socket: &WebSocket, framing: Framing, data: &[u8]) -> Result<(), JsValue> {
+        if framing.is_text(socket) {
+            socket.send_with_str(&framing::encode(data))
+        } else {
+            socket.send_with_u8_array(data)
+        }
+    }
+}
+
+/// Settings of a client applied to its new connections.
+#[derive(Clone, Debug)]
+pub struct ConnectionSettings {
+    /// Base wsproxy url
+    pub base: String,
+    /// Capacity of the raw traffic capture, if enabled
+    pub capture_capacity: Option<usize>,
+    /// Upload and download rate limits, in bytes per second
+    pub throttle_limits: (Option<f64>, Option<f64>),
+    /// Simulated network conditions, if any
+    pub network_conditions: Option<NetworkConditions>,
+    /// How bytes are carried in the frames exchanged with the proxy
+    pub framing: Framing,
+    /// Statistics of the client
+    pub stats: StatsRecorder,
+    /// Name of the client, if any
+    pub client_name: Option<String>,
+    /// Labels attached to new connections
+    pub tags: Vec<String>,
+    /// Lowest TLS version of new HTTPS connections, if a range was requested
+    pub min_tls_version: Option<TLSVersion>,
+    /// Lower TLS versions HTTPS connections may fall back to if the server rejects theirs
+    pub tls_fallback: Vec<TLSVersion>,
+    /// Versions HTTPS connections of the client fell back to, by address
+    pub tls_downgrades: TlsDowngrades,
+    /// Cipher suites and key exchange groups of new HTTPS connections
+    pub tls_options: TlsOptions,
+    /// What happens to connections when a request times out
+    pub timeout_policy: TimeoutPolicy,
+    /// WebSocket subprotocols offered after those of the framing
+    pub subprotocols: Vec<String>,
+    /// Hosts and ports connections may be created to
+    pub host_policy: HostPolicy,
+    /// How connections reach their target through the proxy
+    pub proxy: ProxyCompat,
 }
 
 pub struct SocketAddr;
 
 impl SocketAddr {
     pub fn split_addr(protocol: SocketCapability, addr: String) -> Option<String> {
-        let addr = addr;
         if !addr.contains("://") {
             return Some(addr);
         }
@@ -36,7 +308,7 @@ impl SocketAddr {
         let addr = split.next()?.replace('/', "");
 
         let default_port = match protocol {
-            SocketCapability::TCP => "0",
+            SocketCapability::TCP | SocketCapability::UDP => "0",
             SocketCapability::HTTP => "80",
             SocketCapability::HTTPS(_) => "443",
         };
@@ -45,7 +317,7 @@ impl SocketAddr {
         let addr = split.next()?;
         let port = split.next().unwrap_or(default_port);
 
-        return Some(format!("{}:{}", addr, port));
+        Some(format!("{}:{}", addr, port))
     }
 }
 
@@ -63,20 +335,418 @@ impl Connection {
         protocol: SocketCapability,
         addr: String,
         id: ConnId,
-    ) -> Result<Self, Box<dyn error::Error>> {
-        let base = client.get_addr();
+    ) -> Result<Self, ConnectionError> {
+        Self::new_with_query(client, protocol, addr, id, &[])
+    }
+
+    /// Create a new connection to the given address, passing query parameters to the proxy.
+    ///
+    /// # Arguments
+    ///
+    /// * `client` - Reference to client that owns this connection
+    /// * `protocol` - Protocol to use for this connection
+    /// * `addr` - Address of this connection without protocol (e.g. `tcp://` or `http://`)
+    /// * `id` - ID of this connection
+    /// * `query` - Query parameters appended to the proxy url
+    pub fn new_with_query(
+        client: &Client,
+        protocol: SocketCapability,
+        addr: String,
+        id: ConnId,
+        query: &[(String, String)],
+    ) -> Result<Self, ConnectionError> {
+        Self::new_with_settings(&client.settings(), protocol, addr, id, query)
+    }
+
+    /// Create a new connection to the given address with the given client settings.
+    ///
+    /// Unlike `new_with_query`, the client does not need to be borrowed, so the connection
+    /// can be opened later.
+    ///
+    /// Fails if the host policy refuses the address, or the WebSocket to the proxy cannot be
+    /// created.
+    ///
+    /// # Arguments
+    ///
+    /// * `settings` - Settings of the client that owns this connection
+    /// * `protocol` - Protocol to use for this connection
+    /// * `addr` - Address of this connection without protocol (e.g. `tcp://` or `http://`)
+    /// * `id` - ID of this connection
+    /// * `query` - Query parameters appended to the proxy url
+    pub fn new_with_settings(
+        settings: &ConnectionSettings,
+        protocol: SocketCapability,
+        addr: String,
+        id: ConnId,
+        query: &[(String, String)],
+    ) -> Result<Self, ConnectionError> {
+        settings.host_policy.check(protocol, &addr)?;
+        let url = settings.proxy.url(&settings.base, protocol, &addr, query)?;
 
+        let framing_protocols = if settings.proxy.offer_subprotocols {
+            settings.framing.protocols()
+        } else {
+            &[]
+        };
+        let protocols = framing_protocols
+            .iter()
+            .map(|&p| JsValue::from(p))
+            .chain(
+                settings
+                    .subprotocols
+                    .iter()
+                    .map(|p| JsValue::from(p.as_str())),
+            )
+            .collect::<js_sys::Array>();
         let socket =
-            WebSocket::new_with_str(&format!("{}/{}", base, addr), "binary").unwrap_throw();
+            WebSocket::new_with_str_sequence(&url, &protocols).map_err(|e| ConnectionError {
+                message: format!("Failed to open WebSocket: {:?}", e),
+            })?;
         socket.set_binary_type(web_sys::BinaryType::Arraybuffer);
+        let setup = ConnectionSetup::start(&socket);
+        let pending = PendingRequests::watch(&socket);
+
+        // Registered first, so every other listener receives array buffers
+        Self::normalize_incoming(&socket, settings.framing);
+
+        // Registered next, so held back messages are hidden from all other listeners
+        let throttle = Rc::new(Throttle::default());
+        let (upload, download) = settings.throttle_limits;
+        throttle.set_limits(upload, download);
+        Self::intercept_incoming(&socket, throttle.clone(), settings.network_conditions);
+
+        let stats = settings.stats.clone();
+        stats.record_open(protocol, &settings.tags);
+        {
+            let open_stats = stats.clone();
+            let open_setup = setup.clone();
+            // Registered after the setup timer, which sets the time the socket opened at
+            let onopen = Closure::once_into_js(move || {
+                if let Some(time) = open_setup.connect_time() {
+                    open_stats.record_connect(time);
+                }
+            });
+            socket
+                .add_event_listener_with_callback_and_add_event_listener_options(
+                    "open",
+                    onopen.unchecked_ref(),
+                    AddEventListenerOptions::new().once(true),
+                )
+                .unwrap_throw();
+            let close_stats = stats.clone();
+            let onclose = Closure::once_into_js(move || close_stats.record_close(protocol));
+            socket
+                .add_event_listener_with_callback_and_add_event_listener_options(
+                    "close",
+                    onclose.unchecked_ref(),
+                    AddEventListenerOptions::new().once(true),
+                )
+                .unwrap_throw();
+            let stats = stats.clone();
+            let error_callback: Closure<dyn Fn()> = Closure::wrap(Box::new(move || {
+                stats.record_error();
+            }));
+            socket
+                .add_event_listener_with_callback("error", error_callback.as_ref().unchecked_ref())
+                .unwrap_throw();
+            error_callback.forget();
+        }
+
+        let last_activity = Rc::new(Cell::new(js_sys::Date::now()));
+        {
+            let last_activity = last_activity.clone();
+            let stats = stats.clone();
+            let message_callback: Closure<dyn Fn(MessageEvent)> =
+                Closure::wrap(Box::new(move |evt: MessageEvent| {
+                    last_activity.set(js_sys::Date::now());
+                    if let Some(buffer) = evt.data().dyn_ref::<ArrayBuffer>() {
+                        stats.record_bytes_in(buffer.byte_length() as usize);
+                    }
+                }));
+            socket
+                .add_event_listener_with_callback(
+                    "message",
+                    message_callback.as_ref().unchecked_ref(),
+                )
+                .unwrap_throw();
+            message_callback.forget();
+        }
+
+        let capture = settings
+            .capture_capacity
+            .map(|capacity| Capture::new(protocol, &addr, capacity));
+        if let Some(capture) = &capture {
+            let capture = capture.clone();
+            let message_callback: Closure<dyn Fn(MessageEvent)> =
+                Closure::wrap(Box::new(move |evt: MessageEvent| {
+                    if let Ok(buffer) = evt.data().dyn_into::<ArrayBuffer>() {
+                        capture.record(Direction::Incoming, &Uint8Array::new(&buffer).to_vec());
+                    }
+                }));
+            socket
+                .add_event_listener_with_callback(
+                    "message",
+                    message_callback.as_ref().unchecked_ref(),
+                )
+                .unwrap_throw();
+            message_callback.forget();
+        }
+
+        let tls = match protocol {
+            SocketCapability::HTTPS(max) => Some(
+                TlsVersions::new(
+                    settings.min_tls_version.unwrap_or(max),
+                    max,
+                    &settings.tls_fallback,
+                )
+                .with_downgrades(&settings.tls_downgrades, &addr),
+            ),
+            _ => None,
+        };
+
+        let closer = Rc::new(Closer {
+            socket: socket.clone(),
+            pending: pending.clone(),
+        });
         Ok(Connection {
             socket,
             addr,
             protocol,
             id,
+            write_shutdown: Arc::new(AtomicBool::new(false)),
+            capture,
+            throttle,
+            last_activity,
+            keep_alive_timeout: Rc::new(Cell::new(None)),
+            forward_proxy: None,
+            framing: settings.framing,
+            progress: ProgressReporter::default(),
+            stats,
+            client_name: settings.client_name.clone(),
+            tags: Rc::new(RefCell::new(settings.tags.clone())),
+            tls,
+            tls_options: settings.tls_options.clone(),
+            timeout_policy: settings.timeout_policy,
+            setup,
+            pending,
+            closer,
         })
     }
 
+    /// Deliver the `Blob` messages of the socket again as array buffers, as some proxies send
+    /// blobs even though array buffers were requested, and decode base64 text messages if
+    /// the framing is text.
+    ///
+    /// Blobs are read asynchronously. Until they are read, the messages following them are held
+    /// back too, so messages are delivered in the order they were received.
+    fn normalize_incoming(socket: &WebSocket, framing: Framing) {
+        let target = socket.clone();
+        // Contents of the held back messages, in order of receipt
+        let queue: Rc<RefCell<VecDeque<Promise>>> = Rc::new(RefCell::new(VecDeque::new()));
+        // Whether held back messages are being delivered
+        let delivering = Rc::new(Cell::new(false));
+        let message_callback: Closure<dyn Fn(MessageEvent)> =
+            Closure::wrap(Box::new(move |evt: MessageEvent| {
+                if delivering.get() {
+                    return;
+                }
+                let data = evt.data();
+                let content = if let Some(blob) = data.dyn_ref::<Blob>() {
+                    blob.array_buffer()
+                } else if let Some(text) = data.as_string() {
+                    if !framing.is_text(&target) {
+                        return;
+                    }
+                    evt.stop_immediate_propagation();
+                    let bytes = match framing::decode(&text) {
+                        Some(bytes) => bytes,
+                        None => {
+                            console_log!("Dropped invalid base64 message");
+                            return;
+                        }
+                    };
+                    let buffer = Uint8Array::from(bytes.as_slice()).buffer();
+                    if queue.borrow().is_empty() {
+                        let mut init = MessageEventInit::new();
+                        init.data(&buffer);
+                        let message =
+                            MessageEvent::new_with_event_init_dict("message", &init).unwrap_throw();
+                        delivering.set(true);
+                        let _ = target.dispatch_event(&message);
+                        delivering.set(false);
+                        return;
+                    }
+                    Promise::resolve(&buffer)
+                } else if queue.borrow().is_empty() {
+                    return;
+                } else {
+                    Promise::resolve(&data)
+                };
+                evt.stop_immediate_propagation();
+
+                let mut pending = queue.borrow_mut();
+                pending.push_back(content);
+                if pending.len() > 1 {
+                    // Already being waited for
+                    return;
+                }
+                drop(pending);
+
+                let queue = queue.clone();
+                let delivering = delivering.clone();
+                let target = target.clone();
+                wasm_bindgen_futures::spawn_local(async move {
+                    loop {
+                        let content = match queue.borrow().front() {
+                            Some(content) => content.clone(),
+                            None => return,
+                        };
+                        match JsFuture::from(content).await {
+                            Ok(buffer) => {
+                                let mut init = MessageEventInit::new();
+                                init.data(&buffer);
+                                let message =
+                                    MessageEvent::new_with_event_init_dict("message", &init)
+                                        .unwrap_throw();
+                                delivering.set(true);
+                                let _ = target.dispatch_event(&message);
+                                delivering.set(false);
+                            }
+                            Err(e) => console_log!("Failed to read message: {:?}", e),
+                        }
+                        queue.borrow_mut().pop_front();
+                    }
+                });
+            }));
+        socket
+            .add_event_listener_with_callback("message", message_callback.as_ref().unchecked_ref())
+            .unwrap_throw();
+        message_callback.forget();
+    }
+
+    /// Hold back the messages of the socket that exceed the download limit or are subject to
+    /// simulated network conditions, and deliver them again once due.
+    ///
+    /// Messages are delivered in the order they were received.
+    fn intercept_incoming(
+        socket: &WebSocket,
+        throttle: Rc<Throttle>,
+        conditions: Option<NetworkConditions>,
+    ) {
+        let target = socket.clone();
+        // Time the last held back message is delivered at, in ms since the epoch
+        let last_delivery = Cell::new(0.0);
+        let message_callback: Closure<dyn Fn(MessageEvent)> =
+            Closure::wrap(Box::new(move |evt: MessageEvent| {
+                if throttle.is_delivering()
+                    || (conditions.is_none() && throttle.is_download_unlimited())
+                {
+                    return;
+                }
+                let data = evt.data();
+                let len = match data.dyn_ref::<ArrayBuffer>() {
+                    Some(buffer) => buffer.byte_length() as usize,
+                    None => return,
+                };
+
+                if conditions.is_some_and(|c| c.should_drop()) {
+                    evt.stop_immediate_propagation();
+                    return;
+                }
+
+                let now = js_sys::Date::now();
+                let delay = throttle.download_delay(len) + conditions.map_or(0.0, |c| c.delay());
+                let deliver_at = (now + delay).max(last_delivery.get());
+                if deliver_at <= now {
+                    return;
+                }
+                last_delivery.set(deliver_at);
+
+                evt.stop_immediate_propagation();
+                let mut init = MessageEventInit::new();
+                init.data(&data);
+                let delayed =
+                    MessageEvent::new_with_event_init_dict("message", &init).unwrap_throw();
+                let throttle = throttle.clone();
+                let target = target.clone();
+                set_timeout(
+                    &Closure::once_into_js(move || {
+                        throttle.deliver(|| {
+                            target.dispatch_event(&delayed).unwrap_throw();
+                        })
+                    }),
+                    deliver_at - now,
+                );
+            }));
+        socket
+            .add_event_listener_with_callback("message", message_callback.as_ref().unchecked_ref())
+            .unwrap_throw();
+        message_callback.forget();
+    }
+
+    /// Get a handle sending bytes on this connection.
+    pub fn sender(&self) -> Sender {
+        Sender {
+            socket: self.socket.clone(),
+            capture: self.capture.clone(),
+            throttle: self.throttle.clone(),
+            last_activity: self.last_activity.clone(),
+            framing: self.framing,
+            stats: self.stats.clone(),
+        }
+    }
+
+    /// Get a handle applying the keep-alive semantics of HTTP responses to this connection.
+    pub fn keep_alive(&self) -> KeepAlive {
+        KeepAlive {
+            socket: self.socket.clone(),
+            timeout: self.keep_alive_timeout.clone(),
+        }
+    }
+
+    /// Get the idle time after which the server closes this connection, in ms, if hinted.
+    pub fn get_keep_alive_timeout(&self) -> Option<f64> {
+        self.keep_alive_timeout.get()
+    }
+
+    /// Get the labels attached to this connection.
+    pub fn get_tags(&self) -> Vec<String> {
+        self.tags.borrow().clone()
+    }
+
+    /// Replace the labels attached to this connection.
+    pub fn set_tags(&self, tags: Vec<String>) {
+        *self.tags.borrow_mut() = tags;
+    }
+
+    /// Get the prefix of the log lines of this connection, naming its client and tags if any.
+    pub fn log_prefix(&self) -> String {
+        let tags = self.tags.borrow();
+        match (&self.client_name, tags.is_empty()) {
+            (None, true) => String::new(),
+            (Some(name), true) => format!("[{}] ", name),
+            (None, false) => format!("[{}] ", tags.join(",")),
+            (Some(name), false) => format!("[{} {}] ", name, tags.join(",")),
+        }
+    }
+
+    /// Get a description of this connection.
+    pub fn info(&self) -> ConnectionInfo {
+        ConnectionInfo {
+            id: self.get_id().into(),
+            uuid: self.get_id().uuid_string(),
+            protocol: self.protocol.to_string(),
+            addr: self.addr.clone(),
+            client_name: self.client_name.clone(),
+            tags: self.get_tags(),
+        }
+    }
+
+    /// Get the time since the last byte was sent or received on this connection, in ms.
+    pub fn idle_time(&self) -> f64 {
+        js_sys::Date::now() - self.last_activity.get()
+    }
+
     /// Get the address of this connection.
     pub fn get_addr(&self) -> String {
         self.addr.clone()
@@ -92,11 +762,28 @@ impl Connection {
         self.id
     }
 
+    /// Shut down the write side of this connection, shared by all its clones.
+    pub fn shutdown_write(&self) {
+        self.write_shutdown.store(true, Ordering::Relaxed);
+    }
+
+    /// Whether the write side of this connection has been shut down.
+    pub fn is_write_shutdown(&self) -> bool {
+        self.write_shutdown.load(Ordering::Relaxed)
+    }
+
+    /// Close this connection, shared by all its clones.
+    ///
+    /// Requests in flight are cancelled right away, rather than once the socket closes.
+    /// Connections are also closed once their last clone is dropped.
+    pub fn close(&self) {
+        self.closer.close();
+    }
+
     /// set onready callback
     pub fn set_onready(&self, callback: js_sys::Function, once: Option<bool>) {
         let once = once.unwrap_or(false);
-        let _ = self
-            .socket
+        self.socket
             .add_event_listener_with_callback_and_add_event_listener_options(
                 "open",
                 &callback,
@@ -111,17 +798,27 @@ impl Connection {
     }
 }
 
-impl Drop for Connection {
-    fn drop(&mut self) {
-        let _ = self.socket.close();
-    }
-}
-
 pub struct ConnectionError {
     /// Error message
     pub message: String,
 }
 
+impl ConnectionError {
+    /// Tag this error with the ID of the request it occurred in, if any.
+    ///
+    /// # Arguments
+    ///
+    /// * `request_id` - ID of the request
+    pub fn for_request(self, request_id: Option<&str>) -> Self {
+        match request_id {
+            Some(id) => Self {
+                message: format!("[{}] {}", id, self.message),
+            },
+            None => self,
+        }
+    }
+}
+
 impl fmt::Display for ConnectionError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "Connection error: {}", self.message)
@@ -142,8 +839,8 @@ impl fmt::Debug for ConnectionError {
 
 impl error::Error for ConnectionError {}
 
-impl Into<JsValue> for ConnectionError {
-    fn into(self) -> JsValue {
-        JsValue::from_str(&self.message)
+impl From<ConnectionError> for JsValue {
+    fn from(error: ConnectionError) -> Self {
+        JsValue::from_str(&error.message)
     }
 }
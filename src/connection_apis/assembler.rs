@@ -0,0 +1,725 @@
+use crate::{
+    connection::ConnectionError,
+    sniff::{sniff, RESOURCE_HEADER_SIZE},
+};
+
+use super::http::{HttpConnectionResponse, HttpHeader, MAX_BODY_PREALLOCATION, METHOD_HEAD};
+
+/// Function receiving the body chunks of responses instead of the assembler buffering them.
+pub type BodySink = Box<dyn FnMut(&[u8])>;
+
+/// Function receiving the head of final responses, without their body, as soon as it is parsed.
+pub type HeadSink = Box<dyn FnMut(HttpConnectionResponse)>;
+
+/// Function receiving the number of body bytes received so far and the expected length of the
+/// body, every time a chunk of a response body is received.
+pub type ProgressSink = Box<dyn FnMut(usize, usize)>;
+
+/// Longest response head accepted, status line and headers included.
+const MAX_HEAD_SIZE: usize = 64 * 1024;
+
+/// Longest chunk size or trailer line accepted in a chunked body, extensions included.
+const MAX_CHUNK_LINE: usize = 8 * 1024;
+
+/// Position of the assembler in a body sent with `Transfer-Encoding: chunked`.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+enum ChunkState {
+    /// Reading the size line of the next chunk
+    #[default]
+    Size,
+    /// Reading the data of a chunk, with the number of bytes left
+    Data(usize),
+    /// Reading the CRLF ending the data of a chunk
+    DataEnd,
+    /// Reading the trailer section after the last chunk
+    Trailers,
+    /// Read the whole body
+    Done,
+}
+
+/// Incrementally assembles HTTP responses from the chunks of a byte stream.
+///
+/// The chunks may come from any transport, e.g. WebSocket messages for plain HTTP or
+/// the plaintext yielded by rustls for HTTPS.
+#[derive(Default)]
+pub struct ResponseAssembler {
+    /// Response code, 0 until the head of the response has been parsed
+    code: u16,
+    /// HTTP version of the response
+    version: String,
+    /// Reason phrase of the response
+    reason: String,
+    /// Bytes of the response head received so far, until the end of the headers is seen
+    head: Vec<u8>,
+    /// Number of bytes of `head` already searched for the end of the headers
+    scanned: usize,
+    /// Response headers
+    headers: Vec<HttpHeader>,
+    /// Response body received so far, unless passed to the sink
+    body: Vec<u8>,
+    /// Whether the response has a `Content-Type` header, otherwise its type is sniffed
+    has_content_type: bool,
+    /// First bytes of the body passed to the sink, to sniff its type from
+    sniff_header: Vec<u8>,
+    /// Function receiving the body chunks, if the body is not buffered
+    sink: Option<BodySink>,
+    /// Function receiving the head of responses, if any
+    head_sink: Option<HeadSink>,
+    /// Number of body bytes received so far
+    received: usize,
+    /// Function receiving the progress of the body, if any
+    progress: Option<ProgressSink>,
+    /// Expected length of the body
+    content_length: usize,
+    /// Whether the body is sent with `Transfer-Encoding: chunked`, ignoring `Content-Length`
+    chunked: bool,
+    /// Position in the chunked body
+    chunk_state: ChunkState,
+    /// Bytes of the chunk size or trailer line received so far
+    chunk_line: Vec<u8>,
+    /// Whether the response is HTTP/1.0, which closes connections by default
+    http_1_0: bool,
+    /// Keep-alive requested by the `Connection` header, if any
+    keep_alive: Option<bool>,
+    /// Keep-alive timeout hinted by the `Keep-Alive` header, in seconds
+    keep_alive_timeout: Option<u32>,
+    /// Whether an interim `100 Continue` response was received and not taken yet
+    continued: bool,
+    /// Whether the responses answer a `HEAD` request, so have no body
+    head_request: bool,
+}
+
+impl ResponseAssembler {
+    /// Create a new assembler waiting for the head of a response.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create a new assembler passing the body chunks of responses to the given function,
+    /// so responses are assembled without their body.
+    ///
+    /// # Arguments
+    ///
+    /// * `sink` - Function receiving the body chunks, in order
+    pub fn with_body_sink(sink: BodySink) -> Self {
+        Self {
+            sink: Some(sink),
+            ..Self::default()
+        }
+    }
+
+    /// Pass the head of final responses to the given function as soon as it is parsed, before
+    /// their body is received.
+    ///
+    /// # Arguments
+    ///
+    /// * `head_sink` - Function receiving the responses, without their body
+    pub fn with_head_sink(mut self, head_sink: HeadSink) -> Self {
+        self.head_sink = Some(head_sink);
+        self
+    }
+
+    /// Expect responses to a request with the given method, as responses to `HEAD` requests
+    /// have no body whatever their headers say.
+    ///
+    /// # Arguments
+    ///
+    /// * `method` - Method of the request
+    pub fn with_method(mut self, method: &str) -> Self {
+        self.head_request = method == METHOD_HEAD;
+        self
+    }
+
+    /// Report the progress of response bodies to the given function.
+    ///
+    /// # Arguments
+    ///
+    /// * `progress` - Function receiving the progress of the body as chunks are received
+    pub fn with_progress(mut self, progress: ProgressSink) -> Self {
+        self.progress = Some(progress);
+        self
+    }
+
+    /// Push the next chunk of the stream.
+    ///
+    /// # Arguments
+    ///
+    /// * `chunk` - Bytes received from the transport
+    ///
+    /// # Returns
+    ///
+    /// The response once it is complete, after which the assembler waits for the next one,
+    /// starting with the bytes following the response.
+    pub fn push(
+        &mut self,
+        chunk: &[u8],
+    ) -> Result<Option<HttpConnectionResponse>, ConnectionError> {
+        let rest;
+        if self.code == 0 {
+            // The head may be split anywhere, including inside the terminating CRLFs
+            let start = self.scanned.saturating_sub(3);
+            self.head.extend_from_slice(chunk);
+            let end = self.head[start..]
+                .windows(4)
+                .position(|w| w == b"\r\n\r\n")
+                .map(|end| start + end);
+            // A head never ending must not be buffered forever
+            if end.unwrap_or(self.head.len()) > MAX_HEAD_SIZE {
+                self.reset();
+                return Err(ConnectionError {
+                    message: "Response head too long".to_string(),
+                });
+            }
+            let end = match end {
+                Some(end) => end,
+                None => {
+                    self.scanned = self.head.len();
+                    return Ok(None);
+                }
+            };
+            let mut head = std::mem::take(&mut self.head);
+            let body = head.split_off(end + 4);
+            head.truncate(end);
+            self.parse_head(&String::from_utf8_lossy(&head))?;
+            self.scanned = 0;
+
+            // Interim responses other than protocol switches precede the final response
+            if (100..200).contains(&self.code) && self.code != 101 {
+                self.continued |= self.code == 100;
+                self.reset();
+                return if body.is_empty() {
+                    Ok(None)
+                } else {
+                    self.push(&body)
+                };
+            }
+
+            // Whatever their headers say, e.g. the length of the response to a `GET` instead
+            if self.head_request || [101, 204, 304].contains(&self.code) {
+                self.content_length = 0;
+                self.chunked = false;
+            }
+
+            if let Some(head_sink) = &mut self.head_sink {
+                let keep_alive = self.keep_alive.unwrap_or(!self.http_1_0);
+                head_sink(
+                    HttpConnectionResponse::new(self.code, self.headers.clone(), None)
+                        .with_keep_alive(keep_alive, self.keep_alive_timeout)
+                        .with_status_line(self.version.clone(), self.reason.clone()),
+                );
+            }
+            if self.sink.is_none() {
+                self.body
+                    .reserve(self.content_length.min(MAX_BODY_PREALLOCATION));
+            }
+            let framed = self.push_framed(&body)?;
+            rest = body[framed..].to_vec();
+        } else {
+            let framed = self.push_framed(chunk)?;
+            rest = chunk[framed..].to_vec();
+        }
+
+        let complete = match self.chunked {
+            true => self.chunk_state == ChunkState::Done,
+            false => self.received >= self.content_length,
+        };
+        if !complete {
+            return Ok(None);
+        }
+
+        let buffered = self.sink.is_none();
+        let assembled = self.reset();
+        // Bytes following the body start the next response
+        self.head = rest;
+        let keep_alive = assembled.keep_alive.unwrap_or(!assembled.http_1_0);
+        let sniffed_type = (!assembled.has_content_type && assembled.received > 0).then(|| {
+            let header = if buffered {
+                &assembled.body
+            } else {
+                &assembled.sniff_header
+            };
+            sniff(header).to_string()
+        });
+        let body = buffered.then_some(assembled.body);
+        Ok(Some(
+            HttpConnectionResponse::new(assembled.code, assembled.headers, body)
+                .with_keep_alive(keep_alive, assembled.keep_alive_timeout)
+                .with_status_line(assembled.version, assembled.reason)
+                .with_sniffed_type(sniffed_type),
+        ))
+    }
+
+    /// Add bytes of the message body to the response, decoding its transfer coding.
+    ///
+    /// # Returns
+    ///
+    /// The number of bytes of the body, those following it being left for the next response.
+    fn push_framed(&mut self, bytes: &[u8]) -> Result<usize, ConnectionError> {
+        if !self.chunked {
+            let body = bytes.len().min(self.content_length - self.received);
+            self.push_body(&bytes[..body]);
+            return Ok(body);
+        }
+        let total = bytes.len();
+        let mut bytes = bytes;
+        while !bytes.is_empty() && self.chunk_state != ChunkState::Done {
+            if let ChunkState::Data(left) = self.chunk_state {
+                let data = left.min(bytes.len());
+                self.push_body(&bytes[..data]);
+                bytes = &bytes[data..];
+                self.chunk_state = match left - data {
+                    0 => ChunkState::DataEnd,
+                    left => ChunkState::Data(left),
+                };
+                continue;
+            }
+
+            let line = match self.take_chunk_line(&mut bytes)? {
+                Some(line) => line,
+                None => break,
+            };
+            self.chunk_state = match self.chunk_state {
+                ChunkState::Size => {
+                    // Extensions after `;` are ignored
+                    let size = line.split(|&b| b == b';').next().unwrap_or_default();
+                    let size = std::str::from_utf8(size)
+                        .ok()
+                        .and_then(|size| usize::from_str_radix(size.trim(), 16).ok())
+                        .ok_or_else(|| ConnectionError {
+                            message: format!(
+                                "Invalid chunk size: {}",
+                                String::from_utf8_lossy(&line)
+                            ),
+                        })?;
+                    match size {
+                        0 => ChunkState::Trailers,
+                        size => ChunkState::Data(size),
+                    }
+                }
+                ChunkState::DataEnd if line.is_empty() => ChunkState::Size,
+                ChunkState::DataEnd => {
+                    return Err(ConnectionError {
+                        message: "Chunk data longer than its size".to_string(),
+                    })
+                }
+                // Trailer fields are not exposed, the body ends with an empty line
+                ChunkState::Trailers if line.is_empty() => ChunkState::Done,
+                state => state,
+            };
+        }
+        Ok(total - bytes.len())
+    }
+
+    /// Take the next line of a chunked body, once its end is received.
+    ///
+    /// # Arguments
+    ///
+    /// * `bytes` - Bytes received, advanced past those consumed
+    ///
+    /// # Returns
+    ///
+    /// The line without its line ending, or `None` if its end is not received yet.
+    fn take_chunk_line(&mut self, bytes: &mut &[u8]) -> Result<Option<Vec<u8>>, ConnectionError> {
+        let end = bytes.iter().position(|&b| b == b'\n');
+        let (line, rest) = bytes.split_at(end.map_or(bytes.len(), |end| end + 1));
+        *bytes = rest;
+        self.chunk_line.extend_from_slice(line);
+        if self.chunk_line.len() > MAX_CHUNK_LINE {
+            return Err(ConnectionError {
+                message: "Chunk line too long".to_string(),
+            });
+        }
+        if end.is_none() {
+            return Ok(None);
+        }
+        let mut line = std::mem::take(&mut self.chunk_line);
+        line.pop();
+        if line.last() == Some(&b'\r') {
+            line.pop();
+        }
+        Ok(Some(line))
+    }
+
+    /// Add bytes to the body of the response, or pass them to the sink.
+    fn push_body(&mut self, bytes: &[u8]) {
+        self.received += bytes.len();
+        match &mut self.sink {
+            Some(sink) => {
+                if !self.has_content_type && self.sniff_header.len() < RESOURCE_HEADER_SIZE {
+                    let missing = RESOURCE_HEADER_SIZE - self.sniff_header.len();
+                    self.sniff_header
+                        .extend_from_slice(&bytes[..bytes.len().min(missing)]);
+                }
+                if !bytes.is_empty() {
+                    sink(bytes)
+                }
+            }
+            None => self.body.extend_from_slice(bytes),
+        }
+        if let Some(progress) = &mut self.progress {
+            progress(self.received, self.content_length);
+        }
+    }
+
+    /// Wait for the next response, keeping the sinks, the request method and the interim
+    /// responses not taken yet.
+    ///
+    /// # Returns
+    ///
+    /// The state of the response assembled so far, without the sinks.
+    fn reset(&mut self) -> Self {
+        let next = Self {
+            continued: self.continued,
+            head_request: self.head_request,
+            sink: self.sink.take(),
+            head_sink: self.head_sink.take(),
+            progress: self.progress.take(),
+            ..Self::default()
+        };
+        std::mem::replace(self, next)
+    }
+
+    /// Check if an interim `100 Continue` response was received since the last call.
+    pub fn take_continue(&mut self) -> bool {
+        std::mem::take(&mut self.continued)
+    }
+
+    /// Get the number of bytes held by the buffers of the assembler.
+    pub fn buffered(&self) -> usize {
+        self.head.capacity()
+            + self.body.capacity()
+            + self.sniff_header.capacity()
+            + self.chunk_line.capacity()
+    }
+
+    /// Parse the status line and headers of a response.
+    fn parse_head(&mut self, head: &str) -> Result<(), ConnectionError> {
+        let mut lines = head.split("\r\n");
+
+        let mut status = lines.next().unwrap_or_default().splitn(3, ' ');
+        self.version = status.next().unwrap_or_default().to_string();
+        self.http_1_0 = self.version == "HTTP/1.0";
+        self.code = status
+            .next()
+            .and_then(|code| code.parse().ok())
+            .ok_or_else(|| ConnectionError {
+                message: "Invalid HTTP status line".to_string(),
+            })?;
+        self.reason = status.next().unwrap_or_default().to_string();
+
+        for line in lines.take_while(|line| !line.is_empty()) {
+            // Values may contain colons themselves, e.g. dates and URLs
+            let (name, value) = line.split_once(':').ok_or_else(|| ConnectionError {
+                message: format!("Invalid HTTP header: {}", line),
+            })?;
+            let name = name.trim();
+            let value = value.trim_matches(|c| c == ' ' || c == '\t');
+            self.has_content_type |= name.eq_ignore_ascii_case("Content-Type");
+            if name.eq_ignore_ascii_case("Content-Length") {
+                self.content_length = value.parse().map_err(|_| ConnectionError {
+                    message: format!("Invalid Content-Length: {}", value),
+                })?;
+            } else if name.eq_ignore_ascii_case("Transfer-Encoding") {
+                // Only the last coding frames the body, e.g. `gzip, chunked`
+                self.chunked = value
+                    .rsplit(',')
+                    .next()
+                    .is_some_and(|coding| coding.trim().eq_ignore_ascii_case("chunked"));
+            } else if name.eq_ignore_ascii_case("Connection") {
+                for token in value.split(',').map(str::trim) {
+                    if token.eq_ignore_ascii_case("close") {
+                        self.keep_alive = Some(false);
+                    } else if token.eq_ignore_ascii_case("keep-alive") {
+                        self.keep_alive.get_or_insert(true);
+                    }
+                }
+            } else if name.eq_ignore_ascii_case("Keep-Alive") {
+                self.keep_alive_timeout = value
+                    .split(',')
+                    .filter_map(|param| param.trim().split_once('='))
+                    .find(|(key, _)| key.trim().eq_ignore_ascii_case("timeout"))
+                    .and_then(|(_, timeout)| timeout.trim().parse().ok());
+            }
+            self.headers.push(HttpHeader::new(name, value));
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, rc::Rc};
+
+    use proptest::{collection::vec, prelude::*, sample::Index};
+
+    use super::*;
+
+    const CONTENT_LENGTH: &[u8] =
+        b"HTTP/1.1 200 OK\r\nContent-Type: text/plain\r\nContent-Length: 11\r\n\r\nhello world";
+
+    const CHUNKED: &[u8] = b"HTTP/1.1 200 OK\r\nTransfer-Encoding: gzip, chunked\r\n\r\n\
+        5;name=value\r\nhello\r\n6\r\n world\r\n0\r\nExpires: never\r\n\r\n";
+
+    const CONTINUED: &[u8] = b"HTTP/1.1 100 Continue\r\n\r\n\
+        HTTP/1.1 201 Created\r\nContent-Length: 11\r\n\r\nhello world";
+
+    /// Push a stream split at the given positions, skipping empty parts.
+    ///
+    /// # Returns
+    ///
+    /// The responses completed by each push, or the first error.
+    fn push_split(
+        assembler: &mut ResponseAssembler,
+        stream: &[u8],
+        splits: &[usize],
+    ) -> Result<Vec<Option<HttpConnectionResponse>>, ConnectionError> {
+        let mut splits = splits.to_vec();
+        splits.sort_unstable();
+        let mut start = 0;
+        let mut pushed = Vec::new();
+        for end in splits.into_iter().chain([stream.len()]) {
+            if end == start {
+                continue;
+            }
+            pushed.push(assembler.push(&stream[start..end])?);
+            start = end;
+        }
+        Ok(pushed)
+    }
+
+    /// Assemble a stream holding a single response split at the given positions, checking
+    /// it completes on the push of its last byte and not before.
+    fn assemble(stream: &[u8], splits: &[usize]) -> HttpConnectionResponse {
+        let mut assembler = ResponseAssembler::new();
+        let mut pushed = push_split(&mut assembler, stream, splits).unwrap();
+        let response = pushed.pop().unwrap().expect("response not completed");
+        assert!(pushed.iter().all(Option::is_none));
+        response
+    }
+
+    fn header(response: &HttpConnectionResponse, name: &str) -> Option<String> {
+        response
+            .get_headers()
+            .iter()
+            .find(|h| h.name().eq_ignore_ascii_case(name))
+            .map(|h| h.value().to_string())
+    }
+
+    /// Encode a body with `Transfer-Encoding: chunked`, in chunks of the given sizes.
+    fn encode_chunked(body: &[u8], sizes: &[usize]) -> Vec<u8> {
+        let mut stream = b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n".to_vec();
+        let mut rest = body;
+        for size in sizes.iter().copied().chain([rest.len()]) {
+            let (chunk, next) = rest.split_at(size.min(rest.len()));
+            if !chunk.is_empty() {
+                stream.extend_from_slice(format!("{:x}\r\n", chunk.len()).as_bytes());
+                stream.extend_from_slice(chunk);
+                stream.extend_from_slice(b"\r\n");
+            }
+            rest = next;
+        }
+        stream.extend_from_slice(b"0\r\n\r\n");
+        stream
+    }
+
+    #[test]
+    fn content_length_split_anywhere() {
+        for split in 0..=CONTENT_LENGTH.len() {
+            let mut response = assemble(CONTENT_LENGTH, &[split]);
+            assert_eq!(response.get_code(), 200);
+            assert_eq!(
+                header(&response, "Content-Type").as_deref(),
+                Some("text/plain")
+            );
+            assert_eq!(response.take_body().unwrap(), b"hello world");
+        }
+    }
+
+    #[test]
+    fn chunked_split_anywhere() {
+        for split in 0..=CHUNKED.len() {
+            let mut response = assemble(CHUNKED, &[split]);
+            assert_eq!(response.get_code(), 200);
+            assert_eq!(response.take_body().unwrap(), b"hello world");
+        }
+    }
+
+    #[test]
+    fn byte_by_byte() {
+        for stream in [CONTENT_LENGTH, CHUNKED, CONTINUED] {
+            let splits: Vec<usize> = (1..stream.len()).collect();
+            let mut response = assemble(stream, &splits);
+            assert_eq!(response.take_body().unwrap(), b"hello world");
+        }
+    }
+
+    #[test]
+    fn interim_response_split_anywhere() {
+        for split in 0..=CONTINUED.len() {
+            let mut assembler = ResponseAssembler::new();
+            let mut pushed = push_split(&mut assembler, CONTINUED, &[split]).unwrap();
+            let mut response = pushed.pop().unwrap().unwrap();
+            assert_eq!(response.get_code(), 201);
+            assert_eq!(response.take_body().unwrap(), b"hello world");
+            assert!(assembler.take_continue());
+        }
+    }
+
+    #[test]
+    fn empty_body() {
+        let mut response = assemble(b"HTTP/1.1 204 No Content\r\n\r\n", &[]);
+        assert_eq!(response.get_code(), 204);
+        assert_eq!(response.take_body().unwrap(), b"");
+    }
+
+    #[test]
+    fn next_response_after_chunked() {
+        let mut stream = CHUNKED.to_vec();
+        let split = stream.len();
+        stream.extend_from_slice(CONTENT_LENGTH);
+        let mut assembler = ResponseAssembler::new();
+        let pushed = push_split(&mut assembler, &stream, &[split]).unwrap();
+        for response in pushed {
+            assert_eq!(response.unwrap().take_body().unwrap(), b"hello world");
+        }
+    }
+
+    #[test]
+    fn responses_without_body() {
+        let heads: [(&str, &[u8]); 4] = [
+            ("HEAD", CONTENT_LENGTH.split_at(65).0),
+            ("HEAD", CHUNKED.split_at(53).0),
+            (
+                "GET",
+                b"HTTP/1.1 204 No Content\r\nContent-Length: 11\r\n\r\n",
+            ),
+            (
+                "GET",
+                b"HTTP/1.1 304 Not Modified\r\nContent-Length: 11\r\n\r\n",
+            ),
+        ];
+        for (method, head) in heads {
+            let mut assembler = ResponseAssembler::new().with_method(method);
+            let mut response = assembler
+                .push(head)
+                .unwrap()
+                .expect("response not completed");
+            assert_eq!(response.take_body().unwrap(), b"");
+        }
+    }
+
+    #[test]
+    fn bytes_after_the_body_start_the_next_response() {
+        for (first, second) in [(CONTENT_LENGTH, CHUNKED), (CHUNKED, CONTENT_LENGTH)] {
+            let mut stream = first.to_vec();
+            stream.extend_from_slice(second);
+            let mut assembler = ResponseAssembler::new();
+            let mut responses = vec![assembler.push(&stream).unwrap().unwrap()];
+            responses.push(assembler.push(&[]).unwrap().unwrap());
+            for mut response in responses {
+                assert_eq!(response.take_body().unwrap(), b"hello world");
+            }
+            assert!(assembler.push(&[]).unwrap().is_none());
+        }
+    }
+
+    #[test]
+    fn body_sink_split_anywhere() {
+        for stream in [CONTENT_LENGTH, CHUNKED] {
+            for split in 0..=stream.len() {
+                let body = Rc::new(RefCell::new(Vec::new()));
+                let sunk = body.clone();
+                let mut assembler = ResponseAssembler::with_body_sink(Box::new(move |chunk| {
+                    sunk.borrow_mut().extend_from_slice(chunk)
+                }));
+                let mut pushed = push_split(&mut assembler, stream, &[split]).unwrap();
+                let mut response = pushed.pop().unwrap().unwrap();
+                assert!(response.take_body().is_none());
+                assert_eq!(*body.borrow(), b"hello world");
+            }
+        }
+    }
+
+    #[test]
+    fn invalid_chunked_bodies() {
+        let head = b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n";
+        let long_line = [b'1'; MAX_CHUNK_LINE + 1];
+        let bodies: [&[u8]; 3] = [b"zz\r\n", b"2\r\nabc\r\n", &long_line];
+        for body in bodies {
+            let mut stream = head.to_vec();
+            stream.extend_from_slice(body);
+            let splits: Vec<usize> = (1..stream.len()).collect();
+            assert!(push_split(&mut ResponseAssembler::new(), &stream, &splits).is_err());
+        }
+    }
+
+    #[test]
+    fn head_too_long() {
+        let mut stream = b"HTTP/1.1 200 OK\r\nX-Padding: ".to_vec();
+        stream.resize(MAX_HEAD_SIZE + 1, b'a');
+        let splits: Vec<usize> = (1..stream.len()).step_by(1024).collect();
+        let mut assembler = ResponseAssembler::new();
+        assert!(push_split(&mut assembler, &stream, &splits).is_err());
+
+        // Nor when the end of the head comes in the same chunk
+        stream.extend_from_slice(b"\r\n\r\n");
+        assert!(assembler.push(&stream).is_err());
+    }
+
+    proptest! {
+        #[test]
+        fn arbitrary_splits(splits in vec(any::<Index>(), 0..32)) {
+            for stream in [CONTENT_LENGTH, CHUNKED, CONTINUED] {
+                let splits: Vec<usize> = splits.iter().map(|s| s.index(stream.len() + 1)).collect();
+                let mut response = assemble(stream, &splits);
+                prop_assert_eq!(response.take_body().unwrap(), b"hello world");
+            }
+        }
+
+        #[test]
+        fn consecutive_responses(splits in vec(any::<Index>(), 0..32)) {
+            let stream = [CONTENT_LENGTH, CHUNKED, CONTINUED, CONTENT_LENGTH].concat();
+            let splits: Vec<usize> = splits.iter().map(|s| s.index(stream.len() + 1)).collect();
+            let mut assembler = ResponseAssembler::new();
+            let mut responses: Vec<_> = push_split(&mut assembler, &stream, &splits)
+                .unwrap()
+                .into_iter()
+                .flatten()
+                .collect();
+            // Responses completed by the same push come out with the following ones
+            while let Some(response) = assembler.push(&[]).unwrap() {
+                responses.push(response);
+            }
+            prop_assert_eq!(responses.len(), 4);
+            for mut response in responses {
+                prop_assert_eq!(response.take_body().unwrap(), b"hello world");
+            }
+        }
+
+        #[test]
+        fn arbitrary_chunked_bodies(
+            body in vec(any::<u8>(), 0..1024),
+            sizes in vec(1..64usize, 0..32),
+            splits in vec(any::<Index>(), 0..32),
+        ) {
+            let stream = encode_chunked(&body, &sizes);
+            let splits: Vec<usize> = splits.iter().map(|s| s.index(stream.len() + 1)).collect();
+            let mut response = assemble(&stream, &splits);
+            prop_assert_eq!(response.take_body().unwrap(), body);
+        }
+
+        #[test]
+        fn arbitrary_bytes(
+            head in prop_oneof![Just(&b""[..]), Just(&CONTENT_LENGTH[..65]), Just(&CHUNKED[..53])],
+            bytes in vec(any::<u8>(), 0..1024),
+            splits in vec(any::<Index>(), 0..32),
+        ) {
+            // Garbage after a valid head or none must fail or wait, never panic
+            let mut stream = head.to_vec();
+            stream.extend_from_slice(&bytes);
+            let splits: Vec<usize> = splits.iter().map(|s| s.index(stream.len() + 1)).collect();
+            let _ = push_split(&mut ResponseAssembler::new(), &stream, &splits);
+        }
+    }
+}
@@ -0,0 +1,676 @@
+use std::{
+    cell::{Cell, RefCell},
+    collections::VecDeque,
+    fmt,
+    rc::Rc,
+};
+
+use wasm_bindgen::prelude::*;
+use wasm_bindgen_futures::{
+    future_to_promise,
+    js_sys::{self, ArrayBuffer, Promise, Uint8Array},
+    JsFuture,
+};
+use web_sys::{MessageEvent, WebSocket};
+
+use crate::{
+    batch::{settle_on, sleep},
+    callbacks::ProgressCallback,
+    connection::{Connection, ConnectionError},
+    progress::{Progress, ProgressDirection, ProgressReporter},
+};
+
+use super::tcp::{as_uint8_array, collect, Collected, DialedConnection, TcpDialer};
+
+/// Default port of FTP control connections.
+pub const DEFAULT_FTP_PORT: u16 = 21;
+/// Size of the slices an upload is sent in, in bytes.
+const SLICE_SIZE: u32 = 64 * 1024;
+/// Number of bytes the data socket may have queued before the next slice is sent.
+const MAX_BUFFERED: u32 = 4 * 64 * 1024;
+/// Time to wait for the data socket to drain its queue, in ms.
+const DRAIN_INTERVAL: f64 = 10.0;
+
+/// Get the address of an FTP server, as `host:port`.
+///
+/// # Arguments
+///
+/// * `addr` - Address of the server, either `host:port` or a `ftp://` url, port 21 if none
+///
+/// # Returns
+///
+/// The address, or `None` if it has no host.
+pub fn ftp_addr(addr: &str) -> Option<String> {
+    let addr = addr.strip_prefix("ftp://").unwrap_or(addr);
+    // Credentials and path of urls are not part of the address
+    let addr = addr.split('/').next().unwrap_or_default();
+    let addr = addr.rsplit('@').next().unwrap_or_default();
+    if addr.is_empty() || addr.starts_with(':') {
+        return None;
+    }
+    let has_port = match addr.rsplit_once(':') {
+        Some((host, _)) => !host.contains(':') || host.ends_with(']'),
+        None => false,
+    };
+    if has_port {
+        Some(addr.to_string())
+    } else {
+        Some(format!("{}:{}", addr, DEFAULT_FTP_PORT))
+    }
+}
+
+/// Reply of an FTP server to a command.
+#[derive(Clone, Debug)]
+struct Reply {
+    /// Three digit reply code, 0 if the server sent none
+    code: u16,
+    /// Text of the reply, lines joined with `\n`
+    text: String,
+}
+
+impl Reply {
+    /// Check if the reply announces that the command was started, e.g. `150`.
+    fn is_preliminary(&self) -> bool {
+        (100..200).contains(&self.code)
+    }
+
+    /// Check if the reply announces that the command completed, e.g. `226`.
+    fn is_completion(&self) -> bool {
+        (200..300).contains(&self.code)
+    }
+
+    /// Get the error of a command the server refused with this reply.
+    fn error(&self, command: &str) -> ConnectionError {
+        ConnectionError {
+            message: format!("FTP {} failed: {}", command, self),
+        }
+    }
+}
+
+impl fmt::Display for Reply {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {}", self.code, self.text)
+    }
+}
+
+/// Take the first complete reply out of the bytes received on a control connection.
+///
+/// A reply is either a single line `123 text`, or lines from `123-text` to `123 text`.
+///
+/// # Returns
+///
+/// The reply, or `None` if the bytes don't hold a complete one yet.
+fn take_reply(buffer: &mut Vec<u8>) -> Option<Reply> {
+    let mut lines = Vec::new();
+    let mut start = 0;
+    let mut code = None;
+    while let Some(end) = buffer[start..].iter().position(|&b| b == b'\n') {
+        let line = &buffer[start..start + end];
+        let line = line.strip_suffix(b"\r").unwrap_or(line);
+        let line = String::from_utf8_lossy(line).into_owned();
+        start += end + 1;
+
+        let prefix = line
+            .get(..3)
+            .filter(|p| p.bytes().all(|b| b.is_ascii_digit()));
+        let code = match code {
+            Some(code) => code,
+            None => {
+                let first = prefix.and_then(|p| p.parse::<u16>().ok()).unwrap_or(0);
+                code = Some(first);
+                // Only a first line with a code and a dash opens a multi-line reply
+                if first == 0 || line.as_bytes().get(3) != Some(&b'-') {
+                    let text = line.get(4..).unwrap_or_default().to_string();
+                    buffer.drain(..start);
+                    return Some(Reply { code: first, text });
+                }
+                lines.push(line[4..].to_string());
+                continue;
+            }
+        };
+        let last = prefix.is_some_and(|p| p.parse() == Ok(code))
+            && matches!(line.as_bytes().get(3), None | Some(b' '));
+        if last {
+            lines.push(line.get(4..).unwrap_or_default().to_string());
+            buffer.drain(..start);
+            return Some(Reply {
+                code,
+                text: lines.join("\n"),
+            });
+        }
+        lines.push(line);
+    }
+    None
+}
+
+/// Parse the address of a `227` reply to `PASV`, `(h1,h2,h3,h4,p1,p2)`.
+///
+/// # Returns
+///
+/// The host and port of the address, or `None` if the reply has none.
+fn parse_pasv(text: &str) -> Option<(String, u16)> {
+    text.split(|c: char| !c.is_ascii_digit() && c != ',')
+        .find_map(|group| {
+            let numbers = group
+                .split(',')
+                .map(|n| n.parse::<u8>().ok())
+                .collect::<Option<Vec<_>>>()?;
+            match numbers[..] {
+                [h1, h2, h3, h4, p1, p2] => Some((
+                    format!("{}.{}.{}.{}", h1, h2, h3, h4),
+                    u16::from_be_bytes([p1, p2]),
+                )),
+                _ => None,
+            }
+        })
+}
+
+/// Check that an argument of a command can't end it early and inject another command.
+fn check_argument(argument: &str) -> Result<(), ConnectionError> {
+    if argument.contains(['\r', '\n']) {
+        return Err(ConnectionError {
+            message: format!("FTP argument contains a line break: {:?}", argument),
+        });
+    }
+    Ok(())
+}
+
+/// Collect every byte received on a data connection until it closes, reporting the progress.
+///
+/// # Arguments
+///
+/// * `data` - Data connection of the transfer
+/// * `progress` - Reporter of the progress of the transfer
+/// * `total` - Number of bytes to receive, if known
+fn collect_data(
+    data: &DialedConnection,
+    progress: ProgressReporter,
+    total: Option<usize>,
+) -> Collected {
+    collect(data.socket(), move |loaded| {
+        progress.report(match total {
+            Some(total) => Progress::new(ProgressDirection::Download, loaded, total),
+            None => Progress::indeterminate(ProgressDirection::Download, loaded),
+        });
+    })
+}
+
+/// Replies received on a control connection.
+#[derive(Default)]
+struct Control {
+    /// Bytes received, not yet parsed into a reply
+    buffer: Vec<u8>,
+    /// Replies received, not yet waited for
+    replies: VecDeque<Reply>,
+    /// Function waking the task waiting for the next reply, if any
+    waiting: Option<js_sys::Function>,
+    /// Whether the control connection is closed
+    closed: bool,
+}
+
+/// State of an FTP connection, shared by its API and the operations in progress.
+struct FtpSession {
+    /// Control connection
+    connection: Connection,
+    /// Replies of the control connection
+    control: Rc<RefCell<Control>>,
+    /// Handles to open data connections with
+    dialer: TcpDialer,
+    /// Whether the greeting of the server was received
+    greeted: Cell<bool>,
+    /// Whether an operation is in progress
+    busy: Cell<bool>,
+    /// Whether data connections are made to the host given in `PASV` replies, instead of the
+    /// host of the control connection
+    use_pasv_host: Cell<bool>,
+}
+
+impl FtpSession {
+    /// Wait for the next reply of the server.
+    async fn reply(&self) -> Result<Reply, JsValue> {
+        loop {
+            {
+                let mut control = self.control.borrow_mut();
+                if let Some(reply) = control.replies.pop_front() {
+                    return Ok(reply);
+                }
+                if control.closed {
+                    return Err(ConnectionError {
+                        message: "FTP control connection closed".to_string(),
+                    }
+                    .into());
+                }
+            }
+            let wake = Promise::new(&mut |resolve, _| {
+                self.control.borrow_mut().waiting = Some(resolve);
+            });
+            JsFuture::from(wake).await?;
+        }
+    }
+
+    /// Send a command, then wait for its reply.
+    async fn command(&self, command: &str) -> Result<Reply, JsValue> {
+        if self.control.borrow().closed {
+            return Err(ConnectionError {
+                message: "FTP control connection closed".to_string(),
+            }
+            .into());
+        }
+        self.connection
+            .sender()
+            .send(format!("{}\r\n", command).as_bytes())?;
+        self.reply().await
+    }
+
+    /// Send a command that must complete, e.g. `TYPE I`.
+    async fn expect_completion(&self, command: &str) -> Result<Reply, JsValue> {
+        let reply = self.command(command).await?;
+        if !reply.is_completion() {
+            let verb = command.split(' ').next().unwrap_or_default();
+            return Err(reply.error(verb).into());
+        }
+        Ok(reply)
+    }
+
+    /// Wait for the greeting of the server, once.
+    async fn greet(&self) -> Result<(), JsValue> {
+        if self.greeted.get() {
+            return Ok(());
+        }
+        loop {
+            let reply = self.reply().await?;
+            match reply.code {
+                // Service ready in some minutes, followed by another greeting
+                120 => continue,
+                220 => break,
+                _ => return Err(reply.error("connection").into()),
+            }
+        }
+        self.greeted.set(true);
+        Ok(())
+    }
+
+    /// Log in with a user and password.
+    async fn login(&self, user: &str, password: &str) -> Result<JsValue, JsValue> {
+        self.greet().await?;
+        let mut reply = self.command(&format!("USER {}", user)).await?;
+        if reply.code == 331 {
+            reply = self.command(&format!("PASS {}", password)).await?;
+        }
+        if !reply.is_completion() {
+            return Err(reply.error("login").into());
+        }
+        Ok(JsValue::UNDEFINED)
+    }
+
+    /// Enter passive mode and open the data connection to the port the server listens on.
+    async fn open_data(&self) -> Result<DialedConnection, JsValue> {
+        let reply = self.command("PASV").await?;
+        if reply.code != 227 {
+            return Err(reply.error("PASV").into());
+        }
+        let (host, port) = parse_pasv(&reply.text).ok_or_else(|| ConnectionError {
+            message: format!("Invalid PASV reply: {}", reply),
+        })?;
+        // Servers behind NAT give their private address, so the control host is used instead
+        let host = if self.use_pasv_host.get() {
+            host
+        } else {
+            let addr = &self.connection.addr;
+            addr.rsplit_once(':')
+                .map_or(addr.as_str(), |(host, _)| host)
+                .to_string()
+        };
+        let data = self.dialer.open(format!("{}:{}", host, port))?;
+        JsFuture::from(settle_on(
+            data.socket(),
+            Some("open"),
+            "FTP data connection failed to open",
+        ))
+        .await?;
+        Ok(data)
+    }
+
+    /// Receive the data a command sends over a new data connection.
+    ///
+    /// # Arguments
+    ///
+    /// * `command` - Command sending the data, e.g. `RETR file`
+    /// * `progress` - Reporter of the progress of the transfer
+    /// * `total` - Number of bytes to receive, if known
+    async fn receive(
+        &self,
+        command: &str,
+        progress: ProgressReporter,
+        total: Option<usize>,
+    ) -> Result<Vec<u8>, JsValue> {
+        let data = self.open_data().await?;
+        // Listened to before the command, as the server may send everything right away
+        let closed = settle_on(data.socket(), Some("close"), "FTP data connection failed");
+        let received = collect_data(&data, progress, total);
+
+        let verb = command.split(' ').next().unwrap_or_default();
+        let reply = self.command(command).await?;
+        if !reply.is_preliminary() {
+            return Err(reply.error(verb).into());
+        }
+        JsFuture::from(closed).await?;
+        let reply = self.reply().await?;
+        if !reply.is_completion() {
+            return Err(reply.error(verb).into());
+        }
+        Ok(received.take())
+    }
+
+    /// List the contents of a directory.
+    async fn list(&self, path: Option<String>) -> Result<JsValue, JsValue> {
+        self.expect_completion("TYPE A").await?;
+        let command = match path {
+            Some(path) => format!("LIST {}", path),
+            None => "LIST".to_string(),
+        };
+        let listing = self
+            .receive(&command, ProgressReporter::default(), None)
+            .await?;
+        Ok(String::from_utf8_lossy(&listing).into_owned().into())
+    }
+
+    /// Download a file.
+    async fn download(&self, path: String, progress: ProgressReporter) -> Result<JsValue, JsValue> {
+        self.expect_completion("TYPE I").await?;
+        // The size is only known if the server supports `SIZE`
+        let size = self.command(&format!("SIZE {}", path)).await?;
+        let total = match size.code {
+            213 => size.text.trim().parse::<usize>().ok(),
+            _ => None,
+        };
+        let body = self
+            .receive(&format!("RETR {}", path), progress, total)
+            .await?;
+        Ok(Uint8Array::from(body.as_slice()).into())
+    }
+
+    /// Upload a file.
+    async fn upload(
+        &self,
+        path: String,
+        body: Uint8Array,
+        progress: ProgressReporter,
+    ) -> Result<JsValue, JsValue> {
+        self.expect_completion("TYPE I").await?;
+        let data = self.open_data().await?;
+        let reply = self.command(&format!("STOR {}", path)).await?;
+        if !reply.is_preliminary() {
+            return Err(reply.error("STOR").into());
+        }
+
+        let socket = data.socket().clone();
+        let sender = data.connection.sender();
+        let total = body.length();
+        let mut offset = 0;
+        while offset < total {
+            while socket.buffered_amount() > MAX_BUFFERED {
+                if socket.ready_state() != WebSocket::OPEN {
+                    return Err(ConnectionError {
+                        message: "FTP data connection closed during upload".to_string(),
+                    }
+                    .into());
+                }
+                sleep(DRAIN_INTERVAL).await?;
+            }
+            let end = (offset + SLICE_SIZE).min(total);
+            sender.send_array(&body.subarray(offset, end))?;
+            offset = end;
+            progress.report(Progress::new(
+                ProgressDirection::Upload,
+                offset as usize,
+                total as usize,
+            ));
+        }
+        // The end of the file is the end of the data connection, queued bytes are sent first
+        let _ = socket.close();
+
+        let reply = self.reply().await?;
+        if !reply.is_completion() {
+            return Err(reply.error("STOR").into());
+        }
+        Ok(JsValue::UNDEFINED)
+    }
+
+    /// End the session and close the control connection.
+    async fn quit(&self) -> Result<JsValue, JsValue> {
+        let reply = self.command("QUIT").await;
+        self.connection.close();
+        reply?;
+        Ok(JsValue::UNDEFINED)
+    }
+}
+
+/// API of an FTP connection, transferring files in passive mode.
+///
+/// Operations run one at a time: starting one while another is in progress fails.
+#[wasm_bindgen]
+pub struct FtpConnectionApi {
+    /// State of the connection
+    session: Rc<FtpSession>,
+}
+
+impl FtpConnectionApi {
+    /// Create a new API instance for the given control connection.
+    ///
+    /// # Arguments
+    ///
+    /// * `connection` - Control connection, to the FTP server
+    /// * `dialer` - Handles to open data connections with
+    pub fn new(connection: Connection, dialer: TcpDialer) -> Self {
+        let control = Rc::new(RefCell::new(Control::default()));
+
+        let target = control.clone();
+        let message_callback: Closure<dyn Fn(MessageEvent)> =
+            Closure::wrap(Box::new(move |evt: MessageEvent| {
+                let buffer = match evt.data().dyn_into::<ArrayBuffer>() {
+                    Ok(buffer) => buffer,
+                    Err(_) => return,
+                };
+                let waiting = {
+                    let mut control = target.borrow_mut();
+                    let control = &mut *control;
+                    control.buffer.extend(Uint8Array::new(&buffer).to_vec());
+                    while let Some(reply) = take_reply(&mut control.buffer) {
+                        control.replies.push_back(reply);
+                    }
+                    match control.replies.is_empty() {
+                        true => None,
+                        false => control.waiting.take(),
+                    }
+                };
+                if let Some(wake) = waiting {
+                    let _ = wake.call0(&JsValue::null());
+                }
+            }));
+        connection
+            .socket
+            .add_event_listener_with_callback("message", message_callback.as_ref().unchecked_ref())
+            .unwrap_throw();
+        message_callback.forget();
+
+        let target = control.clone();
+        let close_callback = Closure::once_into_js(move || {
+            let waiting = {
+                let mut control = target.borrow_mut();
+                control.closed = true;
+                control.waiting.take()
+            };
+            if let Some(wake) = waiting {
+                let _ = wake.call0(&JsValue::null());
+            }
+        });
+        connection
+            .socket
+            .add_event_listener_with_callback("close", close_callback.unchecked_ref())
+            .unwrap_throw();
+
+        Self {
+            session: Rc::new(FtpSession {
+                connection,
+                control,
+                dialer,
+                greeted: Cell::new(false),
+                busy: Cell::new(false),
+                use_pasv_host: Cell::new(false),
+            }),
+        }
+    }
+
+    /// Run an operation, unless another one is in progress.
+    fn run<F>(&self, operation: impl FnOnce(Rc<FtpSession>) -> F) -> Promise
+    where
+        F: std::future::Future<Output = Result<JsValue, JsValue>> + 'static,
+    {
+        if self.session.busy.replace(true) {
+            return Promise::reject(&JsValue::from(ConnectionError {
+                message: "Another FTP operation is in progress".to_string(),
+            }));
+        }
+        let session = self.session.clone();
+        let operation = operation(session.clone());
+        future_to_promise(async move {
+            let result = operation.await;
+            session.busy.set(false);
+            result
+        })
+    }
+}
+
+#[wasm_bindgen]
+impl FtpConnectionApi {
+    /// Get the address of this connection.
+    #[wasm_bindgen]
+    pub fn get_addr(&self) -> String {
+        self.session.connection.addr.clone()
+    }
+
+    /// Get the ID of this connection.
+    #[wasm_bindgen]
+    pub fn get_id(&self) -> u64 {
+        self.session.connection.get_id().into()
+    }
+
+    /// Get the UUID of this connection, if made with `ConnIdScheme.UuidV7`.
+    #[wasm_bindgen]
+    pub fn get_uuid(&self) -> Option<String> {
+        self.session.connection.get_id().uuid_string()
+    }
+
+    /// Set whether data connections are made to the host given in replies to `PASV`.
+    ///
+    /// By default they are made to the host of the control connection, as servers behind NAT
+    /// give their private address.
+    #[wasm_bindgen]
+    pub fn set_use_pasv_host(&self, use_pasv_host: bool) {
+        self.session.use_pasv_host.set(use_pasv_host);
+    }
+
+    /// Log in to the server.
+    ///
+    /// # Arguments
+    ///
+    /// * `user` - Name of the user, `anonymous` if `None`
+    /// * `password` - Password of the user, sent if the server asks for one
+    ///
+    /// # Returns
+    ///
+    /// A promise resolving once logged in, or rejecting with a `ConnectionError`.
+    #[wasm_bindgen]
+    pub fn login(&self, user: Option<String>, password: Option<String>) -> Promise {
+        let user = user.unwrap_or_else(|| "anonymous".to_string());
+        let password = password.unwrap_or_default();
+        if let Err(e) = check_argument(&user).and(check_argument(&password)) {
+            return Promise::reject(&e.into());
+        }
+        self.run(|session| async move { session.login(&user, &password).await })
+    }
+
+    /// List the contents of a directory, in the format of the server.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Path of the directory, the current one if `None`
+    ///
+    /// # Returns
+    ///
+    /// A promise resolving to the listing, or rejecting with a `ConnectionError`.
+    #[wasm_bindgen]
+    pub fn list(&self, path: Option<String>) -> Promise {
+        if let Some(Err(e)) = path.as_deref().map(check_argument) {
+            return Promise::reject(&e.into());
+        }
+        self.run(|session| async move { session.list(path).await })
+    }
+
+    /// Download a file.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Path of the file
+    /// * `onprogress` - Function called with a `Progress` as the file is received
+    ///
+    /// # Returns
+    ///
+    /// A promise resolving to the content of the file as a `Uint8Array`, or rejecting with a
+    /// `ConnectionError`.
+    #[wasm_bindgen]
+    pub fn download(&self, path: String, onprogress: Option<ProgressCallback>) -> Promise {
+        if let Err(e) = check_argument(&path) {
+            return Promise::reject(&e.into());
+        }
+        let progress = ProgressReporter::default();
+        progress.set(onprogress.map(Into::into));
+        self.run(|session| async move { session.download(path, progress).await })
+    }
+
+    /// Upload a file, replacing it if it exists.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Path of the file
+    /// * `data` - Content of the file, either a `Uint8Array` or an `ArrayBuffer`
+    /// * `onprogress` - Function called with a `Progress` as the file is sent
+    ///
+    /// # Returns
+    ///
+    /// A promise resolving once the server stored the file, or rejecting with a
+    /// `ConnectionError`.
+    #[wasm_bindgen]
+    pub fn upload(
+        &self,
+        path: String,
+        data: &JsValue,
+        onprogress: Option<ProgressCallback>,
+    ) -> Promise {
+        let body = match check_argument(&path).and_then(|_| as_uint8_array(data)) {
+            Ok(body) => body,
+            Err(e) => return Promise::reject(&e.into()),
+        };
+        let progress = ProgressReporter::default();
+        progress.set(onprogress.map(Into::into));
+        self.run(|session| async move { session.upload(path, body, progress).await })
+    }
+
+    /// End the session with `QUIT`, then close this connection.
+    ///
+    /// # Returns
+    ///
+    /// A promise resolving once the server acknowledged, or rejecting with a
+    /// `ConnectionError`.
+    #[wasm_bindgen]
+    pub fn quit(&self) -> Promise {
+        self.run(|session| async move { session.quit().await })
+    }
+
+    /// Close this connection, failing the operation in progress.
+    #[wasm_bindgen]
+    pub fn close(&self) {
+        self.session.connection.close();
+    }
+}
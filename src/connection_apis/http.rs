@@ -1,21 +1,130 @@
-use std::sync::{Arc, Mutex};
+use std::{
+    cell::{Cell, RefCell},
+    rc::Rc,
+    sync::{Arc, Mutex},
+};
 
 use wasm_bindgen::prelude::*;
 use wasm_bindgen_futures::js_sys::{self, ArrayBuffer, Uint8Array};
-use web_sys::{AddEventListenerOptions, MessageEvent};
+use web_sys::{
+    AddEventListenerOptions, Blob, BlobPropertyBag, FormData, MessageEvent, ReadableStream, Url,
+    WebSocket,
+};
 
 use crate::{
-    connection::{Connection, ConnectionError},
-    console_log, http,
+    callbacks::{HttpResponseCallback, ProgressCallback},
+    connection::{Connection, ConnectionError, KeepAlive},
+    console_log,
+    disposition::ContentDisposition,
+    http,
+    middleware::Pipeline,
+    pending::{release_listener, ConnectionClosed, PendingRequest},
+    progress::{Progress, ProgressDirection},
+    redirect::origin,
+    set_timeout,
+    stats::StatsRecorder,
+    timing::{RequestTimer, RequestTiming},
+    worker::{get, get_bytes, message, to_buffer},
+};
+
+use super::{
+    assembler::{BodySink, ProgressSink, ResponseAssembler},
+    idb::{IdbBody, IdbDownload},
+    stream::{decode_text_stream, StreamBody},
+    upload::BlobUpload,
 };
 
+/// Upper bound of the body capacity reserved up front from a `Content-Length` header.
+pub(crate) const MAX_BODY_PREALLOCATION: usize = 16 * 1024 * 1024;
+
+/// Time to wait for a `100 Continue` response before sending the body anyway, in ms.
+const EXPECT_CONTINUE_TIMEOUT: f64 = 1000.0;
+
+/// Header names common enough to be shared between all headers using them.
+const COMMON_HEADER_NAMES: &[&str] = &[
+    "Accept",
+    "Accept-Encoding",
+    "Accept-Language",
+    "Accept-Ranges",
+    "Age",
+    "Cache-Control",
+    "Connection",
+    "Content-Encoding",
+    "Content-Language",
+    "Content-Length",
+    "Content-Type",
+    "Date",
+    "ETag",
+    "Expires",
+    "Host",
+    "Keep-Alive",
+    "Last-Modified",
+    "Location",
+    "Server",
+    "Set-Cookie",
+    "Transfer-Encoding",
+    "User-Agent",
+    "Vary",
+];
+
+/// Headers describing the body of a response, kept from the cached response when merging
+/// a `304 Not Modified`.
+const BODY_HEADERS: &[&str] = &[
+    "Content-Length",
+    "Content-Encoding",
+    "Content-Range",
+    "Transfer-Encoding",
+];
+
+thread_local! {
+    /// Interned common header names.
+    static INTERNED_HEADER_NAMES: Vec<Arc<str>> =
+        COMMON_HEADER_NAMES.iter().map(|name| Arc::from(*name)).collect();
+}
+
+/// Get a shared header name, reusing the interned one if the name is common.
+fn intern_header_name(name: &str) -> Arc<str> {
+    INTERNED_HEADER_NAMES.with(|names| {
+        names
+            .iter()
+            .find(|interned| interned.as_ref() == name)
+            .cloned()
+            .unwrap_or_else(|| Arc::from(name))
+    })
+}
+
 #[derive(Clone, Debug)]
 #[wasm_bindgen]
 pub struct HttpHeader {
     /// Header name
-    pub(crate) name: String,
+    pub(crate) name: Arc<str>,
     /// Header value
-    pub(crate) value: String,
+    pub(crate) value: Arc<str>,
+}
+
+impl HttpHeader {
+    /// Create a new HTTP header from borrowed parts.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - Header name
+    /// * `value` - Header value
+    pub(crate) fn new(name: &str, value: &str) -> Self {
+        Self {
+            name: intern_header_name(name),
+            value: Arc::from(value),
+        }
+    }
+
+    /// Get the header name without copying it.
+    pub(crate) fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Get the header value without copying it.
+    pub(crate) fn value(&self) -> &str {
+        &self.value
+    }
 }
 
 #[wasm_bindgen]
@@ -28,7 +137,7 @@ impl HttpHeader {
     /// * `value` - Header value
     #[wasm_bindgen]
     pub fn of(name: String, value: String) -> Self {
-        Self { name, value }
+        Self::new(&name, &value)
     }
 
     /// Get the header name.
@@ -36,7 +145,7 @@ impl HttpHeader {
     /// The header name.
     #[wasm_bindgen]
     pub fn get_name(&self) -> String {
-        self.name.clone()
+        self.name.to_string()
     }
 
     /// Get the header value.
@@ -44,20 +153,31 @@ impl HttpHeader {
     /// The header value.
     #[wasm_bindgen]
     pub fn get_value(&self) -> String {
-        self.value.clone()
+        self.value.to_string()
     }
 }
 
 #[wasm_bindgen]
+#[derive(Clone)]
 pub struct HttpConnectionRequest {
     /// Request method
-    method: String,
+    pub(crate) method: String,
     /// Request path
-    path: String,
+    pub(crate) path: String,
     /// Request headers
-    headers: Vec<HttpHeader>,
+    pub(crate) headers: Vec<HttpHeader>,
     /// Request body
-    body: Option<Vec<u8>>,
+    pub(crate) body: Option<Vec<u8>>,
+    /// Correlation ID of the request, if one was assigned
+    pub(crate) request_id: Option<String>,
+    /// Name of the client sending the request, if any, set when sent
+    pub(crate) client_name: Option<String>,
+    /// Labels of the connection sending the request, set when sent
+    pub(crate) tags: Vec<String>,
+    /// Origin of the server the request is sent to, set when sent
+    pub(crate) target: Option<String>,
+    /// Time to wait for the response in ms, if limited
+    pub(crate) timeout: Option<f64>,
 }
 
 #[wasm_bindgen]
@@ -81,18 +201,296 @@ impl HttpConnectionRequest {
             path,
             headers,
             body,
+            request_id: None,
+            client_name: None,
+            tags: Vec::new(),
+            target: None,
+            timeout: None,
         }
     }
+
+    /// Create a new HTTP request with headers given as a plain object.
+    ///
+    /// # Arguments
+    ///
+    /// * `method` - Request method
+    /// * `path` - Request path
+    /// * `headers` - Object mapping header names to values
+    /// * `body` - Request body
+    #[wasm_bindgen]
+    pub fn with_header_object(
+        method: String,
+        path: String,
+        headers: &js_sys::Object,
+        body: Option<Vec<u8>>,
+    ) -> Self {
+        let headers = js_sys::Object::entries(headers)
+            .iter()
+            .map(|entry| {
+                let entry = js_sys::Array::from(&entry);
+                HttpHeader::new(
+                    &entry.get(0).as_string().unwrap_or_default(),
+                    &entry.get(1).as_string().unwrap_or_default(),
+                )
+            })
+            .collect();
+        Self::new(method, path, headers, body)
+    }
+
+    /// Get the standard request methods, e.g. `GET` or `PATCH`.
+    ///
+    /// Extension methods like `PROPFIND` may be sent as well, as long as they are a valid token.
+    #[wasm_bindgen]
+    pub fn get_standard_methods() -> Vec<String> {
+        STANDARD_METHODS.iter().map(|m| m.to_string()).collect()
+    }
+
+    /// Get the request method.
+    #[wasm_bindgen]
+    pub fn get_method(&self) -> String {
+        self.method.clone()
+    }
+
+    /// Get the request path.
+    #[wasm_bindgen]
+    pub fn get_path(&self) -> String {
+        self.path.clone()
+    }
+
+    /// Get the request headers.
+    #[wasm_bindgen]
+    pub fn get_headers(&self) -> Vec<HttpHeader> {
+        self.headers.clone()
+    }
+
+    /// Set a request header, replacing every header of the same name.
+    #[wasm_bindgen]
+    pub fn set_header(&mut self, name: &str, value: &str) {
+        self.remove_header(name);
+        self.headers.push(HttpHeader::new(name, value));
+    }
+
+    /// Remove every request header of a name, e.g. `Authorization`.
+    #[wasm_bindgen]
+    pub fn remove_header(&mut self, name: &str) {
+        self.headers
+            .retain(|h| !h.name().eq_ignore_ascii_case(name));
+    }
+
+    /// Get a copy of the request body.
+    #[wasm_bindgen]
+    pub fn get_body(&self) -> Option<Uint8Array> {
+        self.body.as_deref().map(Uint8Array::from)
+    }
+
+    /// Get the correlation ID of the request, if one was assigned.
+    #[wasm_bindgen]
+    pub fn get_request_id(&self) -> Option<String> {
+        self.request_id.clone()
+    }
+
+    /// Get the name of the client sending the request, if any.
+    #[wasm_bindgen]
+    pub fn get_client_name(&self) -> Option<String> {
+        self.client_name.clone()
+    }
+
+    /// Get the labels of the connection sending the request.
+    #[wasm_bindgen]
+    pub fn get_tags(&self) -> Vec<String> {
+        self.tags.clone()
+    }
+
+    /// Set the time to wait for the response in ms, after which the response is dropped and
+    /// the timeout policy of the client is applied to the connection.
+    ///
+    /// # Arguments
+    ///
+    /// * `timeout` - Time to wait in ms, or `None` to wait forever
+    #[wasm_bindgen]
+    pub fn set_timeout(&mut self, timeout: Option<f64>) {
+        self.timeout = timeout;
+    }
+
+    /// Get the time to wait for the response in ms, if limited.
+    #[wasm_bindgen]
+    pub fn get_timeout(&self) -> Option<f64> {
+        self.timeout
+    }
+
+    /// Make this request conditional on a previously received response having changed,
+    /// using its `ETag` as `If-None-Match` and its `Last-Modified` as `If-Modified-Since`.
+    ///
+    /// A `304 Not Modified` answer is turned into the full response with
+    /// `HttpConnectionResponse::merge_not_modified`.
+    ///
+    /// # Arguments
+    ///
+    /// * `cached` - Response previously received to the same request
+    ///
+    /// # Returns
+    ///
+    /// Whether the cached response has a validator, the request being unchanged if not.
+    #[wasm_bindgen]
+    pub fn revalidate(&mut self, cached: &HttpConnectionResponse) -> bool {
+        let validators = [
+            ("If-None-Match", cached.get_header("ETag")),
+            ("If-Modified-Since", cached.get_header("Last-Modified")),
+        ];
+        let mut conditional = false;
+        for (name, value) in validators {
+            if let Some(value) = value {
+                self.headers
+                    .retain(|h| !h.name().eq_ignore_ascii_case(name));
+                self.headers.push(HttpHeader::new(name, &value));
+                conditional = true;
+            }
+        }
+        conditional
+    }
 }
 
 #[wasm_bindgen]
+#[derive(Clone)]
 pub struct HttpConnectionResponse {
     /// Response code
-    code: u16,
+    pub(crate) code: u16,
+    /// HTTP version from the status line
+    pub(crate) version: String,
+    /// Reason phrase from the status line
+    pub(crate) reason: String,
     /// Response headers
-    headers: Vec<HttpHeader>,
+    pub(crate) headers: Vec<HttpHeader>,
     /// Response body
-    body: Option<Vec<u8>>,
+    pub(crate) body: Option<Vec<u8>>,
+    /// Correlation ID of the request this response answers, if one was assigned
+    pub(crate) request_id: Option<String>,
+    /// Whether the server keeps the connection open after this response
+    pub(crate) keep_alive: bool,
+    /// How long the server keeps the connection open while idle, in seconds, if hinted
+    pub(crate) keep_alive_timeout: Option<u32>,
+    /// Response body accumulated into a blob instead of `body`, if requested
+    pub(crate) blob: Option<Blob>,
+    /// Response body streamed as it is received instead of `body`, if requested
+    pub(crate) stream: Option<ReadableStream>,
+    /// Response body written into IndexedDB instead of `body`, if requested
+    pub(crate) download: Option<IdbDownload>,
+    /// Type sniffed from the start of the body, if received without a `Content-Type`
+    pub(crate) sniffed_type: Option<String>,
+    /// Timing of the request this response answers, if received from a connection
+    pub(crate) timing: Option<RequestTiming>,
+}
+
+impl HttpConnectionResponse {
+    /// Set the correlation ID of the request this response answers.
+    ///
+    /// # Arguments
+    ///
+    /// * `request_id` - ID of the request
+    pub(crate) fn with_request_id(mut self, request_id: Option<String>) -> Self {
+        self.request_id = request_id;
+        self
+    }
+
+    /// Set the keep-alive semantics of this response.
+    ///
+    /// # Arguments
+    ///
+    /// * `keep_alive` - Whether the server keeps the connection open
+    /// * `timeout` - How long the server keeps the connection open while idle, in seconds
+    pub(crate) fn with_keep_alive(mut self, keep_alive: bool, timeout: Option<u32>) -> Self {
+        self.keep_alive = keep_alive;
+        self.keep_alive_timeout = timeout;
+        self
+    }
+
+    /// Set the blob the body of this response was accumulated into.
+    ///
+    /// # Arguments
+    ///
+    /// * `blob` - Blob holding the response body
+    pub(crate) fn with_blob(mut self, blob: Blob) -> Self {
+        self.blob = Some(blob);
+        self
+    }
+
+    /// Set the type sniffed from the start of the body of this response.
+    ///
+    /// # Arguments
+    ///
+    /// * `sniffed_type` - Sniffed type, or `None` if the response has a `Content-Type`
+    pub(crate) fn with_sniffed_type(mut self, sniffed_type: Option<String>) -> Self {
+        self.sniffed_type = sniffed_type;
+        self
+    }
+
+    /// Set the IndexedDB download the body of this response was written into.
+    ///
+    /// # Arguments
+    ///
+    /// * `download` - Handle to the download
+    pub(crate) fn with_download(mut self, download: IdbDownload) -> Self {
+        self.download = Some(download);
+        self
+    }
+
+    /// Set the stream the body of this response is streamed to.
+    ///
+    /// # Arguments
+    ///
+    /// * `stream` - Stream of the response body
+    pub(crate) fn with_stream(mut self, stream: ReadableStream) -> Self {
+        self.stream = Some(stream);
+        self
+    }
+
+    /// Set the timing of the request this response answers.
+    ///
+    /// # Arguments
+    ///
+    /// * `timing` - Timing of the request
+    pub(crate) fn with_timing(mut self, timing: RequestTiming) -> Self {
+        self.timing = Some(timing);
+        self
+    }
+
+    /// Get the charset of the body from the `Content-Type` header, UTF-8 if not given.
+    fn charset(&self) -> String {
+        self.get_header("Content-Type")
+            .and_then(|content_type| {
+                content_type.split(';').skip(1).find_map(|param| {
+                    let (name, value) = param.split_once('=')?;
+                    name.trim()
+                        .eq_ignore_ascii_case("charset")
+                        .then(|| value.trim().trim_matches('"').to_string())
+                })
+            })
+            .unwrap_or_else(|| "utf-8".to_string())
+    }
+
+    /// Set the HTTP version and reason phrase from the status line of this response.
+    ///
+    /// # Arguments
+    ///
+    /// * `version` - HTTP version, e.g. `HTTP/1.1`
+    /// * `reason` - Reason phrase, e.g. `Not Found`
+    pub(crate) fn with_status_line(mut self, version: String, reason: String) -> Self {
+        self.version = version;
+        self.reason = reason;
+        self
+    }
+
+    /// Apply the keep-alive semantics of this response to the connection it was received on.
+    ///
+    /// The connection is closed if the server closes it, otherwise the keep-alive timeout
+    /// hinted by the server is recorded so the connection is reaped before the server drops it.
+    pub(crate) fn apply_keep_alive(&self, keep_alive: &KeepAlive) {
+        if !self.keep_alive {
+            let _ = keep_alive.socket.close();
+        } else if let Some(timeout) = self.keep_alive_timeout {
+            keep_alive.timeout.set(Some(f64::from(timeout) * 1000.0));
+        }
+    }
 }
 
 #[wasm_bindgen]
@@ -108,168 +506,955 @@ impl HttpConnectionResponse {
     pub fn new(code: u16, headers: Vec<HttpHeader>, body: Option<Vec<u8>>) -> Self {
         Self {
             code,
+            version: "HTTP/1.1".to_string(),
+            reason: String::new(),
             headers,
             body,
+            request_id: None,
+            keep_alive: true,
+            keep_alive_timeout: None,
+            blob: None,
+            stream: None,
+            download: None,
+            sniffed_type: None,
+            timing: None,
         }
     }
 
+    /// Check if the server keeps the connection open after this response.
+    ///
+    /// If not, the connection is closed once the response is received.
+    #[wasm_bindgen]
+    pub fn is_keep_alive(&self) -> bool {
+        self.keep_alive
+    }
+
+    /// Get how long the server keeps the connection open while idle, in seconds, if hinted.
+    #[wasm_bindgen]
+    pub fn get_keep_alive_timeout(&self) -> Option<u32> {
+        self.keep_alive_timeout
+    }
+
     /// Get the response code.
     #[wasm_bindgen]
     pub fn get_code(&self) -> u16 {
         self.code
     }
 
+    /// Get the reason phrase from the status line, e.g. `Not Found`.
+    #[wasm_bindgen]
+    pub fn get_reason(&self) -> String {
+        self.reason.clone()
+    }
+
+    /// Get the HTTP version from the status line, e.g. `HTTP/1.1`.
+    #[wasm_bindgen]
+    pub fn get_version(&self) -> String {
+        self.version.clone()
+    }
+
+    /// Check if the response code is a success (2xx).
+    #[wasm_bindgen]
+    pub fn ok(&self) -> bool {
+        (200..300).contains(&self.code)
+    }
+
+    /// Check if the response code is a redirection (3xx).
+    #[wasm_bindgen]
+    pub fn is_redirect(&self) -> bool {
+        (300..400).contains(&self.code)
+    }
+
+    /// Check if the response code is a client error (4xx).
+    #[wasm_bindgen]
+    pub fn is_client_error(&self) -> bool {
+        (400..500).contains(&self.code)
+    }
+
+    /// Check if the response code is a server error (5xx).
+    #[wasm_bindgen]
+    pub fn is_server_error(&self) -> bool {
+        (500..600).contains(&self.code)
+    }
+
+    /// Get the correlation ID of the request this response answers, if one was assigned.
+    #[wasm_bindgen]
+    pub fn get_request_id(&self) -> Option<String> {
+        self.request_id.clone()
+    }
+
+    /// Get the timing of the request this response answers, from queueing its connection to
+    /// receiving the response, or `None` if the response was not received from a connection.
+    #[wasm_bindgen]
+    pub fn get_timing(&self) -> Option<RequestTiming> {
+        self.timing
+    }
+
     /// Get the response headers.
     #[wasm_bindgen]
     pub fn get_headers(&self) -> Vec<HttpHeader> {
         self.headers.clone()
     }
 
-    /// Get the response body.
+    /// Get the value of the first header with the given name, ignoring case.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - Name of the header
+    #[wasm_bindgen]
+    pub fn get_header(&self, name: &str) -> Option<String> {
+        self.headers
+            .iter()
+            .find(|h| h.name().eq_ignore_ascii_case(name))
+            .map(|h| h.value().to_string())
+    }
+
+    /// Get the values of every header with the given name, ignoring case, in order of receipt.
+    ///
+    /// Headers sent several times, such as `Set-Cookie`, are returned individually rather
+    /// than joined, as their values can not be safely combined.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - Name of the header
+    #[wasm_bindgen]
+    pub fn get_all(&self, name: &str) -> Vec<String> {
+        self.headers
+            .iter()
+            .filter(|h| h.name().eq_ignore_ascii_case(name))
+            .map(|h| h.value().to_string())
+            .collect()
+    }
+
+    /// Get a copy of the response body.
+    #[wasm_bindgen]
+    pub fn get_body(&self) -> Option<Uint8Array> {
+        self.body.as_deref().map(Uint8Array::from)
+    }
+
+    /// Get the parsed `Content-Disposition` header, e.g. to get the name a download should be
+    /// saved as, if present.
+    #[wasm_bindgen]
+    pub fn get_content_disposition(&self) -> Option<ContentDisposition> {
+        self.get_header("Content-Disposition")
+            .and_then(|value| ContentDisposition::parse(&value))
+    }
+
+    /// Get the type sniffed from the start of the body, as browsers do, if the response was
+    /// received without a `Content-Type` header.
+    ///
+    /// Responses sent with `send_streaming` are delivered before their body, so they are
+    /// never sniffed.
+    #[wasm_bindgen]
+    pub fn get_sniffed_type(&self) -> Option<String> {
+        self.sniffed_type.clone()
+    }
+
+    /// Get the type of the body: the `Content-Type` header, or else the sniffed type.
+    #[wasm_bindgen]
+    pub fn get_content_type(&self) -> Option<String> {
+        self.get_header("Content-Type")
+            .or_else(|| self.sniffed_type.clone())
+    }
+
+    /// Get the handle to the IndexedDB download the response body was written into, if sent
+    /// with `send_to_indexed_db`.
+    #[wasm_bindgen]
+    pub fn get_download(&self) -> Option<IdbDownload> {
+        self.download.clone()
+    }
+
+    /// Get the blob the response body was accumulated into, if sent with `send_to_blob`.
+    #[wasm_bindgen]
+    pub fn get_blob(&self) -> Option<Blob> {
+        self.blob.clone()
+    }
+
+    /// Create an object URL for the blob the response body was accumulated into.
+    ///
+    /// The URL keeps the blob alive until it is passed to `URL.revokeObjectURL`.
+    ///
+    /// # Returns
+    ///
+    /// The URL, or an error if the response was not sent with `send_to_blob`.
+    #[wasm_bindgen]
+    pub fn create_object_url(&self) -> Result<String, ConnectionError> {
+        let blob = self.blob.as_ref().ok_or_else(|| ConnectionError {
+            message: "Response body was not accumulated into a blob".to_string(),
+        })?;
+        Url::create_object_url_with_blob(blob).map_err(|e| ConnectionError {
+            message: format!("Failed to create object URL: {:?}", e),
+        })
+    }
+
+    /// Get the stream of the response body, if sent with `send_streaming`.
+    ///
+    /// The stream yields `Uint8Array` chunks as they are received, and closes once the whole
+    /// body was received.
+    #[wasm_bindgen]
+    pub fn get_body_stream(&self) -> Option<ReadableStream> {
+        self.stream.clone()
+    }
+
+    /// Get the stream of the response body decoded as text, if sent with `send_streaming`.
+    ///
+    /// The bytes are decoded with the charset of the `Content-Type` header, UTF-8 if not
+    /// given, like a `TextDecoderStream`. Convenient for NDJSON and log tailing. The stream
+    /// of the body can only be read once, so this can't be combined with `get_body_stream`.
+    ///
+    /// # Returns
+    ///
+    /// A stream of strings, or an error if the response was not sent with `send_streaming`
+    /// or its charset is not supported.
+    #[wasm_bindgen]
+    pub fn body_text_stream(&self) -> Result<ReadableStream, ConnectionError> {
+        let stream = self.stream.as_ref().ok_or_else(|| ConnectionError {
+            message: "Response body is not streamed".to_string(),
+        })?;
+        decode_text_stream(stream, &self.charset())
+    }
+
+    /// Get a view of the response body backed by the module memory, without copying it.
+    ///
+    /// The view is invalidated when the module memory grows or this response is freed,
+    /// so it must be consumed (or copied) before calling into this module again.
+    #[wasm_bindgen]
+    pub fn get_body_view(&self) -> Option<Uint8Array> {
+        // SAFETY: the view is only valid until the next allocation, as documented above
+        self.body
+            .as_deref()
+            .map(|body| unsafe { Uint8Array::view(body) })
+    }
+
+    /// Decode the response body as text, replacing invalid sequences.
+    ///
+    /// With the `encoding` feature, the body is decoded with the charset of its `Content-Type`,
+    /// e.g. `iso-8859-1` or `shift_jis`, unless it starts with a byte order mark, which takes
+    /// precedence as in browsers. Unknown charsets fall back to UTF-8. Without the feature, the
+    /// body is always decoded as UTF-8.
+    #[wasm_bindgen]
+    pub fn text(&self) -> String {
+        let body = self.body.as_deref().unwrap_or_default();
+        #[cfg(feature = "encoding")]
+        {
+            let encoding = encoding_rs::Encoding::for_label(self.charset().as_bytes())
+                .unwrap_or(encoding_rs::UTF_8);
+            // Sniffs the byte order mark before falling back to the label
+            let (text, _, _) = encoding.decode(body);
+            text.into_owned()
+        }
+        #[cfg(not(feature = "encoding"))]
+        String::from_utf8_lossy(body).into_owned()
+    }
+
+    /// Parse the response body as JSON.
+    #[wasm_bindgen]
+    pub fn json(&self) -> Result<JsValue, JsValue> {
+        js_sys::JSON::parse(&self.text())
+    }
+
+    /// Take the response body, leaving this response without one.
+    ///
+    /// The body is copied to JS once and freed from the module memory.
+    #[wasm_bindgen]
+    pub fn take_body(&mut self) -> Option<Vec<u8>> {
+        self.body.take()
+    }
+
+    /// Convert this response to a plain object that can be posted to another context, such
+    /// as a worker, and converted back with `from_transferable`.
+    ///
+    /// The body is copied once into its own `ArrayBuffer`, which should be transferred rather
+    /// than cloned, e.g. `worker.postMessage(value, value.body ? [value.body] : [])`. A blob
+    /// body is cloned by reference, and a streamed body must be transferred as well.
+    #[wasm_bindgen]
+    pub fn to_transferable(&self) -> JsValue {
+        let headers: js_sys::Array = self
+            .headers
+            .iter()
+            .map(|h| {
+                JsValue::from(js_sys::Array::of2(
+                    &JsValue::from_str(h.name()),
+                    &JsValue::from_str(h.value()),
+                ))
+            })
+            .collect();
+        message(&[
+            ("code", self.code.into()),
+            ("version", self.version.as_str().into()),
+            ("reason", self.reason.as_str().into()),
+            ("headers", headers.into()),
+            ("body", self.body.as_deref().map(to_buffer).into()),
+            ("request_id", self.request_id.clone().into()),
+            ("keep_alive", self.keep_alive.into()),
+            ("keep_alive_timeout", self.keep_alive_timeout.into()),
+            ("sniffed_type", self.sniffed_type.clone().into()),
+            ("blob", self.blob.clone().into()),
+            ("stream", self.stream.clone().into()),
+        ])
+        .into()
+    }
+
+    /// Convert a plain object made by `to_transferable` back to a response.
+    ///
+    /// # Arguments
+    ///
+    /// * `value` - Object received from another context
     #[wasm_bindgen]
-    pub fn get_body(&self) -> Option<Vec<u8>> {
-        return self.body.clone();
+    pub fn from_transferable(value: &JsValue) -> Result<HttpConnectionResponse, ConnectionError> {
+        let code = get(value, "code").as_f64().ok_or_else(|| ConnectionError {
+            message: "Missing code in transferred response".to_string(),
+        })?;
+        let headers = js_sys::Array::from(&get(value, "headers"))
+            .iter()
+            .map(|header| {
+                let header = js_sys::Array::from(&header);
+                HttpHeader::new(
+                    &header.get(0).as_string().unwrap_or_default(),
+                    &header.get(1).as_string().unwrap_or_default(),
+                )
+            })
+            .collect();
+        let body = get(value, "body")
+            .is_instance_of::<ArrayBuffer>()
+            .then(|| get_bytes(value, "body"));
+        let mut response = HttpConnectionResponse::new(code as u16, headers, body);
+        if let Some(version) = get(value, "version").as_string() {
+            response.version = version;
+        }
+        response.reason = get(value, "reason").as_string().unwrap_or_default();
+        response.request_id = get(value, "request_id").as_string();
+        response.keep_alive = get(value, "keep_alive").as_bool().unwrap_or(true);
+        response.keep_alive_timeout = get(value, "keep_alive_timeout")
+            .as_f64()
+            .map(|timeout| timeout as u32);
+        response.sniffed_type = get(value, "sniffed_type").as_string();
+        response.blob = get(value, "blob").dyn_into::<Blob>().ok();
+        response.stream = get(value, "stream").dyn_into::<ReadableStream>().ok();
+        Ok(response)
+    }
+
+    /// Merge this response to a conditional request with the cached response it validates.
+    ///
+    /// If this response is a `304 Not Modified`, the cached response is returned with its
+    /// headers updated by those of this response, except those describing the body such
+    /// as `Content-Length`. Any other response replaces the cached one, and is returned as is.
+    ///
+    /// # Arguments
+    ///
+    /// * `cached` - Response the conditional request was built from with `revalidate`
+    #[wasm_bindgen]
+    pub fn merge_not_modified(&self, cached: &HttpConnectionResponse) -> HttpConnectionResponse {
+        if self.code != 304 {
+            return self.clone();
+        }
+        let mut merged = cached.clone();
+        let updates = self
+            .headers
+            .iter()
+            .filter(|h| {
+                !BODY_HEADERS
+                    .iter()
+                    .any(|name| h.name().eq_ignore_ascii_case(name))
+            })
+            .collect::<Vec<_>>();
+        merged.headers.retain(|h| {
+            !updates
+                .iter()
+                .any(|u| u.name().eq_ignore_ascii_case(h.name()))
+        });
+        merged.headers.extend(updates.into_iter().cloned());
+        merged.request_id = self.request_id.clone();
+        merged.keep_alive = self.keep_alive;
+        merged.keep_alive_timeout = self.keep_alive_timeout;
+        merged.timing = self.timing;
+        merged
     }
 }
 
+/// Upstream HTTP proxy plain HTTP requests are routed through.
+#[derive(Clone, Debug)]
+pub struct ForwardProxy {
+    /// Address of the proxy
+    pub addr: String,
+    /// Value of the `Proxy-Authorization` header, if any
+    pub authorization: Option<String>,
+}
+
+/// Standard request methods, from RFC 9110 and RFC 5789.
+pub const METHOD_GET: &str = "GET";
+pub const METHOD_HEAD: &str = "HEAD";
+pub const METHOD_POST: &str = "POST";
+pub const METHOD_PUT: &str = "PUT";
+pub const METHOD_DELETE: &str = "DELETE";
+pub const METHOD_CONNECT: &str = "CONNECT";
+pub const METHOD_OPTIONS: &str = "OPTIONS";
+pub const METHOD_TRACE: &str = "TRACE";
+pub const METHOD_PATCH: &str = "PATCH";
+
+/// All standard request methods.
+pub const STANDARD_METHODS: [&str; 9] = [
+    METHOD_GET,
+    METHOD_HEAD,
+    METHOD_POST,
+    METHOD_PUT,
+    METHOD_DELETE,
+    METHOD_CONNECT,
+    METHOD_OPTIONS,
+    METHOD_TRACE,
+    METHOD_PATCH,
+];
+
+/// Check that a request method is a legal HTTP token.
+///
+/// Any token is accepted, so extension methods like `PROPFIND` or `REPORT` are allowed, but
+/// whitespace and control characters that would corrupt the request line are not.
+pub(crate) fn validate_method(method: &str) -> Result<(), ConnectionError> {
+    let is_tchar = |c: char| c.is_ascii_alphanumeric() || "!#$%&'*+-.^_`|~".contains(c);
+    if method.is_empty() || !method.chars().all(is_tchar) {
+        return Err(ConnectionError {
+            message: format!("Invalid request method: {:?}", method),
+        });
+    }
+    Ok(())
+}
+
+/// What happens to a keep-alive connection when a request times out.
 #[wasm_bindgen]
-pub struct HttpConnectionApi {
-    /// Connection to create API for
-    connection: Connection,
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum TimeoutPolicy {
+    /// Close the connection, as the rest of the late response would be read as the response
+    /// of the next request. A new connection must be created to send more requests.
+    #[default]
+    Close,
+    /// Keep the connection open, for servers known to never answer late
+    Keep,
 }
 
-impl HttpConnectionApi {
-    /// Create a new API instance for the given connection.
+/// Reports the errors of a request to its callback, called with `null` and the error.
+#[derive(Clone)]
+pub(crate) struct RequestErrors {
+    /// Callback of the request
+    callback: HttpResponseCallback,
+    /// Correlation ID of the request, if one was assigned
+    request_id: Option<String>,
+    /// Prefix of the log lines of the connection
+    log_prefix: String,
+}
+
+impl RequestErrors {
+    /// Create the reporter of the errors of a request.
     ///
     /// # Arguments
     ///
-    /// * `connection` - Connection to create API for
-    pub fn new(connection: Connection) -> Self {
-        Self { connection }
+    /// * `callback` - Callback of the request
+    /// * `request_id` - Correlation ID of the request, if one was assigned
+    /// * `log_prefix` - Prefix of the log lines of the connection
+    pub(crate) fn new(
+        callback: &HttpResponseCallback,
+        request_id: &Option<String>,
+        log_prefix: &str,
+    ) -> Self {
+        Self {
+            callback: callback.clone(),
+            request_id: request_id.clone(),
+            log_prefix: log_prefix.to_string(),
+        }
+    }
+
+    /// Log an error of the request and pass it to its callback.
+    pub(crate) fn report(&self, error: ConnectionError) {
+        let error = error.for_request(self.request_id.as_deref());
+        console_log!("{}{}", self.log_prefix, error);
+        let _ = self
+            .callback
+            .call2(&JsValue::null(), &JsValue::null(), &error.into());
     }
 }
 
-#[wasm_bindgen]
-impl HttpConnectionApi {
-    #[wasm_bindgen]
-    /// Get the address of this connection.
-    pub fn get_addr(&self) -> String {
-        self.connection.addr.clone()
+/// Deadline of a request, after which its response is dropped.
+#[derive(Clone, Default)]
+pub(crate) struct RequestDeadline {
+    /// Whether the response was received before the deadline
+    done: Rc<Cell<bool>>,
+    /// Whether the deadline passed before the response was received
+    expired: Rc<Cell<bool>>,
+}
+
+impl RequestDeadline {
+    /// Start the deadline of a request, failing it once passed.
+    ///
+    /// # Arguments
+    ///
+    /// * `timeout` - Time to wait for the response in ms, or `None` to wait forever
+    /// * `policy` - What happens to the connection once the deadline passes
+    /// * `socket` - Socket of the connection
+    /// * `stats` - Statistics of the client, recording the timeout as an error
+    /// * `errors` - Reporter of the errors of the request, passed the timeout
+    /// * `in_flight` - Request as tracked by the connection, finished once the deadline passes
+    pub(crate) fn start(
+        timeout: Option<f64>,
+        policy: TimeoutPolicy,
+        socket: WebSocket,
+        stats: StatsRecorder,
+        errors: RequestErrors,
+        in_flight: PendingRequest,
+    ) -> Self {
+        let deadline = Self::default();
+        if let Some(timeout) = timeout {
+            let cb_deadline = deadline.clone();
+            set_timeout(
+                &Closure::once_into_js(move || {
+                    if cb_deadline.done.get() {
+                        return;
+                    }
+                    cb_deadline.expired.set(true);
+                    in_flight.finish();
+                    stats.record_error();
+                    errors.report(ConnectionError {
+                        message: format!("Request timed out after {} ms", timeout),
+                    });
+                    if policy == TimeoutPolicy::Close {
+                        let _ = socket.close();
+                    }
+                }),
+                timeout,
+            );
+        }
+        deadline
     }
 
-    /// Send data to this connection.
+    /// Check if the deadline passed, in which case the response must be dropped.
+    pub(crate) fn is_expired(&self) -> bool {
+        self.expired.get()
+    }
+
+    /// Mark the response as received, stopping the deadline.
+    pub(crate) fn finish(&self) {
+        self.done.set(true);
+    }
+}
+
+/// Check if a request asks the server to accept its body with `100 Continue` first.
+pub(crate) fn expects_continue(request: &HttpConnectionRequest) -> bool {
+    request.body.as_ref().is_some_and(|body| !body.is_empty())
+        && request.headers.iter().any(|h| {
+            h.name().eq_ignore_ascii_case("Expect")
+                && h.value().eq_ignore_ascii_case("100-continue")
+        })
+}
+
+/// Body of a request held back until the server accepts it with `100 Continue`.
+///
+/// The body is sent anyway if the server does not answer in time, and dropped if the server
+/// sends a final response first, e.g. to reject the upload.
+#[derive(Clone, Default)]
+pub(crate) struct PendingBody {
+    /// Body not sent yet, if any
+    body: Arc<Mutex<Option<Vec<u8>>>>,
+}
+
+impl PendingBody {
+    /// Split the body off a serialized request, if the request expects `100 Continue`.
     ///
     /// # Arguments
     ///
-    /// * `data` - Data to send to this connection. The type of this data depends on the implementation.
-    /// * `callback` - Callback to call when data is received from this connection.
+    /// * `req` - Serialized request, truncated to its head if the body is held back
+    /// * `expect_continue` - Whether the request expects `100 Continue`
+    pub(crate) fn split(req: &mut Vec<u8>, expect_continue: bool) -> Self {
+        let pending = Self::default();
+        if expect_continue {
+            if let Some(end) = req.windows(4).position(|w| w == b"\r\n\r\n") {
+                *pending.body.lock().unwrap_throw() = Some(req.split_off(end + 4));
+            }
+        }
+        pending
+    }
+
+    /// Take the body if it is still held back.
+    pub(crate) fn take(&self) -> Option<Vec<u8>> {
+        self.body.lock().unwrap_throw().take()
+    }
+
+    /// Send the body with `send` once the timeout expires, if it is still held back.
+    pub(crate) fn send_after_timeout(&self, send: impl FnOnce(Vec<u8>) + 'static) {
+        if self.body.lock().unwrap_throw().is_none() {
+            return;
+        }
+        let pending = self.clone();
+        set_timeout(
+            &Closure::once_into_js(move || {
+                if let Some(body) = pending.take() {
+                    send(body);
+                }
+            }),
+            EXPECT_CONTINUE_TIMEOUT,
+        );
+    }
+
+    /// Handle the state of the response after a chunk was pushed to its assembler.
+    ///
+    /// # Arguments
+    ///
+    /// * `continued` - Whether a `100 Continue` response was received
+    /// * `final_response` - Whether the final response was received
+    /// * `socket` - Socket of the connection, closed if the body is dropped
     ///
     /// # Returns
     ///
-    /// The function returns a Result containing a void, or an error depending on the success of the send.
-    /// * `ConnectionError` - Error that occurred while sending data to this connection.
-    #[wasm_bindgen]
-    pub fn send(
+    /// The body to send now, if the server accepted it.
+    pub(crate) fn on_response(
+        &self,
+        continued: bool,
+        final_response: bool,
+        socket: &WebSocket,
+    ) -> Option<Vec<u8>> {
+        if final_response {
+            if self.take().is_some() {
+                // The announced body was never sent, so the connection cannot be reused
+                console_log!("Server answered before the body was sent, dropping it");
+                let _ = socket.close();
+            }
+            return None;
+        }
+        if continued {
+            return self.take();
+        }
+        None
+    }
+}
+
+/// Where the body of the responses of a request goes.
+#[derive(Clone, Default)]
+pub(crate) enum BodyTarget {
+    /// Buffered in the module memory
+    #[default]
+    Buffer,
+    /// Accumulated into a blob, see `send_to_blob`
+    Blob(BlobBody),
+    /// Written into IndexedDB, see `send_to_indexed_db`
+    IndexedDb(IdbBody),
+    /// Streamed as it is received, the response being delivered with its head, see
+    /// `send_streaming`
+    Stream(StreamBody),
+}
+
+impl BodyTarget {
+    /// Create the assembler of the responses of a request.
+    ///
+    /// When streamed, the response is passed through the middlewares and to the callback as
+    /// soon as its head is received.
+    ///
+    /// # Arguments
+    ///
+    /// * `progress` - Function receiving the progress of the body
+    /// * `pipeline` - Middlewares of the client
+    /// * `request_id` - Correlation ID of the request, if one was assigned
+    /// * `callback` - Callback to call with the response
+    /// * `timer` - Timer of the request, ended when a streamed response is delivered
+    /// * `errors` - Reporter of the errors of the request, passed those of the middlewares
+    pub(crate) fn assembler(
+        &self,
+        progress: ProgressSink,
+        pipeline: &Pipeline,
+        request_id: &Option<String>,
+        callback: &HttpResponseCallback,
+        timer: &RequestTimer,
+        errors: &RequestErrors,
+    ) -> ResponseAssembler {
+        match self {
+            BodyTarget::Buffer => ResponseAssembler::new(),
+            BodyTarget::Blob(blob) => ResponseAssembler::with_body_sink(blob.sink()),
+            BodyTarget::IndexedDb(idb) => ResponseAssembler::with_body_sink(idb.sink()),
+            BodyTarget::Stream(stream) => {
+                let stream = stream.clone();
+                let sink = stream.sink();
+                let pipeline = pipeline.clone();
+                let request_id = request_id.clone();
+                let callback = callback.clone();
+                let timer = timer.clone();
+                let errors = errors.clone();
+                ResponseAssembler::with_body_sink(sink).with_head_sink(Box::new(
+                    move |head: HttpConnectionResponse| {
+                        let head = head
+                            .with_request_id(request_id.clone())
+                            .with_stream(stream.stream())
+                            .with_timing(timer.finish());
+                        match pipeline.on_response(head) {
+                            Ok(response) => {
+                                callback
+                                    .call1(&JsValue::null(), &JsValue::from(response))
+                                    .unwrap_throw();
+                            }
+                            Err(e) => errors.report(e),
+                        }
+                    },
+                ))
+            }
+        }
+        .with_progress(progress)
+    }
+
+    /// Finish a response once its body was received, passing it through the middlewares
+    /// unless it was already delivered with its head.
+    pub(crate) fn finish(
+        &self,
+        response: HttpConnectionResponse,
+        pipeline: &Pipeline,
+        request_id: &Option<String>,
+    ) -> Result<HttpConnectionResponse, ConnectionError> {
+        let response = match self {
+            BodyTarget::Buffer => response,
+            BodyTarget::Blob(blob) => blob.finish(response)?,
+            BodyTarget::IndexedDb(idb) => idb.finish(response),
+            BodyTarget::Stream(stream) => {
+                stream.finish();
+                return Ok(response);
+            }
+        };
+        pipeline.on_response(response.with_request_id(request_id.clone()))
+    }
+
+    /// Check if responses are delivered with their head instead of once complete.
+    pub(crate) fn is_streamed(&self) -> bool {
+        matches!(self, BodyTarget::Stream(_))
+    }
+
+    /// Fail a response cancelled by its connection closing, so a stream of its body does
+    /// not wait for the rest forever.
+    pub(crate) fn cancel(&self, closed: &ConnectionClosed) {
+        if let BodyTarget::Stream(stream) = self {
+            stream.fail(&closed.clone().into());
+        }
+    }
+}
+
+/// Body of responses accumulated into a `Blob` as it is received, so large downloads are not
+/// buffered in the module memory.
+#[derive(Clone, Default)]
+pub(crate) struct BlobBody {
+    /// Chunks of the body received so far
+    parts: Rc<RefCell<js_sys::Array>>,
+}
+
+impl BlobBody {
+    /// Get a function adding the body chunks it receives to the blob.
+    pub(crate) fn sink(&self) -> BodySink {
+        let parts = self.parts.clone();
+        Box::new(move |chunk| {
+            parts.borrow().push(&Uint8Array::from(chunk));
+        })
+    }
+
+    /// Attach the chunks received so far to a response as a blob, and start a new one.
+    ///
+    /// The type of the blob is taken from the `Content-Type` header of the response, or else
+    /// sniffed from the start of the body.
+    pub(crate) fn finish(
+        &self,
+        response: HttpConnectionResponse,
+    ) -> Result<HttpConnectionResponse, ConnectionError> {
+        let parts = self.parts.replace(js_sys::Array::new());
+        let mut options = BlobPropertyBag::new();
+        if let Some(content_type) = response.get_content_type() {
+            options.type_(&content_type);
+        }
+        let blob = Blob::new_with_u8_array_sequence_and_options(&parts, &options).map_err(|e| {
+            ConnectionError {
+                message: format!("Failed to create blob: {:?}", e),
+            }
+        })?;
+        Ok(response.with_blob(blob))
+    }
+}
+
+#[wasm_bindgen]
+pub struct HttpConnectionApi {
+    /// Connection to create API for
+    connection: Connection,
+    /// Middlewares of the client
+    pipeline: Pipeline,
+}
+
+impl HttpConnectionApi {
+    /// Create a new API instance for the given connection.
+    ///
+    /// # Arguments
+    ///
+    /// * `connection` - Connection to create API for
+    /// * `pipeline` - Middlewares to run requests and responses through
+    pub fn new(connection: Connection, pipeline: Pipeline) -> Self {
+        Self {
+            connection,
+            pipeline,
+        }
+    }
+
+    /// Get the connection of this API.
+    pub(crate) fn connection(&self) -> &Connection {
+        &self.connection
+    }
+
+    /// Send a request, delivering the body of its response to `target`.
+    ///
+    /// If `upload` is given, it is sent as the body of the request in place of its own.
+    fn send_request(
         &self,
         data: HttpConnectionRequest,
-        callback: js_sys::Function,
+        callback: HttpResponseCallback,
+        target: BodyTarget,
+        upload: Option<BlobUpload>,
     ) -> Result<(), ConnectionError> {
-        if (self.connection.socket.ready_state() as u16) != 1 {
+        if self.connection.socket.ready_state() != 1 {
             return Err(ConnectionError {
                 message: "Connection is not open".to_string(),
             });
         }
-        let req = if let Some(body) = data.body {
+        let mut data = data;
+        data.client_name = self.connection.client_name.clone();
+        data.tags = self.connection.get_tags();
+        data.target = Some(origin(&format!("http://{}", self.connection.addr)));
+        let mut data = self.pipeline.on_request(data)?;
+        validate_method(&data.method)?;
+        if let Some(upload) = &upload {
+            upload.prepare(&mut data);
+        }
+        if let Some(proxy) = &self.connection.forward_proxy {
+            // Proxies expect the absolute form of the request target
+            if data.path.starts_with('/') {
+                data.path = format!("http://{}{}", self.connection.addr, data.path);
+            }
+            if let Some(authorization) = &proxy.authorization {
+                data.headers
+                    .push(HttpHeader::new("Proxy-Authorization", authorization));
+            }
+        }
+        let in_flight = self.connection.pending.start()?;
+        let request_id = data.request_id.clone();
+        let expect_continue = expects_continue(&data);
+        let timeout = data.timeout;
+        let mut req = if let Some(body) = data.body {
             http!(data.method, data.path, data.headers, body.to_vec())
         } else {
             http!(data.method, data.path, data.headers)
         };
-        console_log!("Sending request: {:?}", req);
+        let log_prefix = self.connection.log_prefix();
+        let timer = RequestTimer::start(&self.connection.setup);
+        match &request_id {
+            Some(id) => console_log!("{}[{}] Sending request: {:?}", log_prefix, id, req),
+            None => console_log!("{}Sending request: {:?}", log_prefix, req),
+        }
 
-        let response_code: Arc<Mutex<u16>> = Arc::new(Mutex::new(0u16));
+        let errors = RequestErrors::new(&callback, &request_id, &log_prefix);
 
-        let response_headers: Arc<Mutex<Vec<HttpHeader>>> = Arc::new(Mutex::new(Vec::new()));
+        // Holds JS values when not buffering the body, so it stays on this thread
+        let assembler: Rc<RefCell<ResponseAssembler>> = Rc::new(RefCell::new(
+            target
+                .assembler(
+                    self.connection.progress.sink(ProgressDirection::Download),
+                    &self.pipeline,
+                    &request_id,
+                    &callback,
+                    &timer,
+                    &errors,
+                )
+                .with_method(&data.method),
+        ));
 
-        let response_body: Arc<Mutex<Vec<u8>>> = Arc::new(Mutex::new(Vec::new()));
+        // Reused across messages so chunks are not copied into a fresh Vec each time
+        let scratch: Arc<Mutex<Vec<u8>>> = Arc::new(Mutex::new(Vec::new()));
 
-        let content_length: Arc<Mutex<usize>> = Arc::new(Mutex::new(0usize));
+        let progress = self.connection.progress.clone();
+        let total = req.len() + upload.as_ref().map_or(0, BlobUpload::size);
+        let pending = PendingBody::split(&mut req, expect_continue);
+
+        let pipeline = self.pipeline.clone();
+        let keep_alive = self.connection.keep_alive();
+        let cb_pending = pending.clone();
+        let cb_sender = self.connection.sender();
+        let cb_progress = progress.clone();
+        let stats = self.connection.stats.clone();
+        stats.record_request();
+        let deadline = RequestDeadline::start(
+            timeout,
+            self.connection.timeout_policy,
+            self.connection.socket.clone(),
+            stats.clone(),
+            errors.clone(),
+            in_flight.clone(),
+        );
+        let cb_in_flight = in_flight.clone();
+        let cb_deadline = deadline.clone();
+        let cb_target = target.clone();
+        let cb_errors = errors.clone();
+        let probe_assembler = assembler.clone();
 
         let message_callback: Closure<dyn Fn(MessageEvent)> =
             Closure::wrap(Box::new(move |evt: MessageEvent| {
-                let buffer = evt.data().dyn_into::<ArrayBuffer>().unwrap_throw();
-                let bytes = Uint8Array::new(&buffer).to_vec();
-
-                console_log!("Waiting for mutex lock...");
-
-                let mut response_code = response_code.lock().unwrap_throw();
-                let mut response_headers = response_headers.lock().unwrap_throw();
-                let mut response_body = response_body.lock().unwrap_throw();
-                let mut content_length = content_length.lock().unwrap_throw();
-
-                console_log!("Mutex lock acquired");
-
-                if response_code.eq(&0u16) {
-                    let str = String::from_utf8(Uint8Array::new(&buffer).to_vec()).unwrap_throw();
-
-                    console_log!("Received initial response");
-
-                    let mut lines = str.split("\r\n");
-
-                    *response_code = lines
-                        .nth(0)
-                        .unwrap_throw()
-                        .split(' ')
-                        .nth(1)
-                        .unwrap_throw()
-                        .parse()
-                        .unwrap_throw();
-
-                    lines
-                        .clone()
-                        .take_while(|line| !line.is_empty())
-                        .for_each(|line| {
-                            let mut split = line.split(": ");
-                            let name = split.next().unwrap_throw().to_string();
-                            let value = split.next().unwrap_throw().to_string();
-                            if name == "Content-Length" {
-                                *content_length = value.parse().unwrap_throw();
-                            }
-                            (*response_headers).push(HttpHeader::of(name, value));
-                        });
-
-                    lines
-                        .skip_while(|line| !line.is_empty())
-                        .skip(1)
-                        .for_each(|line| {
-                            (*response_body).extend_from_slice(line.as_bytes());
-                        });
-                } else {
-                    console_log!("Received another chunk");
-                    response_body.extend_from_slice(&bytes);
+                if deadline.is_expired() {
+                    return;
                 }
+                timer.first_byte();
+                let buffer = evt.data().dyn_into::<ArrayBuffer>().unwrap_throw();
+                let array = Uint8Array::new(&buffer);
+
+                let mut scratch = scratch.lock().unwrap_throw();
+                scratch.resize(array.length() as usize, 0);
+                array.copy_to(&mut scratch);
+
+                let mut assembler = assembler.borrow_mut();
+                let pushed = assembler.push(&scratch);
+                let malformed = pushed.is_err();
+                let response = pushed.and_then(|response| {
+                    response
+                        .map(|r| {
+                            r.apply_keep_alive(&keep_alive);
+                            target.finish(r.with_timing(timer.finish()), &pipeline, &request_id)
+                        })
+                        .transpose()
+                });
 
-                if response_body.len() >= *content_length {
-                    let response = HttpConnectionResponse::new(
-                        *response_code,
-                        (*response_headers).clone(),
-                        Some((*response_body).clone()),
-                    );
-                    console_log!("Last chunk received");
-                    let this = JsValue::null();
-
-                    callback
-                        .call1(&this, &JsValue::from(response))
-                        .unwrap_throw();
+                let continued = assembler.take_continue();
+                let final_response = matches!(response, Ok(Some(_)));
+                if let Some(body) =
+                    cb_pending.on_response(continued, final_response, &keep_alive.socket)
+                {
+                    cb_sender.send(&body).unwrap_throw();
+                    cb_progress.report(Progress::new(ProgressDirection::Upload, total, total));
                 }
+                drop(assembler);
 
-                drop(response_code);
-                drop(response_headers);
-                drop(response_body);
-                drop(content_length);
+                match response {
+                    Ok(Some(response)) => {
+                        console_log!("Last chunk received");
+                        deadline.finish();
+                        cb_in_flight.finish();
+                        stats.record_response(timer.elapsed(), timer.time_to_first_byte());
+                        if target.is_streamed() {
+                            return;
+                        }
+                        let this = JsValue::null();
+
+                        callback
+                            .call1(&this, &JsValue::from(response))
+                            .unwrap_throw();
+                    }
+                    Ok(None) => console_log!("Received another chunk"),
+                    Err(e) => {
+                        deadline.finish();
+                        cb_in_flight.finish();
+                        if malformed {
+                            // The rest of the response would be read as the response of the
+                            // next request
+                            let _ = keep_alive.socket.close();
+                        }
+                        stats.record_error();
+                        errors.report(e);
+                    }
+                }
             }));
 
-        let _ = self
-            .connection
+        self.connection
             .socket
             .add_event_listener_with_callback_and_add_event_listener_options(
                 "message",
@@ -278,16 +1463,206 @@ impl HttpConnectionApi {
             )
             .unwrap_throw();
 
-        message_callback.forget();
+        in_flight.set_probe(move |usage| {
+            if let Ok(assembler) = probe_assembler.try_borrow() {
+                usage.add_assembler(assembler.buffered());
+            }
+        });
 
-        let _ = self
-            .connection
-            .socket
-            .send_with_u8_array(&req)
-            .unwrap_throw();
+        let socket = self.connection.socket.clone();
+        in_flight.on_release(move |closed| {
+            release_listener(&socket, "message", message_callback);
+            if let Some(closed) = closed {
+                cb_deadline.finish();
+                cb_target.cancel(closed);
+                cb_errors.report(ConnectionError {
+                    message: format!("Request cancelled, {}", closed.get_message()),
+                });
+            }
+        });
+
+        let sender = self.connection.sender();
+        sender.send(&req).unwrap_throw();
+        progress.report(Progress::new(ProgressDirection::Upload, req.len(), total));
+        if let Some(upload) = upload {
+            let sender = sender.clone();
+            upload.stream(
+                self.connection.socket.clone(),
+                move |slice| sender.send(&slice).unwrap_throw(),
+                progress.clone(),
+                req.len(),
+                self.connection.log_prefix(),
+            );
+        }
+        pending.send_after_timeout(move |body| {
+            sender.send(&body).unwrap_throw();
+            progress.report(Progress::new(ProgressDirection::Upload, total, total));
+        });
 
         Ok(())
     }
+}
+
+#[wasm_bindgen]
+impl HttpConnectionApi {
+    #[wasm_bindgen]
+    /// Get the address of this connection.
+    pub fn get_addr(&self) -> String {
+        self.connection.addr.clone()
+    }
+
+    /// Get the ID of this connection.
+    #[wasm_bindgen]
+    pub fn get_id(&self) -> u64 {
+        self.connection.get_id().into()
+    }
+
+    /// Get the UUID of this connection, if made with `ConnIdScheme.UuidV7`.
+    #[wasm_bindgen]
+    pub fn get_uuid(&self) -> Option<String> {
+        self.connection.get_id().uuid_string()
+    }
+
+    /// Send data to this connection.
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - Data to send to this connection. The type of this data depends on the implementation.
+    /// * `callback` - Callback to call when data is received from this connection, or with
+    ///   `null` and the error if the request fails after being sent.
+    ///
+    /// # Returns
+    ///
+    /// The function returns a Result containing a void, or an error depending on the success of the send.
+    /// * `ConnectionError` - Error that occurred while sending data to this connection.
+    #[wasm_bindgen]
+    pub fn send(
+        &self,
+        data: HttpConnectionRequest,
+        callback: HttpResponseCallback,
+    ) -> Result<(), ConnectionError> {
+        self.send_request(data, callback, BodyTarget::Buffer, None)
+    }
+
+    /// Send a request, accumulating the body of its response into a `Blob` instead of the
+    /// module memory.
+    ///
+    /// Meant for large downloads. The response passed to `callback` has no body, its
+    /// contents are available from `get_blob` or `create_object_url`.
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - Request to send
+    /// * `callback` - Callback to call with the response once received
+    #[wasm_bindgen]
+    pub fn send_to_blob(
+        &self,
+        data: HttpConnectionRequest,
+        callback: HttpResponseCallback,
+    ) -> Result<(), ConnectionError> {
+        self.send_request(data, callback, BodyTarget::Blob(BlobBody::default()), None)
+    }
+
+    /// Send a request, writing the body of its response into IndexedDB as it is received.
+    ///
+    /// Meant for downloads too large for the memory of the page. The body is stored in
+    /// records of 1 MiB under `key`, replacing any download of the same key, next to its
+    /// metadata. The response passed to `callback` has no body, its contents are reassembled
+    /// from `get_download`, or later from `IdbDownload.load`.
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - Request to send
+    /// * `database` - Name of the database to store the download in
+    /// * `key` - Key of the download in the database
+    /// * `callback` - Callback to call with the response once received
+    #[wasm_bindgen]
+    pub fn send_to_indexed_db(
+        &self,
+        data: HttpConnectionRequest,
+        database: String,
+        key: String,
+        callback: HttpResponseCallback,
+    ) -> Result<(), ConnectionError> {
+        let target = BodyTarget::IndexedDb(IdbBody::open(database, key));
+        self.send_request(data, callback, target, None)
+    }
+
+    /// Send a request with the contents of a `Blob` or `File` as its body.
+    ///
+    /// The blob is read and sent in slices as the connection drains, so it is never copied
+    /// into the module memory as a whole. The `Content-Length` of the request is set to the
+    /// size of the blob, and its `Content-Type` to the type of the blob unless already set.
+    /// The progress of the upload is reported to the `set_onprogress` callback.
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - Request to send, its own body is ignored
+    /// * `body` - Blob to send as the body
+    /// * `callback` - Callback to call with the response once received
+    #[wasm_bindgen]
+    pub fn send_blob(
+        &self,
+        data: HttpConnectionRequest,
+        body: Blob,
+        callback: HttpResponseCallback,
+    ) -> Result<(), ConnectionError> {
+        let upload = BlobUpload::new(body);
+        self.send_request(data, callback, BodyTarget::Buffer, Some(upload))
+    }
+
+    /// Send a request with a `FormData` as its body, encoded as `multipart/form-data` like
+    /// `fetch` does.
+    ///
+    /// String entries and `File` entries, with their name and type, become the parts of the
+    /// body, which is streamed like with `send_blob`. The `Content-Type` of the request is set
+    /// with the generated boundary, so it should be left unset.
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - Request to send, its own body is ignored
+    /// * `form` - Form to send as the body
+    /// * `callback` - Callback to call with the response once received
+    #[wasm_bindgen]
+    pub fn send_form_data(
+        &self,
+        data: HttpConnectionRequest,
+        form: FormData,
+        callback: HttpResponseCallback,
+    ) -> Result<(), ConnectionError> {
+        let upload = BlobUpload::from_form_data(&form)?;
+        self.send_request(data, callback, BodyTarget::Buffer, Some(upload))
+    }
+
+    /// Send a request, streaming the body of its response as it is received.
+    ///
+    /// `callback` is called as soon as the head of the response is received. The response
+    /// has no body, its contents are read from `get_body_stream`, or `body_text_stream` for
+    /// text, which close once the whole body was received.
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - Request to send
+    /// * `callback` - Callback to call with the response once its head is received
+    #[wasm_bindgen]
+    pub fn send_streaming(
+        &self,
+        data: HttpConnectionRequest,
+        callback: HttpResponseCallback,
+    ) -> Result<(), ConnectionError> {
+        self.send_request(data, callback, BodyTarget::Stream(StreamBody::new()?), None)
+    }
+
+    /// Set the function called with the progress of the requests sent and responses received
+    /// on this connection.
+    ///
+    /// # Arguments
+    ///
+    /// * `callback` - Function called with a `Progress`, or `None` to stop reporting
+    #[wasm_bindgen]
+    pub fn set_onprogress(&self, callback: Option<ProgressCallback>) {
+        self.connection.progress.set(callback.map(Into::into));
+    }
 
     /// Ping this connection.
     ///
@@ -301,6 +1676,6 @@ impl HttpConnectionApi {
 
     /// Close this connection.
     pub fn close(&self) {
-        let _ = self.connection.socket.close();
+        self.connection.close();
     }
 }
@@ -0,0 +1,816 @@
+use std::{
+    cell::RefCell,
+    collections::{HashMap, VecDeque},
+    net::{Ipv4Addr, SocketAddr},
+    rc::Rc,
+    sync::Arc,
+};
+
+use bytes::{Bytes, BytesMut};
+use quinn_proto::{
+    crypto::rustls::QuicClientConfig, rustls, ClientConfig, ConnectionHandle, DatagramEvent, Dir,
+    Endpoint, EndpointConfig, Event, ReadError, StreamEvent, StreamId, VarInt, WriteError,
+};
+use wasm_bindgen::prelude::*;
+use wasm_bindgen_futures::js_sys::{ArrayBuffer, Uint8Array};
+use web_sys::{AddEventListenerOptions, MessageEvent, WebSocket};
+use web_time::Instant;
+
+use crate::{
+    callbacks::HttpResponseCallback,
+    connection::{Connection, ConnectionError, Sender},
+    console_log,
+    middleware::Pipeline,
+    qpack,
+    redirect::origin,
+    set_timeout,
+    stats::StatsRecorder,
+    timing::RequestTimer,
+};
+
+use super::http::{validate_method, HttpConnectionRequest, HttpConnectionResponse, HttpHeader};
+
+/// ALPN protocol of HTTP/3.
+const ALPN_H3: &[u8] = b"h3";
+/// Address standing for the target of the UDP connection, which the proxy relays datagrams
+/// to, as QUIC wants one.
+const TUNNEL_ADDR: Ipv4Addr = Ipv4Addr::new(192, 0, 2, 1);
+/// Largest field section accepted in a `HEADERS` frame, in bytes.
+const MAX_FIELD_SECTION_SIZE: u64 = 64 * 1024;
+
+/// Frame types of HTTP/3, RFC 9114 section 7.2.
+const FRAME_DATA: u64 = 0x0;
+const FRAME_HEADERS: u64 = 0x1;
+const FRAME_CANCEL_PUSH: u64 = 0x3;
+const FRAME_SETTINGS: u64 = 0x4;
+const FRAME_PUSH_PROMISE: u64 = 0x5;
+const FRAME_GOAWAY: u64 = 0x7;
+const FRAME_MAX_PUSH_ID: u64 = 0xD;
+
+/// Type of the control stream of HTTP/3.
+const STREAM_CONTROL: u64 = 0x0;
+/// Settings of QPACK, advertised as 0 so the server doesn't use the dynamic table.
+const SETTING_QPACK_MAX_TABLE_CAPACITY: u64 = 0x1;
+const SETTING_QPACK_BLOCKED_STREAMS: u64 = 0x7;
+/// Error code closing the connection without error.
+const H3_NO_ERROR: u32 = 0x100;
+
+/// Headers specific to HTTP/1.1 connections, which HTTP/3 requests must not carry.
+const CONNECTION_HEADERS: &[&str] = &[
+    "connection",
+    "host",
+    "keep-alive",
+    "proxy-connection",
+    "transfer-encoding",
+    "upgrade",
+];
+
+/// Encode a variable length integer of QUIC.
+fn encode_varint(out: &mut Vec<u8>, value: u64) {
+    match value {
+        0..=0x3F => out.push(value as u8),
+        0x40..=0x3FFF => out.extend_from_slice(&(value as u16 | 0x4000).to_be_bytes()),
+        0x4000..=0x3FFF_FFFF => out.extend_from_slice(&(value as u32 | 0x8000_0000).to_be_bytes()),
+        _ => out.extend_from_slice(&(value | 0xC000_0000_0000_0000).to_be_bytes()),
+    }
+}
+
+/// Decode a variable length integer of QUIC.
+///
+/// # Returns
+///
+/// The integer and its encoded length, or `None` if `buf` ends before it does.
+fn decode_varint(buf: &[u8]) -> Option<(u64, usize)> {
+    let first = *buf.first()?;
+    let len = 1 << (first >> 6);
+    let bytes = buf.get(..len)?;
+    let value = bytes[1..]
+        .iter()
+        .fold(u64::from(first & 0x3F), |value, &b| {
+            value << 8 | u64::from(b)
+        });
+    Some((value, len))
+}
+
+/// Encode a frame.
+fn encode_frame(frame_type: u64, payload: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(payload.len() + 16);
+    encode_varint(&mut out, frame_type);
+    encode_varint(&mut out, payload.len() as u64);
+    out.extend_from_slice(payload);
+    out
+}
+
+/// Split the next complete frame off the start of a buffer.
+///
+/// # Returns
+///
+/// The type and payload of the frame, or `None` if it isn't fully received yet.
+fn take_frame(buf: &mut Vec<u8>) -> Result<Option<(u64, Vec<u8>)>, ConnectionError> {
+    let (frame_type, type_len) = match decode_varint(buf) {
+        Some(varint) => varint,
+        None => return Ok(None),
+    };
+    let (len, len_len) = match decode_varint(&buf[type_len..]) {
+        Some(varint) => varint,
+        None => return Ok(None),
+    };
+    if frame_type == FRAME_HEADERS && len > MAX_FIELD_SECTION_SIZE {
+        return Err(ConnectionError {
+            message: format!("HTTP/3 field section of {} bytes is too large", len),
+        });
+    }
+    let len = usize::try_from(len).map_err(|_| ConnectionError {
+        message: format!("HTTP/3 frame of {} bytes is too large", len),
+    })?;
+    let start = type_len + len_len;
+    let end = start.saturating_add(len);
+    if buf.len() < end {
+        return Ok(None);
+    }
+    let payload = buf[start..end].to_vec();
+    buf.drain(..end);
+    Ok(Some((frame_type, payload)))
+}
+
+/// Encode a request as the frames of its stream.
+///
+/// # Arguments
+///
+/// * `request` - Request to encode
+/// * `authority` - Authority of the request if it has no `Host` header
+fn encode_request(request: &HttpConnectionRequest, authority: &str) -> Vec<u8> {
+    let authority = request
+        .headers
+        .iter()
+        .find(|h| h.name().eq_ignore_ascii_case("Host"))
+        .map_or(authority, |h| h.value());
+    let mut fields = vec![
+        (":method".to_string(), request.method.clone()),
+        (":scheme".to_string(), "https".to_string()),
+        (":authority".to_string(), authority.to_string()),
+        (":path".to_string(), request.path.clone()),
+    ];
+    for header in &request.headers {
+        let name = header.name().to_ascii_lowercase();
+        // `TE` is only allowed to announce support for trailers
+        let te = name == "te" && !header.value().eq_ignore_ascii_case("trailers");
+        if CONNECTION_HEADERS.contains(&name.as_str()) || te {
+            continue;
+        }
+        fields.push((name, header.value().to_string()));
+    }
+    let mut out = encode_frame(FRAME_HEADERS, &qpack::encode_field_section(&fields));
+    if let Some(body) = request.body.as_ref().filter(|body| !body.is_empty()) {
+        out.extend(encode_frame(FRAME_DATA, body));
+    }
+    out
+}
+
+/// Request sent or waiting for a stream, with its response as received so far.
+struct RequestStream {
+    /// Callback to call with the response
+    callback: HttpResponseCallback,
+    /// Correlation ID of the request, if one was assigned
+    request_id: Option<String>,
+    /// Timer of the request
+    timer: RequestTimer,
+    /// Frames of the request not accepted by the stream yet
+    outgoing: Vec<u8>,
+    /// Whether the end of the request was sent
+    finished: bool,
+    /// Bytes of the response not parsed into frames yet
+    incoming: Vec<u8>,
+    /// Status and headers of the final response, once received
+    head: Option<(u16, Vec<HttpHeader>)>,
+    /// Body of the response received so far
+    body: Vec<u8>,
+}
+
+impl RequestStream {
+    /// Parse the complete frames of the response received so far.
+    fn parse_frames(&mut self) -> Result<(), ConnectionError> {
+        while let Some((frame_type, payload)) = take_frame(&mut self.incoming)? {
+            match frame_type {
+                FRAME_HEADERS if self.head.is_none() => {
+                    let fields = qpack::decode_field_section(&payload)?;
+                    let status = fields
+                        .iter()
+                        .find(|(name, _)| name == ":status")
+                        .and_then(|(_, value)| value.parse::<u16>().ok())
+                        .ok_or_else(|| ConnectionError {
+                            message: "HTTP/3 response without a valid :status".to_string(),
+                        })?;
+                    // Informational responses precede the final one
+                    if (100..200).contains(&status) {
+                        continue;
+                    }
+                    let headers = fields
+                        .iter()
+                        .filter(|(name, _)| !name.starts_with(':'))
+                        .map(|(name, value)| HttpHeader::new(name, value))
+                        .collect();
+                    self.head = Some((status, headers));
+                }
+                // Trailers are not exposed
+                FRAME_HEADERS => {}
+                FRAME_DATA if self.head.is_some() => self.body.extend_from_slice(&payload),
+                FRAME_DATA | FRAME_CANCEL_PUSH | FRAME_SETTINGS | FRAME_PUSH_PROMISE
+                | FRAME_GOAWAY | FRAME_MAX_PUSH_ID => {
+                    return Err(ConnectionError {
+                        message: format!("Unexpected HTTP/3 frame {:#x} on request", frame_type),
+                    })
+                }
+                // Unknown and reserved frame types are ignored
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Build the response once its stream ended.
+    fn finish(&mut self) -> Result<HttpConnectionResponse, ConnectionError> {
+        let (code, headers) = self.head.take().ok_or_else(|| ConnectionError {
+            message: "HTTP/3 stream ended before the response headers".to_string(),
+        })?;
+        let body = std::mem::take(&mut self.body);
+        Ok(HttpConnectionResponse::new(code, headers, Some(body))
+            .with_status_line("HTTP/3".to_string(), String::new())
+            .with_timing(self.timer.finish()))
+    }
+}
+
+/// Responses completed or failed while driving the connection, to deliver once the state
+/// is no longer borrowed.
+type Completed = Vec<(
+    RequestStream,
+    Result<HttpConnectionResponse, ConnectionError>,
+)>;
+
+/// QUIC connection carrying HTTP/3 over the datagrams of a UDP connection.
+struct Http3State {
+    /// Sender of the datagrams of the UDP connection
+    sender: Sender,
+    /// Endpoint of the QUIC connection
+    endpoint: Endpoint,
+    /// QUIC connection, once the UDP connection is open
+    quic: Option<(ConnectionHandle, quinn_proto::Connection)>,
+    /// Config of the QUIC connection, until it is started
+    config: Option<ClientConfig>,
+    /// Address the QUIC connection is made to
+    remote: SocketAddr,
+    /// TLS server name and default authority of requests
+    server_name: String,
+    /// Whether the handshake completed
+    connected: bool,
+    /// Whether the connection is closed or going away, refusing new requests
+    closed: bool,
+    /// Requests waiting for the handshake or a stream
+    queued: VecDeque<RequestStream>,
+    /// Requests sent, by stream
+    requests: HashMap<StreamId, RequestStream>,
+    /// Time the next timer of the connection is scheduled at, if any
+    timer: Option<Instant>,
+    /// Statistics of the client
+    stats: StatsRecorder,
+    /// Prefix of the log lines of the connection
+    log_prefix: String,
+}
+
+impl Http3State {
+    /// Start the QUIC connection, once the UDP connection is open.
+    fn connect(&mut self) -> Completed {
+        let config = match self.config.take() {
+            Some(config) => config,
+            None => return Vec::new(),
+        };
+        match self
+            .endpoint
+            .connect(Instant::now(), config, self.remote, &self.server_name)
+        {
+            Ok(quic) => {
+                self.quic = Some(quic);
+                Vec::new()
+            }
+            Err(e) => self.fail_all(&format!("Failed to start QUIC connection: {}", e)),
+        }
+    }
+
+    /// Handle a datagram received from the UDP connection.
+    fn receive(&mut self, datagram: &[u8]) {
+        let (handle, quic) = match &mut self.quic {
+            Some(quic) => quic,
+            None => return,
+        };
+        let mut buf = Vec::new();
+        let event = self.endpoint.handle(
+            Instant::now(),
+            self.remote,
+            None,
+            None,
+            BytesMut::from(datagram),
+            &mut buf,
+        );
+        match event {
+            Some(DatagramEvent::ConnectionEvent(h, event)) if h == *handle => {
+                quic.handle_event(event)
+            }
+            Some(DatagramEvent::Response(transmit)) => {
+                let _ = self.sender.send(&buf[..transmit.size]);
+            }
+            _ => {}
+        }
+    }
+
+    /// Handle the expiry of the timer of the connection.
+    fn handle_timeout(&mut self) {
+        self.timer = None;
+        if let Some((_, quic)) = &mut self.quic {
+            quic.handle_timeout(Instant::now());
+        }
+    }
+
+    /// Process the events of the connection, start queued requests and send what the
+    /// connection has to send.
+    fn poll(&mut self) -> Completed {
+        let mut completed = Vec::new();
+        let (handle, quic) = match &mut self.quic {
+            Some(quic) => quic,
+            None => return completed,
+        };
+        let handle = *handle;
+        let mut lost = None;
+        loop {
+            while let Some(event) = quic.poll_endpoint_events() {
+                if let Some(event) = self.endpoint.handle_event(handle, event) {
+                    quic.handle_event(event);
+                }
+            }
+            let event = match quic.poll() {
+                Some(event) => event,
+                None => break,
+            };
+            match event {
+                Event::Connected => {
+                    self.connected = true;
+                    open_control_stream(quic);
+                    for request in &self.queued {
+                        request.timer.handshake_done();
+                    }
+                }
+                Event::ConnectionLost { reason } => {
+                    lost = Some(format!("HTTP/3 connection lost: {}", reason));
+                    break;
+                }
+                Event::Stream(StreamEvent::Opened { dir: Dir::Uni }) => {
+                    while let Some(id) = quic.streams().accept(Dir::Uni) {
+                        discard(quic, id);
+                    }
+                }
+                Event::Stream(StreamEvent::Readable { id }) => {
+                    if !self.requests.contains_key(&id) {
+                        discard(quic, id);
+                        continue;
+                    }
+                    match read_response(quic, id, self.requests.get_mut(&id).unwrap()) {
+                        Ok(false) => {}
+                        Ok(true) => {
+                            let mut request = self.requests.remove(&id).unwrap();
+                            let response = request.finish();
+                            completed.push((request, response));
+                        }
+                        Err(e) => {
+                            let request = self.requests.remove(&id).unwrap();
+                            completed.push((request, Err(e)));
+                        }
+                    }
+                }
+                Event::Stream(StreamEvent::Writable { id }) => {
+                    if let Some(request) = self.requests.get_mut(&id) {
+                        flush(quic, id, request);
+                    }
+                }
+                _ => {}
+            }
+        }
+        if let Some(message) = lost {
+            completed.extend(self.fail_all(&message));
+            return completed;
+        }
+
+        if self.connected {
+            while !self.queued.is_empty() {
+                let id = match quic.streams().open(Dir::Bi) {
+                    Some(id) => id,
+                    None => break,
+                };
+                let mut request = self.queued.pop_front().unwrap();
+                flush(quic, id, &mut request);
+                self.requests.insert(id, request);
+            }
+        }
+
+        let mut buf = Vec::new();
+        while let Some(transmit) = quic.poll_transmit(Instant::now(), 1, &mut buf) {
+            let _ = self.sender.send(&buf[..transmit.size]);
+            buf.clear();
+        }
+        completed
+    }
+
+    /// Close the QUIC connection, sending the close to the server.
+    fn close(&mut self) -> Completed {
+        if let Some((_, quic)) = &mut self.quic {
+            quic.close(Instant::now(), VarInt::from_u32(H3_NO_ERROR), Bytes::new());
+        }
+        let mut completed = self.poll();
+        completed.extend(self.fail_all("HTTP/3 connection closed"));
+        completed
+    }
+
+    /// Fail every request, and refuse new ones.
+    fn fail_all(&mut self, message: &str) -> Completed {
+        self.closed = true;
+        self.queued
+            .drain(..)
+            .chain(self.requests.drain().map(|(_, request)| request))
+            .map(|request| {
+                let error = ConnectionError {
+                    message: message.to_string(),
+                };
+                (request, Err(error))
+            })
+            .collect()
+    }
+}
+
+/// Open the control stream and send the settings of the client.
+fn open_control_stream(quic: &mut quinn_proto::Connection) {
+    let id = match quic.streams().open(Dir::Uni) {
+        Some(id) => id,
+        None => return,
+    };
+    let mut settings = Vec::new();
+    for (setting, value) in [
+        (SETTING_QPACK_MAX_TABLE_CAPACITY, 0),
+        (SETTING_QPACK_BLOCKED_STREAMS, 0),
+    ] {
+        encode_varint(&mut settings, setting);
+        encode_varint(&mut settings, value);
+    }
+    let mut stream = Vec::new();
+    encode_varint(&mut stream, STREAM_CONTROL);
+    stream.extend(encode_frame(FRAME_SETTINGS, &settings));
+    // Small enough to fit in the initial flow control window
+    let _ = quic.send_stream(id).write(&stream);
+}
+
+/// Read and drop what the server sent on a stream other than a request stream.
+///
+/// The control stream of the server is only needed for extensions, and QPACK streams are
+/// empty as the dynamic table has no capacity.
+fn discard(quic: &mut quinn_proto::Connection, id: StreamId) {
+    let mut stream = quic.recv_stream(id);
+    if let Ok(mut chunks) = stream.read(true) {
+        while let Ok(Some(_)) = chunks.next(usize::MAX) {}
+        let _ = chunks.finalize();
+    };
+}
+
+/// Send what the stream accepts of a request, then its end.
+fn flush(quic: &mut quinn_proto::Connection, id: StreamId, request: &mut RequestStream) {
+    let mut stream = quic.send_stream(id);
+    if !request.outgoing.is_empty() {
+        match stream.write(&request.outgoing) {
+            Ok(written) => {
+                request.outgoing.drain(..written);
+            }
+            Err(WriteError::Blocked) => {}
+            Err(_) => return,
+        }
+    }
+    if request.outgoing.is_empty() && !request.finished {
+        request.finished = stream.finish().is_ok();
+    }
+}
+
+/// Read what was received of a response.
+///
+/// # Returns
+///
+/// Whether the stream ended.
+fn read_response(
+    quic: &mut quinn_proto::Connection,
+    id: StreamId,
+    request: &mut RequestStream,
+) -> Result<bool, ConnectionError> {
+    let mut stream = quic.recv_stream(id);
+    let mut chunks = stream.read(true).map_err(|e| ConnectionError {
+        message: format!("Failed to read HTTP/3 stream: {}", e),
+    })?;
+    let mut ended = false;
+    loop {
+        match chunks.next(usize::MAX) {
+            Ok(Some(chunk)) => {
+                request.timer.first_byte();
+                request.incoming.extend_from_slice(&chunk.bytes);
+            }
+            Ok(None) => {
+                ended = true;
+                break;
+            }
+            Err(ReadError::Blocked) => break,
+            Err(ReadError::Reset(code)) => {
+                return Err(ConnectionError {
+                    message: format!("HTTP/3 stream reset by the server: {}", code),
+                })
+            }
+        }
+    }
+    let _ = chunks.finalize();
+    request.parse_frames()?;
+    Ok(ended)
+}
+
+/// Drive the connection, then deliver the responses completed and schedule its timer.
+fn drive(state: &Rc<RefCell<Http3State>>, pipeline: &Pipeline) {
+    let completed = state.borrow_mut().poll();
+    deliver(state, pipeline, completed);
+
+    let mut inner = state.borrow_mut();
+    let deadline = inner
+        .quic
+        .as_mut()
+        .and_then(|(_, quic)| quic.poll_timeout());
+    if deadline.is_none() || deadline == inner.timer {
+        return;
+    }
+    inner.timer = deadline;
+    let delay = deadline
+        .unwrap()
+        .saturating_duration_since(Instant::now())
+        .as_secs_f64()
+        * 1000.0;
+    drop(inner);
+
+    let state = state.clone();
+    let pipeline = pipeline.clone();
+    let handler = Closure::once_into_js(move || {
+        // A later deadline replaced this one
+        if state.borrow().timer != deadline {
+            return;
+        }
+        state.borrow_mut().handle_timeout();
+        drive(&state, &pipeline);
+    });
+    set_timeout(&handler, delay);
+}
+
+/// Pass completed responses through the middlewares and to their callbacks.
+fn deliver(state: &Rc<RefCell<Http3State>>, pipeline: &Pipeline, completed: Completed) {
+    if completed.is_empty() {
+        return;
+    }
+    let (stats, log_prefix) = {
+        let state = state.borrow();
+        (state.stats.clone(), state.log_prefix.clone())
+    };
+    for (request, response) in completed {
+        let response = response
+            .and_then(|r| pipeline.on_response(r.with_request_id(request.request_id.clone())));
+        match response {
+            Ok(response) => {
+                stats.record_response(request.timer.elapsed(), request.timer.time_to_first_byte());
+                request
+                    .callback
+                    .call1(&JsValue::null(), &JsValue::from(response))
+                    .unwrap_throw();
+            }
+            Err(e) => {
+                stats.record_error();
+                let e = e.for_request(request.request_id.as_deref());
+                console_log!("{}{}", log_prefix, e);
+                let _ = request
+                    .callback
+                    .call2(&JsValue::null(), &JsValue::null(), &e.into());
+            }
+        }
+    }
+}
+
+/// Build the TLS config of HTTP/3 connections, negotiating `h3`.
+fn client_config() -> Result<ClientConfig, ConnectionError> {
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    let mut tls = rustls::ClientConfig::builder_with_provider(provider)
+        .with_protocol_versions(&[&rustls::version::TLS13])
+        .map_err(|e| ConnectionError {
+            message: format!("TLS error: {}", e),
+        })?
+        .with_root_certificates(rustls::RootCertStore {
+            roots: webpki_roots::TLS_SERVER_ROOTS.into(),
+        })
+        .with_no_client_auth();
+    tls.alpn_protocols = vec![ALPN_H3.to_vec()];
+    let crypto = QuicClientConfig::try_from(tls).map_err(|e| ConnectionError {
+        message: format!("TLS error: {}", e),
+    })?;
+    Ok(ClientConfig::new(Arc::new(crypto)))
+}
+
+/// Experimental HTTP/3 connection, carried by QUIC over a UDP connection through the proxy.
+///
+/// Requests are multiplexed on the connection as soon as its handshake completes, and their
+/// responses are delivered once complete. The QPACK dynamic table is disabled, and server
+/// push and trailers are not supported.
+#[wasm_bindgen]
+pub struct Http3ConnectionApi {
+    /// UDP connection carrying the QUIC connection
+    connection: Connection,
+    /// Middlewares to run requests and responses through
+    pipeline: Pipeline,
+    /// State of the QUIC connection
+    state: Rc<RefCell<Http3State>>,
+}
+
+impl Http3ConnectionApi {
+    /// Create a new HTTP/3 connection over a UDP connection, started once it is open.
+    ///
+    /// # Arguments
+    ///
+    /// * `connection` - UDP connection to the server
+    /// * `pipeline` - Middlewares to run requests and responses through
+    pub fn new(connection: Connection, pipeline: Pipeline) -> Result<Self, ConnectionError> {
+        let (host, port) = match connection.addr.rsplit_once(':') {
+            Some((host, port)) => (host, port.parse().unwrap_or(443)),
+            None => (connection.addr.as_str(), 443),
+        };
+        let state = Rc::new(RefCell::new(Http3State {
+            sender: connection.sender(),
+            endpoint: Endpoint::new(Arc::new(EndpointConfig::default()), None, true, None),
+            quic: None,
+            config: Some(client_config()?),
+            remote: SocketAddr::from((TUNNEL_ADDR, port)),
+            server_name: host.trim_matches(['[', ']']).to_string(),
+            connected: false,
+            closed: false,
+            queued: VecDeque::new(),
+            requests: HashMap::new(),
+            timer: None,
+            stats: connection.stats.clone(),
+            log_prefix: connection.log_prefix(),
+        }));
+        let api = Self {
+            connection,
+            pipeline,
+            state,
+        };
+        api.listen();
+        Ok(api)
+    }
+
+    /// Start the QUIC connection once the socket is open, and feed it the datagrams received.
+    fn listen(&self) {
+        let socket = &self.connection.socket;
+        let start = {
+            let state = self.state.clone();
+            let pipeline = self.pipeline.clone();
+            move || {
+                let failed = state.borrow_mut().connect();
+                deliver(&state, &pipeline, failed);
+                drive(&state, &pipeline);
+            }
+        };
+        if socket.ready_state() == WebSocket::OPEN {
+            start();
+        } else {
+            socket
+                .add_event_listener_with_callback_and_add_event_listener_options(
+                    "open",
+                    Closure::once_into_js(start).unchecked_ref(),
+                    AddEventListenerOptions::new().once(true),
+                )
+                .unwrap_throw();
+        }
+
+        let state = self.state.clone();
+        let pipeline = self.pipeline.clone();
+        let message_callback: Closure<dyn Fn(MessageEvent)> =
+            Closure::wrap(Box::new(move |evt: MessageEvent| {
+                if let Some(buffer) = evt.data().dyn_ref::<ArrayBuffer>() {
+                    state
+                        .borrow_mut()
+                        .receive(&Uint8Array::new(buffer).to_vec());
+                    drive(&state, &pipeline);
+                }
+            }));
+        socket
+            .add_event_listener_with_callback("message", message_callback.as_ref().unchecked_ref())
+            .unwrap_throw();
+        message_callback.forget();
+
+        let state = self.state.clone();
+        let pipeline = self.pipeline.clone();
+        let onclose = Closure::once_into_js(move || {
+            let failed = state.borrow_mut().fail_all("UDP connection closed");
+            deliver(&state, &pipeline, failed);
+        });
+        socket
+            .add_event_listener_with_callback_and_add_event_listener_options(
+                "close",
+                onclose.unchecked_ref(),
+                AddEventListenerOptions::new().once(true),
+            )
+            .unwrap_throw();
+    }
+}
+
+#[wasm_bindgen]
+impl Http3ConnectionApi {
+    /// Get the address of this connection.
+    #[wasm_bindgen]
+    pub fn get_addr(&self) -> String {
+        self.connection.addr.clone()
+    }
+
+    /// Get the ID of the UDP connection carrying this connection.
+    #[wasm_bindgen]
+    pub fn get_id(&self) -> u64 {
+        self.connection.get_id().into()
+    }
+
+    /// Get the UUID of the UDP connection carrying this connection, if made with
+    /// `ConnIdScheme.UuidV7`.
+    #[wasm_bindgen]
+    pub fn get_uuid(&self) -> Option<String> {
+        self.connection.get_id().uuid_string()
+    }
+
+    /// Check if the QUIC handshake of this connection completed.
+    #[wasm_bindgen]
+    pub fn is_connected(&self) -> bool {
+        self.state.borrow().connected
+    }
+
+    /// Send a request on a new stream of this connection.
+    ///
+    /// Requests sent before the handshake completes are queued until it does. Connection
+    /// specific headers, like `Connection` or `Transfer-Encoding`, are not sent, and `Host`
+    /// becomes the `:authority` of the request.
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - Request to send
+    /// * `callback` - Callback to call with the response once received
+    #[wasm_bindgen]
+    pub fn send(
+        &self,
+        data: HttpConnectionRequest,
+        callback: HttpResponseCallback,
+    ) -> Result<(), ConnectionError> {
+        if self.state.borrow().closed {
+            return Err(ConnectionError {
+                message: "HTTP/3 connection is closed".to_string(),
+            });
+        }
+        let mut data = data;
+        data.client_name = self.connection.client_name.clone();
+        data.tags = self.connection.get_tags();
+        data.target = Some(origin(&format!("https://{}", self.connection.addr)));
+        let data = self.pipeline.on_request(data)?;
+        validate_method(&data.method)?;
+
+        let mut state = self.state.borrow_mut();
+        let timer = RequestTimer::start(&self.connection.setup);
+        if state.connected {
+            timer.handshake_done();
+        }
+        match &data.request_id {
+            Some(id) => console_log!("{}[{}] Sending HTTP/3 request", state.log_prefix, id),
+            None => console_log!("{}Sending HTTP/3 request", state.log_prefix),
+        }
+        state.stats.record_request();
+        let outgoing = encode_request(&data, &state.server_name);
+        state.queued.push_back(RequestStream {
+            callback,
+            request_id: data.request_id,
+            timer,
+            outgoing,
+            finished: false,
+            incoming: Vec::new(),
+            head: None,
+            body: Vec::new(),
+        });
+        drop(state);
+
+        drive(&self.state, &self.pipeline);
+        Ok(())
+    }
+
+    /// Close this connection, failing the requests waiting for a response.
+    #[wasm_bindgen]
+    pub fn close(&self) {
+        let failed = self.state.borrow_mut().close();
+        deliver(&self.state, &self.pipeline, failed);
+        self.connection.close();
+    }
+}
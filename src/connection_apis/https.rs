@@ -1,168 +1,461 @@
 use std::{
-    io::{Read, Write},
-    sync::{Arc, Mutex},
+    cell::{Cell, OnceCell, RefCell},
+    rc::{Rc, Weak},
 };
 
-use rustls::{
-    client::ClientConnectionData,
-    server,
-    version::{TLS12, TLS13},
-    ClientConfig, ClientConnection, RootCertStore,
-};
-use rustls_pki_types::{DnsName, IpAddr, ServerName};
 use wasm_bindgen::prelude::*;
-use wasm_bindgen_futures::js_sys::{self, ArrayBuffer, Uint8Array};
-use web_sys::{AddEventListenerOptions, MessageEvent};
+use wasm_bindgen_futures::js_sys::{ArrayBuffer, Uint8Array};
+use web_sys::{AddEventListenerOptions, Blob, FormData, MessageEvent, WebSocket};
 
 use crate::{
-    connection::{Connection, ConnectionError},
-    console_log, http, SocketCapability, TLSVersion,
+    callbacks::{HandshakeCallback, HttpResponseCallback, ProgressCallback},
+    connection::{Connection, ConnectionError, KeepAlive, Sender},
+    http,
+    middleware::Pipeline,
+    pending::{release_listener, PendingRequest},
+    progress::{HandshakeEvent, Progress, ProgressDirection, ProgressReporter},
+    redirect::origin,
+    set_timeout,
+    stats::StatsRecorder,
+    timing::RequestTimer,
+    tls::{is_version_rejection, TlsEngine, TlsOptions, TlsOutputCallback, TlsVersions},
+    worker::TlsWorker,
+    SocketCapability,
 };
 
-use super::http::HttpHeader;
+use super::{
+    assembler::ResponseAssembler,
+    http::{
+        expects_continue, validate_method, BlobBody, BodyTarget, HttpConnectionRequest,
+        PendingBody, RequestDeadline, RequestErrors,
+    },
+    idb::IdbBody,
+    stream::StreamBody,
+    upload::BlobUpload,
+};
 
 #[wasm_bindgen]
-pub struct HttpsConnectionRequest {
-    /// Request method
-    method: String,
-    /// Request path
-    path: String,
-    /// Request headers
-    headers: Vec<HttpHeader>,
-    /// Request body
-    body: Option<Vec<u8>>,
+pub struct HttpsConnectionApi {
+    /// Connection to create API for
+    connection: Connection,
+    /// Worker to run the TLS state machine in, if any
+    tls_worker: Option<TlsWorker>,
+    /// Middlewares of the client
+    pipeline: Pipeline,
 }
 
-#[wasm_bindgen]
-impl HttpsConnectionRequest {
-    /// Create a new HTTP request.
+impl HttpsConnectionApi {
+    /// Create a new API instance for the given connection.
     ///
     /// # Arguments
     ///
-    /// * `method` - Request method
-    /// * `headers` - Request headers
-    /// * `body` - Request body
-    #[wasm_bindgen(constructor)]
-    pub fn new(
-        method: String,
-        path: String,
-        headers: Vec<HttpHeader>,
-        body: Option<Vec<u8>>,
-    ) -> Self {
+    /// * `connection` - Connection to create API for
+    /// * `tls_worker` - Worker to run the TLS state machine in, or `None` to run it on this thread
+    /// * `pipeline` - Middlewares to run requests and responses through
+    pub fn new(connection: Connection, tls_worker: Option<TlsWorker>, pipeline: Pipeline) -> Self {
         Self {
-            method,
-            path,
-            headers,
-            body,
+            connection,
+            tls_worker,
+            pipeline,
         }
     }
-}
 
-#[wasm_bindgen]
-pub struct HttpsConnectionResponse {
-    /// Response code
-    code: u16,
-    /// Response headers
-    headers: Vec<HttpHeader>,
-    /// Response body
-    body: Option<Vec<u8>>,
-}
+    /// Get the connection of this API.
+    pub(crate) fn connection(&self) -> &Connection {
+        &self.connection
+    }
 
-#[wasm_bindgen]
-impl HttpsConnectionResponse {
-    /// Create a new HTTP response.
-    ///
-    /// # Arguments
+    /// Send a request, delivering the body of its response to `target`.
     ///
-    /// * `code` - Response code
-    /// * `headers` - Response headers
-    /// * `body` - Response body
-    #[wasm_bindgen(constructor)]
-    pub fn new(code: u16, headers: Vec<HttpHeader>, body: Option<Vec<u8>>) -> Self {
-        Self {
-            code,
-            headers,
-            body,
+    /// If `upload` is given, it is sent as the body of the request in place of its own.
+    fn send_request(
+        &self,
+        data: HttpConnectionRequest,
+        callback: HttpResponseCallback,
+        target: BodyTarget,
+        upload: Option<BlobUpload>,
+    ) -> Result<(), ConnectionError> {
+        if self.connection.socket.ready_state() != 1 {
+            return Err(ConnectionError {
+                message: "Connection is not open".to_string(),
+            });
         }
-    }
-
-    /// Get the response code.
-    #[wasm_bindgen]
-    pub fn get_code(&self) -> u16 {
-        self.code
-    }
+        let tls = match (self.connection.protocol, &self.connection.tls) {
+            (SocketCapability::HTTPS(_), Some(tls)) => tls.clone(),
+            _ => {
+                return Err(ConnectionError {
+                    message: "Connection is not a HTTPS connection".to_string(),
+                })
+            }
+        };
+        let mut data = data;
+        data.client_name = self.connection.client_name.clone();
+        data.tags = self.connection.get_tags();
+        data.target = Some(origin(&format!("https://{}", self.connection.addr)));
+        let mut data = self.pipeline.on_request(data)?;
+        validate_method(&data.method)?;
+        if let Some(upload) = &upload {
+            upload.prepare(&mut data);
+        }
+        let in_flight = self.connection.pending.start()?;
+        let request_id = data.request_id.clone();
+        let expect_continue = expects_continue(&data);
+        let timeout = data.timeout;
+        let method = data.method.clone();
+        let req = if let Some(body) = data.body {
+            http!(data.method, data.path, data.headers, body.to_vec())
+        } else {
+            http!(data.method, data.path, data.headers)
+        };
 
-    /// Get the response headers.
-    #[wasm_bindgen]
-    pub fn get_headers(&self) -> Vec<HttpHeader> {
-        self.headers.clone()
+        self.connection.stats.record_request();
+        let log_prefix = self.connection.log_prefix();
+        let errors = RequestErrors::new(&callback, &request_id, &log_prefix);
+        let deadline = RequestDeadline::start(
+            timeout,
+            self.connection.timeout_policy,
+            self.connection.socket.clone(),
+            self.connection.stats.clone(),
+            errors.clone(),
+            in_flight.clone(),
+        );
+        let context = RequestContext {
+            socket: self.connection.socket.clone(),
+            sender: self.connection.sender(),
+            keep_alive: self.connection.keep_alive(),
+            progress: self.connection.progress.clone(),
+            stats: self.connection.stats.clone(),
+            pipeline: self.pipeline.clone(),
+            tls_worker: self.tls_worker.clone(),
+            tls,
+            tls_options: self.connection.tls_options.clone(),
+            addr: self.connection.addr.clone(),
+            log_prefix,
+        };
+        context
+            .exchange(Exchange {
+                req,
+                method,
+                expect_continue,
+                request_id,
+                callback,
+                errors,
+                target,
+                upload,
+                timer: RequestTimer::start(&self.connection.setup),
+                deadline: deadline.clone(),
+                in_flight: in_flight.clone(),
+            })
+            .inspect_err(|_| {
+                deadline.finish();
+                in_flight.finish();
+            })
     }
+}
 
-    /// Get the response body.
-    #[wasm_bindgen]
-    pub fn get_body(&self) -> Option<Vec<u8>> {
-        return self.body.clone();
-    }
+/// Handles of a connection needed to run a request.
+struct RequestContext {
+    /// WebSocket connection
+    socket: WebSocket,
+    /// Sender of the connection
+    sender: Sender,
+    /// Keep-alive handle of the connection
+    keep_alive: KeepAlive,
+    /// Progress reporter of the connection
+    progress: ProgressReporter,
+    /// Statistics of the client
+    stats: StatsRecorder,
+    /// Middlewares of the client
+    pipeline: Pipeline,
+    /// Worker to run the TLS state machine in, if any
+    tls_worker: Option<TlsWorker>,
+    /// TLS versions of the connection
+    tls: TlsVersions,
+    /// Cipher suites and key exchange groups of the connection
+    tls_options: TlsOptions,
+    /// Address of the connection
+    addr: String,
+    /// Prefix of the log lines of the connection
+    log_prefix: String,
 }
 
-#[wasm_bindgen]
-pub struct HttpsConnectionApi {
-    /// Connection to create API for
-    connection: Connection,
-    /// TLS client config
-    config: Arc<ClientConfig>,
-    /// TLS server name
-    server_name: ServerName<'static>,
+/// Request being sent through a TLS session.
+struct Exchange {
+    /// Serialized request, head and body
+    req: Vec<u8>,
+    /// Method of the request
+    method: String,
+    /// Whether the body is held back until the server sends `100 Continue`
+    expect_continue: bool,
+    /// Correlation ID of the request, if one was assigned
+    request_id: Option<String>,
+    /// Callback called with the response
+    callback: HttpResponseCallback,
+    /// Reporter of the errors of the request
+    errors: RequestErrors,
+    /// Where the body of the response goes
+    target: BodyTarget,
+    /// Blob sent as the body of the request after `req`, if any
+    upload: Option<BlobUpload>,
+    /// Timer of the request, started when it was sent
+    timer: RequestTimer,
+    /// Deadline of the response
+    deadline: RequestDeadline,
+    /// Request as tracked by the connection
+    in_flight: PendingRequest,
 }
 
-impl HttpsConnectionApi {
-    /// Create a new API instance for the given connection.
-    ///
-    /// # Arguments
+impl RequestContext {
+    /// Start a TLS session and send a request through it.
     ///
-    /// * `connection` - Connection to create API for
-    pub fn new(connection: Connection) -> Self {
-        let root_store = RootCertStore {
-            roots: webpki_roots::TLS_SERVER_ROOTS.into(),
-        };
-
-        let protocol_version = match connection.protocol {
-            SocketCapability::HTTPS(TLSVersion::TLSv1_2) => &TLS12,
-            SocketCapability::HTTPS(TLSVersion::TLSv1_3) => &TLS13,
-            _ => panic!("Invalid protocol version"),
-        };
-
-        let config = Arc::new(
-            ClientConfig::builder_with_protocol_versions(&[protocol_version])
-                .with_root_certificates(root_store)
-                .with_no_client_auth(),
-        );
-
-        // Determine if the server name is an IP address or a domain name
-
-        let addr: String = connection
-            .addr
-            .clone()
-            .split(':')
-            .next()
-            .unwrap_throw()
-            .to_string();
-
-        console_log!("Connecting to {}", addr);
+    /// If the server rejects the offered TLS versions during the handshake, the request fails
+    /// and the connection is closed, new connections falling back to the next lower version
+    /// the client allows, if any.
+    fn exchange(&self, exchange: Exchange) -> Result<(), ConnectionError> {
+        let Exchange {
+            mut req,
+            method,
+            expect_continue,
+            request_id,
+            callback,
+            errors,
+            target,
+            upload,
+            timer,
+            deadline,
+            in_flight,
+        } = exchange;
+
+        // Holds JS values when not buffering the body, so it stays on this thread
+        let assembler: Rc<RefCell<ResponseAssembler>> = Rc::new(RefCell::new(
+            target
+                .assembler(
+                    self.progress.sink(ProgressDirection::Download),
+                    &self.pipeline,
+                    &request_id,
+                    &callback,
+                    &timer,
+                    &errors,
+                )
+                .with_method(&method),
+        ));
+
+        let progress = self.progress.clone();
+        let total = req.len() + upload.as_ref().map_or(0, BlobUpload::size);
+        let pending = PendingBody::split(&mut req, expect_continue);
+        // Set once the engine is started, so the output callback can write the held back body
+        let engine_slot: Rc<OnceCell<Weak<TlsEngine>>> = Rc::new(OnceCell::new());
+
+        let sender = self.sender.clone();
+        let pipeline = self.pipeline.clone();
+        let keep_alive = self.keep_alive.clone();
+        let cb_pending = pending.clone();
+        let cb_engine_slot = engine_slot.clone();
+        let cb_progress = progress.clone();
+        let stats = self.stats.clone();
+        let tls = self.tls.clone();
+        // Whether the handshake is in progress, and the number of bytes received during it
+        let handshaking = Rc::new(Cell::new(true));
+        let handshake_read = Rc::new(Cell::new(0));
+        let cb_handshaking = handshaking.clone();
+        let cb_handshake_read = handshake_read.clone();
+        let cb_in_flight = in_flight.clone();
+        let cb_deadline = deadline.clone();
+        let cb_target = target.clone();
+        let cb_errors = errors.clone();
+        let probe_assembler = assembler.clone();
+
+        let output_callback: TlsOutputCallback = Box::new(move |output| {
+            if deadline.is_expired() {
+                return;
+            }
+            let output = match output {
+                Ok(output) => output,
+                Err(mut e) => {
+                    if cb_handshaking.get() && is_version_rejection(&e) {
+                        if let Some(version) = tls.fall_back() {
+                            e.message = format!(
+                                "{}, falling back to {} on new connections",
+                                e.message, version
+                            );
+                        }
+                    }
+                    deadline.finish();
+                    cb_in_flight.finish();
+                    // The session is broken, so the rest of its records cannot be read
+                    let _ = keep_alive.socket.close();
+                    stats.record_error();
+                    errors.report(e);
+                    return;
+                }
+            };
+
+            if !output.tls.is_empty() {
+                sender.send(&output.tls).unwrap_throw();
+            }
+
+            #[cfg(feature = "keylog")]
+            crate::keylog::emit(output.key_log.clone());
+
+            for stage in &output.stages {
+                cb_progress.report_handshake(HandshakeEvent::new(
+                    *stage,
+                    output.version,
+                    output.cipher_suite.clone(),
+                    timer.elapsed(),
+                ));
+            }
+
+            if cb_handshaking.get() {
+                let read = cb_handshake_read.get();
+                if output.handshaking {
+                    cb_progress.report(Progress::indeterminate(ProgressDirection::Handshake, read));
+                } else {
+                    cb_handshaking.set(false);
+                    timer.handshake_done();
+                    if let Some(version) = output.version {
+                        tls.set_negotiated(version);
+                    }
+                    cb_progress.report(Progress::new(ProgressDirection::Handshake, read, read));
+                }
+            }
+
+            if output.plaintext.is_empty() {
+                return;
+            }
+            timer.first_byte();
+
+            let mut assembler = assembler.borrow_mut();
+            let pushed = assembler.push(&output.plaintext);
+            let malformed = pushed.is_err();
+            let response = pushed.and_then(|response| {
+                response
+                    .map(|r| {
+                        r.apply_keep_alive(&keep_alive);
+                        target.finish(r.with_timing(timer.finish()), &pipeline, &request_id)
+                    })
+                    .transpose()
+            });
 
-        let ip_regex = regex::Regex::new(r"^(?:(?:25[0-5]|2[0-4][0-9]|[01]?[0-9][0-9]?)\.){3}(?:25[0-5]|2[0-4][0-9]|[01]?[0-9][0-9]?)$|^([0-9A-Fa-f]{0,4}:){2,7}([0-9A-Fa-f]{1,4}$|((25[0-5]|2[0-4][0-9]|[01]?[0-9][0-9]?)(\.|$)){4})$").unwrap_throw();
+            let continued = assembler.take_continue();
+            let final_response = matches!(response, Ok(Some(_)));
+            if let Some(body) =
+                cb_pending.on_response(continued, final_response, &keep_alive.socket)
+            {
+                // Deferred, as the engine may still be busy producing this output
+                if let Some(engine) = cb_engine_slot.get().and_then(Weak::upgrade) {
+                    let progress = cb_progress.clone();
+                    set_timeout(
+                        &Closure::once_into_js(move || {
+                            engine.write(body);
+                            progress.report(Progress::new(ProgressDirection::Upload, total, total));
+                        }),
+                        0.0,
+                    );
+                }
+            }
+            drop(assembler);
+
+            match response {
+                Ok(Some(response)) => {
+                    deadline.finish();
+                    cb_in_flight.finish();
+                    stats.record_response(timer.elapsed(), timer.time_to_first_byte());
+                    if target.is_streamed() {
+                        return;
+                    }
+                    let this = JsValue::null();
+
+                    callback
+                        .call1(&this, &JsValue::from(response))
+                        .unwrap_throw();
+                }
+                Ok(None) => {}
+                Err(e) => {
+                    deadline.finish();
+                    cb_in_flight.finish();
+                    if malformed {
+                        // The rest of the response would be read as the response of the next
+                        // request
+                        let _ = keep_alive.socket.close();
+                    }
+                    stats.record_error();
+                    errors.report(e);
+                }
+            }
+        });
+
+        let (min, max) = self.tls.range();
+        let engine = Rc::new(TlsEngine::start(
+            self.tls_worker.clone(),
+            min,
+            max,
+            &self.tls_options,
+            &self.addr,
+            output_callback,
+        )?);
+
+        let _ = engine_slot.set(Rc::downgrade(&engine));
+
+        let cb_engine = engine.clone();
+        let message_callback: Closure<dyn Fn(MessageEvent)> =
+            Closure::wrap(Box::new(move |evt: MessageEvent| {
+                let buffer = evt.data().dyn_into::<ArrayBuffer>().unwrap_throw();
+                if handshaking.get() {
+                    handshake_read.set(handshake_read.get() + buffer.byte_length() as usize);
+                }
+                cb_engine.read(Uint8Array::new(&buffer).to_vec());
+            }));
 
-        let server_name: ServerName = if ip_regex.is_match(&addr) {
-            ServerName::IpAddress(IpAddr::try_from(addr.as_str()).unwrap_throw())
-        } else {
-            ServerName::DnsName(DnsName::try_from(addr).unwrap_throw())
-        };
+        self.socket
+            .add_event_listener_with_callback_and_add_event_listener_options(
+                "message",
+                message_callback.as_ref().unchecked_ref(),
+                AddEventListenerOptions::new().once(false),
+            )
+            .unwrap_throw();
 
-        Self {
-            connection,
-            config,
-            server_name,
+        let probe_engine = Rc::downgrade(&engine);
+        in_flight.set_probe(move |usage| {
+            if let Ok(assembler) = probe_assembler.try_borrow() {
+                usage.add_assembler(assembler.buffered());
+            }
+            if let Some(engine) = probe_engine.upgrade() {
+                usage.add_tls_session(engine.buffered());
+            }
+        });
+
+        let socket = self.socket.clone();
+        in_flight.on_release(move |closed| {
+            release_listener(&socket, "message", message_callback);
+            if let Some(closed) = closed {
+                cb_deadline.finish();
+                cb_target.cancel(closed);
+                cb_errors.report(ConnectionError {
+                    message: format!("Request cancelled, {}", closed.get_message()),
+                });
+            }
+        });
+
+        let sent = req.len();
+        engine.write(req);
+        progress.report(Progress::new(ProgressDirection::Upload, sent, total));
+        if let Some(upload) = upload {
+            let engine = engine.clone();
+            upload.stream(
+                self.socket.clone(),
+                move |slice| engine.write(slice),
+                progress.clone(),
+                sent,
+                self.log_prefix.clone(),
+            );
         }
+        pending.send_after_timeout(move |body| {
+            engine.write(body);
+            progress.report(Progress::new(ProgressDirection::Upload, total, total));
+        });
+
+        Ok(())
     }
 }
 
@@ -174,12 +467,38 @@ impl HttpsConnectionApi {
         self.connection.addr.clone()
     }
 
+    /// Get the ID of this connection.
+    #[wasm_bindgen]
+    pub fn get_id(&self) -> u64 {
+        self.connection.get_id().into()
+    }
+
+    /// Get the UUID of this connection, if made with `ConnIdScheme.UuidV7`.
+    #[wasm_bindgen]
+    pub fn get_uuid(&self) -> Option<String> {
+        self.connection.get_id().uuid_string()
+    }
+
+    /// Get the TLS version negotiated by the last completed handshake, e.g. `tls1_3`.
+    ///
+    /// Lower than requested if the connection fell back to another version, see
+    /// `Client::set_tls_fallback`.
+    #[wasm_bindgen]
+    pub fn get_tls_version(&self) -> Option<String> {
+        self.connection
+            .tls
+            .as_ref()
+            .and_then(TlsVersions::negotiated)
+            .map(|v| v.to_string())
+    }
+
     /// Send data to this connection.
     ///
     /// # Arguments
     ///
     /// * `data` - Data to send to this connection. The type of this data depends on the implementation.
-    /// * `callback` - Callback to call when data is received from this connection.
+    /// * `callback` - Callback to call when data is received from this connection, or with
+    ///   `null` and the error if the request fails after being sent.
     ///
     /// # Returns
     ///
@@ -188,105 +507,144 @@ impl HttpsConnectionApi {
     #[wasm_bindgen]
     pub fn send(
         &self,
-        data: HttpsConnectionRequest,
-        callback: js_sys::Function,
+        data: HttpConnectionRequest,
+        callback: HttpResponseCallback,
     ) -> Result<(), ConnectionError> {
-        if (self.connection.socket.ready_state() as u16) != 1 {
-            return Err(ConnectionError {
-                message: "Connection is not open".to_string(),
-            });
-        }
-        let req = if let Some(body) = data.body {
-            http!(data.method, data.path, data.headers, body.to_vec())
-        } else {
-            http!(data.method, data.path, data.headers)
-        };
-
-        let mut conn = rustls::ClientConnection::new(self.config.clone(), self.server_name.clone())
-            .unwrap_throw();
-
-        conn.writer().write_all(&req).unwrap_throw();
-
-        let mut tls = Vec::new();
-        conn.write_tls(&mut tls).unwrap_throw();
-
-        let _ = conn.process_new_packets().unwrap_throw();
-
-        let cb_conn = Arc::new(Mutex::new(conn));
-
-        let encoded_response: Arc<Mutex<Vec<u8>>> = Arc::new(Mutex::new(Vec::new()));
-
-        let response_code: Arc<Mutex<u16>> = Arc::new(Mutex::new(0u16));
-
-        let response_headers: Arc<Mutex<Vec<HttpHeader>>> = Arc::new(Mutex::new(Vec::new()));
-
-        let response_body: Arc<Mutex<Vec<u8>>> = Arc::new(Mutex::new(Vec::new()));
-
-        let content_length: Arc<Mutex<usize>> = Arc::new(Mutex::new(0usize));
-
-        let message_callback: Closure<dyn Fn(MessageEvent)> =
-            Closure::wrap(Box::new(move |evt: MessageEvent| {
-                let buffer = evt.data().dyn_into::<ArrayBuffer>().unwrap_throw();
-                let tls = Uint8Array::new(&buffer).to_vec();
-
-                let mut encoded_response = encoded_response.lock().unwrap_throw();
-
-                let mut cb_conn = cb_conn.lock().unwrap_throw();
-
-                console_log!("Received TLS: {:?}", tls);
-
-                (*encoded_response).extend_from_slice(&tls);
-
-                console_log!("cumulative TLS: {:?}", *encoded_response);
-                console_log!("TLS len: {}", encoded_response.len());
-
-                if encoded_response.len() == 4011 {
-                    console_log!("TESTING: example tls complete");
-                    cb_conn
-                        .read_tls(&mut encoded_response.as_slice())
-                        .unwrap_throw();
-                    let mut vec: Vec<u8> = Vec::new();
-
-                    // cb_conn.reader().read_to_end(&mut vec).unwrap_throw();
-
-                    console_log!("Received response: {:?}", cb_conn.);
-                }
-
-                drop(encoded_response);
-                drop(cb_conn)
-
-                // cb_conn.read_tls(&mut tls.as_slice()).unwrap_throw();
-                // let _ = cb_conn.process_new_packets().unwrap_throw();
+        self.send_request(data, callback, BodyTarget::Buffer, None)
+    }
 
-                // let mut vec: Vec<u8> = Vec::new();
+    /// Send a request, accumulating the body of its response into a `Blob` instead of the
+    /// module memory.
+    ///
+    /// Meant for large downloads. The response passed to `callback` has no body, its
+    /// contents are available from `get_blob` or `create_object_url`.
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - Request to send
+    /// * `callback` - Callback to call with the response once received
+    #[wasm_bindgen]
+    pub fn send_to_blob(
+        &self,
+        data: HttpConnectionRequest,
+        callback: HttpResponseCallback,
+    ) -> Result<(), ConnectionError> {
+        self.send_request(data, callback, BodyTarget::Blob(BlobBody::default()), None)
+    }
 
-                // cb_conn.reader().read_to_end(&mut vec).unwrap_throw();
+    /// Send a request, writing the body of its response into IndexedDB as it is received.
+    ///
+    /// Meant for downloads too large for the memory of the page. The body is stored in
+    /// records of 1 MiB under `key`, replacing any download of the same key, next to its
+    /// metadata. The response passed to `callback` has no body, its contents are reassembled
+    /// from `get_download`, or later from `IdbDownload.load`.
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - Request to send
+    /// * `database` - Name of the database to store the download in
+    /// * `key` - Key of the download in the database
+    /// * `callback` - Callback to call with the response once received
+    #[wasm_bindgen]
+    pub fn send_to_indexed_db(
+        &self,
+        data: HttpConnectionRequest,
+        database: String,
+        key: String,
+        callback: HttpResponseCallback,
+    ) -> Result<(), ConnectionError> {
+        let target = BodyTarget::IndexedDb(IdbBody::open(database, key));
+        self.send_request(data, callback, target, None)
+    }
 
-                // console_log!(
-                //     "Received response: {}",
-                //     String::from_utf8(vec.clone()).unwrap_throw()
-                // );
-            }));
+    /// Send a request with the contents of a `Blob` or `File` as its body.
+    ///
+    /// The blob is read, encrypted and sent in slices as the connection drains, so it is never
+    /// copied into the module memory as a whole. The `Content-Length` of the request is set to
+    /// the size of the blob, and its `Content-Type` to the type of the blob unless already
+    /// set. The progress of the upload is reported to the `set_onprogress` callback.
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - Request to send, its own body is ignored
+    /// * `body` - Blob to send as the body
+    /// * `callback` - Callback to call with the response once received
+    #[wasm_bindgen]
+    pub fn send_blob(
+        &self,
+        data: HttpConnectionRequest,
+        body: Blob,
+        callback: HttpResponseCallback,
+    ) -> Result<(), ConnectionError> {
+        let upload = BlobUpload::new(body);
+        self.send_request(data, callback, BodyTarget::Buffer, Some(upload))
+    }
 
-        let _ = self
-            .connection
-            .socket
-            .add_event_listener_with_callback_and_add_event_listener_options(
-                "message",
-                message_callback.as_ref().unchecked_ref(),
-                AddEventListenerOptions::new().once(false),
-            )
-            .unwrap_throw();
+    /// Send a request with a `FormData` as its body, encoded as `multipart/form-data` like
+    /// `fetch` does.
+    ///
+    /// String entries and `File` entries, with their name and type, become the parts of the
+    /// body, which is streamed like with `send_blob`. The `Content-Type` of the request is set
+    /// with the generated boundary, so it should be left unset.
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - Request to send, its own body is ignored
+    /// * `form` - Form to send as the body
+    /// * `callback` - Callback to call with the response once received
+    #[wasm_bindgen]
+    pub fn send_form_data(
+        &self,
+        data: HttpConnectionRequest,
+        form: FormData,
+        callback: HttpResponseCallback,
+    ) -> Result<(), ConnectionError> {
+        let upload = BlobUpload::from_form_data(&form)?;
+        self.send_request(data, callback, BodyTarget::Buffer, Some(upload))
+    }
 
-        message_callback.forget();
+    /// Send a request, streaming the body of its response as it is received.
+    ///
+    /// `callback` is called as soon as the head of the response is received. The response
+    /// has no body, its contents are read from `get_body_stream`, or `body_text_stream` for
+    /// text, which close once the whole body was received.
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - Request to send
+    /// * `callback` - Callback to call with the response once its head is received
+    #[wasm_bindgen]
+    pub fn send_streaming(
+        &self,
+        data: HttpConnectionRequest,
+        callback: HttpResponseCallback,
+    ) -> Result<(), ConnectionError> {
+        self.send_request(data, callback, BodyTarget::Stream(StreamBody::new()?), None)
+    }
 
-        let _ = self
-            .connection
-            .socket
-            .send_with_u8_array(&tls)
-            .unwrap_throw();
+    /// Set the function called with the progress of the handshake, requests sent and
+    /// responses received on this connection.
+    ///
+    /// # Arguments
+    ///
+    /// * `callback` - Function called with a `Progress`, or `None` to stop reporting
+    #[wasm_bindgen]
+    pub fn set_onprogress(&self, callback: Option<ProgressCallback>) {
+        self.connection.progress.set(callback.map(Into::into));
+    }
 
-        Ok(())
+    /// Set the function called with every milestone of the TLS handshakes of requests sent
+    /// on this connection, from the `ClientHello` being sent to the handshake completing
+    /// with the negotiated version and cipher suite.
+    ///
+    /// # Arguments
+    ///
+    /// * `callback` - Function called with a `HandshakeEvent`, or `None` to stop reporting
+    #[wasm_bindgen]
+    pub fn set_onhandshake(&self, callback: Option<HandshakeCallback>) {
+        self.connection
+            .progress
+            .set_handshake(callback.map(Into::into));
     }
 
     /// Ping this connection.
@@ -301,6 +659,6 @@ impl HttpsConnectionApi {
 
     /// Close this connection.
     pub fn close(&self) {
-        let _ = self.connection.socket.close();
+        self.connection.close();
     }
 }
@@ -0,0 +1,480 @@
+use std::{cell::RefCell, rc::Rc};
+
+use wasm_bindgen::prelude::*;
+use wasm_bindgen_futures::{
+    future_to_promise,
+    js_sys::{self, Array, Object, Promise, Reflect, Uint8Array},
+    spawn_local, JsFuture,
+};
+use web_sys::{
+    Blob, BlobPropertyBag, IdbDatabase, IdbFactory, IdbKeyRange, IdbOpenDbRequest, IdbRequest,
+    IdbTransactionMode,
+};
+
+use crate::connection::ConnectionError;
+
+use super::{assembler::BodySink, http::HttpConnectionResponse};
+
+/// Size of the records a body is split into, in bytes.
+const RECORD_SIZE: usize = 1024 * 1024;
+/// Version of the schema of the databases downloads are stored in.
+const SCHEMA_VERSION: u32 = 1;
+/// Object store holding the records of the bodies, keyed by `[key, index]`.
+const RECORDS_STORE: &str = "records";
+/// Object store holding the metadata of the downloads, keyed by their key.
+const DOWNLOADS_STORE: &str = "downloads";
+
+/// Create an error from a failed IndexedDB call.
+fn idb_error(action: &str, e: JsValue) -> ConnectionError {
+    ConnectionError {
+        message: format!("Failed to {}: {:?}", action, e),
+    }
+}
+
+/// Get the key of a record of a download.
+fn record_key(key: &str, index: f64) -> JsValue {
+    Array::of2(&key.into(), &index.into()).into()
+}
+
+/// Get the range of the keys of the records of a download.
+fn records_range(key: &str) -> Result<JsValue, JsValue> {
+    IdbKeyRange::bound(&record_key(key, 0.0), &record_key(key, f64::INFINITY)).map(Into::into)
+}
+
+/// Get a promise settled by an IndexedDB request, resolving to its result.
+fn settle(request: &IdbRequest) -> Promise {
+    Promise::new(&mut |resolve, reject| {
+        let cb_request = request.clone();
+        let onsuccess = Closure::once_into_js(move || {
+            let result = cb_request.result().unwrap_or(JsValue::UNDEFINED);
+            let _ = resolve.call1(&JsValue::null(), &result);
+        });
+        let cb_request = request.clone();
+        let onerror = Closure::once_into_js(move || {
+            let error = Reflect::get(&cb_request, &"error".into()).unwrap_or_default();
+            let _ = reject.call1(
+                &JsValue::null(),
+                &idb_error("complete IndexedDB request", error).into(),
+            );
+        });
+        request.set_onsuccess(Some(onsuccess.unchecked_ref()));
+        request.set_onerror(Some(onerror.unchecked_ref()));
+    })
+}
+
+/// Open the database downloads are stored in, creating its object stores if needed.
+async fn open_database(name: &str) -> Result<IdbDatabase, JsValue> {
+    let factory: IdbFactory = Reflect::get(&js_sys::global(), &"indexedDB".into())
+        .ok()
+        .and_then(|factory| factory.dyn_into().ok())
+        .ok_or_else(|| ConnectionError {
+            message: "IndexedDB is not available".to_string(),
+        })?;
+    let request: IdbOpenDbRequest = factory
+        .open_with_u32(name, SCHEMA_VERSION)
+        .map_err(|e| idb_error("open database", e))?;
+    let cb_request = request.clone();
+    let onupgradeneeded = Closure::once_into_js(move || {
+        if let Ok(database) = cb_request.result() {
+            let database: IdbDatabase = database.unchecked_into();
+            let _ = database.create_object_store(RECORDS_STORE);
+            let _ = database.create_object_store(DOWNLOADS_STORE);
+        }
+    });
+    request.set_onupgradeneeded(Some(onupgradeneeded.unchecked_ref()));
+    Ok(JsFuture::from(settle(&request)).await?.unchecked_into())
+}
+
+/// Write to an IndexedDB object store, queued until the database is open.
+enum Write {
+    /// Put a value under a key
+    Put(&'static str, JsValue, JsValue),
+    /// Delete the values under a key or key range
+    Delete(&'static str, JsValue),
+}
+
+/// State of the writes of a body into IndexedDB.
+struct IdbWriter {
+    /// Database, once open
+    database: Option<IdbDatabase>,
+    /// Writes waiting for the database to open
+    queued: Vec<Write>,
+    /// Bytes received since the last record
+    buffer: Vec<u8>,
+    /// Number of records written
+    records: u32,
+    /// Number of bytes received
+    size: f64,
+}
+
+/// Body of responses written into IndexedDB as it is received, in records of `RECORD_SIZE`
+/// bytes, so downloads larger than the memory of the page can be stored.
+#[derive(Clone)]
+pub(crate) struct IdbBody {
+    /// Name of the database
+    database: String,
+    /// Key of the download in the database
+    key: String,
+    /// State of the writes
+    writer: Rc<RefCell<IdbWriter>>,
+    /// First error of the writes, if any
+    error: Rc<RefCell<Option<String>>>,
+    /// Function recording the error of a failed transaction
+    onerror: js_sys::Function,
+}
+
+impl IdbBody {
+    /// Start opening a database to write a body into, replacing any download of the same key.
+    ///
+    /// # Arguments
+    ///
+    /// * `database` - Name of the database
+    /// * `key` - Key of the download
+    pub(crate) fn open(database: String, key: String) -> Self {
+        let error = Rc::new(RefCell::new(None));
+        let cb_error = error.clone();
+        let onerror = Closure::<dyn Fn(JsValue)>::new(move |event: JsValue| {
+            let transaction = Reflect::get(&event, &"target".into()).unwrap_or_default();
+            let e = Reflect::get(&transaction, &"error".into()).unwrap_or_default();
+            cb_error
+                .borrow_mut()
+                .get_or_insert_with(|| idb_error("write download", e).message);
+        });
+        let body = Self {
+            database,
+            key,
+            writer: Rc::new(RefCell::new(IdbWriter {
+                database: None,
+                queued: Vec::new(),
+                buffer: Vec::new(),
+                records: 0,
+                size: 0.0,
+            })),
+            error,
+            onerror: onerror.into_js_value().unchecked_into(),
+        };
+        body.write(Write::Delete(
+            RECORDS_STORE,
+            records_range(&body.key).unwrap_throw(),
+        ));
+        body.write(Write::Delete(DOWNLOADS_STORE, body.key.as_str().into()));
+
+        let cb_body = body.clone();
+        spawn_local(async move {
+            match open_database(&cb_body.database).await {
+                Ok(database) => {
+                    let queued = {
+                        let mut writer = cb_body.writer.borrow_mut();
+                        writer.database = Some(database);
+                        std::mem::take(&mut writer.queued)
+                    };
+                    for write in queued {
+                        cb_body.write(write);
+                    }
+                }
+                Err(e) => {
+                    *cb_body.error.borrow_mut() = Some(format!("{:?}", e));
+                }
+            }
+        });
+        body
+    }
+
+    /// Write to the database, or queue the write until it is open.
+    fn write(&self, write: Write) {
+        let database = self.writer.borrow().database.clone();
+        let database = match database {
+            Some(database) => database,
+            None => {
+                self.writer.borrow_mut().queued.push(write);
+                return;
+            }
+        };
+        let store = match &write {
+            Write::Put(store, _, _) | Write::Delete(store, _) => *store,
+        };
+        let result = database
+            .transaction_with_str_and_mode(store, IdbTransactionMode::Readwrite)
+            .and_then(|transaction| {
+                transaction.set_onerror(Some(&self.onerror));
+                let store = transaction.object_store(store)?;
+                match &write {
+                    Write::Put(_, key, value) => store.put_with_key(value, key),
+                    Write::Delete(_, key) => store.delete(key),
+                }
+            });
+        if let Err(e) = result {
+            self.error
+                .borrow_mut()
+                .get_or_insert_with(|| idb_error("write download", e).message);
+        }
+    }
+
+    /// Write the bytes received since the last record as a record.
+    fn flush(&self) {
+        let (index, bytes) = {
+            let mut writer = self.writer.borrow_mut();
+            if writer.buffer.is_empty() {
+                return;
+            }
+            writer.records += 1;
+            let bytes = std::mem::take(&mut writer.buffer);
+            (writer.records - 1, bytes)
+        };
+        self.write(Write::Put(
+            RECORDS_STORE,
+            record_key(&self.key, f64::from(index)),
+            Uint8Array::from(bytes.as_slice()).into(),
+        ));
+    }
+
+    /// Get a function writing the body chunks it receives into the database.
+    pub(crate) fn sink(&self) -> BodySink {
+        let body = self.clone();
+        Box::new(move |chunk| {
+            let full = {
+                let mut writer = body.writer.borrow_mut();
+                writer.size += chunk.len() as f64;
+                writer.buffer.extend_from_slice(chunk);
+                writer.buffer.len() >= RECORD_SIZE
+            };
+            if full {
+                body.flush();
+            }
+        })
+    }
+
+    /// Write the rest of the body and the metadata of the download, and attach a handle to
+    /// the download to a response.
+    ///
+    /// The type of the download is taken from the `Content-Type` header of the response, or
+    /// else sniffed from the start of the body.
+    pub(crate) fn finish(&self, response: HttpConnectionResponse) -> HttpConnectionResponse {
+        self.flush();
+        let (records, size) = {
+            let mut writer = self.writer.borrow_mut();
+            // Reset, so a later response to the same request starts a new download
+            let done = (writer.records, writer.size);
+            writer.records = 0;
+            writer.size = 0.0;
+            done
+        };
+        let download = IdbDownload {
+            database: self.database.clone(),
+            key: self.key.clone(),
+            records,
+            size,
+            content_type: response.get_content_type(),
+            error: self.error.clone(),
+        };
+        self.write(Write::Put(
+            DOWNLOADS_STORE,
+            self.key.as_str().into(),
+            download.metadata(response.code).into(),
+        ));
+        response.with_download(download)
+    }
+}
+
+/// Handle to a response body stored in IndexedDB by `send_to_indexed_db`, reassembled on
+/// demand.
+#[wasm_bindgen]
+#[derive(Clone)]
+pub struct IdbDownload {
+    /// Name of the database
+    database: String,
+    /// Key of the download in the database
+    key: String,
+    /// Number of records the body is split into
+    records: u32,
+    /// Size of the body in bytes
+    size: f64,
+    /// Type of the body, from the `Content-Type` header of the response or sniffed
+    content_type: Option<String>,
+    /// First error of the writes of the download, if any
+    error: Rc<RefCell<Option<String>>>,
+}
+
+impl IdbDownload {
+    /// Get the metadata of this download stored next to its records.
+    fn metadata(&self, code: u16) -> Object {
+        let metadata = Object::new();
+        let props: [(&str, JsValue); 5] = [
+            ("records", self.records.into()),
+            ("size", self.size.into()),
+            ("contentType", self.content_type.clone().into()),
+            ("code", code.into()),
+            ("savedAt", js_sys::Date::now().into()),
+        ];
+        for (key, value) in props {
+            Reflect::set(&metadata, &key.into(), &value).unwrap_throw();
+        }
+        metadata
+    }
+
+    /// Fail if a write of this download failed.
+    fn check(&self) -> Result<(), ConnectionError> {
+        match self.error.borrow().as_ref() {
+            Some(error) => Err(ConnectionError {
+                message: format!("Download {} was not fully stored: {}", self.key, error),
+            }),
+            None => Ok(()),
+        }
+    }
+
+    /// Read a record of this download.
+    async fn record(&self, database: &IdbDatabase, index: u32) -> Result<Uint8Array, JsValue> {
+        let request = database
+            .transaction_with_str(RECORDS_STORE)
+            .and_then(|transaction| transaction.object_store(RECORDS_STORE))
+            .and_then(|store| store.get(&record_key(&self.key, f64::from(index))))
+            .map_err(|e| idb_error("read download", e))?;
+        JsFuture::from(settle(&request))
+            .await?
+            .dyn_into::<Uint8Array>()
+            .map_err(|_| {
+                ConnectionError {
+                    message: format!("Record {} of download {} is missing", index, self.key),
+                }
+                .into()
+            })
+    }
+}
+
+#[wasm_bindgen]
+impl IdbDownload {
+    /// Get a handle to a download stored earlier, e.g. before the page was reloaded.
+    ///
+    /// # Arguments
+    ///
+    /// * `database` - Name of the database the download was stored in
+    /// * `key` - Key of the download
+    ///
+    /// # Returns
+    ///
+    /// A promise resolving to the handle, or rejecting if no download is stored under `key`.
+    #[wasm_bindgen]
+    pub fn load(database: String, key: String) -> Promise {
+        future_to_promise(async move {
+            let db = open_database(&database).await?;
+            let request = db
+                .transaction_with_str(DOWNLOADS_STORE)
+                .and_then(|transaction| transaction.object_store(DOWNLOADS_STORE))
+                .and_then(|store| store.get(&key.as_str().into()))
+                .map_err(|e| idb_error("read download", e))?;
+            let metadata = JsFuture::from(settle(&request)).await?;
+            if !metadata.is_object() {
+                return Err(ConnectionError {
+                    message: format!("No download stored under key {}", key),
+                }
+                .into());
+            }
+            let get = |name: &str| Reflect::get(&metadata, &name.into()).unwrap_or_default();
+            Ok(IdbDownload {
+                database,
+                key,
+                records: get("records").as_f64().unwrap_or_default() as u32,
+                size: get("size").as_f64().unwrap_or_default(),
+                content_type: get("contentType").as_string(),
+                error: Rc::default(),
+            }
+            .into())
+        })
+    }
+
+    /// Get the name of the database the download is stored in.
+    #[wasm_bindgen]
+    pub fn get_database(&self) -> String {
+        self.database.clone()
+    }
+
+    /// Get the key of the download in its database.
+    #[wasm_bindgen]
+    pub fn get_key(&self) -> String {
+        self.key.clone()
+    }
+
+    /// Get the size of the body in bytes.
+    #[wasm_bindgen]
+    pub fn get_size(&self) -> f64 {
+        self.size
+    }
+
+    /// Get the number of records the body is split into.
+    #[wasm_bindgen]
+    pub fn get_records(&self) -> u32 {
+        self.records
+    }
+
+    /// Get the type of the body, from the `Content-Type` header of the response or sniffed.
+    #[wasm_bindgen]
+    pub fn get_content_type(&self) -> Option<String> {
+        self.content_type.clone()
+    }
+
+    /// Read a record of the body, to process it piece by piece without loading all of it.
+    ///
+    /// # Returns
+    ///
+    /// A promise resolving to the bytes of the record.
+    #[wasm_bindgen]
+    pub fn read_record(&self, index: u32) -> Promise {
+        let download = self.clone();
+        future_to_promise(async move {
+            download.check()?;
+            let database = open_database(&download.database).await?;
+            Ok(download.record(&database, index).await?.into())
+        })
+    }
+
+    /// Reassemble the body into a `Blob`, typed from the `Content-Type` of the response.
+    ///
+    /// # Returns
+    ///
+    /// A promise resolving to the blob, or rejecting if a record is missing.
+    #[wasm_bindgen]
+    pub fn to_blob(&self) -> Promise {
+        let download = self.clone();
+        future_to_promise(async move {
+            download.check()?;
+            let database = open_database(&download.database).await?;
+            let parts = Array::new();
+            for index in 0..download.records {
+                let record = download.record(&database, index).await?;
+                parts.push(&record);
+            }
+            let mut options = BlobPropertyBag::new();
+            if let Some(content_type) = &download.content_type {
+                options.type_(content_type);
+            }
+            Blob::new_with_u8_array_sequence_and_options(&parts, &options)
+                .map(Into::into)
+                .map_err(|e| idb_error("create blob", e).into())
+        })
+    }
+
+    /// Delete the download from its database.
+    ///
+    /// # Returns
+    ///
+    /// A promise resolving once the download is deleted.
+    #[wasm_bindgen]
+    pub fn delete(&self) -> Promise {
+        let download = self.clone();
+        future_to_promise(async move {
+            let database = open_database(&download.database).await?;
+            for (store, key) in [
+                (RECORDS_STORE, records_range(&download.key)?),
+                (DOWNLOADS_STORE, download.key.as_str().into()),
+            ] {
+                let request = database
+                    .transaction_with_str_and_mode(store, IdbTransactionMode::Readwrite)
+                    .and_then(|transaction| transaction.object_store(store))
+                    .and_then(|store| store.delete(&key))
+                    .map_err(|e| idb_error("delete download", e))?;
+                JsFuture::from(settle(&request)).await?;
+            }
+            Ok(JsValue::UNDEFINED)
+        })
+    }
+}
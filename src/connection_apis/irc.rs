@@ -0,0 +1,792 @@
+use std::{
+    cell::{OnceCell, RefCell},
+    rc::{Rc, Weak},
+};
+
+use wasm_bindgen::prelude::*;
+use wasm_bindgen_futures::js_sys::{self, ArrayBuffer, Uint8Array};
+use web_sys::{MessageEvent, WebSocket};
+
+use crate::{
+    callbacks::IrcMessageCallback,
+    connection::{Connection, ConnectionError, Sender},
+    console_log, set_timeout,
+    tls::{TlsEngine, TlsOutputCallback},
+    worker::TlsWorker,
+};
+
+/// Default port of plain IRC connections.
+pub const DEFAULT_IRC_PORT: u16 = 6667;
+/// Default port of IRC connections over TLS.
+pub const DEFAULT_IRCS_PORT: u16 = 6697;
+/// Maximum length of a received line, the 512 bytes of RFC 1459 and 8191 bytes of IRCv3 tags.
+const MAX_LINE_LENGTH: usize = 512 + 8191;
+/// Number of times a nick is retried with a `_` appended when taken, during registration.
+const MAX_NICK_RETRIES: usize = 3;
+
+/// Get the address of an IRC server, as `host:port`.
+///
+/// # Arguments
+///
+/// * `addr` - Address of the server, with or without port
+/// * `tls` - Whether the connection uses TLS, port 6697 if no port is given, 6667 otherwise
+pub fn irc_addr(addr: &str, tls: bool) -> String {
+    let has_port = match addr.rsplit_once(':') {
+        Some((host, _)) => !host.contains(':') || host.ends_with(']'),
+        None => false,
+    };
+    match (has_port, tls) {
+        (true, _) => addr.to_string(),
+        (false, true) => format!("{}:{}", addr, DEFAULT_IRCS_PORT),
+        (false, false) => format!("{}:{}", addr, DEFAULT_IRC_PORT),
+    }
+}
+
+/// Take the complete lines out of the bytes received on a connection.
+///
+/// Lines end with CRLF, or LF as some servers send, and empty ones are skipped.
+///
+/// # Returns
+///
+/// The lines, or an error if the bytes left hold a line longer than allowed.
+fn take_lines(buffer: &mut Vec<u8>) -> Result<Vec<String>, ConnectionError> {
+    let mut lines = Vec::new();
+    let mut start = 0;
+    while let Some(end) = buffer[start..].iter().position(|&b| b == b'\n') {
+        let line = &buffer[start..start + end];
+        let line = line.strip_suffix(b"\r").unwrap_or(line);
+        if !line.is_empty() {
+            lines.push(String::from_utf8_lossy(line).into_owned());
+        }
+        start += end + 1;
+    }
+    buffer.drain(..start);
+    if buffer.len() > MAX_LINE_LENGTH {
+        buffer.clear();
+        return Err(ConnectionError {
+            message: format!("IRC line longer than {} bytes", MAX_LINE_LENGTH),
+        });
+    }
+    Ok(lines)
+}
+
+/// Unescape the value of an IRCv3 message tag.
+fn unescape_tag(value: &str) -> String {
+    let mut unescaped = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        // A trailing backslash is dropped, others escape themselves
+        match chars.next() {
+            Some(':') => unescaped.push(';'),
+            Some('s') => unescaped.push(' '),
+            Some('r') => unescaped.push('\r'),
+            Some('n') => unescaped.push('\n'),
+            Some(c) => unescaped.push(c),
+            None => {}
+        }
+    }
+    unescaped
+}
+
+/// Escape the value of an IRCv3 message tag.
+fn escape_tag(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            ';' => escaped.push_str("\\:"),
+            ' ' => escaped.push_str("\\s"),
+            '\\' => escaped.push_str("\\\\"),
+            '\r' => escaped.push_str("\\r"),
+            '\n' => escaped.push_str("\\n"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Message of the IRC protocol, `[@tags] [:prefix] COMMAND [params] [:trailing]`.
+#[wasm_bindgen]
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct IrcMessage {
+    /// IRCv3 tags, with their value unescaped, empty if the tag has none
+    tags: Vec<(String, String)>,
+    /// Source of the message, `nick!user@host` or a server name, if any
+    prefix: Option<String>,
+    /// Command, in uppercase, or a three digit numeric reply
+    command: String,
+    /// Parameters, the trailing one included
+    params: Vec<String>,
+}
+
+impl IrcMessage {
+    /// Parse a line received from a server, without its line ending.
+    pub fn parse_line(line: &str) -> Result<Self, ConnectionError> {
+        let invalid = || ConnectionError {
+            message: format!("Invalid IRC message: {:?}", line),
+        };
+        let mut message = IrcMessage::default();
+        let mut rest = line.trim_end_matches(['\r', '\n']);
+
+        if let Some(tagged) = rest.strip_prefix('@') {
+            let (tags, after) = tagged.split_once(' ').ok_or_else(invalid)?;
+            message.tags = tags
+                .split(';')
+                .filter(|tag| !tag.is_empty())
+                .map(|tag| match tag.split_once('=') {
+                    Some((key, value)) => (key.to_string(), unescape_tag(value)),
+                    None => (tag.to_string(), String::new()),
+                })
+                .collect();
+            rest = after.trim_start_matches(' ');
+        }
+        if let Some(prefixed) = rest.strip_prefix(':') {
+            let (prefix, after) = prefixed.split_once(' ').ok_or_else(invalid)?;
+            message.prefix = Some(prefix.to_string());
+            rest = after.trim_start_matches(' ');
+        }
+
+        let (command, mut rest) = rest.split_once(' ').unwrap_or((rest, ""));
+        if command.is_empty() || !command.bytes().all(|b| b.is_ascii_alphanumeric()) {
+            return Err(invalid());
+        }
+        message.command = command.to_ascii_uppercase();
+
+        loop {
+            rest = rest.trim_start_matches(' ');
+            if rest.is_empty() {
+                break;
+            }
+            if let Some(trailing) = rest.strip_prefix(':') {
+                message.params.push(trailing.to_string());
+                break;
+            }
+            let (param, after) = rest.split_once(' ').unwrap_or((rest, ""));
+            message.params.push(param.to_string());
+            rest = after;
+        }
+        Ok(message)
+    }
+}
+
+#[wasm_bindgen]
+impl IrcMessage {
+    /// Create a new message to send.
+    ///
+    /// # Arguments
+    ///
+    /// * `command` - Command, e.g. `PRIVMSG`, or a three digit numeric reply
+    /// * `params` - Parameters, only the last one may contain spaces, start with `:` or be empty
+    #[wasm_bindgen(constructor)]
+    pub fn new(command: String, params: Vec<String>) -> Result<IrcMessage, ConnectionError> {
+        if command.is_empty() || !command.bytes().all(|b| b.is_ascii_alphanumeric()) {
+            return Err(ConnectionError {
+                message: format!("Invalid IRC command: {:?}", command),
+            });
+        }
+        for (i, param) in params.iter().enumerate() {
+            if param.contains(['\r', '\n', '\0']) {
+                return Err(ConnectionError {
+                    message: format!("IRC parameter contains a line break: {:?}", param),
+                });
+            }
+            let middle = i + 1 < params.len();
+            if middle && (param.is_empty() || param.contains(' ') || param.starts_with(':')) {
+                return Err(ConnectionError {
+                    message: format!("Only the last IRC parameter may be {:?}", param),
+                });
+            }
+        }
+        Ok(Self {
+            tags: Vec::new(),
+            prefix: None,
+            command: command.to_ascii_uppercase(),
+            params,
+        })
+    }
+
+    /// Parse a line received from a server.
+    ///
+    /// # Arguments
+    ///
+    /// * `line` - Line, with or without its line ending
+    #[wasm_bindgen]
+    pub fn parse(line: &str) -> Result<IrcMessage, ConnectionError> {
+        Self::parse_line(line)
+    }
+
+    /// Get the value of an IRCv3 tag, empty if the tag has none, or `None` if it is not set.
+    #[wasm_bindgen]
+    pub fn get_tag(&self, name: &str) -> Option<String> {
+        self.tags
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.clone())
+    }
+
+    /// Set an IRCv3 tag, e.g. `+draft/reply`, replacing its value if it is set.
+    #[wasm_bindgen]
+    pub fn set_tag(&mut self, name: String, value: Option<String>) -> Result<(), ConnectionError> {
+        if name.is_empty() || name.contains(['=', ';', ' ', '\r', '\n', '\0']) {
+            return Err(ConnectionError {
+                message: format!("Invalid IRC tag name: {:?}", name),
+            });
+        }
+        let value = value.unwrap_or_default();
+        match self.tags.iter_mut().find(|(key, _)| *key == name) {
+            Some(tag) => tag.1 = value,
+            None => self.tags.push((name, value)),
+        }
+        Ok(())
+    }
+
+    /// Get the source of this message, `nick!user@host` or a server name, if any.
+    #[wasm_bindgen]
+    pub fn get_prefix(&self) -> Option<String> {
+        self.prefix.clone()
+    }
+
+    /// Get the nick of the source of this message, the prefix up to any `!` or `@`.
+    #[wasm_bindgen]
+    pub fn get_nick(&self) -> Option<String> {
+        let prefix = self.prefix.as_deref()?;
+        prefix.split(['!', '@']).next().map(str::to_string)
+    }
+
+    /// Get the command, in uppercase, e.g. `PRIVMSG` or `001`.
+    #[wasm_bindgen]
+    pub fn get_command(&self) -> String {
+        self.command.clone()
+    }
+
+    /// Get the parameters, the trailing one included.
+    #[wasm_bindgen]
+    pub fn get_params(&self) -> Vec<String> {
+        self.params.clone()
+    }
+
+    /// Get a parameter, e.g. the text of a `PRIVMSG` at 1, if given.
+    #[wasm_bindgen]
+    pub fn get_param(&self, index: usize) -> Option<String> {
+        self.params.get(index).cloned()
+    }
+
+    /// Encode this message as a line, without its line ending.
+    #[wasm_bindgen]
+    pub fn to_line(&self) -> String {
+        let mut line = String::new();
+        if !self.tags.is_empty() {
+            line.push('@');
+            let tags: Vec<String> = self
+                .tags
+                .iter()
+                .map(|(key, value)| match value.is_empty() {
+                    true => key.clone(),
+                    false => format!("{}={}", key, escape_tag(value)),
+                })
+                .collect();
+            line.push_str(&tags.join(";"));
+            line.push(' ');
+        }
+        if let Some(prefix) = &self.prefix {
+            line.push(':');
+            line.push_str(prefix);
+            line.push(' ');
+        }
+        line.push_str(&self.command);
+        for (i, param) in self.params.iter().enumerate() {
+            line.push(' ');
+            let last = i + 1 == self.params.len();
+            if last && (param.is_empty() || param.contains(' ') || param.starts_with(':')) {
+                line.push(':');
+            }
+            line.push_str(param);
+        }
+        line
+    }
+}
+
+/// Registration of an IRC connection, sent once it is open.
+#[wasm_bindgen]
+#[derive(Clone, Debug)]
+pub struct IrcRegistration {
+    /// Nick to register
+    nick: String,
+    /// User name, the nick if not set
+    user: Option<String>,
+    /// Real name, the nick if not set
+    realname: Option<String>,
+    /// Connection password, sent with `PASS` if set
+    password: Option<String>,
+}
+
+#[wasm_bindgen]
+impl IrcRegistration {
+    /// Create a new registration.
+    ///
+    /// # Arguments
+    ///
+    /// * `nick` - Nick to register, retried with `_` appended if taken
+    #[wasm_bindgen(constructor)]
+    pub fn new(nick: String) -> Self {
+        Self {
+            nick,
+            user: None,
+            realname: None,
+            password: None,
+        }
+    }
+
+    /// Set the user name.
+    #[wasm_bindgen]
+    pub fn set_user(&mut self, user: String) {
+        self.user = Some(user);
+    }
+
+    /// Set the real name.
+    #[wasm_bindgen]
+    pub fn set_realname(&mut self, realname: String) {
+        self.realname = Some(realname);
+    }
+
+    /// Set the connection password, sent with `PASS`.
+    #[wasm_bindgen]
+    pub fn set_password(&mut self, password: String) {
+        self.password = Some(password);
+    }
+}
+
+impl IrcRegistration {
+    /// Check that the nick and names can be sent.
+    pub fn check(&self) -> Result<(), ConnectionError> {
+        self.messages().map(|_| ())
+    }
+
+    /// Get the messages registering the connection.
+    fn messages(&self) -> Result<Vec<IrcMessage>, ConnectionError> {
+        let mut messages = Vec::new();
+        if let Some(password) = &self.password {
+            messages.push(IrcMessage::new("PASS".to_string(), vec![password.clone()])?);
+        }
+        messages.push(IrcMessage::new(
+            "NICK".to_string(),
+            vec![self.nick.clone()],
+        )?);
+        let user = self.user.clone().unwrap_or_else(|| self.nick.clone());
+        let realname = self.realname.clone().unwrap_or_else(|| self.nick.clone());
+        messages.push(IrcMessage::new(
+            "USER".to_string(),
+            vec![user, "0".to_string(), "*".to_string(), realname],
+        )?);
+        Ok(messages)
+    }
+}
+
+/// Where the lines of an IRC connection are written.
+#[derive(Clone)]
+enum Transport {
+    /// Straight to the socket
+    Plain(Sender),
+    /// Through a TLS session, set once the session is started
+    Tls(Rc<OnceCell<Weak<TlsEngine>>>),
+}
+
+impl Transport {
+    /// Write messages, each as a line.
+    fn write(&self, messages: &[IrcMessage]) {
+        let mut bytes = Vec::new();
+        for message in messages {
+            bytes.extend(message.to_line().as_bytes());
+            bytes.extend(b"\r\n");
+        }
+        match self {
+            Transport::Plain(sender) => {
+                let _ = sender.send(&bytes);
+            }
+            Transport::Tls(engine) => {
+                if let Some(engine) = engine.get().and_then(Weak::upgrade) {
+                    engine.write(bytes);
+                }
+            }
+        }
+    }
+}
+
+/// State of an IRC connection, shared by its API and the listeners of its socket.
+struct IrcState {
+    /// Bytes received, not yet split into lines
+    buffer: Vec<u8>,
+    /// Current nick, confirmed by the server once registered
+    nick: String,
+    /// Number of times the nick was retried during registration
+    nick_retries: usize,
+    /// Whether the server accepted the registration
+    registered: bool,
+    /// Time of the last line received, in ms since the epoch
+    last_received: f64,
+    /// Interval of keepalive pings, in ms, if enabled
+    keepalive: Option<f64>,
+    /// Function called with every message received, if set
+    callback: Option<js_sys::Function>,
+    /// Prefix of the log lines of the connection
+    log_prefix: String,
+}
+
+impl IrcState {
+    /// Handle the bytes received, answering what the protocol requires.
+    ///
+    /// # Returns
+    ///
+    /// The messages received, to deliver, and the messages to send in reply.
+    fn receive(&mut self, bytes: &[u8]) -> (Vec<IrcMessage>, Vec<IrcMessage>) {
+        self.buffer.extend_from_slice(bytes);
+        let lines = match take_lines(&mut self.buffer) {
+            Ok(lines) => lines,
+            Err(e) => {
+                console_log!("{}{}", self.log_prefix, e);
+                Vec::new()
+            }
+        };
+        if !lines.is_empty() {
+            self.last_received = js_sys::Date::now();
+        }
+
+        let mut received = Vec::new();
+        let mut replies = Vec::new();
+        for line in lines {
+            let message = match IrcMessage::parse_line(&line) {
+                Ok(message) => message,
+                Err(e) => {
+                    console_log!("{}{}", self.log_prefix, e);
+                    continue;
+                }
+            };
+            match message.command.as_str() {
+                "PING" => replies.push(IrcMessage {
+                    command: "PONG".to_string(),
+                    params: message.params.clone(),
+                    ..IrcMessage::default()
+                }),
+                // RPL_WELCOME, whose first parameter is the nick as registered
+                "001" => {
+                    self.registered = true;
+                    if let Some(nick) = message.params.first() {
+                        self.nick = nick.clone();
+                    }
+                }
+                // ERR_NICKNAMEINUSE
+                "433" if !self.registered && self.nick_retries < MAX_NICK_RETRIES => {
+                    self.nick_retries += 1;
+                    self.nick.push('_');
+                    replies.push(IrcMessage {
+                        command: "NICK".to_string(),
+                        params: vec![self.nick.clone()],
+                        ..IrcMessage::default()
+                    });
+                }
+                "NICK" if message.get_nick().as_ref() == Some(&self.nick) => {
+                    if let Some(nick) = message.params.first() {
+                        self.nick = nick.clone();
+                    }
+                }
+                _ => {}
+            }
+            received.push(message);
+        }
+        (received, replies)
+    }
+}
+
+/// Handle the bytes received on an IRC connection, outside of any borrow of its state.
+fn receive(state: &Rc<RefCell<IrcState>>, transport: &Transport, bytes: &[u8]) {
+    let (received, replies) = state.borrow_mut().receive(bytes);
+    if !replies.is_empty() {
+        match transport {
+            Transport::Plain(_) => transport.write(&replies),
+            Transport::Tls(_) => {
+                // Deferred, as the engine may still be busy producing the received bytes
+                let transport = transport.clone();
+                set_timeout(
+                    &Closure::once_into_js(move || transport.write(&replies)),
+                    0.0,
+                );
+            }
+        }
+    }
+    // Cloned, so the callback may replace itself
+    let callback = state.borrow().callback.clone();
+    if let Some(callback) = callback {
+        for message in received {
+            let _ = callback.call1(&JsValue::null(), &JsValue::from(message));
+        }
+    }
+}
+
+/// Ping the server when the connection is idle, and close it if the server stays silent.
+fn schedule_keepalive(
+    state: Rc<RefCell<IrcState>>,
+    transport: Transport,
+    socket: WebSocket,
+    interval: f64,
+) {
+    let tick = Closure::once_into_js(move || {
+        if state.borrow().keepalive != Some(interval) || socket.ready_state() != WebSocket::OPEN {
+            return;
+        }
+        let (idle, log_prefix) = {
+            let state = state.borrow();
+            (
+                js_sys::Date::now() - state.last_received,
+                state.log_prefix.clone(),
+            )
+        };
+        if idle >= 2.0 * interval {
+            console_log!("{}IRC ping timeout after {} ms", log_prefix, idle);
+            let _ = socket.close();
+            return;
+        }
+        if idle >= interval {
+            transport.write(&[IrcMessage {
+                command: "PING".to_string(),
+                params: vec!["socketguard".to_string()],
+                ..IrcMessage::default()
+            }]);
+        }
+        schedule_keepalive(state, transport, socket, interval);
+    });
+    set_timeout(&tick, interval);
+}
+
+#[wasm_bindgen]
+pub struct IrcConnectionApi {
+    /// Connection to create API for
+    connection: Connection,
+    /// State of the connection
+    state: Rc<RefCell<IrcState>>,
+    /// Where lines are written, through a TLS session kept by the listener of the socket
+    transport: Transport,
+}
+
+impl IrcConnectionApi {
+    /// Create a new API instance for the given connection, registering once it is open.
+    ///
+    /// # Arguments
+    ///
+    /// * `connection` - Connection to the IRC server, speaking TLS if its TLS versions are set
+    /// * `tls_worker` - Worker to run the TLS state machine in, or `None` to run it on this thread
+    /// * `registration` - Registration to send once the connection is open
+    /// * `callback` - Function called with every message received
+    pub fn new(
+        connection: Connection,
+        tls_worker: Option<TlsWorker>,
+        registration: &IrcRegistration,
+        callback: Option<js_sys::Function>,
+    ) -> Result<Self, ConnectionError> {
+        let messages = registration.messages()?;
+        let state = Rc::new(RefCell::new(IrcState {
+            buffer: Vec::new(),
+            nick: registration.nick.clone(),
+            nick_retries: 0,
+            registered: false,
+            last_received: js_sys::Date::now(),
+            keepalive: None,
+            callback,
+            log_prefix: connection.log_prefix(),
+        }));
+
+        let (transport, engine) = match &connection.tls {
+            None => (Transport::Plain(connection.sender()), None),
+            Some(tls) => {
+                let slot = Rc::new(OnceCell::new());
+                let transport = Transport::Tls(slot.clone());
+                let sender = connection.sender();
+                let socket = connection.socket.clone();
+                let cb_state = state.clone();
+                let cb_transport = transport.clone();
+                let log_prefix = connection.log_prefix();
+                let output_callback: TlsOutputCallback = Box::new(move |output| {
+                    let output = match output {
+                        Ok(output) => output,
+                        Err(e) => {
+                            console_log!("{}{}", log_prefix, e);
+                            let _ = socket.close();
+                            return;
+                        }
+                    };
+                    if !output.tls.is_empty() {
+                        let _ = sender.send(&output.tls);
+                    }
+                    #[cfg(feature = "keylog")]
+                    crate::keylog::emit(output.key_log.clone());
+                    if !output.plaintext.is_empty() {
+                        receive(&cb_state, &cb_transport, &output.plaintext);
+                    }
+                });
+                let (min, max) = tls.range();
+                let engine = Rc::new(TlsEngine::start(
+                    tls_worker,
+                    min,
+                    max,
+                    &connection.tls_options,
+                    &connection.addr,
+                    output_callback,
+                )?);
+                let _ = slot.set(Rc::downgrade(&engine));
+                (transport, Some(engine))
+            }
+        };
+
+        let cb_state = state.clone();
+        let cb_transport = transport.clone();
+        let cb_engine = engine.clone();
+        let message_callback: Closure<dyn Fn(MessageEvent)> =
+            Closure::wrap(Box::new(move |evt: MessageEvent| {
+                let buffer = match evt.data().dyn_into::<ArrayBuffer>() {
+                    Ok(buffer) => buffer,
+                    Err(_) => return,
+                };
+                let bytes = Uint8Array::new(&buffer).to_vec();
+                match &cb_engine {
+                    Some(engine) => engine.read(bytes),
+                    None => receive(&cb_state, &cb_transport, &bytes),
+                }
+            }));
+        connection
+            .socket
+            .add_event_listener_with_callback("message", message_callback.as_ref().unchecked_ref())
+            .unwrap_throw();
+        message_callback.forget();
+
+        let cb_transport = transport.clone();
+        let onready = Closure::once_into_js(move || cb_transport.write(&messages));
+        connection.set_onready(onready.unchecked_into(), Some(true));
+
+        Ok(Self {
+            connection,
+            state,
+            transport,
+        })
+    }
+}
+
+#[wasm_bindgen]
+impl IrcConnectionApi {
+    /// Get the address of this connection.
+    #[wasm_bindgen]
+    pub fn get_addr(&self) -> String {
+        self.connection.addr.clone()
+    }
+
+    /// Get the ID of this connection.
+    #[wasm_bindgen]
+    pub fn get_id(&self) -> u64 {
+        self.connection.get_id().into()
+    }
+
+    /// Get the UUID of this connection, if made with `ConnIdScheme.UuidV7`.
+    #[wasm_bindgen]
+    pub fn get_uuid(&self) -> Option<String> {
+        self.connection.get_id().uuid_string()
+    }
+
+    /// Get the current nick, as confirmed by the server once registered.
+    #[wasm_bindgen]
+    pub fn get_nick(&self) -> String {
+        self.state.borrow().nick.clone()
+    }
+
+    /// Check if the server accepted the registration.
+    #[wasm_bindgen]
+    pub fn is_registered(&self) -> bool {
+        self.state.borrow().registered
+    }
+
+    /// Set the function called with every message received, or `None` to stop delivering.
+    ///
+    /// `PING` is answered whether or not a function is set.
+    #[wasm_bindgen]
+    pub fn set_onmessage(&self, callback: Option<IrcMessageCallback>) {
+        self.state.borrow_mut().callback = callback.map(Into::into);
+    }
+
+    /// Ping the server when nothing was received for an interval, and close this connection
+    /// if nothing is received for two.
+    ///
+    /// # Arguments
+    ///
+    /// * `interval` - Interval in ms, or `None` to stop
+    #[wasm_bindgen]
+    pub fn set_keepalive(&self, interval: Option<f64>) {
+        let interval = interval.filter(|interval| *interval > 0.0);
+        self.state.borrow_mut().keepalive = interval;
+        if let Some(interval) = interval {
+            schedule_keepalive(
+                self.state.clone(),
+                self.transport.clone(),
+                self.connection.socket.clone(),
+                interval,
+            );
+        }
+    }
+
+    /// Send a message.
+    ///
+    /// # Returns
+    ///
+    /// The function returns a void, or an error if this connection is not open.
+    #[wasm_bindgen]
+    pub fn send(&self, message: &IrcMessage) -> Result<(), ConnectionError> {
+        if self.connection.socket.ready_state() != WebSocket::OPEN {
+            return Err(ConnectionError {
+                message: "Connection is not open".to_string(),
+            });
+        }
+        self.transport.write(std::slice::from_ref(message));
+        Ok(())
+    }
+
+    /// Send a `PRIVMSG` to a channel or nick.
+    ///
+    /// # Arguments
+    ///
+    /// * `target` - Channel or nick
+    /// * `text` - Text of the message
+    #[wasm_bindgen]
+    pub fn privmsg(&self, target: String, text: String) -> Result<(), ConnectionError> {
+        self.send(&IrcMessage::new("PRIVMSG".to_string(), vec![target, text])?)
+    }
+
+    /// Join a channel.
+    ///
+    /// # Arguments
+    ///
+    /// * `channel` - Channel, e.g. `#rust`
+    /// * `key` - Key of the channel, if it has one
+    #[wasm_bindgen]
+    pub fn join(&self, channel: String, key: Option<String>) -> Result<(), ConnectionError> {
+        let params = std::iter::once(channel).chain(key).collect();
+        self.send(&IrcMessage::new("JOIN".to_string(), params)?)
+    }
+
+    /// Leave the server with `QUIT`, which then closes this connection.
+    ///
+    /// # Arguments
+    ///
+    /// * `reason` - Reason shown to other users, if any
+    #[wasm_bindgen]
+    pub fn quit(&self, reason: Option<String>) -> Result<(), ConnectionError> {
+        self.send(&IrcMessage::new(
+            "QUIT".to_string(),
+            reason.into_iter().collect(),
+        )?)
+    }
+
+    /// Close this connection.
+    #[wasm_bindgen]
+    pub fn close(&self) {
+        self.connection.close();
+    }
+}
@@ -1,3 +1,13 @@
+pub mod assembler;
+pub mod ftp;
 pub mod http;
+#[cfg(feature = "http3")]
+pub mod http3;
 pub mod https;
+pub mod idb;
+pub mod irc;
+pub mod stream;
 pub mod tcp;
+pub mod tunnel;
+pub mod udp;
+pub mod upload;
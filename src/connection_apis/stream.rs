@@ -0,0 +1,136 @@
+use std::{cell::RefCell, rc::Rc};
+
+use wasm_bindgen::prelude::*;
+use wasm_bindgen_futures::js_sys::{self, Object, Reflect, Uint8Array};
+use web_sys::{
+    ReadableStream, ReadableStreamDefaultController, ReadableWritablePair, TextDecodeOptions,
+    TextDecoder, TransformStream, TransformStreamDefaultController,
+};
+
+use crate::connection::ConnectionError;
+
+use super::assembler::BodySink;
+
+/// Construct an instance of a global JS class.
+///
+/// Used for the stream classes, whose constructors are unstable in `web_sys`.
+fn construct(class: &str, args: &[&JsValue]) -> Result<JsValue, ConnectionError> {
+    let error = |e: JsValue| ConnectionError {
+        message: format!("Failed to create {}: {:?}", class, e),
+    };
+    let constructor = Reflect::get(&js_sys::global(), &class.into()).map_err(error)?;
+    let args: js_sys::Array = args.iter().copied().collect();
+    Reflect::construct(constructor.unchecked_ref(), &args).map_err(error)
+}
+
+/// Body of a response streamed to JS as it is received, as a `ReadableStream` of `Uint8Array`.
+#[derive(Clone)]
+pub(crate) struct StreamBody {
+    /// Stream read by JS
+    stream: ReadableStream,
+    /// Controller of the stream, set once the stream starts
+    controller: Rc<RefCell<Option<ReadableStreamDefaultController>>>,
+}
+
+impl StreamBody {
+    /// Create a new stream, waiting for the body chunks.
+    pub(crate) fn new() -> Result<Self, ConnectionError> {
+        let controller = Rc::new(RefCell::new(None));
+        let cb_controller = controller.clone();
+        let start = Closure::once_into_js(move |c: ReadableStreamDefaultController| {
+            *cb_controller.borrow_mut() = Some(c);
+        });
+        let source = Object::new();
+        Reflect::set(&source, &"start".into(), &start).unwrap_throw();
+        let stream = construct("ReadableStream", &[&source])?.unchecked_into();
+        Ok(Self { stream, controller })
+    }
+
+    /// Get the stream read by JS.
+    pub(crate) fn stream(&self) -> ReadableStream {
+        self.stream.clone()
+    }
+
+    /// Get a function enqueueing the body chunks it receives into the stream.
+    pub(crate) fn sink(&self) -> BodySink {
+        let controller = self.controller.clone();
+        Box::new(move |chunk| {
+            if let Some(controller) = controller.borrow().as_ref() {
+                // Fails if JS cancelled the stream, in which case the rest is dropped
+                let _ = controller.enqueue_with_chunk(&Uint8Array::from(chunk));
+            }
+        })
+    }
+
+    /// Close the stream once the whole body was enqueued.
+    pub(crate) fn finish(&self) {
+        if let Some(controller) = self.controller.borrow().as_ref() {
+            let _ = controller.close();
+        }
+    }
+
+    /// Fail the stream before the whole body was enqueued, rejecting the pending reads.
+    pub(crate) fn fail(&self, error: &JsValue) {
+        if let Some(controller) = self.controller.borrow().as_ref() {
+            controller.error_with_e(error);
+        }
+    }
+}
+
+/// Decode a stream of bytes into a stream of strings.
+///
+/// Characters split across chunks are decoded once complete, like a `TextDecoderStream`.
+///
+/// # Arguments
+///
+/// * `stream` - Stream of `Uint8Array` chunks
+/// * `charset` - Label of the encoding of the bytes, e.g. `utf-8` or `iso-8859-1`
+pub(crate) fn decode_text_stream(
+    stream: &ReadableStream,
+    charset: &str,
+) -> Result<ReadableStream, ConnectionError> {
+    let decoder = TextDecoder::new_with_label(charset).map_err(|_| ConnectionError {
+        message: format!("Unsupported charset: {}", charset),
+    })?;
+
+    let cb_decoder = decoder.clone();
+    let transform = Closure::wrap(Box::new(
+        move |chunk: Uint8Array, controller: TransformStreamDefaultController| {
+            let mut bytes = chunk.to_vec();
+            let text = cb_decoder.decode_with_u8_array_and_options(
+                &mut bytes,
+                TextDecodeOptions::new().stream(true),
+            )?;
+            if !text.is_empty() {
+                controller.enqueue_with_chunk(&text.into())?;
+            }
+            Ok(())
+        },
+    )
+        as Box<dyn Fn(Uint8Array, TransformStreamDefaultController) -> Result<(), JsValue>>);
+    let flush = Closure::wrap(
+        Box::new(move |controller: TransformStreamDefaultController| {
+            // Incomplete characters left at the end are decoded as replacement characters
+            let text = decoder.decode()?;
+            if !text.is_empty() {
+                controller.enqueue_with_chunk(&text.into())?;
+            }
+            Ok(())
+        }) as Box<dyn Fn(TransformStreamDefaultController) -> Result<(), JsValue>>,
+    );
+
+    let transformer = Object::new();
+    Reflect::set(
+        &transformer,
+        &"transform".into(),
+        &transform.into_js_value(),
+    )
+    .unwrap_throw();
+    Reflect::set(&transformer, &"flush".into(), &flush.into_js_value()).unwrap_throw();
+    let decoding: TransformStream = construct("TransformStream", &[&transformer])?.unchecked_into();
+
+    Ok(stream.pipe_through(&ReadableWritablePair::new(
+        &decoding.readable(),
+        &decoding.writable(),
+    )))
+}
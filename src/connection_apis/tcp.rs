@@ -1,8 +1,307 @@
+use std::{cell::RefCell, collections::VecDeque, future::Future, rc::Rc};
+
 use wasm_bindgen::prelude::*;
-use wasm_bindgen_futures::js_sys::{self, ArrayBuffer, Uint8Array};
-use web_sys::{AddEventListenerOptions, MessageEvent};
+use wasm_bindgen_futures::{
+    future_to_promise,
+    js_sys::{self, Array, ArrayBuffer, Promise, Uint8Array},
+    JsFuture,
+};
+use web_sys::{AddEventListenerOptions, MessageEvent, WebSocket};
+
+use crate::{
+    batch::{object, settle_on},
+    callbacks::TcpResponseCallback,
+    connection::{Connection, ConnectionError, ConnectionSettings, Sender},
+    id::ConnIdFactory,
+    limiter::ConnectionLimiter,
+    pending::release_listener,
+    proxy_protocol::ProxyHeader,
+    set_timeout, SocketCapability,
+};
+
+/// Get a JS buffer as a `Uint8Array`, without copying it.
+///
+/// # Arguments
+///
+/// * `data` - Either a `Uint8Array` or an `ArrayBuffer`
+pub(crate) fn as_uint8_array(data: &JsValue) -> Result<Uint8Array, ConnectionError> {
+    if let Some(buffer) = data.dyn_ref::<ArrayBuffer>() {
+        Ok(Uint8Array::new(buffer))
+    } else if let Some(array) = data.dyn_ref::<Uint8Array>() {
+        Ok(array.clone())
+    } else {
+        Err(ConnectionError {
+            message: "Expected a Uint8Array or an ArrayBuffer".to_string(),
+        })
+    }
+}
+
+/// Bytes collected on a socket, listened to until dropped.
+pub(crate) struct Collected {
+    /// Socket listened to
+    socket: WebSocket,
+    /// Bytes received so far
+    received: Rc<RefCell<Vec<u8>>>,
+    /// Listener of the messages of the socket, released on drop
+    listener: Option<Closure<dyn Fn(MessageEvent)>>,
+}
+
+impl Collected {
+    /// Stop listening to the socket.
+    ///
+    /// # Returns
+    ///
+    /// Every byte received so far.
+    pub(crate) fn take(self) -> Vec<u8> {
+        self.received.take()
+    }
+}
+
+impl Drop for Collected {
+    fn drop(&mut self) {
+        if let Some(listener) = self.listener.take() {
+            release_listener(&self.socket, "message", listener);
+        }
+    }
+}
+
+/// Collect every byte received on a socket from now on, until the returned collection is
+/// taken or dropped.
+///
+/// # Arguments
+///
+/// * `socket` - Socket to listen to
+/// * `onreceive` - Function called with the number of bytes received so far, after every chunk
+pub(crate) fn collect(socket: &WebSocket, onreceive: impl Fn(usize) + 'static) -> Collected {
+    let received = Rc::new(RefCell::new(Vec::new()));
+    let target = received.clone();
+    let message_callback: Closure<dyn Fn(MessageEvent)> =
+        Closure::wrap(Box::new(move |evt: MessageEvent| {
+            let buffer = match evt.data().dyn_into::<ArrayBuffer>() {
+                Ok(buffer) => buffer,
+                Err(_) => return,
+            };
+            let loaded = {
+                let mut received = target.borrow_mut();
+                received.extend(Uint8Array::new(&buffer).to_vec());
+                received.len()
+            };
+            onreceive(loaded);
+        }));
+    socket
+        .add_event_listener_with_callback("message", message_callback.as_ref().unchecked_ref())
+        .unwrap_throw();
+    Collected {
+        socket: socket.clone(),
+        received,
+        listener: Some(message_callback),
+    }
+}
+
+/// Read every byte received on a socket from now on, until the remote end closes it.
+///
+/// The socket is listened to right away, so bytes received before the future is awaited
+/// are not missed.
+///
+/// # Returns
+///
+/// The bytes, or an error if the socket is already closed or fails.
+pub(crate) fn read_to_close(socket: &WebSocket) -> impl Future<Output = Result<Vec<u8>, JsValue>> {
+    let closed = match socket.ready_state() {
+        WebSocket::CLOSING | WebSocket::CLOSED => None,
+        _ => Some(settle_on(socket, Some("close"), "Connection failed")),
+    };
+    let received = collect(socket, |_| {});
+    async move {
+        let closed = closed.ok_or_else(|| ConnectionError {
+            message: "Connection is closed".to_string(),
+        })?;
+        JsFuture::from(closed).await?;
+        Ok(received.take())
+    }
+}
+
+/// Handles of a client needed to open TCP connections without borrowing it, e.g. the data
+/// connections of an FTP connection.
+#[derive(Clone)]
+pub struct TcpDialer {
+    /// Settings of the new connections
+    pub settings: ConnectionSettings,
+    /// Factory for the connection IDs of the client
+    pub factory: Rc<RefCell<ConnIdFactory>>,
+    /// Limiter of the connections of the client
+    pub limiter: ConnectionLimiter,
+    /// Connections of the client, where new connections are added while open
+    pub connections: Rc<RefCell<Vec<Connection>>>,
+}
+
+impl TcpDialer {
+    /// Open a connection and add it to the client, if the host policy and limit allow.
+    ///
+    /// # Arguments
+    ///
+    /// * `addr` - Address to connect to, as `host:port`
+    /// * `query` - Query parameters appended to the proxy url, e.g. from `TcpOptions`
+    pub(crate) fn connect(
+        &self,
+        addr: String,
+        query: &[(String, String)],
+    ) -> Result<Connection, ConnectionError> {
+        let protocol = SocketCapability::TCP;
+        self.settings.host_policy.check(protocol, &addr)?;
+        if !self.limiter.try_acquire() {
+            return Err(ConnectionError {
+                message: "Too many connections".to_string(),
+            });
+        }
+        let id = self.factory.borrow_mut().generate(protocol);
+        let connection = Connection::new_with_settings(&self.settings, protocol, addr, id, query)
+            .inspect_err(|_| self.limiter.release())?;
+        self.limiter.track(&connection);
+        self.connections.borrow_mut().push(connection.clone());
+        Ok(connection)
+    }
+
+    /// Open a connection for as long as it is used, removed from the client once dropped.
+    ///
+    /// # Arguments
+    ///
+    /// * `addr` - Address to connect to, as `host:port`
+    pub(crate) fn open(&self, addr: String) -> Result<DialedConnection, ConnectionError> {
+        Ok(DialedConnection {
+            connection: self.connect(addr, &[])?,
+            connections: self.connections.clone(),
+        })
+    }
+
+    /// Open connections to many addresses, with at most `concurrency` of them opening at once.
+    ///
+    /// # Arguments
+    ///
+    /// * `addrs` - Addresses to connect to, as `host:port`
+    /// * `options` - Socket options of every connection
+    /// * `timeout` - Time a connection has to open in ms, or `None` to wait forever
+    /// * `concurrency` - Number of connections opening at once
+    ///
+    /// # Returns
+    ///
+    /// A promise resolving, once every connection has opened or failed, to an array of the
+    /// outcomes in the order of `addrs`.
+    pub(crate) fn open_all(
+        self,
+        addrs: Vec<String>,
+        options: TcpOptions,
+        timeout: Option<f64>,
+        concurrency: usize,
+    ) -> Promise {
+        let results = Array::new_with_length(addrs.len() as u32);
+        let workers = concurrency.clamp(1, addrs.len().max(1));
+        let queue = Rc::new(RefCell::new(
+            addrs.into_iter().enumerate().collect::<VecDeque<_>>(),
+        ));
+        let options = Rc::new(options);
+
+        let workers: Array = (0..workers)
+            .map(|_| {
+                let dialer = self.clone();
+                let queue = queue.clone();
+                let results = results.clone();
+                let options = options.clone();
+                JsValue::from(future_to_promise(async move {
+                    loop {
+                        // Released before awaiting, so other workers can take addresses
+                        let next = queue.borrow_mut().pop_front();
+                        let (index, addr) = match next {
+                            Some(next) => next,
+                            None => return Ok(JsValue::UNDEFINED),
+                        };
+                        let outcome = dialer.open_one(addr, &options, timeout).await;
+                        results.set(index as u32, outcome);
+                    }
+                }))
+            })
+            .collect();
 
-use crate::connection::{Connection, ConnectionError};
+        future_to_promise(async move {
+            JsFuture::from(Promise::all(&workers)).await?;
+            Ok(results.into())
+        })
+    }
+
+    /// Open a connection for `open_all`, describing its outcome.
+    async fn open_one(&self, addr: String, options: &TcpOptions, timeout: Option<f64>) -> JsValue {
+        let start = js_sys::Date::now();
+        let outcome = |status: &str, extra: (&str, JsValue)| {
+            object(&[
+                ("addr", addr.as_str().into()),
+                ("status", status.into()),
+                ("elapsed", (js_sys::Date::now() - start).into()),
+                extra,
+            ])
+        };
+        let connection = match self.connect(addr.clone(), &options.to_query()) {
+            Ok(connection) => connection,
+            Err(e) => return outcome("error", ("error", e.message.into())),
+        };
+
+        let opened = settle_on(&connection.socket, Some("open"), "Connection refused");
+        let opened = match timeout {
+            Some(timeout) => {
+                let expired = Promise::new(&mut |resolve, _| {
+                    let ontimeout = Closure::once_into_js(move || {
+                        let _ = resolve.call1(&JsValue::null(), &"timeout".into());
+                    });
+                    set_timeout(&ontimeout, timeout);
+                });
+                Promise::race(&Array::of2(&opened, &expired))
+            }
+            None => opened,
+        };
+        let (status, error) = match JsFuture::from(opened).await {
+            Ok(value) if value.as_string().as_deref() == Some("timeout") => {
+                ("timeout", "Connection timed out")
+            }
+            Ok(_) => {
+                if let Some(header) = options.proxy_header() {
+                    let _ = connection.sender().send(header.as_bytes());
+                }
+                let api = TcpConnectionApi::new(connection);
+                return outcome("connected", ("connection", api.into()));
+            }
+            Err(_) => ("refused", "Connection refused"),
+        };
+        let id = u64::from(connection.get_id());
+        self.connections
+            .borrow_mut()
+            .retain(|c| u64::from(c.get_id()) != id);
+        outcome(status, ("error", error.into()))
+    }
+}
+
+/// Connection opened by a `TcpDialer`, removed from its client and closed once dropped.
+pub(crate) struct DialedConnection {
+    /// Connection opened
+    pub(crate) connection: Connection,
+    /// Connections of the client
+    connections: Rc<RefCell<Vec<Connection>>>,
+}
+
+impl DialedConnection {
+    /// Get the socket of the connection.
+    pub(crate) fn socket(&self) -> &WebSocket {
+        &self.connection.socket
+    }
+}
+
+impl Drop for DialedConnection {
+    fn drop(&mut self) {
+        let id = u64::from(self.connection.get_id());
+        self.connections
+            .borrow_mut()
+            .retain(|c| u64::from(c.get_id()) != id);
+        self.connection.close();
+    }
+}
 
 #[wasm_bindgen]
 pub struct TcpConnectionRequest {
@@ -21,6 +320,90 @@ impl TcpConnectionRequest {
     pub fn new(body: Vec<u8>) -> Self {
         Self { body }
     }
+
+    /// Create a new TCP request from a JS buffer, copying it into the module memory once.
+    ///
+    /// # Arguments
+    ///
+    /// * `body` - Request body, either a `Uint8Array` or an `ArrayBuffer`
+    #[wasm_bindgen]
+    pub fn from_buffer(body: &JsValue) -> Result<TcpConnectionRequest, ConnectionError> {
+        Ok(Self {
+            body: as_uint8_array(body)?.to_vec(),
+        })
+    }
+}
+
+/// Socket options for the remote end of a TCP connection.
+///
+/// The options are passed to the proxy as query parameters of the connection url, and are
+/// only hints: proxies that don't support them ignore them.
+#[wasm_bindgen]
+#[derive(Clone, Default)]
+pub struct TcpOptions {
+    /// Whether to disable Nagle's algorithm (`TCP_NODELAY`)
+    nodelay: Option<bool>,
+    /// Keepalive interval in seconds
+    keepalive: Option<u32>,
+    /// Connect timeout in milliseconds
+    connect_timeout: Option<u32>,
+    /// PROXY protocol header to send before any other data
+    proxy_header: Option<ProxyHeader>,
+}
+
+#[wasm_bindgen]
+impl TcpOptions {
+    /// Create new TCP options, leaving every option to the proxy default.
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set whether to disable Nagle's algorithm (`TCP_NODELAY`).
+    #[wasm_bindgen]
+    pub fn set_nodelay(&mut self, nodelay: bool) {
+        self.nodelay = Some(nodelay);
+    }
+
+    /// Set the keepalive interval in seconds.
+    #[wasm_bindgen]
+    pub fn set_keepalive(&mut self, seconds: u32) {
+        self.keepalive = Some(seconds);
+    }
+
+    /// Set the connect timeout in milliseconds.
+    #[wasm_bindgen]
+    pub fn set_connect_timeout(&mut self, millis: u32) {
+        self.connect_timeout = Some(millis);
+    }
+
+    /// Set a PROXY protocol header to send as the first bytes of the connection.
+    #[wasm_bindgen]
+    pub fn set_proxy_header(&mut self, header: &ProxyHeader) {
+        self.proxy_header = Some(header.clone());
+    }
+}
+
+impl TcpOptions {
+    /// Get the PROXY protocol header to send before any other data.
+    pub fn proxy_header(&self) -> Option<&ProxyHeader> {
+        self.proxy_header.as_ref()
+    }
+
+    /// Get the query parameters carrying these options.
+    pub fn to_query(&self) -> Vec<(String, String)> {
+        let mut query = Vec::new();
+        if let Some(nodelay) = self.nodelay {
+            query.push(("nodelay".to_string(), (nodelay as u8).to_string()));
+        }
+        if let Some(keepalive) = self.keepalive {
+            query.push(("keepalive".to_string(), keepalive.to_string()));
+        }
+        if let Some(connect_timeout) = self.connect_timeout {
+            query.push(("connect_timeout".to_string(), connect_timeout.to_string()));
+        }
+        query
+    }
 }
 
 #[wasm_bindgen]
@@ -42,10 +425,28 @@ impl TcpConnectionResponse {
     pub fn new(body: Vec<u8>) -> Self {
         Self { body }
     }
-    /// Get the response body.
+    /// Get a copy of the response body.
+    #[wasm_bindgen]
+    pub fn get_body(&self) -> Uint8Array {
+        Uint8Array::from(self.body.as_slice())
+    }
+
+    /// Get a view of the response body backed by the module memory, without copying it.
+    ///
+    /// The view is invalidated when the module memory grows or this response is freed,
+    /// so it must be consumed (or copied) before calling into this module again.
+    #[wasm_bindgen]
+    pub fn get_body_view(&self) -> Uint8Array {
+        // SAFETY: the view is only valid until the next allocation, as documented above
+        unsafe { Uint8Array::view(&self.body) }
+    }
+
+    /// Take the response body, leaving this response with an empty one.
+    ///
+    /// The body is copied to JS once and freed from the module memory.
     #[wasm_bindgen]
-    pub fn get_body(&self) -> Vec<u8> {
-        return self.body.clone();
+    pub fn take_body(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.body)
     }
 }
 
@@ -64,6 +465,11 @@ impl TcpConnectionApi {
     pub fn new(connection: Connection) -> Self {
         Self { connection }
     }
+
+    /// Get the connection of this API.
+    pub(crate) fn connection(&self) -> &Connection {
+        &self.connection
+    }
 }
 
 #[wasm_bindgen]
@@ -74,6 +480,18 @@ impl TcpConnectionApi {
         self.connection.addr.clone()
     }
 
+    /// Get the ID of this connection.
+    #[wasm_bindgen]
+    pub fn get_id(&self) -> u64 {
+        self.connection.get_id().into()
+    }
+
+    /// Get the UUID of this connection, if made with `ConnIdScheme.UuidV7`.
+    #[wasm_bindgen]
+    pub fn get_uuid(&self) -> Option<String> {
+        self.connection.get_id().uuid_string()
+    }
+
     /// Send data to this connection.
     ///
     /// # Arguments
@@ -89,15 +507,124 @@ impl TcpConnectionApi {
     pub fn send(
         &self,
         data: TcpConnectionRequest,
-        callback: js_sys::Function,
+        callback: TcpResponseCallback,
+    ) -> Result<(), ConnectionError> {
+        self.send_with(callback.into(), |sender| sender.send(&data.body))
+    }
+
+    /// Send a JS buffer to this connection without copying it into the module memory.
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - Data to send, either a `Uint8Array` or an `ArrayBuffer`
+    /// * `callback` - Callback to call when data is received from this connection.
+    ///
+    /// # Returns
+    ///
+    /// The function returns a Result containing a void, or an error depending on the success of the send.
+    /// * `ConnectionError` - Error that occurred while sending data to this connection.
+    #[wasm_bindgen]
+    pub fn send_bytes(
+        &self,
+        data: &JsValue,
+        callback: TcpResponseCallback,
     ) -> Result<(), ConnectionError> {
-        if (self.connection.socket.ready_state() as u16) != 1 {
+        let array = as_uint8_array(data)?;
+        self.send_with(callback.into(), |sender| sender.send_array(&array))
+    }
+
+    /// Write data to this connection without waiting for a response.
+    ///
+    /// Unlike `send`, no listener is registered, for protocols where writes and reads are decoupled.
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - Data to write, either a `Uint8Array` or an `ArrayBuffer`
+    ///
+    /// # Returns
+    ///
+    /// The function returns a Result containing a void, or an error depending on the success of the write.
+    /// * `ConnectionError` - Error that occurred while writing data to this connection.
+    #[wasm_bindgen]
+    pub fn write(&self, data: &JsValue) -> Result<(), ConnectionError> {
+        let array = as_uint8_array(data)?;
+        self.ensure_open()?;
+        self.connection.sender().send_array(&array).unwrap_throw();
+        Ok(())
+    }
+
+    /// Read every byte received on this connection from now on, until the remote end closes it.
+    ///
+    /// For protocols whose response ends with the connection, e.g. WHOIS. Bytes received
+    /// before the call are not included.
+    ///
+    /// # Returns
+    ///
+    /// A promise resolving to the bytes as a `Uint8Array`, or rejecting with a
+    /// `ConnectionError` if this connection is already closed or fails.
+    #[wasm_bindgen]
+    pub fn read_to_end(&self) -> Promise {
+        // Held until the end, keeping the connection open while it is read
+        let connection = self.connection.clone();
+        future_to_promise(async move {
+            let received = read_to_close(&connection.socket).await?;
+            Ok(Uint8Array::from(received.as_slice()).into())
+        })
+    }
+
+    /// Shut down the write side of this connection, while still receiving data.
+    ///
+    /// Further writes and sends on this connection fail. The wsproxy protocol has no way to
+    /// signal a half-close, so the remote socket is only fully closed with the connection.
+    #[wasm_bindgen]
+    pub fn shutdown_write(&self) {
+        self.connection.shutdown_write();
+    }
+
+    /// Ping this connection.
+    ///
+    /// # Returns
+    ///
+    /// The function returns a void, or an error depending on the success of the ping.
+    #[wasm_bindgen]
+    pub fn ping(&self) -> Result<(), ConnectionError> {
+        Ok(())
+    }
+
+    /// Close this connection.
+    pub fn close(&self) {
+        self.connection.close();
+    }
+}
+
+impl TcpConnectionApi {
+    /// Fail if this connection is not open.
+    fn ensure_open(&self) -> Result<(), ConnectionError> {
+        if self.connection.socket.ready_state() != 1 {
             return Err(ConnectionError {
                 message: "Connection is not open".to_string(),
             });
         }
+        if self.connection.is_write_shutdown() {
+            return Err(ConnectionError {
+                message: "Connection is shut down for writing".to_string(),
+            });
+        }
+        Ok(())
+    }
+
+    /// Register a callback for the next message of this connection, then send data with `send`.
+    fn send_with(
+        &self,
+        callback: js_sys::Function,
+        send: impl FnOnce(&Sender) -> Result<(), JsValue>,
+    ) -> Result<(), ConnectionError> {
+        self.ensure_open()?;
 
-        let message_callback: JsValue = Closure::once_into_js(move |evt: MessageEvent| {
+        let in_flight = self.connection.pending.start()?;
+        let cb_in_flight = in_flight.clone();
+        let message_callback = Closure::once(move |evt: MessageEvent| {
+            cb_in_flight.finish();
             let buffer = evt.data().dyn_into::<ArrayBuffer>().unwrap_throw();
             let vec = Uint8Array::new(&buffer).to_vec();
 
@@ -110,8 +637,7 @@ impl TcpConnectionApi {
                 .unwrap_throw();
         });
 
-        let _ = self
-            .connection
+        self.connection
             .socket
             .add_event_listener_with_callback_and_add_event_listener_options(
                 "message",
@@ -119,28 +645,11 @@ impl TcpConnectionApi {
                 AddEventListenerOptions::new().once(true),
             )
             .unwrap_throw();
+        let socket = self.connection.socket.clone();
+        in_flight.on_release(move |_| release_listener(&socket, "message", message_callback));
 
-        let _ = self
-            .connection
-            .socket
-            .send_with_u8_array(&data.body)
-            .unwrap_throw();
+        send(&self.connection.sender()).unwrap_throw();
 
         Ok(())
     }
-
-    /// Ping this connection.
-    ///
-    /// # Returns
-    ///
-    /// The function returns a void, or an error depending on the success of the ping.
-    #[wasm_bindgen]
-    pub fn ping(&self) -> Result<(), ConnectionError> {
-        Ok(())
-    }
-
-    /// Close this connection.
-    pub fn close(&self) {
-        let _ = self.connection.socket.close();
-    }
 }
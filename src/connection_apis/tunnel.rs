@@ -0,0 +1,109 @@
+use std::{
+    cell::{Cell, RefCell},
+    rc::Rc,
+};
+
+use wasm_bindgen::prelude::*;
+use wasm_bindgen_futures::js_sys::{ArrayBuffer, Uint8Array};
+use web_sys::{AddEventListenerOptions, MessageEvent, MessageEventInit, WebSocket};
+
+use crate::connection::{ConnectionError, Sender};
+
+use super::http::METHOD_CONNECT;
+
+/// Callback receiving the outcome of a CONNECT request.
+pub type TunnelCallback = Box<dyn FnOnce(Result<(), ConnectionError>)>;
+
+/// Establish a tunnel through an HTTP proxy with a CONNECT request, once the socket is open.
+///
+/// After a successful response, the bytes of the socket belong to the tunnel. Bytes received
+/// right after the response are delivered again as a message once `callback` returns, so
+/// listeners registered by the callback receive them.
+///
+/// # Arguments
+///
+/// * `socket` - Socket connected to the proxy
+/// * `sender` - Handle sending bytes on the socket
+/// * `target` - Address to reach through the proxy, as `host:port`
+/// * `proxy_authorization` - Value of the `Proxy-Authorization` header, if any
+/// * `callback` - Callback receiving the outcome of the request
+pub fn establish(
+    socket: &WebSocket,
+    sender: Sender,
+    target: String,
+    proxy_authorization: Option<String>,
+    callback: TunnelCallback,
+) {
+    let mut request = format!("{} {1} HTTP/1.1\r\nHost: {1}\r\n", METHOD_CONNECT, target);
+    if let Some(authorization) = proxy_authorization {
+        request.push_str(&format!("Proxy-Authorization: {}\r\n", authorization));
+    }
+    request.push_str("\r\n");
+
+    let onready = Closure::once_into_js(move || {
+        if let Err(e) = sender.send(request.as_bytes()) {
+            crate::console_log!("Failed to send CONNECT request: {:?}", e);
+        }
+    });
+    socket
+        .add_event_listener_with_callback_and_add_event_listener_options(
+            "open",
+            onready.unchecked_ref(),
+            AddEventListenerOptions::new().once(true),
+        )
+        .unwrap_throw();
+
+    let head = RefCell::new(Vec::new());
+    let done = Cell::new(false);
+    let callback = Rc::new(RefCell::new(Some(callback)));
+    let target = socket.clone();
+    let message_callback: Closure<dyn Fn(MessageEvent)> =
+        Closure::wrap(Box::new(move |evt: MessageEvent| {
+            if done.get() {
+                return;
+            }
+            let buffer = match evt.data().dyn_into::<ArrayBuffer>() {
+                Ok(buffer) => buffer,
+                Err(_) => return,
+            };
+            // The response belongs to the tunnel setup, not to other listeners
+            evt.stop_immediate_propagation();
+
+            let mut head = head.borrow_mut();
+            head.extend_from_slice(&Uint8Array::new(&buffer).to_vec());
+            let end = match head.windows(4).position(|w| w == b"\r\n\r\n") {
+                Some(end) => end,
+                None => return,
+            };
+            done.set(true);
+
+            let status = String::from_utf8_lossy(&head[..end]);
+            let status = status.lines().next().unwrap_or_default();
+            let code = status
+                .split(' ')
+                .nth(1)
+                .and_then(|code| code.parse::<u16>().ok());
+            let callback = callback.borrow_mut().take().unwrap_throw();
+            if !code.is_some_and(|code| (200..300).contains(&code)) {
+                let _ = target.close();
+                callback(Err(ConnectionError {
+                    message: format!("Proxy refused CONNECT: {}", status),
+                }));
+                return;
+            }
+            callback(Ok(()));
+
+            let rest = head.split_off(end + 4);
+            if !rest.is_empty() {
+                let mut init = MessageEventInit::new();
+                init.data(&Uint8Array::from(rest.as_slice()).buffer());
+                let message =
+                    MessageEvent::new_with_event_init_dict("message", &init).unwrap_throw();
+                target.dispatch_event(&message).unwrap_throw();
+            }
+        }));
+    socket
+        .add_event_listener_with_callback("message", message_callback.as_ref().unchecked_ref())
+        .unwrap_throw();
+    message_callback.forget();
+}
@@ -0,0 +1,155 @@
+use wasm_bindgen::prelude::*;
+use wasm_bindgen_futures::js_sys::{ArrayBuffer, Uint8Array};
+use web_sys::MessageEvent;
+
+use crate::{
+    callbacks::DatagramCallback,
+    connection::{Connection, ConnectionError},
+};
+
+/// Largest payload of a UDP datagram over IPv4.
+pub const MAX_DATAGRAM_SIZE: u32 = 65507;
+
+#[wasm_bindgen]
+pub struct UdpConnectionApi {
+    /// Connection to create API for
+    connection: Connection,
+    /// Largest datagram that may be sent or received
+    max_datagram_size: u32,
+}
+
+impl UdpConnectionApi {
+    /// Create a new API instance for the given connection.
+    ///
+    /// # Arguments
+    ///
+    /// * `connection` - Connection to create API for
+    pub fn new(connection: Connection) -> Self {
+        Self {
+            connection,
+            max_datagram_size: MAX_DATAGRAM_SIZE,
+        }
+    }
+
+    /// Get the connection of this API.
+    pub(crate) fn connection(&self) -> &Connection {
+        &self.connection
+    }
+}
+
+#[wasm_bindgen]
+impl UdpConnectionApi {
+    #[wasm_bindgen]
+    /// Get the address of this connection.
+    pub fn get_addr(&self) -> String {
+        self.connection.addr.clone()
+    }
+
+    /// Get the ID of this connection.
+    #[wasm_bindgen]
+    pub fn get_id(&self) -> u64 {
+        self.connection.get_id().into()
+    }
+
+    /// Get the UUID of this connection, if made with `ConnIdScheme.UuidV7`.
+    #[wasm_bindgen]
+    pub fn get_uuid(&self) -> Option<String> {
+        self.connection.get_id().uuid_string()
+    }
+
+    /// Get the largest datagram that may be sent or received.
+    #[wasm_bindgen]
+    pub fn get_max_datagram_size(&self) -> u32 {
+        self.max_datagram_size
+    }
+
+    /// Set the largest datagram that may be sent or received, capped to `MAX_DATAGRAM_SIZE`.
+    ///
+    /// Only affects callbacks registered after this call.
+    #[wasm_bindgen]
+    pub fn set_max_datagram_size(&mut self, size: u32) {
+        self.max_datagram_size = size.min(MAX_DATAGRAM_SIZE);
+    }
+
+    /// Send a datagram to this connection.
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - Datagram to send, either a `Uint8Array` or an `ArrayBuffer`
+    ///
+    /// # Returns
+    ///
+    /// The function returns a Result containing a void, or an error depending on the success of the send.
+    /// * `ConnectionError` - Error that occurred while sending the datagram.
+    #[wasm_bindgen]
+    pub fn send(&self, data: &JsValue) -> Result<(), ConnectionError> {
+        let datagram = if let Some(buffer) = data.dyn_ref::<ArrayBuffer>() {
+            Uint8Array::new(buffer)
+        } else if let Some(array) = data.dyn_ref::<Uint8Array>() {
+            array.clone()
+        } else {
+            return Err(ConnectionError {
+                message: "Expected a Uint8Array or an ArrayBuffer".to_string(),
+            });
+        };
+
+        if datagram.length() > self.max_datagram_size {
+            return Err(ConnectionError {
+                message: format!(
+                    "Datagram of {} bytes exceeds the maximum of {} bytes",
+                    datagram.length(),
+                    self.max_datagram_size
+                ),
+            });
+        }
+
+        if self.connection.socket.ready_state() != 1 {
+            return Err(ConnectionError {
+                message: "Connection is not open".to_string(),
+            });
+        }
+
+        // Each WebSocket message carries exactly one datagram
+        self.connection
+            .sender()
+            .send_array(&datagram)
+            .unwrap_throw();
+
+        Ok(())
+    }
+
+    /// Set the callback called with every datagram received from this connection.
+    ///
+    /// Datagrams larger than the maximum datagram size are dropped.
+    ///
+    /// # Arguments
+    ///
+    /// * `callback` - Callback called with a `Uint8Array` per datagram
+    #[wasm_bindgen]
+    pub fn set_ondatagram(&self, callback: DatagramCallback) {
+        let max_datagram_size = self.max_datagram_size;
+        let message_callback: Closure<dyn Fn(MessageEvent)> =
+            Closure::wrap(Box::new(move |evt: MessageEvent| {
+                let buffer = evt.data().dyn_into::<ArrayBuffer>().unwrap_throw();
+                let datagram = Uint8Array::new(&buffer);
+                if datagram.length() > max_datagram_size {
+                    return;
+                }
+
+                let this = JsValue::null();
+
+                callback.call1(&this, &datagram).unwrap_throw();
+            }));
+
+        self.connection
+            .socket
+            .set_onmessage(Some(message_callback.as_ref().unchecked_ref()));
+
+        message_callback.forget();
+    }
+
+    /// Close this connection.
+    pub fn close(&self) {
+        self.connection.close();
+    }
+}
@@ -0,0 +1,219 @@
+use wasm_bindgen::prelude::*;
+use wasm_bindgen_futures::{
+    js_sys::{self, Array, Uint8Array},
+    spawn_local, JsFuture,
+};
+use web_sys::{Blob, BlobPropertyBag, File, FormData, WebSocket};
+
+use crate::{
+    batch::sleep,
+    connection::ConnectionError,
+    console_log,
+    progress::{Progress, ProgressDirection, ProgressReporter},
+};
+
+use super::http::{HttpConnectionRequest, HttpHeader};
+
+/// Size of the slices a blob body is read and sent in, in bytes.
+const SLICE_SIZE: f64 = 256.0 * 1024.0;
+/// Number of bytes the socket may have queued before the next slice is read.
+const MAX_BUFFERED: u32 = 4 * 256 * 1024;
+/// Time to wait for the socket to drain its queue, in ms.
+const DRAIN_INTERVAL: f64 = 10.0;
+/// Characters the random part of multipart boundaries is made of.
+const BOUNDARY_CHARS: &[u8] = b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz";
+
+/// Body of a request read from a `Blob` or `File` slice by slice as it is sent, instead of
+/// being copied into the module memory as a whole.
+#[derive(Clone)]
+pub(crate) struct BlobUpload {
+    /// Blob holding the body
+    blob: Blob,
+}
+
+impl BlobUpload {
+    /// Create a new upload of a blob.
+    ///
+    /// # Arguments
+    ///
+    /// * `blob` - Blob or `File` holding the body
+    pub(crate) fn new(blob: Blob) -> Self {
+        Self { blob }
+    }
+
+    /// Create a new upload of a `FormData`, encoded as `multipart/form-data` like `fetch` does.
+    ///
+    /// The parts are assembled into a blob referencing the files of the form rather than
+    /// copying them, with a `Content-Type` carrying a generated boundary.
+    ///
+    /// # Arguments
+    ///
+    /// * `form` - Form whose entries, strings and files, become the parts of the body
+    pub(crate) fn from_form_data(form: &FormData) -> Result<Self, ConnectionError> {
+        let boundary = generate_boundary();
+        let parts = Array::new();
+        let entries = js_sys::try_iter(form)
+            .ok()
+            .flatten()
+            .ok_or_else(|| ConnectionError {
+                message: "FormData is not iterable".to_string(),
+            })?;
+        for entry in entries {
+            let entry = Array::from(&entry.map_err(|e| ConnectionError {
+                message: format!("Failed to read form entry: {:?}", e),
+            })?);
+            let name = escape_form_name(&entry.get(0).as_string().unwrap_or_default());
+            let value = entry.get(1);
+            let mut head = format!(
+                "--{}\r\nContent-Disposition: form-data; name=\"{}\"",
+                boundary, name
+            );
+            match value.dyn_into::<File>() {
+                Ok(file) => {
+                    let content_type = match file.type_() {
+                        t if t.is_empty() => "application/octet-stream".to_string(),
+                        t => t,
+                    };
+                    head.push_str(&format!(
+                        "; filename=\"{}\"\r\nContent-Type: {}\r\n\r\n",
+                        escape_form_name(&file.name()),
+                        content_type
+                    ));
+                    parts.push(&head.into());
+                    parts.push(&file);
+                }
+                Err(value) => {
+                    let value = value.as_string().unwrap_or_default();
+                    head.push_str("\r\n\r\n");
+                    head.push_str(&normalize_newlines(&value));
+                    parts.push(&head.into());
+                }
+            }
+            parts.push(&"\r\n".into());
+        }
+        parts.push(&format!("--{}--\r\n", boundary).into());
+
+        let mut options = BlobPropertyBag::new();
+        options.type_(&format!("multipart/form-data; boundary={}", boundary));
+        let blob = Blob::new_with_blob_sequence_and_options(&parts, &options).map_err(|e| {
+            ConnectionError {
+                message: format!("Failed to encode form: {:?}", e),
+            }
+        })?;
+        Ok(Self::new(blob))
+    }
+
+    /// Get the size of the body, in bytes.
+    pub(crate) fn size(&self) -> usize {
+        self.blob.size() as usize
+    }
+
+    /// Make a request announce the body: drop any body it holds, set its `Content-Length`, and
+    /// its `Content-Type` from the type of the blob unless it already has one.
+    pub(crate) fn prepare(&self, request: &mut HttpConnectionRequest) {
+        request.body = None;
+        request.remove_header("Content-Length");
+        request.remove_header("Transfer-Encoding");
+        request
+            .headers
+            .push(HttpHeader::new("Content-Length", &self.size().to_string()));
+        let content_type = self.blob.type_();
+        let has_type = request
+            .headers
+            .iter()
+            .any(|h| h.name().eq_ignore_ascii_case("Content-Type"));
+        if !has_type && !content_type.is_empty() {
+            request
+                .headers
+                .push(HttpHeader::new("Content-Type", &content_type));
+        }
+    }
+
+    /// Read the blob slice by slice, passing every slice to `write` as soon as it is read.
+    ///
+    /// The next slice is only read once the socket has sent most of what it queued, so the
+    /// body is never held in memory as a whole. The connection is closed if a slice can't be
+    /// read, as the server would otherwise wait for the rest of the body.
+    ///
+    /// # Arguments
+    ///
+    /// * `socket` - Socket of the connection
+    /// * `write` - Function sending a slice over the connection
+    /// * `progress` - Reporter of the progress of the upload
+    /// * `sent` - Number of bytes of the request sent before the body
+    /// * `log_prefix` - Prefix of the log lines of the connection
+    pub(crate) fn stream(
+        self,
+        socket: WebSocket,
+        write: impl Fn(Vec<u8>) + 'static,
+        progress: ProgressReporter,
+        sent: usize,
+        log_prefix: String,
+    ) {
+        spawn_local(async move {
+            let size = self.blob.size();
+            let total = sent + self.size();
+            let mut offset = 0.0;
+            while offset < size {
+                while socket.buffered_amount() > MAX_BUFFERED {
+                    if socket.ready_state() != WebSocket::OPEN {
+                        return;
+                    }
+                    let _ = sleep(DRAIN_INTERVAL).await;
+                }
+                let end = (offset + SLICE_SIZE).min(size);
+                let slice = match self.read(offset, end).await {
+                    Ok(slice) => slice,
+                    Err(e) => {
+                        console_log!("{}Failed to read request body: {:?}", log_prefix, e);
+                        let _ = socket.close();
+                        return;
+                    }
+                };
+                if socket.ready_state() != WebSocket::OPEN {
+                    return;
+                }
+                write(slice);
+                offset = end;
+                progress.report(Progress::new(
+                    ProgressDirection::Upload,
+                    sent + offset as usize,
+                    total,
+                ));
+            }
+        });
+    }
+
+    /// Read a slice of the blob.
+    async fn read(&self, start: f64, end: f64) -> Result<Vec<u8>, JsValue> {
+        let slice = self.blob.slice_with_f64_and_f64(start, end)?;
+        let buffer = JsFuture::from(slice.array_buffer()).await?;
+        Ok(Uint8Array::new(&buffer).to_vec())
+    }
+}
+
+/// Generate a boundary for a `multipart/form-data` body, unlikely to occur in its parts.
+fn generate_boundary() -> String {
+    let random: String = (0..24)
+        .map(|_| {
+            let i = (js_sys::Math::random() * BOUNDARY_CHARS.len() as f64) as usize;
+            BOUNDARY_CHARS[i.min(BOUNDARY_CHARS.len() - 1)] as char
+        })
+        .collect();
+    format!("----SocketguardFormBoundary{}", random)
+}
+
+/// Escape a field name or file name for a `Content-Disposition` header, as browsers do.
+fn escape_form_name(name: &str) -> String {
+    name.replace('\n', "%0A")
+        .replace('\r', "%0D")
+        .replace('"', "%22")
+}
+
+/// Convert every line break of a form value to CRLF.
+fn normalize_newlines(value: &str) -> String {
+    value
+        .replace("\r\n", "\n")
+        .replace('\r', "\n")
+        .replace('\n', "\r\n")
+}
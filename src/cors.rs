@@ -0,0 +1,374 @@
+use std::{cell::RefCell, collections::VecDeque};
+
+use crate::{
+    connection::ConnectionError,
+    connection_apis::http::{HttpConnectionRequest, HttpConnectionResponse, HttpHeader},
+    middleware::{generate_request_id, Middleware},
+    timing::RequestTiming,
+};
+
+/// Number of requests remembered until their response is checked, the oldest being
+/// forgotten first, e.g. when requests fail without a response.
+const MAX_TRACKED_REQUESTS: usize = 256;
+
+/// Methods browsers send without a preflight.
+const SIMPLE_METHODS: [&str; 3] = ["GET", "HEAD", "POST"];
+
+/// Request headers browsers send without a preflight.
+const SAFELISTED_REQUEST_HEADERS: [&str; 4] = [
+    "Accept",
+    "Accept-Language",
+    "Content-Language",
+    "Content-Type",
+];
+
+/// Values of `Content-Type` browsers send without a preflight.
+const SIMPLE_CONTENT_TYPES: [&str; 3] = [
+    "application/x-www-form-urlencoded",
+    "multipart/form-data",
+    "text/plain",
+];
+
+/// Request headers set by browsers rather than scripts, which never need a preflight.
+const FORBIDDEN_REQUEST_HEADERS: [&str; 21] = [
+    "Accept-Charset",
+    "Accept-Encoding",
+    "Access-Control-Request-Headers",
+    "Access-Control-Request-Method",
+    "Connection",
+    "Content-Length",
+    "Cookie",
+    "Cookie2",
+    "Date",
+    "DNT",
+    "Expect",
+    "Host",
+    "Keep-Alive",
+    "Origin",
+    "Referer",
+    "Set-Cookie",
+    "TE",
+    "Trailer",
+    "Transfer-Encoding",
+    "Upgrade",
+    "Via",
+];
+
+/// Response headers browsers expose to cross-origin requests without
+/// `Access-Control-Expose-Headers`.
+const SAFELISTED_RESPONSE_HEADERS: [&str; 7] = [
+    "Cache-Control",
+    "Content-Language",
+    "Content-Length",
+    "Content-Type",
+    "Expires",
+    "Last-Modified",
+    "Pragma",
+];
+
+/// Response headers kept regardless of the policy, as the client follows redirects and
+/// retries requests with them.
+const CLIENT_RESPONSE_HEADERS: [&str; 2] = ["Location", "Retry-After"];
+
+/// Request sent by a `CorsPolicy`, until its response is checked.
+#[derive(Default)]
+struct TrackedRequest {
+    /// Correlation ID of the request
+    id: String,
+    /// Whether the request was sent to another origin
+    cross_origin: bool,
+}
+
+/// Middleware emulating the same-origin policy of browsers on the HTTP and HTTPS requests of
+/// a client, which bypass it by default as they are tunneled, for a page or service worker
+/// of a given origin.
+///
+/// Requests to another origin carry an `Origin` header, and their `Cookie` header is removed
+/// unless credentials are included. Their response is rejected unless its
+/// `Access-Control-Allow-*` headers allow the origin and the credentials. The headers of
+/// allowed responses are then filtered to those browsers expose, and their timing is reduced
+/// to its start and duration unless `Timing-Allow-Origin` allows the origin.
+///
+/// Preflights are not supported, so cross-origin requests browsers would send one for, e.g.
+/// with a method other than `GET`, `HEAD` or `POST` or a header not safelisted, are refused
+/// before being sent. Requests are matched with their response by their correlation ID, so
+/// requests without one are assigned one.
+pub struct CorsPolicy {
+    /// Origin the requests are sent from, e.g. `https://app.example.com`
+    origin: String,
+    /// Whether cross-origin requests include credentials
+    credentials: bool,
+    /// Requests sent, oldest first, until their response is checked
+    requests: RefCell<VecDeque<TrackedRequest>>,
+}
+
+impl CorsPolicy {
+    /// Create a new CORS policy.
+    ///
+    /// # Arguments
+    ///
+    /// * `origin` - Origin the requests are sent from, e.g. `https://app.example.com`
+    /// * `credentials` - Whether cross-origin requests include credentials, like
+    ///   `credentials: "include"` in fetch
+    pub fn new(origin: &str, credentials: bool) -> Result<Self, ConnectionError> {
+        let valid = match origin.split_once("://") {
+            Some((scheme, host)) => {
+                !scheme.is_empty() && !host.is_empty() && !host.contains(['/', '?', '#'])
+            }
+            None => false,
+        };
+        if !valid {
+            return Err(ConnectionError {
+                message: format!("Invalid origin: {}", origin),
+            });
+        }
+        Ok(Self {
+            origin: crate::redirect::origin(origin),
+            credentials,
+            requests: RefCell::new(VecDeque::new()),
+        })
+    }
+
+    /// Check a response to a cross-origin request against its `Access-Control-Allow-*`
+    /// headers.
+    ///
+    /// # Returns
+    ///
+    /// Why the response is rejected, if it is.
+    fn check(&self, response: &HttpConnectionResponse) -> Result<(), String> {
+        let allow_origin = response.get_header("Access-Control-Allow-Origin");
+        match allow_origin.as_deref().map(str::trim) {
+            Some("*") if !self.credentials => {}
+            Some(allowed) if allowed == self.origin => {}
+            Some(allowed) => {
+                return Err(format!(
+                    "Access-Control-Allow-Origin {} does not allow origin {}",
+                    allowed, self.origin
+                ))
+            }
+            None => return Err("missing Access-Control-Allow-Origin header".to_string()),
+        }
+        if self.credentials {
+            let allow_credentials = response.get_header("Access-Control-Allow-Credentials");
+            if allow_credentials.as_deref().map(str::trim) != Some("true") {
+                return Err("Access-Control-Allow-Credentials does not allow credentials".into());
+            }
+        }
+        Ok(())
+    }
+
+    /// Check if the timing of a response may be exposed per its `Timing-Allow-Origin` header.
+    fn timing_allowed(&self, response: &HttpConnectionResponse) -> bool {
+        list(&response.headers, "Timing-Allow-Origin")
+            .iter()
+            .any(|allowed| allowed == "*" || *allowed == self.origin)
+    }
+
+    /// Remove the headers of a response to a cross-origin request that browsers do not
+    /// expose.
+    fn filter_headers(&self, headers: &mut Vec<HttpHeader>) {
+        let exposed = list(headers, "Access-Control-Expose-Headers");
+        let all = !self.credentials && exposed.iter().any(|e| e == "*");
+        headers.retain(|h| {
+            let name = h.name();
+            let is = |other: &str| name.eq_ignore_ascii_case(other);
+            if is("Set-Cookie") || is("Set-Cookie2") {
+                return false;
+            }
+            all || SAFELISTED_RESPONSE_HEADERS.into_iter().any(is)
+                || CLIENT_RESPONSE_HEADERS.into_iter().any(is)
+                || exposed.iter().any(|e| is(e))
+        });
+    }
+}
+
+/// Get the elements of the comma-separated list headers with the given name.
+fn list(headers: &[HttpHeader], name: &str) -> Vec<String> {
+    headers
+        .iter()
+        .filter(|h| h.name().eq_ignore_ascii_case(name))
+        .flat_map(|h| h.value().split(','))
+        .map(|element| element.trim().to_string())
+        .filter(|element| !element.is_empty())
+        .collect()
+}
+
+/// Check if browsers send a request header without a preflight, or set it themselves.
+fn is_simple_header(header: &HttpHeader) -> bool {
+    let name = header.name();
+    let is = |other: &str| name.eq_ignore_ascii_case(other);
+    if FORBIDDEN_REQUEST_HEADERS.into_iter().any(is)
+        || ["Proxy-", "Sec-"].into_iter().any(|prefix| {
+            name.get(..prefix.len())
+                .is_some_and(|start| start.eq_ignore_ascii_case(prefix))
+        })
+    {
+        return true;
+    }
+    if is("Content-Type") {
+        let essence = header.value().split(';').next().unwrap_or_default().trim();
+        return SIMPLE_CONTENT_TYPES
+            .into_iter()
+            .any(|t| essence.eq_ignore_ascii_case(t));
+    }
+    SAFELISTED_REQUEST_HEADERS.into_iter().any(is)
+}
+
+impl Middleware for CorsPolicy {
+    fn on_request(
+        &self,
+        mut request: HttpConnectionRequest,
+    ) -> Result<HttpConnectionRequest, ConnectionError> {
+        let cross_origin = request.target.as_ref() != Some(&self.origin);
+        if cross_origin {
+            let method = request.method.to_ascii_uppercase();
+            if !SIMPLE_METHODS.contains(&method.as_str()) {
+                return Err(ConnectionError {
+                    message: format!("CORS preflight not supported, needed for method {}", method),
+                });
+            }
+            if let Some(header) = request.headers.iter().find(|h| !is_simple_header(h)) {
+                return Err(ConnectionError {
+                    message: format!(
+                        "CORS preflight not supported, needed for header {}",
+                        header.name()
+                    ),
+                });
+            }
+            request.headers.retain(|h| {
+                !h.name().eq_ignore_ascii_case("Origin")
+                    && (self.credentials || !h.name().eq_ignore_ascii_case("Cookie"))
+            });
+            request
+                .headers
+                .push(HttpHeader::new("Origin", &self.origin));
+        }
+        let tracked = TrackedRequest {
+            id: request
+                .request_id
+                .get_or_insert_with(generate_request_id)
+                .clone(),
+            cross_origin,
+        };
+
+        let mut requests = self.requests.borrow_mut();
+        if requests.len() == MAX_TRACKED_REQUESTS {
+            requests.pop_front();
+        }
+        requests.push_back(tracked);
+        Ok(request)
+    }
+
+    fn on_response(
+        &self,
+        mut response: HttpConnectionResponse,
+    ) -> Result<HttpConnectionResponse, ConnectionError> {
+        let tracked = {
+            let mut requests = self.requests.borrow_mut();
+            response
+                .request_id
+                .as_ref()
+                .and_then(|id| requests.iter().position(|r| r.id == *id))
+                .and_then(|index| requests.remove(index))
+        };
+        // Responses to forgotten requests are checked as simple cross-origin requests
+        let tracked = tracked.unwrap_or(TrackedRequest {
+            cross_origin: true,
+            ..TrackedRequest::default()
+        });
+        if !tracked.cross_origin {
+            return Ok(response);
+        }
+
+        self.check(&response).map_err(|reason| ConnectionError {
+            message: format!("CORS check failed: {}", reason),
+        })?;
+        if !self.timing_allowed(&response) {
+            response.timing = response.timing.map(RequestTiming::restricted);
+        }
+        self.filter_headers(&mut response.headers);
+        Ok(response)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Create a request to `target` with a correlation ID, as sent by a connection.
+    fn request(method: &str, target: &str, headers: &[(&str, &str)]) -> HttpConnectionRequest {
+        let headers = headers
+            .iter()
+            .map(|(name, value)| HttpHeader::new(name, value))
+            .collect();
+        let mut request =
+            HttpConnectionRequest::new(method.to_string(), "/".to_string(), headers, None);
+        request.request_id = Some("1".to_string());
+        request.target = Some(target.to_string());
+        request
+    }
+
+    /// Create a response to the request created by `request`.
+    fn response(headers: &[(&str, &str)]) -> HttpConnectionResponse {
+        let headers = headers
+            .iter()
+            .map(|(name, value)| HttpHeader::new(name, value))
+            .collect();
+        HttpConnectionResponse::new(200, headers, None).with_request_id(Some("1".to_string()))
+    }
+
+    /// Create the policy of a page of `https://app.example.com`, without credentials.
+    fn policy() -> CorsPolicy {
+        CorsPolicy::new("https://app.example.com", false).unwrap()
+    }
+
+    #[test]
+    fn refuses_requests_needing_a_preflight() {
+        let policy = policy();
+        let target = "https://api.example.com";
+        assert!(policy.on_request(request("PUT", target, &[])).is_err());
+        let json = [("Content-Type", "application/json")];
+        assert!(policy.on_request(request("POST", target, &json)).is_err());
+        let custom = [("X-Token", "secret")];
+        assert!(policy.on_request(request("GET", target, &custom)).is_err());
+        assert!(policy.requests.borrow().is_empty());
+
+        let form = [
+            ("Content-Type", "text/plain; charset=utf-8"),
+            ("Host", "api"),
+        ];
+        let sent = policy.on_request(request("post", target, &form)).unwrap();
+        let origin = sent.headers.last().map(HttpHeader::value);
+        assert_eq!(origin, Some("https://app.example.com"));
+    }
+
+    #[test]
+    fn sends_same_origin_requests_as_is() {
+        let policy = policy();
+        let custom = [("X-Token", "secret")];
+        let sent = policy
+            .on_request(request("DELETE", "https://app.example.com", &custom))
+            .unwrap();
+        assert_eq!(sent.headers.len(), 1);
+        let received = policy.on_response(response(&[("X-Secret", "1")])).unwrap();
+        assert_eq!(received.headers.len(), 1);
+    }
+
+    #[test]
+    fn checks_responses_to_cross_origin_requests() {
+        let policy = policy();
+        let target = "https://api.example.com";
+        policy.on_request(request("GET", target, &[])).unwrap();
+        assert!(policy.on_response(response(&[])).is_err());
+
+        policy.on_request(request("GET", target, &[])).unwrap();
+        let allowed = [
+            ("Access-Control-Allow-Origin", "https://app.example.com"),
+            ("Content-Type", "text/plain"),
+            ("X-Secret", "1"),
+        ];
+        let received = policy.on_response(response(&allowed)).unwrap();
+        assert!(received.get_header("X-Secret").is_none());
+        assert!(received.get_header("Content-Type").is_some());
+    }
+}
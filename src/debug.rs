@@ -0,0 +1,275 @@
+use std::{
+    cell::{Cell, RefCell},
+    collections::VecDeque,
+    rc::{Rc, Weak},
+};
+
+use wasm_bindgen::prelude::*;
+use wasm_bindgen_futures::js_sys::{self, Array, Object, Reflect};
+
+use crate::connection::Connection;
+
+/// Name of the global debug object installed by `init_debug`.
+pub const DEBUG_GLOBAL: &str = "__socketguard";
+
+/// Number of log entries kept once debugging is enabled.
+pub const DEBUG_LOG_CAPACITY: usize = 500;
+
+/// Client known to the debug object.
+struct DebugClient {
+    /// Base wsproxy url of the client
+    addr: String,
+    /// Name of the client, if any
+    name: Option<String>,
+    /// Connections of the client, gone once the client is freed
+    connections: Weak<RefCell<Vec<Connection>>>,
+}
+
+/// State inspected by the debug object.
+#[derive(Default)]
+struct DebugState {
+    /// Whether `init_debug` was called, so log entries are kept
+    enabled: bool,
+    /// Clients created on this thread
+    clients: Vec<DebugClient>,
+    /// Most recent log entries, as time in ms since the epoch and message
+    logs: VecDeque<(f64, String)>,
+}
+
+thread_local! {
+    static STATE: RefCell<DebugState> = RefCell::new(DebugState::default());
+    /// Whether log lines are written, see `set_logging`
+    static LOGGING: Cell<bool> = const { Cell::new(true) };
+}
+
+/// Make a client visible to the debug object.
+///
+/// # Arguments
+///
+/// * `addr` - Base wsproxy url of the client
+/// * `connections` - Connections of the client
+pub fn register(addr: &str, connections: &Rc<RefCell<Vec<Connection>>>) {
+    STATE.with(|state| {
+        let mut state = state.borrow_mut();
+        state.clients.retain(|c| c.connections.strong_count() > 0);
+        state.clients.push(DebugClient {
+            addr: addr.to_string(),
+            name: None,
+            connections: Rc::downgrade(connections),
+        });
+    });
+}
+
+/// Update the name of a client shown by the debug object.
+///
+/// # Arguments
+///
+/// * `connections` - Connections of the client, identifying it
+/// * `name` - Name of the client, if any
+pub fn rename(connections: &Rc<RefCell<Vec<Connection>>>, name: Option<String>) {
+    STATE.with(|state| {
+        let mut state = state.borrow_mut();
+        let client = state
+            .clients
+            .iter_mut()
+            .find(|c| Weak::as_ptr(&c.connections) == Rc::as_ptr(connections));
+        if let Some(client) = client {
+            client.name = name;
+        }
+    });
+}
+
+/// Write a line to the console, keeping it for the debug object if debugging is enabled.
+pub fn log(message: &str) {
+    STATE.with(|state| {
+        let mut state = state.borrow_mut();
+        if state.enabled {
+            if state.logs.len() == DEBUG_LOG_CAPACITY {
+                state.logs.pop_front();
+            }
+            state
+                .logs
+                .push_back((js_sys::Date::now(), message.to_string()));
+        }
+    });
+    crate::log(message);
+}
+
+/// Check if log lines are written, see `set_logging`.
+pub fn logging_enabled() -> bool {
+    LOGGING.with(Cell::get)
+}
+
+/// Enable or disable the log lines written to the console on this thread, e.g. to keep
+/// request urls and headers out of the console in production. Logging is enabled by default.
+///
+/// Builds without the `console-log` feature never log, whatever this is set to.
+///
+/// # Arguments
+///
+/// * `enabled` - Whether log lines are written
+#[wasm_bindgen]
+pub fn set_logging(enabled: bool) {
+    LOGGING.with(|logging| logging.set(enabled));
+}
+
+/// Install the global `__socketguard` debug object, for inspection from the devtools console.
+///
+/// The object exposes:
+/// * `clients()` - Live clients and their connections
+/// * `logs()` - Log entries written since debugging was enabled, the oldest first
+/// * `clearLogs()` - Discard the kept log entries
+/// * `close(id, client)` - Close a connection, of the client at the given index of
+///   `clients()` or of every client if omitted, returning the number of connections closed
+///
+/// Log entries are only kept once this is called.
+#[wasm_bindgen]
+pub fn init_debug() -> Result<(), JsValue> {
+    STATE.with(|state| state.borrow_mut().enabled = true);
+
+    let debug = Object::new();
+    let clients = Closure::wrap(Box::new(clients) as Box<dyn Fn() -> Array>);
+    let logs = Closure::wrap(Box::new(logs) as Box<dyn Fn() -> Array>);
+    let clear_logs = Closure::wrap(Box::new(|| {
+        STATE.with(|state| state.borrow_mut().logs.clear());
+    }) as Box<dyn Fn()>);
+    let close = Closure::wrap(Box::new(close) as Box<dyn Fn(JsValue, Option<u32>) -> u32>);
+    Reflect::set(&debug, &"clients".into(), &clients.into_js_value())?;
+    Reflect::set(&debug, &"logs".into(), &logs.into_js_value())?;
+    Reflect::set(&debug, &"clearLogs".into(), &clear_logs.into_js_value())?;
+    Reflect::set(&debug, &"close".into(), &close.into_js_value())?;
+    Reflect::set(&debug, &"version".into(), &env!("CARGO_PKG_VERSION").into())?;
+
+    Reflect::set(&js_sys::global(), &DEBUG_GLOBAL.into(), &debug)?;
+    Ok(())
+}
+
+/// Create a JS object from its properties.
+fn object(properties: &[(&str, JsValue)]) -> JsValue {
+    let object = Object::new();
+    for (key, value) in properties {
+        Reflect::set(&object, &(*key).into(), value).unwrap_throw();
+    }
+    object.into()
+}
+
+/// Describe the live clients and their connections.
+fn clients() -> Array {
+    STATE.with(|state| {
+        let mut state = state.borrow_mut();
+        state.clients.retain(|c| c.connections.strong_count() > 0);
+        state
+            .clients
+            .iter()
+            .enumerate()
+            .filter_map(|(index, client)| {
+                let connections = client.connections.upgrade()?;
+                let connections: Array = connections
+                    .borrow()
+                    .iter()
+                    .map(|c| {
+                        let tags: Array = c
+                            .get_tags()
+                            .iter()
+                            .map(|tag| JsValue::from(tag.as_str()))
+                            .collect();
+                        // Sent as a string, as IDs do not fit in a JS number
+                        let id: u64 = c.get_id().into();
+                        object(&[
+                            ("id", id.to_string().into()),
+                            ("uuid", c.get_id().uuid_string().into()),
+                            ("protocol", c.protocol.to_string().into()),
+                            ("addr", c.addr.as_str().into()),
+                            ("tags", tags.into()),
+                            ("readyState", c.socket.ready_state().into()),
+                            ("idleTime", c.idle_time().into()),
+                        ])
+                    })
+                    .collect();
+                Some(object(&[
+                    ("index", index.into()),
+                    ("name", client.name.as_deref().into()),
+                    ("addr", client.addr.as_str().into()),
+                    ("connections", connections.into()),
+                ]))
+            })
+            .collect()
+    })
+}
+
+/// Get the kept log entries, the oldest first.
+fn logs() -> Array {
+    STATE.with(|state| {
+        state
+            .borrow()
+            .logs
+            .iter()
+            .map(|(time, message)| {
+                object(&[
+                    ("time", js_sys::Date::new(&(*time).into()).into()),
+                    ("message", message.as_str().into()),
+                ])
+            })
+            .collect()
+    })
+}
+
+/// Close and remove the connections with the given ID.
+///
+/// # Arguments
+///
+/// * `id` - ID of the connection, as a string, number or bigint
+/// * `client` - Index of the client in `clients()`, or `None` for every client
+///
+/// # Returns
+///
+/// The number of connections closed.
+fn close(id: JsValue, client: Option<u32>) -> u32 {
+    let id = id
+        .as_string()
+        .or_else(|| id.as_f64().map(|id| id.to_string()))
+        .or_else(|| {
+            id.is_bigint()
+                .then(|| {
+                    js_sys::BigInt::from(id)
+                        .to_string(10)
+                        .ok()
+                        .map(String::from)
+                })
+                .flatten()
+        })
+        .and_then(|id| id.parse::<u64>().ok());
+    let id = match id {
+        Some(id) => id,
+        None => return 0,
+    };
+
+    // Collected first, so closing does not run listeners while the state is borrowed
+    let targets: Vec<Rc<RefCell<Vec<Connection>>>> = STATE.with(|state| {
+        state
+            .borrow()
+            .clients
+            .iter()
+            .enumerate()
+            .filter(|(index, _)| client.is_none_or(|client| client as usize == *index))
+            .filter_map(|(_, c)| c.connections.upgrade())
+            .collect()
+    });
+
+    let mut closed = 0;
+    for connections in targets {
+        let removed: Vec<Connection> = {
+            let mut connections = connections.borrow_mut();
+            let (removed, kept) = connections
+                .drain(..)
+                .partition(|c| Into::<u64>::into(c.get_id()) == id);
+            *connections = kept;
+            removed
+        };
+        closed += removed.len() as u32;
+        for connection in removed {
+            connection.close();
+        }
+    }
+    closed
+}
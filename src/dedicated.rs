@@ -0,0 +1,268 @@
+use std::{
+    cell::{Cell, RefCell},
+    collections::HashMap,
+    rc::Rc,
+};
+
+use wasm_bindgen::{convert::TryFromJsValue, prelude::*};
+use wasm_bindgen_futures::{
+    js_sys::{self, Array, ArrayBuffer, Function, Object, Promise},
+    spawn_local, JsFuture,
+};
+use web_sys::{DedicatedWorkerGlobalScope, MessageEvent, Worker};
+
+use crate::{
+    client::Client,
+    connection_apis::http::HttpConnectionResponse,
+    worker::{get, message},
+};
+
+/// Key marking a response converted by `to_transferable` in a reply.
+const RESPONSE_KEY: &str = "$response";
+
+/// Convert the result of a call to a value that can be posted back to the page.
+///
+/// Responses are converted with `to_transferable`, including those of the outcomes of
+/// `fetch_all`, and their bodies are added to `transfer`.
+fn encode(value: JsValue, transfer: &Array) -> JsValue {
+    if let Ok(response) = HttpConnectionResponse::try_from_js_value(value.clone()) {
+        let value = response.to_transferable();
+        let body = get(&value, "body");
+        if body.is_instance_of::<ArrayBuffer>() {
+            transfer.push(&body);
+        }
+        return message(&[(RESPONSE_KEY, value)]).into();
+    }
+    if Array::is_array(&value) {
+        return Array::from(&value)
+            .iter()
+            .map(|item| encode(item, transfer))
+            .collect::<Array>()
+            .into();
+    }
+    if value.is_object() && get(&value, "status").is_string() {
+        // Outcome of `fetch_all`
+        let outcome = Object::assign(&Object::new(), value.unchecked_ref());
+        let result = encode(get(&value, "value"), transfer);
+        js_sys::Reflect::set(&outcome, &"value".into(), &result).unwrap_throw();
+        return outcome.into();
+    }
+    value
+}
+
+/// Convert a value posted by the worker back to the result of a call.
+fn decode(value: JsValue) -> JsValue {
+    let response = get(&value, RESPONSE_KEY);
+    if value.is_object() && !response.is_undefined() {
+        return HttpConnectionResponse::from_transferable(&response)
+            .map(JsValue::from)
+            .unwrap_or(JsValue::NULL);
+    }
+    if Array::is_array(&value) {
+        return Array::from(&value)
+            .iter()
+            .map(decode)
+            .collect::<Array>()
+            .into();
+    }
+    if value.is_object() && get(&value, "status").is_string() {
+        let result = decode(get(&value, "value"));
+        js_sys::Reflect::set(&value, &"value".into(), &result).unwrap_throw();
+    }
+    value
+}
+
+/// Get the message of an error thrown or rejected by a call.
+fn error_message(error: JsValue) -> JsValue {
+    match error.as_string() {
+        Some(message) => message.into(),
+        None => js_sys::Reflect::get(&error, &"message".into())
+            .ok()
+            .filter(JsValue::is_string)
+            .unwrap_or_else(|| format!("{:?}", error).into()),
+    }
+}
+
+/// Call a method of the client hosted by the worker.
+///
+/// # Returns
+///
+/// The result of the method, awaited if it is a promise.
+async fn dispatch(client: &JsValue, method: &str, args: &Array) -> Result<JsValue, JsValue> {
+    let function = js_sys::Reflect::get(client, &method.into())?
+        .dyn_into::<Function>()
+        .map_err(|_| JsValue::from(format!("Unknown client method: {}", method)))?;
+    let result = function.apply(client, args)?;
+    match result.dyn_into::<Promise>() {
+        Ok(promise) => JsFuture::from(promise).await,
+        Err(result) => Ok(result),
+    }
+}
+
+/// Host a client in the current dedicated worker, keeping TLS and response parsing off the
+/// thread of the page.
+///
+/// The page talks to this client through `WorkerClient`. The worker script must initialize
+/// this module and then call this function, e.g.
+///
+/// ```js
+/// import init, { start_worker_client } from "./socketguard.js";
+/// await init();
+/// start_worker_client("ws://localhost:3000");
+/// ```
+///
+/// # Arguments
+///
+/// * `addr` - Base wsproxy url of the client
+#[wasm_bindgen]
+pub fn start_worker_client(addr: String) {
+    let scope: DedicatedWorkerGlobalScope = js_sys::global().unchecked_into();
+    let client = JsValue::from(Client::new(addr));
+
+    let cb_scope = scope.clone();
+    let message_callback: Closure<dyn Fn(MessageEvent)> =
+        Closure::wrap(Box::new(move |evt: MessageEvent| {
+            let msg = evt.data();
+            let call = get(&msg, "call");
+            let method = get(&msg, "method").as_string().unwrap_or_default();
+            let args = Array::from(&get(&msg, "args"));
+
+            let client = client.clone();
+            let scope = cb_scope.clone();
+            spawn_local(async move {
+                let transfer = Array::new();
+                let reply = match dispatch(&client, &method, &args).await {
+                    Ok(result) => message(&[("call", call), ("result", encode(result, &transfer))]),
+                    Err(error) => message(&[("call", call), ("error", error_message(error))]),
+                };
+                scope
+                    .post_message_with_transfer(&reply, &transfer)
+                    .unwrap_throw();
+            });
+        }));
+
+    scope.set_onmessage(Some(message_callback.as_ref().unchecked_ref()));
+    message_callback.forget();
+}
+
+/// Stub for a client hosted in a dedicated worker by `start_worker_client`.
+///
+/// Every call is forwarded to the client in the worker and returns a promise of its
+/// result. Arguments must be cloneable by `postMessage`, so requests are made with `get`,
+/// `post`, `put` and `delete`, or `call` for any other method of `Client`. Responses are
+/// moved back from the worker with their body transferred, not copied.
+#[wasm_bindgen]
+pub struct WorkerClient {
+    /// Worker hosting the client
+    worker: Worker,
+    /// Functions settling the promises of pending calls
+    calls: Rc<RefCell<HashMap<u32, (Function, Function)>>>,
+    /// ID of the last call
+    last_call: Rc<Cell<u32>>,
+}
+
+#[wasm_bindgen]
+impl WorkerClient {
+    /// Create a new stub for the client hosted in the given worker.
+    ///
+    /// # Arguments
+    ///
+    /// * `worker` - Worker that has called `start_worker_client`
+    #[wasm_bindgen(constructor)]
+    pub fn new(worker: Worker) -> Self {
+        let calls: Rc<RefCell<HashMap<u32, (Function, Function)>>> =
+            Rc::new(RefCell::new(HashMap::new()));
+
+        let cb_calls = calls.clone();
+        let message_callback: Closure<dyn Fn(MessageEvent)> =
+            Closure::wrap(Box::new(move |evt: MessageEvent| {
+                let msg = evt.data();
+                let call = get(&msg, "call").as_f64().unwrap_or_default() as u32;
+                let (resolve, reject) = match cb_calls.borrow_mut().remove(&call) {
+                    Some(settle) => settle,
+                    None => return,
+                };
+                let error = get(&msg, "error");
+                if error.is_undefined() {
+                    let _ = resolve.call1(&JsValue::null(), &decode(get(&msg, "result")));
+                } else {
+                    let _ = reject.call1(&JsValue::null(), &error);
+                }
+            }));
+
+        worker.set_onmessage(Some(message_callback.as_ref().unchecked_ref()));
+        message_callback.forget();
+
+        Self {
+            worker,
+            calls,
+            last_call: Rc::new(Cell::new(0)),
+        }
+    }
+
+    /// Call a method of the client in the worker.
+    ///
+    /// # Arguments
+    ///
+    /// * `method` - Name of the method of `Client`, e.g. `set_max_connections`
+    /// * `args` - Arguments of the method, cloneable by `postMessage`
+    ///
+    /// # Returns
+    ///
+    /// A promise resolving to the result of the method, or rejecting with its error.
+    #[wasm_bindgen]
+    pub fn call(&self, method: String, args: Array) -> Promise {
+        let call = self.last_call.get().wrapping_add(1);
+        self.last_call.set(call);
+
+        let mut settle = None;
+        let promise = Promise::new(&mut |resolve, reject| settle = Some((resolve, reject)));
+        self.calls.borrow_mut().insert(call, settle.unwrap_throw());
+
+        let msg = message(&[
+            ("call", call.into()),
+            ("method", method.into()),
+            ("args", args.into()),
+        ]);
+        if let Err(error) = self.worker.post_message(&msg) {
+            // Arguments that cannot be cloned
+            if let Some((_, reject)) = self.calls.borrow_mut().remove(&call) {
+                let _ = reject.call1(&JsValue::null(), &error_message(error));
+            }
+        }
+        promise
+    }
+
+    /// Send a `GET` request with the client in the worker, like `Client::get`.
+    #[wasm_bindgen]
+    pub fn get(&self, url: String, headers: Option<Object>) -> Promise {
+        self.call("get".to_string(), Array::of2(&url.into(), &headers.into()))
+    }
+
+    /// Send a `POST` request with the client in the worker, like `Client::post`.
+    #[wasm_bindgen]
+    pub fn post(&self, url: String, body: Option<Vec<u8>>, headers: Option<Object>) -> Promise {
+        self.call("post".to_string(), request_args(url, body, headers))
+    }
+
+    /// Send a `PUT` request with the client in the worker, like `Client::put`.
+    #[wasm_bindgen]
+    pub fn put(&self, url: String, body: Option<Vec<u8>>, headers: Option<Object>) -> Promise {
+        self.call("put".to_string(), request_args(url, body, headers))
+    }
+
+    /// Send a `DELETE` request with the client in the worker, like `Client::delete`.
+    #[wasm_bindgen]
+    pub fn delete(&self, url: String, headers: Option<Object>) -> Promise {
+        self.call(
+            "delete".to_string(),
+            Array::of2(&url.into(), &headers.into()),
+        )
+    }
+}
+
+/// Build the arguments of a request with a body.
+fn request_args(url: String, body: Option<Vec<u8>>, headers: Option<Object>) -> Array {
+    let body = body.map(|body| js_sys::Uint8Array::from(body.as_slice()));
+    Array::of3(&url.into(), &body.into(), &headers.into())
+}
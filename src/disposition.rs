@@ -0,0 +1,248 @@
+use wasm_bindgen::prelude::*;
+
+/// Parsed `Content-Disposition` header of a response, as defined by RFC 6266.
+#[wasm_bindgen]
+#[derive(Clone, Debug)]
+pub struct ContentDisposition {
+    /// Disposition type in lowercase, e.g. `attachment`
+    disposition_type: String,
+    /// Parameters with their name in lowercase, values unquoted but not decoded
+    params: Vec<(String, String)>,
+}
+
+impl ContentDisposition {
+    /// Parse the value of a `Content-Disposition` header.
+    ///
+    /// # Returns
+    ///
+    /// The parsed header, or `None` if it has no disposition type.
+    pub fn parse(value: &str) -> Option<Self> {
+        let mut chars = value.chars().peekable();
+        let disposition_type: String = chars.by_ref().take_while(|&c| c != ';').collect();
+        let disposition_type = disposition_type.trim().to_ascii_lowercase();
+        if disposition_type.is_empty() {
+            return None;
+        }
+
+        let mut params = Vec::new();
+        while chars.peek().is_some() {
+            let mut name = String::new();
+            while let Some(&c) = chars.peek() {
+                if c == '=' || c == ';' {
+                    break;
+                }
+                name.push(c);
+                chars.next();
+            }
+            if chars.next() != Some('=') {
+                // Parameter without value, skipped
+                continue;
+            }
+            while chars.next_if(|c| c.is_ascii_whitespace()).is_some() {}
+            let mut param = String::new();
+            if chars.next_if_eq(&'"').is_some() {
+                while let Some(c) = chars.next() {
+                    match c {
+                        '"' => break,
+                        '\\' => param.extend(chars.next()),
+                        c => param.push(c),
+                    }
+                }
+                // Anything between the closing quote and the next parameter is ignored
+                for c in chars.by_ref() {
+                    if c == ';' {
+                        break;
+                    }
+                }
+            } else {
+                for c in chars.by_ref() {
+                    if c == ';' {
+                        break;
+                    }
+                    param.push(c);
+                }
+                param = param.trim_end().to_string();
+            }
+            params.push((name.trim().to_ascii_lowercase(), param));
+        }
+
+        Some(Self {
+            disposition_type,
+            params,
+        })
+    }
+
+    /// Get the value of a parameter, the first one if repeated.
+    fn param(&self, name: &str) -> Option<&str> {
+        self.params
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+#[wasm_bindgen]
+impl ContentDisposition {
+    /// Get the disposition type in lowercase, e.g. `attachment`, `inline` or `form-data`.
+    #[wasm_bindgen]
+    pub fn get_type(&self) -> String {
+        self.disposition_type.clone()
+    }
+
+    /// Check if the body should be downloaded rather than displayed.
+    ///
+    /// Unknown disposition types are handled as `attachment`, as RFC 6266 requires.
+    #[wasm_bindgen]
+    pub fn is_attachment(&self) -> bool {
+        self.disposition_type != "inline"
+    }
+
+    /// Get the name of the file the body should be saved as, if given.
+    ///
+    /// The `filename*` parameter, decoded from its RFC 5987 form, e.g.
+    /// `UTF-8''%e2%82%ac%20rates.txt`, takes precedence over `filename`. Only the last
+    /// component of paths is kept, so the name can't point to another directory.
+    #[wasm_bindgen]
+    pub fn get_filename(&self) -> Option<String> {
+        let filename = self
+            .param("filename*")
+            .and_then(decode_ext_value)
+            .or_else(|| self.param("filename").map(str::to_string))?;
+        let filename = filename
+            .rsplit(['/', '\\'])
+            .next()
+            .unwrap_or_default()
+            .trim();
+        (!filename.is_empty() && filename != "." && filename != "..").then(|| filename.to_string())
+    }
+
+    /// Get the value of a parameter, e.g. `name` for `form-data`, if given.
+    ///
+    /// Values of extended parameters, whose name ends with `*`, are decoded from their
+    /// RFC 5987 form.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - Name of the parameter, compared ignoring ASCII case
+    #[wasm_bindgen]
+    pub fn get_param(&self, name: &str) -> Option<String> {
+        let name = name.to_ascii_lowercase();
+        let value = self.param(&name)?;
+        if name.ends_with('*') {
+            decode_ext_value(value)
+        } else {
+            Some(value.to_string())
+        }
+    }
+}
+
+/// Decode an RFC 5987 extended value, `charset'language'percent-encoded`.
+///
+/// # Returns
+///
+/// The decoded value, or `None` if it is malformed or its charset is neither UTF-8 nor
+/// ISO-8859-1.
+fn decode_ext_value(value: &str) -> Option<String> {
+    let mut parts = value.splitn(3, '\'');
+    let charset = parts.next()?.trim();
+    let _language = parts.next()?;
+    let encoded = parts.next()?;
+
+    let mut bytes = Vec::with_capacity(encoded.len());
+    let mut iter = encoded.bytes();
+    while let Some(b) = iter.next() {
+        if b == b'%' {
+            let hex = [iter.next()?, iter.next()?];
+            let hex = std::str::from_utf8(&hex).ok()?;
+            bytes.push(u8::from_str_radix(hex, 16).ok()?);
+        } else {
+            bytes.push(b);
+        }
+    }
+
+    if charset.eq_ignore_ascii_case("utf-8") {
+        String::from_utf8(bytes).ok()
+    } else if charset.eq_ignore_ascii_case("iso-8859-1") {
+        Some(bytes.into_iter().map(char::from).collect())
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn filename(value: &str) -> Option<String> {
+        ContentDisposition::parse(value)?.get_filename()
+    }
+
+    #[test]
+    fn parses_token_and_quoted_filenames() {
+        assert_eq!(
+            filename("attachment; filename=rates.txt").as_deref(),
+            Some("rates.txt")
+        );
+        assert_eq!(
+            filename("attachment; filename=\"Q1 rates; final.txt\"").as_deref(),
+            Some("Q1 rates; final.txt")
+        );
+        assert_eq!(
+            filename(r#"attachment; filename="say \"hi\".txt""#).as_deref(),
+            Some("say \"hi\".txt")
+        );
+        assert_eq!(
+            filename("attachment; FILENAME = \"a.txt\" junk; size=3").as_deref(),
+            Some("a.txt")
+        );
+    }
+
+    #[test]
+    fn prefers_rfc_5987_filenames() {
+        let value = "attachment; filename=\"EURO rates.txt\"; \
+                     filename*=UTF-8''%e2%82%ac%20rates.txt";
+        assert_eq!(filename(value).as_deref(), Some("€ rates.txt"));
+        assert_eq!(
+            filename("attachment; filename*=iso-8859-1'en'%A3%20rates.txt").as_deref(),
+            Some("£ rates.txt")
+        );
+    }
+
+    #[test]
+    fn falls_back_on_malformed_rfc_5987_filenames() {
+        let cases = [
+            "attachment; filename=plain.txt; filename*=UTF-8''%e2%82",
+            "attachment; filename=plain.txt; filename*=UTF-8''%zz.txt",
+            "attachment; filename=plain.txt; filename*=UTF-16''rates.txt",
+            "attachment; filename=plain.txt; filename*=rates.txt",
+        ];
+        for value in cases {
+            assert_eq!(filename(value).as_deref(), Some("plain.txt"), "{}", value);
+        }
+    }
+
+    #[test]
+    fn keeps_only_the_last_component_of_paths() {
+        assert_eq!(
+            filename("attachment; filename=\"../../etc/passwd\"").as_deref(),
+            Some("passwd")
+        );
+        assert_eq!(
+            filename("attachment; filename*=UTF-8''..%5Cwin.ini").as_deref(),
+            Some("win.ini")
+        );
+        assert_eq!(filename("attachment; filename=\"a/..\""), None);
+        assert_eq!(filename("attachment; filename=\"\""), None);
+    }
+
+    #[test]
+    fn parses_types_and_params() {
+        let disposition = ContentDisposition::parse("Form-Data; name=\"field\"; flag").unwrap();
+        assert_eq!(disposition.get_type(), "form-data");
+        assert!(disposition.is_attachment());
+        assert_eq!(disposition.get_param("NAME").as_deref(), Some("field"));
+        assert_eq!(disposition.get_param("flag"), None);
+        assert!(!ContentDisposition::parse("inline").unwrap().is_attachment());
+        assert!(ContentDisposition::parse(" ; filename=a.txt").is_none());
+    }
+}
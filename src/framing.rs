@@ -0,0 +1,166 @@
+use wasm_bindgen::prelude::*;
+use web_sys::WebSocket;
+
+/// WebSocket subprotocol of proxies relaying raw bytes in binary frames.
+pub const BINARY_PROTOCOL: &str = "binary";
+/// WebSocket subprotocol of proxies relaying bytes as base64 in text frames.
+pub const BASE64_PROTOCOL: &str = "base64";
+
+/// Alphabet of standard base64, with padding.
+const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// How bytes are carried in the WebSocket frames exchanged with the proxy.
+#[wasm_bindgen]
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum Framing {
+    /// Raw bytes in binary frames
+    #[default]
+    Binary,
+    /// Base64 in text frames, for proxies that only relay text frames
+    Base64,
+    /// Binary frames, unless the proxy selects the `base64` subprotocol
+    Negotiate,
+}
+
+impl Framing {
+    /// Get the WebSocket subprotocols offered to the proxy, in order of preference.
+    pub fn protocols(self) -> &'static [&'static str] {
+        match self {
+            Framing::Binary => &[BINARY_PROTOCOL],
+            Framing::Base64 => &[BASE64_PROTOCOL],
+            Framing::Negotiate => &[BINARY_PROTOCOL, BASE64_PROTOCOL],
+        }
+    }
+
+    /// Check if bytes are carried as base64 in text frames on the given socket.
+    ///
+    /// Negotiated framing is only known once the socket is open.
+    pub fn is_text(self, socket: &WebSocket) -> bool {
+        match self {
+            Framing::Binary => false,
+            Framing::Base64 => true,
+            Framing::Negotiate => socket.protocol() == BASE64_PROTOCOL,
+        }
+    }
+}
+
+/// Encode bytes as standard base64, with padding.
+pub fn encode(data: &[u8]) -> String {
+    let mut encoded = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let bits = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |bits, (i, &b)| bits | (b as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(ALPHABET[(bits >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+/// Decode standard base64, with or without padding.
+///
+/// # Returns
+///
+/// The decoded bytes, or `None` if `data` is not valid base64, e.g. if its padding does not
+/// complete its last group of 4 characters.
+pub fn decode(data: &str) -> Option<Vec<u8>> {
+    let padded = data.trim_end();
+    let data = padded.trim_end_matches('=');
+    let padding = padded.len() - data.len();
+    if padding > 0 && (padding > 2 || !padded.len().is_multiple_of(4)) {
+        return None;
+    }
+    let data = data.as_bytes();
+    if data.len() % 4 == 1 {
+        return None;
+    }
+    let mut decoded = Vec::with_capacity(data.len() * 3 / 4);
+    for chunk in data.chunks(4) {
+        let mut bits = 0u32;
+        for (i, &c) in chunk.iter().enumerate() {
+            let value = ALPHABET.iter().position(|&a| a == c)? as u32;
+            bits |= value << (18 - 6 * i);
+        }
+        for i in 0..chunk.len() - 1 {
+            decoded.push((bits >> (16 - 8 * i)) as u8);
+        }
+    }
+    Some(decoded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Test vectors of RFC 4648.
+    const VECTORS: [(&str, &str); 7] = [
+        ("", ""),
+        ("f", "Zg=="),
+        ("fo", "Zm8="),
+        ("foo", "Zm9v"),
+        ("foob", "Zm9vYg=="),
+        ("fooba", "Zm9vYmE="),
+        ("foobar", "Zm9vYmFy"),
+    ];
+
+    #[test]
+    fn encodes_with_padding() {
+        for (data, encoded) in VECTORS {
+            assert_eq!(encode(data.as_bytes()), encoded);
+        }
+    }
+
+    #[test]
+    fn decodes_with_and_without_padding() {
+        for (data, encoded) in VECTORS {
+            assert_eq!(
+                decode(encoded).as_deref(),
+                Some(data.as_bytes()),
+                "{}",
+                encoded
+            );
+            let unpadded = encoded.trim_end_matches('=');
+            assert_eq!(
+                decode(unpadded).as_deref(),
+                Some(data.as_bytes()),
+                "{}",
+                unpadded
+            );
+        }
+        assert_eq!(decode("Zm9vYmFy\r\n").as_deref(), Some(&b"foobar"[..]));
+    }
+
+    #[test]
+    fn round_trips_every_byte() {
+        let data: Vec<u8> = (0..=255).collect();
+        for len in 0..data.len() {
+            assert_eq!(decode(&encode(&data[..len])).as_deref(), Some(&data[..len]));
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_input() {
+        let cases = [
+            "Z",
+            "Zm9vY",
+            "Zg=",
+            "Zg===",
+            "Zm9v=",
+            "Zm9vYmE==",
+            "=",
+            "Zg==Zg==",
+            "Zm9v YmFy",
+            "Zm9v-_8=",
+            "Zm9\0",
+        ];
+        for data in cases {
+            assert_eq!(decode(data), None, "{:?}", data);
+        }
+    }
+}
@@ -0,0 +1,138 @@
+use std::{
+    cell::RefCell,
+    rc::{Rc, Weak},
+};
+
+use wasm_bindgen::prelude::*;
+
+use crate::{
+    connection::{Connection, ConnectionError},
+    connection_apis::{
+        http::HttpConnectionApi, https::HttpsConnectionApi, tcp::TcpConnectionApi,
+        udp::UdpConnectionApi,
+    },
+    middleware::Pipeline,
+    worker::TlsWorker,
+    SocketCapability,
+};
+
+/// Opaque handle of a connection of a protocol picked at runtime, from which its typed API
+/// is requested.
+///
+/// The handle does not keep the client alive: once the client is freed, no API can be requested.
+#[wasm_bindgen]
+pub struct ConnectionHandle {
+    /// ID of the connection
+    id: u64,
+    /// Protocol of the connection
+    protocol: SocketCapability,
+    /// Connections of the client owning the connection, gone once the client is freed
+    connections: Weak<RefCell<Vec<Connection>>>,
+    /// Middlewares of the client
+    pipeline: Pipeline,
+    /// Worker to run the TLS state machine in, if any
+    tls_worker: Option<TlsWorker>,
+}
+
+impl ConnectionHandle {
+    /// Create a new handle of a connection.
+    ///
+    /// # Arguments
+    ///
+    /// * `connection` - Connection to create the handle of
+    /// * `connections` - Connections of the client owning the connection
+    /// * `pipeline` - Middlewares of the client
+    /// * `tls_worker` - Worker to run the TLS state machine in, if any
+    pub fn new(
+        connection: &Connection,
+        connections: &Rc<RefCell<Vec<Connection>>>,
+        pipeline: Pipeline,
+        tls_worker: Option<TlsWorker>,
+    ) -> Self {
+        Self {
+            id: connection.get_id().into(),
+            protocol: connection.protocol,
+            connections: Rc::downgrade(connections),
+            pipeline,
+            tls_worker,
+        }
+    }
+
+    /// Find the connection, checking it has the expected protocol.
+    fn connection(&self, expected: &str) -> Result<Connection, ConnectionError> {
+        let protocol = match self.protocol {
+            SocketCapability::HTTPS(_) => "https".to_string(),
+            protocol => protocol.to_string(),
+        };
+        if protocol != expected {
+            return Err(ConnectionError {
+                message: format!("Connection is a {} connection, not {}", protocol, expected),
+            });
+        }
+        let connections = self.connections.upgrade().ok_or_else(|| ConnectionError {
+            message: "Client of the connection was freed".to_string(),
+        })?;
+        let connections = connections.borrow();
+        connections
+            .iter()
+            .find(|c| Into::<u64>::into(c.get_id()) == self.id)
+            .cloned()
+            .ok_or_else(|| ConnectionError {
+                message: "Connection was removed from its client".to_string(),
+            })
+    }
+}
+
+#[wasm_bindgen]
+impl ConnectionHandle {
+    /// Get the ID of the connection.
+    #[wasm_bindgen]
+    pub fn get_id(&self) -> u64 {
+        self.id
+    }
+
+    /// Get the protocol of the connection, e.g. `tcp` or `https_tls1_2`.
+    #[wasm_bindgen]
+    pub fn get_protocol(&self) -> String {
+        self.protocol.to_string()
+    }
+
+    /// Get the TCP API of the connection.
+    ///
+    /// Fails if the connection is not a TCP connection or was removed from its client.
+    #[wasm_bindgen]
+    pub fn as_tcp(&self) -> Result<TcpConnectionApi, ConnectionError> {
+        Ok(TcpConnectionApi::new(self.connection("tcp")?))
+    }
+
+    /// Get the UDP API of the connection.
+    ///
+    /// Fails if the connection is not a UDP connection or was removed from its client.
+    #[wasm_bindgen]
+    pub fn as_udp(&self) -> Result<UdpConnectionApi, ConnectionError> {
+        Ok(UdpConnectionApi::new(self.connection("udp")?))
+    }
+
+    /// Get the HTTP API of the connection.
+    ///
+    /// Fails if the connection is not a HTTP connection or was removed from its client.
+    #[wasm_bindgen]
+    pub fn as_http(&self) -> Result<HttpConnectionApi, ConnectionError> {
+        Ok(HttpConnectionApi::new(
+            self.connection("http")?,
+            self.pipeline.clone(),
+        ))
+    }
+
+    /// Get the HTTPS API of the connection.
+    ///
+    /// Fails if the connection is not a HTTPS connection or was removed from its client.
+    #[wasm_bindgen]
+    pub fn as_https(&self) -> Result<HttpsConnectionApi, ConnectionError> {
+        Ok(HttpsConnectionApi::new(
+            self.connection("https")?,
+            self.tls_worker.clone(),
+            self.pipeline.clone(),
+        ))
+    }
+}
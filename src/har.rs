@@ -0,0 +1,275 @@
+use std::{cell::RefCell, collections::HashMap, rc::Rc};
+
+use wasm_bindgen::prelude::*;
+use wasm_bindgen_futures::js_sys::{self, Array, Object, Reflect};
+
+use crate::{
+    connection::ConnectionError,
+    connection_apis::http::{HttpConnectionRequest, HttpConnectionResponse, HttpHeader},
+    middleware::{generate_request_id, Middleware},
+    timing::RequestTiming,
+};
+
+/// Request waiting for its response.
+struct PendingRequest {
+    /// Time the request was sent at, in ms since the epoch
+    started: f64,
+    /// Request method
+    method: String,
+    /// Request URL
+    url: String,
+    /// Request headers
+    headers: Vec<HttpHeader>,
+    /// Size of the request body
+    body_size: usize,
+    /// Name of the client that sent the request, if any
+    client_name: Option<String>,
+    /// Labels of the connection that sent the request
+    tags: Vec<String>,
+}
+
+/// Completed request/response exchange.
+struct HarEntry {
+    /// The request
+    request: PendingRequest,
+    /// Time taken until the response was complete, in ms
+    time: f64,
+    /// Response code
+    status: u16,
+    /// Response headers
+    headers: Vec<HttpHeader>,
+    /// Size of the response body
+    body_size: usize,
+    /// Timing of the request, if measured by its connection
+    timing: Option<RequestTiming>,
+}
+
+/// Recorded traffic of a recorder.
+#[derive(Default)]
+struct HarLog {
+    /// Requests waiting for their response, by request ID
+    pending: HashMap<String, PendingRequest>,
+    /// Completed exchanges, in order of completion
+    entries: Vec<HarEntry>,
+}
+
+/// Recorder of the HTTP and HTTPS traffic of a client, exported as an HTTP Archive (HAR).
+///
+/// Only metadata and timings are recorded, not bodies. Requests without a `Host` header
+/// are recorded with their path as URL.
+#[wasm_bindgen]
+#[derive(Clone, Default)]
+pub struct HarRecorder {
+    /// Recorded traffic
+    log: Rc<RefCell<HarLog>>,
+}
+
+impl HarRecorder {
+    /// Create a new empty recorder.
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[wasm_bindgen]
+impl HarRecorder {
+    /// Get the number of completed exchanges recorded.
+    #[wasm_bindgen]
+    pub fn len(&self) -> usize {
+        self.log.borrow().entries.len()
+    }
+
+    /// Check if no exchange was completed yet.
+    #[wasm_bindgen]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Discard all recorded exchanges.
+    #[wasm_bindgen]
+    pub fn clear(&self) {
+        self.log.borrow_mut().entries.clear();
+    }
+
+    /// Export the recorded exchanges as a HAR object.
+    #[wasm_bindgen]
+    pub fn to_har(&self) -> JsValue {
+        let entries: Array = self.log.borrow().entries.iter().map(entry).collect();
+
+        let creator = object(&[
+            ("name", "socketguard".into()),
+            ("version", env!("CARGO_PKG_VERSION").into()),
+        ]);
+        let log = object(&[
+            ("version", "1.2".into()),
+            ("creator", creator),
+            ("entries", entries.into()),
+        ]);
+        object(&[("log", log)])
+    }
+
+    /// Export the recorded exchanges as HAR JSON.
+    #[wasm_bindgen]
+    pub fn export(&self) -> Result<String, JsValue> {
+        js_sys::JSON::stringify_with_replacer_and_space(&self.to_har(), &JsValue::null(), &2.into())
+            .map(String::from)
+    }
+}
+
+impl Middleware for HarRecorder {
+    fn on_request(
+        &self,
+        mut request: HttpConnectionRequest,
+    ) -> Result<HttpConnectionRequest, ConnectionError> {
+        let id = request
+            .request_id
+            .get_or_insert_with(generate_request_id)
+            .clone();
+        let url = match request
+            .headers
+            .iter()
+            .find(|h| h.name().eq_ignore_ascii_case("Host"))
+        {
+            Some(host) => format!("//{}{}", host.value(), request.path),
+            None => request.path.clone(),
+        };
+        self.log.borrow_mut().pending.insert(
+            id,
+            PendingRequest {
+                started: js_sys::Date::now(),
+                method: request.method.clone(),
+                url,
+                headers: request.headers.clone(),
+                body_size: request.body.as_ref().map_or(0, Vec::len),
+                client_name: request.client_name.clone(),
+                tags: request.tags.clone(),
+            },
+        );
+        Ok(request)
+    }
+
+    fn on_response(
+        &self,
+        response: HttpConnectionResponse,
+    ) -> Result<HttpConnectionResponse, ConnectionError> {
+        let mut log = self.log.borrow_mut();
+        let request = response
+            .request_id
+            .as_ref()
+            .and_then(|id| log.pending.remove(id));
+        if let Some(request) = request {
+            log.entries.push(HarEntry {
+                time: js_sys::Date::now() - request.started,
+                request,
+                status: response.code,
+                headers: response.headers.clone(),
+                body_size: response.body.as_ref().map_or(0, Vec::len),
+                timing: response.timing,
+            });
+        }
+        Ok(response)
+    }
+}
+
+/// Create a JS object from its properties.
+fn object(properties: &[(&str, JsValue)]) -> JsValue {
+    let object = Object::new();
+    for (key, value) in properties {
+        Reflect::set(&object, &(*key).into(), value).unwrap_throw();
+    }
+    object.into()
+}
+
+/// Convert headers to HAR name/value objects.
+fn headers(headers: &[HttpHeader]) -> JsValue {
+    headers
+        .iter()
+        .map(|h| object(&[("name", h.name().into()), ("value", h.value().into())]))
+        .collect::<Array>()
+        .into()
+}
+
+/// Convert an exchange to a HAR entry object.
+fn entry(entry: &HarEntry) -> JsValue {
+    let started = js_sys::Date::new(&entry.request.started.into());
+    let mime_type = entry
+        .headers
+        .iter()
+        .find(|h| h.name().eq_ignore_ascii_case("Content-Type"))
+        .map_or("", HttpHeader::value);
+
+    let request = object(&[
+        ("method", entry.request.method.as_str().into()),
+        ("url", entry.request.url.as_str().into()),
+        ("httpVersion", "HTTP/1.1".into()),
+        ("cookies", Array::new().into()),
+        ("headers", headers(&entry.request.headers)),
+        ("queryString", Array::new().into()),
+        ("headersSize", (-1).into()),
+        ("bodySize", entry.request.body_size.into()),
+    ]);
+    let content = object(&[
+        ("size", entry.body_size.into()),
+        ("mimeType", mime_type.into()),
+    ]);
+    let response = object(&[
+        ("status", entry.status.into()),
+        ("statusText", "".into()),
+        ("httpVersion", "HTTP/1.1".into()),
+        ("cookies", Array::new().into()),
+        ("headers", headers(&entry.headers)),
+        ("content", content),
+        ("redirectURL", "".into()),
+        ("headersSize", (-1).into()),
+        ("bodySize", entry.body_size.into()),
+    ]);
+    let (time, timings) = match entry.timing {
+        Some(timing) => {
+            let tls = timing.get_tls_handshake();
+            let first_byte = timing.get_time_to_first_byte();
+            let timings = object(&[
+                ("blocked", timing.get_queue_wait().into()),
+                ("dns", (-1).into()),
+                // Includes the TLS handshake, as the HAR format requires
+                (
+                    "connect",
+                    (timing.get_connect() + tls.unwrap_or(0.0)).into(),
+                ),
+                ("ssl", tls.unwrap_or(-1.0).into()),
+                ("send", 0.into()),
+                ("wait", (first_byte - tls.unwrap_or(0.0)).into()),
+                ("receive", (timing.get_duration() - first_byte).into()),
+            ]);
+            (timing.get_total(), timings)
+        }
+        None => {
+            let timings = object(&[
+                ("send", 0.into()),
+                ("wait", entry.time.into()),
+                ("receive", 0.into()),
+            ]);
+            (entry.time, timings)
+        }
+    };
+
+    object(&[
+        ("startedDateTime", started.to_iso_string().into()),
+        ("time", time.into()),
+        ("request", request),
+        ("response", response),
+        ("cache", Object::new().into()),
+        ("timings", timings),
+        // Custom fields, prefixed with an underscore as the HAR format requires
+        ("_client", entry.request.client_name.as_deref().into()),
+        (
+            "_tags",
+            entry
+                .request
+                .tags
+                .iter()
+                .map(|tag| JsValue::from(tag.as_str()))
+                .collect::<Array>()
+                .into(),
+        ),
+    ])
+}
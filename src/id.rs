@@ -1,51 +1,112 @@
-use std::{thread, time::Duration};
+use wasm_bindgen::prelude::*;
+use wasm_bindgen_futures::js_sys;
 
-use wasm_timer::{SystemTime, UNIX_EPOCH};
+use crate::{connection::ConnectionError, SocketCapability, TLSVersion};
 
-use crate::{SocketCapability, TLSVersion};
+/// How connection IDs are made by a client.
+#[wasm_bindgen]
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum ConnIdScheme {
+    /// 64-bit IDs made of the time, connection type and a counter
+    #[default]
+    Compact,
+    /// 64-bit IDs as with `Compact`, each with a UUIDv7 for external systems expecting UUIDs
+    UuidV7,
+}
 
+/// Factory of unique connection IDs.
+///
+/// IDs are made from a logical clock following `Date.now()`, never going backwards when the
+/// wall clock does, and moving ahead by a millisecond once 256 IDs were made in the same
+/// one, rather than blocking the thread, which Deno, browsers and workers all forbid.
+///
+/// With `ConnIdScheme::UuidV7`, IDs also get a UUIDv7 following the same clock, with the
+/// counter in the 8 bits after the version followed by 4 random bits, so UUIDs sort like the
+/// IDs they go with.
 #[derive(Clone)]
 pub struct ConnIdFactory {
-    last_time: SystemTime,
+    last_time: u64,
     incr: u8,
+    scheme: ConnIdScheme,
 }
 
 impl ConnIdFactory {
     pub fn new() -> Self {
         Self {
-            last_time: SystemTime::now(),
+            last_time: js_sys::Date::now() as u64,
             incr: 0,
+            scheme: ConnIdScheme::default(),
         }
     }
 
+    /// Get how IDs are made.
+    pub fn scheme(&self) -> ConnIdScheme {
+        self.scheme
+    }
+
+    /// Set how the next IDs are made.
+    pub fn set_scheme(&mut self, scheme: ConnIdScheme) {
+        self.scheme = scheme;
+    }
+
     pub fn generate(&mut self, conn_type: SocketCapability) -> ConnId {
-        let since = SystemTime::now().duration_since(self.last_time).unwrap();
+        self.generate_at(conn_type, js_sys::Date::now() as u64)
+    }
+
+    /// Make an ID at the given time of the wall clock.
+    ///
+    /// # Arguments
+    ///
+    /// * `conn_type` - Type of the connection
+    /// * `now` - Time of the wall clock in ms since the epoch
+    fn generate_at(&mut self, conn_type: SocketCapability, now: u64) -> ConnId {
         let conn_type: u8 = conn_type.into();
 
-        if since.as_millis() == 0 {
-            if self.incr == u8::MAX {
-                thread::sleep(Duration::from_millis(1));
-                self.incr = 0;
-            } else {
-                self.incr = self.incr + 1;
-            }
-        } else {
+        if now > self.last_time {
+            self.last_time = now;
+            self.incr = 0;
+        } else if self.incr == u8::MAX {
+            self.last_time += 1;
             self.incr = 0;
+        } else {
+            self.incr += 1;
         }
 
-        self.last_time = SystemTime::now();
+        let uuid = match self.scheme {
+            ConnIdScheme::Compact => None,
+            ConnIdScheme::UuidV7 => Some(uuid_v7(self.last_time, self.incr)),
+        };
+
         ConnId {
-            time: self
-                .last_time
-                .duration_since(UNIX_EPOCH)
-                .unwrap()
-                .as_millis() as u64,
+            time: self.last_time,
             conn_type,
             incr: self.incr,
+            uuid,
         }
     }
 }
 
+/// Make a UUIDv7 from a time, a counter and random bits.
+///
+/// The random bits come from `crypto.getRandomValues()`.
+///
+/// # Arguments
+///
+/// * `time` - Time in ms since the epoch, of which the low 48 bits are kept
+/// * `incr` - Counter of the IDs made in the same ms
+fn uuid_v7(time: u64, incr: u8) -> u128 {
+    let mut random = [0u8; 16];
+    getrandom::getrandom(&mut random).unwrap_throw();
+    let random = u128::from_le_bytes(random);
+    let rand_a = (u128::from(incr) << 4) | (random & 0xF);
+    let rand_b = (random >> 4) & ((1 << 62) - 1);
+    (u128::from(time & 0xFFFF_FFFF_FFFF) << 80)
+        | (0x7 << 76)
+        | (rand_a << 64)
+        | (0b10 << 62)
+        | rand_b
+}
+
 #[derive(Copy, Clone, Debug)]
 pub struct ConnId {
     /// Time in ms (first 48 bits)
@@ -54,45 +115,122 @@ pub struct ConnId {
     pub conn_type: u8,
     /// Incremental fallback when multiple ids are created in 1ms (8 bits)
     pub incr: u8,
+    /// UUIDv7 of the connection, if made with `ConnIdScheme::UuidV7`
+    pub uuid: Option<u128>,
 }
 
-impl Into<u64> for ConnId {
-    fn into(self) -> u64 {
-        ((self.time as u64) << 16) | ((self.conn_type as u64) << 8) | (self.incr as u64)
+impl ConnId {
+    /// Get the UUIDv7 of the connection in its hyphenated form, if it has one.
+    pub fn uuid_string(&self) -> Option<String> {
+        self.uuid.map(|uuid| {
+            format!(
+                "{:08x}-{:04x}-{:04x}-{:04x}-{:012x}",
+                uuid >> 96,
+                (uuid >> 80) & 0xFFFF,
+                (uuid >> 64) & 0xFFFF,
+                (uuid >> 48) & 0xFFFF,
+                uuid & 0xFFFF_FFFF_FFFF
+            )
+        })
     }
 }
 
+impl From<ConnId> for u64 {
+    fn from(id: ConnId) -> Self {
+        (id.time << 16) | ((id.conn_type as u64) << 8) | (id.incr as u64)
+    }
+}
+
+/// The UUID of IDs made with `ConnIdScheme::UuidV7` is lost, as it does not fit in a `u64`.
 impl From<u64> for ConnId {
     fn from(value: u64) -> Self {
-        let time: u64 = (value >> 16) as u64;
-        let conn_type: u8 = (((value >> 8) & 0xFF) as u8).into();
+        let time: u64 = value >> 16;
+        let conn_type: u8 = ((value >> 8) & 0xFF) as u8;
         let incr: u8 = (value & 0xFF) as u8;
         Self {
             time,
             conn_type,
             incr,
+            uuid: None,
         }
     }
 }
 
-impl From<u8> for SocketCapability {
-    fn from(value: u8) -> Self {
+/// Components of a connection ID, decoded for display, e.g. by dashboards.
+#[wasm_bindgen]
+#[derive(Copy, Clone, Debug)]
+pub struct ConnIdParts {
+    /// Decoded ID
+    id: ConnId,
+}
+
+impl From<ConnId> for ConnIdParts {
+    fn from(id: ConnId) -> Self {
+        Self { id }
+    }
+}
+
+#[wasm_bindgen]
+impl ConnIdParts {
+    /// Get the ID, as a number.
+    #[wasm_bindgen]
+    pub fn get_id(&self) -> u64 {
+        self.id.into()
+    }
+
+    /// Get the time the ID was made at, which may run ahead of the wall clock by a few ms
+    /// when many IDs are made at once.
+    #[wasm_bindgen]
+    pub fn get_timestamp(&self) -> js_sys::Date {
+        js_sys::Date::new(&JsValue::from(self.id.time as f64))
+    }
+
+    /// Get the type of the connection, e.g. `tcp` or `https_tls1_2`, or `None` if the ID
+    /// holds an unknown type.
+    #[wasm_bindgen]
+    pub fn get_conn_type(&self) -> Option<String> {
+        SocketCapability::try_from(self.id.conn_type)
+            .ok()
+            .map(|c| c.to_string())
+    }
+
+    /// Get the sequence number of the ID among those made in the same ms.
+    #[wasm_bindgen]
+    pub fn get_sequence(&self) -> u8 {
+        self.id.incr
+    }
+
+    /// Get the UUID of the connection, if made with `ConnIdScheme.UuidV7` and known.
+    #[wasm_bindgen]
+    pub fn get_uuid(&self) -> Option<String> {
+        self.id.uuid_string()
+    }
+}
+
+impl TryFrom<u8> for SocketCapability {
+    type Error = ConnectionError;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
         match value {
-            0 => SocketCapability::TCP,
-            10 => SocketCapability::HTTP,
-            20 => SocketCapability::HTTPS(TLSVersion::TLSv1_0),
-            21 => SocketCapability::HTTPS(TLSVersion::TLSv1_1),
-            22 => SocketCapability::HTTPS(TLSVersion::TLSv1_2),
-            23 => SocketCapability::HTTPS(TLSVersion::TLSv1_3),
-            _ => panic!("Invalid socket capability"),
+            0 => Ok(SocketCapability::TCP),
+            1 => Ok(SocketCapability::UDP),
+            10 => Ok(SocketCapability::HTTP),
+            20 => Ok(SocketCapability::HTTPS(TLSVersion::TLSv1_0)),
+            21 => Ok(SocketCapability::HTTPS(TLSVersion::TLSv1_1)),
+            22 => Ok(SocketCapability::HTTPS(TLSVersion::TLSv1_2)),
+            23 => Ok(SocketCapability::HTTPS(TLSVersion::TLSv1_3)),
+            _ => Err(ConnectionError {
+                message: format!("Invalid socket capability: {}", value),
+            }),
         }
     }
 }
 
-impl Into<u8> for SocketCapability {
-    fn into(self) -> u8 {
-        match self {
+impl From<SocketCapability> for u8 {
+    fn from(capability: SocketCapability) -> Self {
+        match capability {
             SocketCapability::TCP => 0,
+            SocketCapability::UDP => 1,
             SocketCapability::HTTP => 10,
             SocketCapability::HTTPS(TLSVersion::TLSv1_0) => 20,
             SocketCapability::HTTPS(TLSVersion::TLSv1_1) => 21,
@@ -101,3 +239,167 @@ impl Into<u8> for SocketCapability {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use proptest::{collection::vec, prelude::*};
+
+    use super::*;
+
+    /// Largest time held by an ID, in ms since the epoch.
+    const MAX_TIME: u64 = 0xFFFF_FFFF_FFFF;
+
+    /// Create a factory whose clock starts at `time`, as `ConnIdFactory::new` reads the wall
+    /// clock.
+    fn factory(time: u64) -> ConnIdFactory {
+        ConnIdFactory {
+            last_time: time,
+            incr: 0,
+            scheme: ConnIdScheme::Compact,
+        }
+    }
+
+    /// Get any connection type.
+    fn conn_type() -> impl Strategy<Value = SocketCapability> {
+        prop::sample::select(vec![
+            SocketCapability::TCP,
+            SocketCapability::UDP,
+            SocketCapability::HTTP,
+            SocketCapability::HTTPS(TLSVersion::TLSv1_2),
+            SocketCapability::HTTPS(TLSVersion::TLSv1_3),
+        ])
+    }
+
+    #[test]
+    fn conn_id_round_trip() {
+        for value in [
+            0,
+            1,
+            0xFF,
+            0xFF00,
+            0x1_0000,
+            0xFFFF_FFFF_FFFF_0000,
+            u64::MAX - 1,
+            u64::MAX,
+        ] {
+            let id = ConnId::from(value);
+            assert_eq!(u64::from(id), value);
+        }
+
+        for time in [0, 1, 1_700_000_000_000, 0xFFFF_FFFF_FFFF] {
+            for (conn_type, incr) in [(0, 0), (23, 1), (u8::MAX, u8::MAX)] {
+                let id = ConnId::from(u64::from(ConnId {
+                    time,
+                    conn_type,
+                    incr,
+                    uuid: None,
+                }));
+                assert_eq!((id.time, id.conn_type, id.incr), (time, conn_type, incr));
+                assert!(id.uuid.is_none());
+            }
+        }
+    }
+
+    #[test]
+    fn socket_capability_from_every_byte() {
+        let mut valid = 0;
+        for byte in 0..=u8::MAX {
+            if let Ok(capability) = SocketCapability::try_from(byte) {
+                assert_eq!(u8::from(capability), byte);
+                valid += 1;
+            }
+        }
+        assert_eq!(valid, 7);
+    }
+
+    #[test]
+    fn uuid_v7_layout() {
+        for (time, incr) in [(0, 0), (1_700_000_000_000, 42), (u64::MAX, u8::MAX)] {
+            let uuid = uuid_v7(time, incr);
+            assert_eq!((uuid >> 80) as u64, time & 0xFFFF_FFFF_FFFF);
+            assert_eq!((uuid >> 76) & 0xF, 0x7, "version");
+            assert_eq!((uuid >> 68) & 0xFF, u128::from(incr));
+            assert_eq!((uuid >> 62) & 0b11, 0b10, "variant");
+
+            let id = ConnId {
+                time,
+                conn_type: 0,
+                incr,
+                uuid: Some(uuid),
+            };
+            let uuid = id.uuid_string().unwrap();
+            assert_eq!(uuid.len(), 36);
+            assert_eq!(&uuid[14..15], "7");
+            assert!(matches!(&uuid[19..20], "8" | "9" | "a" | "b"));
+        }
+    }
+
+    #[test]
+    fn uuid_v7_sorts_by_counter() {
+        let uuids: Vec<u128> = (0..=u8::MAX).map(|incr| uuid_v7(1, incr)).collect();
+        assert!(uuids.windows(2).all(|w| w[0] < w[1]));
+    }
+
+    #[test]
+    fn generate_increases_within_a_ms() {
+        let mut factory = factory(1_000);
+        let ids: Vec<ConnId> = (0..600)
+            .map(|_| factory.generate_at(SocketCapability::TCP, 1_000))
+            .collect();
+        assert!(ids.windows(2).all(|w| u64::from(w[0]) < u64::from(w[1])));
+        // Moves ahead of the wall clock once the counter of a ms runs out
+        assert_eq!((ids[254].time, ids[254].incr), (1_000, u8::MAX));
+        assert_eq!((ids[255].time, ids[255].incr), (1_001, 0));
+        assert_eq!(ids[599].time, 1_002);
+    }
+
+    proptest! {
+        #[test]
+        fn u64_round_trip(value in any::<u64>()) {
+            prop_assert_eq!(u64::from(ConnId::from(value)), value);
+        }
+
+        #[test]
+        fn parts_round_trip(time in 0..=MAX_TIME, conn_type in any::<u8>(), incr in any::<u8>()) {
+            let id = ConnId::from(u64::from(ConnId { time, conn_type, incr, uuid: None }));
+            prop_assert_eq!((id.time, id.conn_type, id.incr), (time, conn_type, incr));
+        }
+
+        #[test]
+        fn ids_sort_like_their_parts(
+            a in (0..=MAX_TIME, any::<u8>(), any::<u8>()),
+            b in (0..=MAX_TIME, any::<u8>(), any::<u8>()),
+        ) {
+            let id = |(time, conn_type, incr)| u64::from(ConnId { time, conn_type, incr, uuid: None });
+            prop_assert_eq!(id(a).cmp(&id(b)), a.cmp(&b));
+        }
+
+        #[test]
+        fn uuids_sort_like_their_ids(a in (0..=MAX_TIME, any::<u8>()), b in (0..=MAX_TIME, any::<u8>())) {
+            prop_assume!(a != b);
+            prop_assert_eq!(uuid_v7(a.0, a.1).cmp(&uuid_v7(b.0, b.1)), a.cmp(&b));
+        }
+
+        #[test]
+        fn generate_increases_when_the_clock_repeats_or_goes_back(
+            start in 0..MAX_TIME / 2,
+            steps in vec((-3i64..=2, conn_type()), 1..1000),
+        ) {
+            let mut factory = factory(start);
+            let mut now = start;
+            let mut last: Option<ConnId> = None;
+            for (step, conn_type) in steps {
+                now = now.saturating_add_signed(step);
+                let id = factory.generate_at(conn_type, now);
+                prop_assert!(id.time >= now);
+                if let Some(last) = last {
+                    prop_assert!((id.time, id.incr) > (last.time, last.incr));
+                    if id.conn_type == last.conn_type {
+                        prop_assert!(u64::from(id) > u64::from(last));
+                    }
+                }
+                last = Some(id);
+            }
+        }
+    }
+}
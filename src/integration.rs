@@ -0,0 +1,128 @@
+//! End-to-end tests through the wsproxy of the test server and the local targets it starts.
+//!
+//! Run in a browser with `wasm-pack test --headless --firefox` while the test server is
+//! running, see `test/index.ts`. The HTTPS test also needs internet access.
+
+use wasm_bindgen::{convert::TryFromJsValue, prelude::*};
+use wasm_bindgen_futures::{js_sys::Promise, JsFuture};
+use wasm_bindgen_test::*;
+
+use crate::{
+    client::Client,
+    connection_apis::{
+        http::{HttpConnectionRequest, HttpConnectionResponse, HttpHeader},
+        tcp::{TcpConnectionRequest, TcpConnectionResponse},
+    },
+};
+
+wasm_bindgen_test_configure!(run_in_browser);
+
+/// Base url of the wsproxy of the test server.
+const PROXY: &str = "ws://localhost:3000";
+
+/// Address of the TCP echo server of the test server.
+const ECHO_ADDR: &str = "127.0.0.1:4242";
+
+/// Address of the HTTP server of the test server.
+const HTTP_ADDR: &str = "127.0.0.1:8080";
+
+/// Create a promise along with the function resolving it, to pass as a callback.
+fn callback() -> (Promise, JsValue) {
+    let mut resolve = None;
+    let promise = Promise::new(&mut |res, _| resolve = Some(res));
+    (promise, resolve.unwrap().into())
+}
+
+/// Wait for the response to a request of `Client::get` or `Client::post`.
+async fn fetched(request: Promise) -> HttpConnectionResponse {
+    let response = JsFuture::from(request).await.unwrap();
+    HttpConnectionResponse::try_from_js_value(response).unwrap()
+}
+
+/// Create a GET request to the HTTP server of the test server.
+fn get(path: &str) -> HttpConnectionRequest {
+    HttpConnectionRequest::new(
+        "GET".to_string(),
+        path.to_string(),
+        vec![HttpHeader::new("Host", HTTP_ADDR)],
+        None,
+    )
+}
+
+#[wasm_bindgen_test]
+async fn tcp_echo() {
+    let mut client = Client::new(PROXY.to_string());
+    let (open, onready) = callback();
+    let api = client
+        .create_tcp_connection_with_onready(ECHO_ADDR.to_string(), onready.unchecked_into())
+        .unwrap()
+        .unwrap();
+    JsFuture::from(open).await.unwrap();
+
+    let (answer, onresponse) = callback();
+    api.send(
+        TcpConnectionRequest::new(b"ping\n".to_vec()),
+        onresponse.unchecked_into(),
+    )
+    .unwrap();
+    let response = JsFuture::from(answer).await.unwrap();
+    let mut response = TcpConnectionResponse::try_from_js_value(response).unwrap();
+    assert_eq!(response.take_body(), b"ping\n");
+}
+
+#[wasm_bindgen_test]
+async fn http_get_content_length() {
+    let mut client = Client::new(PROXY.to_string());
+    let url = format!("http://{}/content-length", HTTP_ADDR);
+    let response = fetched(client.get(url, None).unwrap()).await;
+    assert_eq!(response.get_code(), 200);
+    assert_eq!(response.text(), "hello");
+}
+
+#[wasm_bindgen_test]
+async fn http_get_chunked() {
+    let mut client = Client::new(PROXY.to_string());
+    let url = format!("http://{}/chunked", HTTP_ADDR);
+    let response = fetched(client.get(url, None).unwrap()).await;
+    assert_eq!(response.get_code(), 200);
+    assert_eq!(response.text(), "hello");
+}
+
+#[wasm_bindgen_test]
+async fn http_keep_alive_after_chunked() {
+    let mut client = Client::new(PROXY.to_string());
+    let (open, onready) = callback();
+    let api = client
+        .create_http_connection_with_onready(HTTP_ADDR.to_string(), onready.unchecked_into())
+        .unwrap()
+        .unwrap();
+    JsFuture::from(open).await.unwrap();
+
+    // The framing of the chunked body must not be left for the next response
+    for path in ["/chunked", "/content-length"] {
+        let (answer, onresponse) = callback();
+        api.send(get(path), onresponse.unchecked_into()).unwrap();
+        let response = JsFuture::from(answer).await.unwrap();
+        let response = HttpConnectionResponse::try_from_js_value(response).unwrap();
+        assert_eq!(response.get_code(), 200);
+        assert_eq!(response.text(), "hello");
+    }
+}
+
+#[wasm_bindgen_test]
+async fn http_post() {
+    let mut client = Client::new(PROXY.to_string());
+    let url = format!("http://{}/echo", HTTP_ADDR);
+    let body = Some(b"posted".to_vec());
+    let response = fetched(client.post(url, body, None).unwrap()).await;
+    assert_eq!(response.get_code(), 200);
+    assert_eq!(response.text(), "posted");
+}
+
+#[wasm_bindgen_test]
+async fn https_handshake() {
+    let mut client = Client::new(PROXY.to_string());
+    let url = "https://example.com/".to_string();
+    let response = fetched(client.get(url, None).unwrap()).await;
+    assert_eq!(response.get_code(), 200);
+}
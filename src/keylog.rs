@@ -0,0 +1,79 @@
+use std::{cell::RefCell, fmt::Write};
+
+use rustls::KeyLog;
+use wasm_bindgen::prelude::*;
+use wasm_bindgen_futures::js_sys;
+
+#[wasm_bindgen(typescript_custom_section)]
+const KEY_LOG_TYPES: &'static str = r#"
+/** Called with every line of the TLS key log, in the NSS `SSLKEYLOGFILE` format. */
+export type KeyLogCallback = (line: string) => void;
+"#;
+
+#[wasm_bindgen]
+extern "C" {
+    /// JS function called with every line of the TLS key log.
+    #[wasm_bindgen(extends = js_sys::Function, typescript_type = "KeyLogCallback")]
+    #[derive(Clone, Debug)]
+    pub type KeyLogCallback;
+}
+
+thread_local! {
+    /// Function called with every line of the key log, if set.
+    static CALLBACK: RefCell<Option<js_sys::Function>> = const { RefCell::new(None) };
+    /// Lines logged by rustls and not yet taken by a TLS session.
+    static PENDING: RefCell<Vec<String>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Key log of the TLS sessions of this thread, in the NSS `SSLKEYLOGFILE` format.
+///
+/// Lines are collected rather than delivered right away, so sessions running in a TLS worker
+/// can send them to the thread of the page with their output.
+#[derive(Debug)]
+pub struct NssKeyLog;
+
+impl KeyLog for NssKeyLog {
+    fn log(&self, label: &str, client_random: &[u8], secret: &[u8]) {
+        let line = format!("{} {} {}", label, hex(client_random), hex(secret));
+        PENDING.with(|pending| pending.borrow_mut().push(line));
+    }
+}
+
+/// Encode bytes as lowercase hex.
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().fold(String::new(), |mut out, b| {
+        let _ = write!(out, "{:02x}", b);
+        out
+    })
+}
+
+/// Take the lines logged since the last call.
+pub fn take_lines() -> Vec<String> {
+    PENDING.with(|pending| std::mem::take(&mut *pending.borrow_mut()))
+}
+
+/// Call the key log callback with lines taken from a TLS session, if set.
+pub fn emit(lines: Vec<String>) {
+    // Cloned, so the callback may replace itself
+    let callback = CALLBACK.with(|callback| callback.borrow().clone());
+    if let Some(callback) = callback {
+        for line in lines {
+            let _ = callback.call1(&JsValue::null(), &line.into());
+        }
+    }
+}
+
+/// Set the function called with every line of the key log of the HTTPS connections of this
+/// module, in the NSS `SSLKEYLOGFILE` format, so captured sessions can be decrypted in
+/// Wireshark. Lines of sessions running in a TLS worker are delivered on this thread too.
+///
+/// Only available when built with the `keylog` feature. The key log holds the secrets of
+/// every session, it must never be enabled in production.
+///
+/// # Arguments
+///
+/// * `callback` - Function called with every line, or `None` to stop logging
+#[wasm_bindgen]
+pub fn set_tls_key_log(callback: Option<KeyLogCallback>) {
+    CALLBACK.with(|slot| *slot.borrow_mut() = callback.map(Into::into));
+}
@@ -1,10 +1,62 @@
+mod backoff;
+mod batch;
+mod callbacks;
+mod capture;
 mod client;
+mod compat;
+mod conditions;
 mod connection;
 mod connection_apis;
+mod cors;
+mod debug;
+mod dedicated;
+mod disposition;
+mod framing;
+mod handle;
+mod har;
 mod id;
+#[cfg(all(test, target_arch = "wasm32"))]
+mod integration;
+#[cfg(feature = "keylog")]
+mod keylog;
+mod limiter;
 mod macros;
+mod middleware;
+mod options;
+mod pending;
+mod policy;
+mod progress;
+mod proxy_protocol;
+#[cfg(feature = "http3")]
+mod qpack;
+mod reaper;
+mod redirect;
+mod retry;
+mod shared;
+mod snapshot;
+mod sniff;
+mod stats;
+mod throttle;
+mod timing;
+mod tls;
+mod usage;
+mod websocket;
+mod whois;
+mod worker;
+
+/// Internals measured by the benches of `benches/`, not part of the API.
+#[doc(hidden)]
+pub mod bench {
+    pub use crate::{
+        connection_apis::assembler::ResponseAssembler,
+        tls::{TlsOutput, TlsSession},
+    };
+}
+
+use std::fmt;
 
 use wasm_bindgen::prelude::*;
+use wasm_bindgen_futures::js_sys;
 
 #[derive(Eq, PartialOrd, Ord, PartialEq, Copy, Clone, Debug)]
 pub enum TLSVersion {
@@ -14,9 +66,35 @@ pub enum TLSVersion {
     TLSv1_3 = 3,
 }
 
+impl TLSVersion {
+    /// Parse the name of a TLS version, e.g. `tls1_2`.
+    pub fn from_string(s: &str) -> Option<TLSVersion> {
+        match s {
+            "tls1_0" => Some(TLSVersion::TLSv1_0),
+            "tls1_1" => Some(TLSVersion::TLSv1_1),
+            "tls1_2" => Some(TLSVersion::TLSv1_2),
+            "tls1_3" => Some(TLSVersion::TLSv1_3),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for TLSVersion {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let s = match self {
+            TLSVersion::TLSv1_0 => "tls1_0",
+            TLSVersion::TLSv1_1 => "tls1_1",
+            TLSVersion::TLSv1_2 => "tls1_2",
+            TLSVersion::TLSv1_3 => "tls1_3",
+        };
+        write!(f, "{}", s)
+    }
+}
+
 #[derive(Copy, Clone, Debug)]
 pub enum SocketCapability {
     TCP,
+    UDP,
     HTTP,
     HTTPS(TLSVersion),
 }
@@ -25,6 +103,7 @@ impl SocketCapability {
     pub fn from_string(s: &str) -> Option<SocketCapability> {
         match s {
             "tcp" => Some(SocketCapability::TCP),
+            "udp" => Some(SocketCapability::UDP),
             "http" => Some(SocketCapability::HTTP),
             "https_tls1_0" => Some(SocketCapability::HTTPS(TLSVersion::TLSv1_0)),
             "https_tls1_1" => Some(SocketCapability::HTTPS(TLSVersion::TLSv1_1)),
@@ -33,16 +112,20 @@ impl SocketCapability {
             _ => None,
         }
     }
-    pub fn to_string(&self) -> String {
-        match self {
+}
+
+impl fmt::Display for SocketCapability {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let s = match self {
             SocketCapability::TCP => "tcp",
+            SocketCapability::UDP => "udp",
             SocketCapability::HTTP => "http",
             SocketCapability::HTTPS(TLSVersion::TLSv1_0) => "https_tls1_0",
             SocketCapability::HTTPS(TLSVersion::TLSv1_1) => "https_tls1_1",
             SocketCapability::HTTPS(TLSVersion::TLSv1_2) => "https_tls1_2",
             SocketCapability::HTTPS(TLSVersion::TLSv1_3) => "https_tls1_3",
-        }
-        .to_string()
+        };
+        write!(f, "{}", s)
     }
 }
 
@@ -50,13 +133,45 @@ impl SocketCapability {
 pub fn get_capabilities() -> Vec<SocketCapability> {
     vec![
         SocketCapability::TCP,
+        SocketCapability::UDP,
         SocketCapability::HTTP,
         SocketCapability::HTTPS(TLSVersion::TLSv1_2),
     ]
 }
 
+/// Get the JS runtime this module runs in: `deno`, `node`, `worker` or `browser`.
+///
+/// Only the standard `WebSocket`, `setTimeout` and `Date` APIs are relied upon, so every
+/// runtime providing them is supported. This is meant for logs and scripts.
+#[wasm_bindgen]
+pub fn get_runtime() -> String {
+    let global = js_sys::global();
+    let has = |name: &str| {
+        js_sys::Reflect::get(&global, &name.into()).is_ok_and(|value| !value.is_undefined())
+    };
+    let runtime = if has("Deno") {
+        "deno"
+    } else if has("process") {
+        "node"
+    } else if has("importScripts") {
+        "worker"
+    } else {
+        "browser"
+    };
+    runtime.to_string()
+}
+
 #[wasm_bindgen]
 extern "C" {
     #[wasm_bindgen(js_namespace = console)]
     pub fn log(s: &str);
+
+    #[wasm_bindgen(js_name = setTimeout)]
+    pub fn set_timeout(handler: &JsValue, timeout: f64) -> JsValue;
+
+    #[wasm_bindgen(js_name = setInterval)]
+    pub fn set_interval(handler: &JsValue, timeout: f64) -> JsValue;
+
+    #[wasm_bindgen(js_name = clearInterval)]
+    pub fn clear_interval(handle: &JsValue);
 }
@@ -0,0 +1,260 @@
+use std::{cell::RefCell, collections::VecDeque, rc::Rc};
+
+use wasm_bindgen::prelude::*;
+use wasm_bindgen_futures::js_sys;
+use web_sys::AddEventListenerOptions;
+
+use crate::connection::{Connection, ConnectionError};
+
+/// Priority of a connection creation waiting for a free slot.
+#[wasm_bindgen]
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum Priority {
+    /// Interactive requests, served most often
+    High,
+    /// Regular requests
+    #[default]
+    Normal,
+    /// Bulk transfers, served least often but never starved
+    Low,
+}
+
+/// Order in which priorities are served while all have queued creations, weighting
+/// high, normal and low 4:2:1.
+const SCHEDULE: [Priority; 7] = [
+    Priority::High,
+    Priority::Normal,
+    Priority::High,
+    Priority::Low,
+    Priority::High,
+    Priority::Normal,
+    Priority::High,
+];
+
+impl Priority {
+    /// Get the index of the queue of this priority.
+    fn index(self) -> usize {
+        match self {
+            Priority::High => 0,
+            Priority::Normal => 1,
+            Priority::Low => 2,
+        }
+    }
+}
+
+/// Connection creation waiting for a free slot.
+struct QueuedConnection {
+    /// Open the connection
+    open: Box<dyn FnOnce() -> Result<Connection, ConnectionError>>,
+    /// Function resolving the promise of the creation with the connection ID
+    resolve: js_sys::Function,
+    /// Function rejecting the promise of the creation, if the limiter is shut down or the
+    /// connection fails to open
+    reject: js_sys::Function,
+    /// Time the creation was queued at, in ms since the epoch
+    queued: f64,
+}
+
+/// State shared by a limiter and the close listeners of its connections.
+#[derive(Default)]
+struct LimiterState {
+    /// Maximum number of concurrent connections, if limited
+    max: Option<usize>,
+    /// Number of connections not closed yet
+    active: usize,
+    /// Creations waiting for a free slot, by priority, in order of request
+    queues: [VecDeque<QueuedConnection>; 3],
+    /// Position in `SCHEDULE` of the priority served next
+    turn: usize,
+    /// Whether new connections are refused, as the client is shutting down
+    shut_down: bool,
+}
+
+impl LimiterState {
+    /// Take the next queued creation, following `SCHEDULE` and skipping empty queues.
+    fn pop(&mut self) -> Option<QueuedConnection> {
+        for _ in 0..SCHEDULE.len() {
+            let priority = SCHEDULE[self.turn];
+            self.turn = (self.turn + 1) % SCHEDULE.len();
+            if let Some(queued) = self.queues[priority.index()].pop_front() {
+                return Some(queued);
+            }
+        }
+        None
+    }
+
+    /// Get the number of creations waiting for a free slot.
+    fn queued(&self) -> usize {
+        self.queues.iter().map(VecDeque::len).sum()
+    }
+}
+
+/// Limit of the number of concurrent connections of a client.
+///
+/// A connection holds its slot until its socket is closed, either by the remote end or
+/// by removing the connection from the client.
+#[derive(Clone)]
+pub struct ConnectionLimiter {
+    /// Limit, counter and queues
+    state: Rc<RefCell<LimiterState>>,
+    /// Connections of the client, where queued connections are added once opened
+    connections: Rc<RefCell<Vec<Connection>>>,
+}
+
+impl ConnectionLimiter {
+    /// Create a new limiter without limit.
+    ///
+    /// # Arguments
+    ///
+    /// * `connections` - Connections of the client
+    pub fn new(connections: Rc<RefCell<Vec<Connection>>>) -> Self {
+        Self {
+            state: Rc::new(RefCell::new(LimiterState::default())),
+            connections,
+        }
+    }
+
+    /// Set the maximum number of concurrent connections, opening queued ones if it grew.
+    pub fn set_max(&self, max: Option<usize>) {
+        self.state.borrow_mut().max = max;
+        self.drain();
+    }
+
+    /// Get the maximum number of concurrent connections, if limited.
+    pub fn max(&self) -> Option<usize> {
+        self.state.borrow().max
+    }
+
+    /// Get the number of creations waiting for a free slot.
+    pub fn queued(&self) -> usize {
+        self.state.borrow().queued()
+    }
+
+    /// Take a slot for a new connection if one is free.
+    ///
+    /// # Returns
+    ///
+    /// Whether a slot was taken, in which case the connection must be passed to `track`.
+    pub fn try_acquire(&self) -> bool {
+        let mut state = self.state.borrow_mut();
+        // Queued creations come first
+        if state.shut_down || state.queued() > 0 || state.max.is_some_and(|max| state.active >= max)
+        {
+            return false;
+        }
+        state.active += 1;
+        true
+    }
+
+    /// Free a slot taken by `try_acquire` for a connection that failed to open.
+    pub fn release(&self) {
+        self.state.borrow_mut().active -= 1;
+        self.drain();
+    }
+
+    /// Free the slot of a connection once its socket is closed.
+    pub fn track(&self, connection: &Connection) {
+        let limiter = self.clone();
+        let onclose = Closure::once_into_js(move || {
+            limiter.state.borrow_mut().active -= 1;
+            limiter.drain();
+        });
+        connection
+            .socket
+            .add_event_listener_with_callback_and_add_event_listener_options(
+                "close",
+                onclose.unchecked_ref(),
+                AddEventListenerOptions::new().once(true),
+            )
+            .unwrap_throw();
+    }
+
+    /// Queue the creation of a connection until a slot is free.
+    ///
+    /// # Arguments
+    ///
+    /// * `open` - Open the connection
+    /// * `resolve` - Function called with the connection ID once opened
+    /// * `reject` - Function called with an error if the limiter is shut down first, or the
+    ///   connection fails to open
+    /// * `priority` - Priority of the creation over the other queued ones
+    pub fn enqueue(
+        &self,
+        open: Box<dyn FnOnce() -> Result<Connection, ConnectionError>>,
+        resolve: js_sys::Function,
+        reject: js_sys::Function,
+        priority: Priority,
+    ) {
+        if self.state.borrow().shut_down {
+            let _ = reject.call1(&JsValue::null(), &Self::shut_down_error());
+            return;
+        }
+        self.state.borrow_mut().queues[priority.index()].push_back(QueuedConnection {
+            open,
+            resolve,
+            reject,
+            queued: js_sys::Date::now(),
+        });
+        self.drain();
+    }
+
+    /// Refuse new connections from now on, failing the queued creations.
+    pub fn shut_down(&self) {
+        let queued = {
+            let mut state = self.state.borrow_mut();
+            state.shut_down = true;
+            std::mem::take(&mut state.queues)
+        };
+        for queued in queued.into_iter().flatten() {
+            let _ = queued
+                .reject
+                .call1(&JsValue::null(), &Self::shut_down_error());
+        }
+    }
+
+    /// Get the error creations are failed with once the limiter is shut down.
+    fn shut_down_error() -> JsValue {
+        ConnectionError {
+            message: "Client is shutting down".to_string(),
+        }
+        .into()
+    }
+
+    /// Open queued connections while slots are free.
+    fn drain(&self) {
+        loop {
+            let queued = {
+                let mut state = self.state.borrow_mut();
+                if state.max.is_some_and(|max| state.active >= max) {
+                    return;
+                }
+                match state.pop() {
+                    Some(queued) => {
+                        state.active += 1;
+                        queued
+                    }
+                    None => return,
+                }
+            };
+
+            let connection = match (queued.open)() {
+                Ok(connection) => connection,
+                Err(e) => {
+                    self.state.borrow_mut().active -= 1;
+                    let _ = queued.reject.call1(&JsValue::null(), &e.into());
+                    continue;
+                }
+            };
+            connection
+                .setup
+                .set_queue_wait(js_sys::Date::now() - queued.queued);
+            self.track(&connection);
+            let id: u64 = connection.get_id().into();
+            self.connections.borrow_mut().push(connection);
+            queued
+                .resolve
+                .call1(&JsValue::null(), &JsValue::from(id))
+                .unwrap_throw();
+        }
+    }
+}
@@ -9,7 +9,7 @@ macro_rules! http {
     ($method:expr, $path:expr, $headers:expr, $body:expr) => {{
         let mut request = format!("{} {} HTTP/1.1\r\n", $method, $path);
 
-        let headers: Vec<crate::connection_apis::http::HttpHeader> = $headers;
+        let headers: Vec<$crate::connection_apis::http::HttpHeader> = $headers;
 
         let body: Vec<u8> = $body;
 
@@ -18,14 +18,16 @@ macro_rules! http {
         }
 
         request.push_str(&format!("Content-Length: {}\r\n\r\n", body.len()));
-        request.push_str(&String::from_utf8_lossy(body.as_slice()));
 
-        request.into_bytes()
+        // The body is appended as is, it may not be UTF-8
+        let mut request = request.into_bytes();
+        request.extend_from_slice(&body);
+        request
     }};
     ($method:expr, $path:expr, $headers:expr) => {{
         let mut request = format!("{} {} HTTP/1.1\r\n", $method, $path);
 
-        let headers: Vec<crate::connection_apis::http::HttpHeader> = $headers;
+        let headers: Vec<$crate::connection_apis::http::HttpHeader> = $headers;
 
         for header in headers {
             request.push_str(&format!("{}: {}\r\n", header.name, header.value));
@@ -37,7 +39,36 @@ macro_rules! http {
     }};
 }
 
+/// A macro to write a formatted line to the console, see `debug::log`.
+///
+/// Without the `console-log` feature the line is never formatted, and the logging code is
+/// compiled out. Otherwise it is only formatted while logging is enabled, see `set_logging`.
 #[macro_export]
 macro_rules! console_log {
-    ($($t:tt)*) => (crate::log(&format_args!($($t)*).to_string()))
+    ($($t:tt)*) => {
+        if cfg!(feature = "console-log") && $crate::debug::logging_enabled() {
+            $crate::debug::log(&format_args!($($t)*).to_string())
+        }
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::connection_apis::http::HttpHeader;
+
+    #[test]
+    fn http_keeps_binary_body() {
+        let body = vec![0x00, 0xFF, 0xC3, 0x28, 0x80];
+        let headers = vec![HttpHeader::new("Host", "a")];
+        let request = http!("POST", "/", headers, body.clone());
+        let head = b"POST / HTTP/1.1\r\nHost: a\r\nContent-Length: 5\r\n\r\n";
+        assert_eq!(request[..head.len()], head[..]);
+        assert_eq!(request[head.len()..], body[..]);
+    }
+
+    #[test]
+    fn http_without_body() {
+        let request = http!("GET", "/", vec![HttpHeader::new("Host", "a")]);
+        assert_eq!(request, b"GET / HTTP/1.1\r\nHost: a\r\n\r\n");
+    }
 }
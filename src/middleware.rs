@@ -0,0 +1,378 @@
+use std::{cell::Cell, rc::Rc};
+
+use wasm_bindgen::{convert::TryFromJsValue, prelude::*};
+use wasm_bindgen_futures::js_sys;
+
+use crate::{
+    connection::ConnectionError,
+    connection_apis::http::{HttpConnectionRequest, HttpConnectionResponse, HttpHeader},
+};
+
+/// Default header carrying the correlation ID of a request.
+pub const DEFAULT_REQUEST_ID_HEADER: &str = "X-Request-ID";
+
+/// Default name of the hop added to the `Via` header, for clients without a name.
+pub const DEFAULT_VIA_PSEUDONYM: &str = "socketguard";
+
+/// Headers describing a single hop of a message rather than the message, per RFC 7230,
+/// besides those named by its `Connection` header.
+pub const HOP_BY_HOP_HEADERS: [&str; 9] = [
+    "Connection",
+    "Keep-Alive",
+    "Proxy-Authenticate",
+    "Proxy-Authorization",
+    "Proxy-Connection",
+    "TE",
+    "Trailer",
+    "Transfer-Encoding",
+    "Upgrade",
+];
+
+/// Interceptor of the requests and responses of HTTP and HTTPS connections.
+pub trait Middleware {
+    /// Inspect or replace an outgoing request.
+    ///
+    /// # Arguments
+    ///
+    /// * `request` - Request about to be sent
+    fn on_request(
+        &self,
+        request: HttpConnectionRequest,
+    ) -> Result<HttpConnectionRequest, ConnectionError> {
+        Ok(request)
+    }
+
+    /// Inspect or replace an incoming response.
+    ///
+    /// # Arguments
+    ///
+    /// * `response` - Response about to be handed to the callback of the request
+    fn on_response(
+        &self,
+        response: HttpConnectionResponse,
+    ) -> Result<HttpConnectionResponse, ConnectionError> {
+        Ok(response)
+    }
+}
+
+/// Middleware implemented by JS functions.
+///
+/// Each function is called with the request or response and must return the object to use
+/// in its place, which may be the same one.
+pub struct JsMiddleware {
+    /// Function called with outgoing requests
+    on_request: Option<js_sys::Function>,
+    /// Function called with incoming responses
+    on_response: Option<js_sys::Function>,
+}
+
+impl JsMiddleware {
+    /// Create a new middleware from JS functions.
+    ///
+    /// # Arguments
+    ///
+    /// * `on_request` - Function called with outgoing requests
+    /// * `on_response` - Function called with incoming responses
+    pub fn new(
+        on_request: Option<js_sys::Function>,
+        on_response: Option<js_sys::Function>,
+    ) -> Self {
+        Self {
+            on_request,
+            on_response,
+        }
+    }
+}
+
+/// Call a JS middleware function and convert its result back.
+fn call<T: TryFromJsValue + Into<JsValue>>(
+    function: &Option<js_sys::Function>,
+    value: T,
+    kind: &str,
+) -> Result<T, ConnectionError> {
+    let function = match function {
+        Some(function) => function,
+        None => return Ok(value),
+    };
+    let result = function
+        .call1(&JsValue::null(), &value.into())
+        .map_err(|e| ConnectionError {
+            message: format!("Middleware threw: {:?}", e),
+        })?;
+    T::try_from_js_value(result).map_err(|_| ConnectionError {
+        message: format!("Middleware must return a {}", kind),
+    })
+}
+
+impl Middleware for JsMiddleware {
+    fn on_request(
+        &self,
+        request: HttpConnectionRequest,
+    ) -> Result<HttpConnectionRequest, ConnectionError> {
+        call(&self.on_request, request, "HttpConnectionRequest")
+    }
+
+    fn on_response(
+        &self,
+        response: HttpConnectionResponse,
+    ) -> Result<HttpConnectionResponse, ConnectionError> {
+        call(&self.on_response, response, "HttpConnectionResponse")
+    }
+}
+
+thread_local! {
+    /// Number of request IDs generated so far
+    static REQUEST_ID_COUNT: Cell<u32> = const { Cell::new(0) };
+}
+
+/// Generate a new request ID from the current time, a counter and a random part.
+pub fn generate_request_id() -> String {
+    let count = REQUEST_ID_COUNT.with(|c| {
+        let count = c.get().wrapping_add(1);
+        c.set(count);
+        count
+    });
+    let random = (js_sys::Math::random() * f64::from(u32::MAX)) as u32;
+    format!(
+        "{:x}-{:x}-{:08x}",
+        js_sys::Date::now() as u64,
+        count,
+        random
+    )
+}
+
+/// Middleware assigning a unique correlation ID to every request.
+///
+/// The ID is sent in a header so it can be matched with the logs of the server, and is
+/// returned on the response. A request already carrying the header or an ID keeps it.
+pub struct RequestIdMiddleware {
+    /// Header carrying the ID
+    header: String,
+}
+
+impl RequestIdMiddleware {
+    /// Create a new request ID middleware.
+    ///
+    /// # Arguments
+    ///
+    /// * `header` - Header carrying the ID
+    pub fn new(header: String) -> Self {
+        Self { header }
+    }
+}
+
+impl Middleware for RequestIdMiddleware {
+    fn on_request(
+        &self,
+        mut request: HttpConnectionRequest,
+    ) -> Result<HttpConnectionRequest, ConnectionError> {
+        let existing = request
+            .headers
+            .iter()
+            .find(|h| h.name().eq_ignore_ascii_case(&self.header))
+            .map(|h| h.value().to_string());
+        let id = match existing {
+            Some(id) => id,
+            None => {
+                let id = request
+                    .request_id
+                    .take()
+                    .unwrap_or_else(generate_request_id);
+                request.headers.push(HttpHeader::new(&self.header, &id));
+                id
+            }
+        };
+        request.request_id = Some(id);
+        Ok(request)
+    }
+}
+
+/// Remove the hop-by-hop headers of a message, and those its `Connection` header names.
+fn strip_hop_by_hop(headers: &mut Vec<HttpHeader>) {
+    let named: Vec<String> = headers
+        .iter()
+        .filter(|h| h.name().eq_ignore_ascii_case("Connection"))
+        .flat_map(|h| h.value().split(','))
+        .map(|token| token.trim().to_string())
+        .filter(|token| !token.is_empty())
+        .collect();
+    headers.retain(|h| {
+        !HOP_BY_HOP_HEADERS
+            .iter()
+            .any(|name| h.name().eq_ignore_ascii_case(name))
+            && !named.iter().any(|name| h.name().eq_ignore_ascii_case(name))
+    });
+}
+
+/// Middleware removing hop-by-hop headers from requests and responses, per RFC 7230, for
+/// clients forwarding messages from or to another HTTP layer, e.g. a fetch shim.
+///
+/// Headers of the request set for the hop to the proxy, like `Proxy-Authorization` for the
+/// upstream HTTP proxy, are added after the middlewares and kept. Keep-alive is applied
+/// from the response before it reaches the middlewares.
+pub struct HopByHopMiddleware;
+
+impl Middleware for HopByHopMiddleware {
+    fn on_request(
+        &self,
+        mut request: HttpConnectionRequest,
+    ) -> Result<HttpConnectionRequest, ConnectionError> {
+        strip_hop_by_hop(&mut request.headers);
+        Ok(request)
+    }
+
+    fn on_response(
+        &self,
+        mut response: HttpConnectionResponse,
+    ) -> Result<HttpConnectionResponse, ConnectionError> {
+        strip_hop_by_hop(&mut response.headers);
+        Ok(response)
+    }
+}
+
+/// Middleware identifying the client as a hop of the requests it sends, for the server to
+/// tell tunneled traffic apart in its logs.
+///
+/// The hop is appended to the `Via` header, as `1.1 <pseudonym>`. Forwarding metadata is
+/// only sent when configured: the original client is appended to `X-Forwarded-For`, while
+/// `X-Forwarded-Host` and `X-Forwarded-Proto` describe the first hop and are kept if set.
+pub struct ViaMiddleware {
+    /// Name of the hop, the name of the client sending the request if not given
+    pseudonym: Option<String>,
+    /// Address of the original client
+    forwarded_for: Option<String>,
+    /// Original `Host` of the request
+    forwarded_host: Option<String>,
+    /// Original protocol of the request
+    forwarded_proto: Option<String>,
+}
+
+impl ViaMiddleware {
+    /// Create a new via middleware.
+    ///
+    /// # Arguments
+    ///
+    /// * `pseudonym` - Name of the hop, a token
+    /// * `forwarded_for` - Address of the original client
+    /// * `forwarded_host` - Original `Host` of the request
+    /// * `forwarded_proto` - Original protocol of the request, e.g. `https`
+    pub fn new(
+        pseudonym: Option<String>,
+        forwarded_for: Option<String>,
+        forwarded_host: Option<String>,
+        forwarded_proto: Option<String>,
+    ) -> Result<Self, ConnectionError> {
+        let is_tchar = |c: char| c.is_ascii_alphanumeric() || "!#$%&'*+-.^_`|~".contains(c);
+        if let Some(pseudonym) = &pseudonym {
+            if pseudonym.is_empty() || !pseudonym.chars().all(is_tchar) {
+                return Err(ConnectionError {
+                    message: format!("Invalid Via pseudonym: {:?}", pseudonym),
+                });
+            }
+        }
+        for value in [&forwarded_for, &forwarded_host, &forwarded_proto]
+            .into_iter()
+            .flatten()
+        {
+            if value.is_empty() || value.chars().any(|c| c.is_control()) {
+                return Err(ConnectionError {
+                    message: format!("Invalid forwarding header value: {:?}", value),
+                });
+            }
+        }
+        Ok(Self {
+            pseudonym,
+            forwarded_for,
+            forwarded_host,
+            forwarded_proto,
+        })
+    }
+}
+
+/// Append an element to a comma-separated list header, adding the header if missing.
+fn append_to_list(headers: &mut Vec<HttpHeader>, name: &str, element: &str) {
+    match headers
+        .iter_mut()
+        .find(|h| h.name().eq_ignore_ascii_case(name))
+    {
+        Some(header) => {
+            let value = format!("{}, {}", header.value(), element);
+            *header = HttpHeader::new(header.name(), &value);
+        }
+        None => headers.push(HttpHeader::new(name, element)),
+    }
+}
+
+impl Middleware for ViaMiddleware {
+    fn on_request(
+        &self,
+        mut request: HttpConnectionRequest,
+    ) -> Result<HttpConnectionRequest, ConnectionError> {
+        let pseudonym = self
+            .pseudonym
+            .as_deref()
+            .or(request.client_name.as_deref())
+            .filter(|name| !name.is_empty() && !name.contains(char::is_whitespace))
+            .unwrap_or(DEFAULT_VIA_PSEUDONYM);
+        let hop = format!("1.1 {}", pseudonym);
+        append_to_list(&mut request.headers, "Via", &hop);
+        if let Some(forwarded_for) = &self.forwarded_for {
+            append_to_list(&mut request.headers, "X-Forwarded-For", forwarded_for);
+        }
+        let forwarded = [
+            ("X-Forwarded-Host", &self.forwarded_host),
+            ("X-Forwarded-Proto", &self.forwarded_proto),
+        ];
+        for (name, value) in forwarded
+            .into_iter()
+            .filter_map(|(name, value)| value.as_ref().map(|v| (name, v)))
+        {
+            if !request
+                .headers
+                .iter()
+                .any(|h| h.name().eq_ignore_ascii_case(name))
+            {
+                request.headers.push(HttpHeader::new(name, value));
+            }
+        }
+        Ok(request)
+    }
+}
+
+/// Ordered middlewares of a client.
+///
+/// Requests go through the middlewares in registration order, and responses in reverse
+/// order, so the first middleware registered sees the request first and the response last.
+#[derive(Clone, Default)]
+pub struct Pipeline {
+    /// Registered middlewares
+    middlewares: Vec<Rc<dyn Middleware>>,
+}
+
+impl Pipeline {
+    /// Add a middleware at the end of the pipeline.
+    pub fn push(&mut self, middleware: Rc<dyn Middleware>) {
+        self.middlewares.push(middleware);
+    }
+
+    /// Run a request through the pipeline.
+    pub fn on_request(
+        &self,
+        request: HttpConnectionRequest,
+    ) -> Result<HttpConnectionRequest, ConnectionError> {
+        self.middlewares
+            .iter()
+            .try_fold(request, |request, m| m.on_request(request))
+    }
+
+    /// Run a response through the pipeline.
+    pub fn on_response(
+        &self,
+        response: HttpConnectionResponse,
+    ) -> Result<HttpConnectionResponse, ConnectionError> {
+        self.middlewares
+            .iter()
+            .rev()
+            .try_fold(response, |response, m| m.on_response(response))
+    }
+}
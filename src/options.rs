@@ -0,0 +1,148 @@
+use wasm_bindgen::prelude::*;
+
+use crate::{
+    connection::ConnectionError, connection_apis::tcp::TcpOptions, framing::Framing, TLSVersion,
+};
+
+/// Parse the name of a TLS version, e.g. `tls1_2`.
+pub fn parse_tls_version(version: &str) -> Result<TLSVersion, ConnectionError> {
+    TLSVersion::from_string(&version.to_lowercase()).ok_or_else(|| ConnectionError {
+        message: format!("Unknown TLS version: {}", version),
+    })
+}
+
+/// Options of a connection created with `Client::create_connection_with_options`.
+///
+/// Every option left unset keeps the behavior of the plain `create_*_connection` functions.
+#[wasm_bindgen]
+#[derive(Clone, Default)]
+pub struct ConnectionOptions {
+    /// Time to wait for the connection to open in ms, forever if unset
+    timeout: Option<f64>,
+    /// WebSocket subprotocols offered after those of the framing
+    subprotocols: Vec<String>,
+    /// Extra query parameters of the proxy url
+    query: Vec<(String, String)>,
+    /// Lowest and highest TLS versions of HTTPS connections, the highest supported if unset
+    tls_versions: Option<(TLSVersion, TLSVersion)>,
+    /// Framing of the connection, that of the client if unset
+    framing: Option<Framing>,
+    /// Labels attached to the connection
+    tags: Vec<String>,
+    /// Socket options of TCP connections
+    tcp: Option<TcpOptions>,
+    /// Whether the connection may be made to a port blocked by `Client::set_block_bad_ports`
+    allow_bad_port: bool,
+}
+
+#[wasm_bindgen]
+impl ConnectionOptions {
+    /// Create new connection options, matching the behavior of `create_*_connection`.
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the time to wait for the connection to open in ms.
+    #[wasm_bindgen]
+    pub fn set_timeout(&mut self, millis: f64) {
+        self.timeout = Some(millis);
+    }
+
+    /// Offer a WebSocket subprotocol to the proxy, after those of the framing.
+    #[wasm_bindgen]
+    pub fn add_subprotocol(&mut self, subprotocol: String) {
+        self.subprotocols.push(subprotocol);
+    }
+
+    /// Attach a query parameter to the proxy url.
+    #[wasm_bindgen]
+    pub fn add_query_param(&mut self, name: String, value: String) {
+        self.query.push((name, value));
+    }
+
+    /// Set the TLS version of HTTPS connections, e.g. `tls1_2` or `tls1_3`.
+    #[wasm_bindgen]
+    pub fn set_tls_version(&mut self, version: &str) -> Result<(), ConnectionError> {
+        let version = parse_tls_version(version)?;
+        self.tls_versions = Some((version, version));
+        Ok(())
+    }
+
+    /// Set the range of TLS versions offered by HTTPS connections, e.g. `tls1_2` to `tls1_3`.
+    #[wasm_bindgen]
+    pub fn set_tls_version_range(&mut self, min: &str, max: &str) -> Result<(), ConnectionError> {
+        self.tls_versions = Some((parse_tls_version(min)?, parse_tls_version(max)?));
+        Ok(())
+    }
+
+    /// Set how bytes are carried in the frames exchanged with the proxy.
+    #[wasm_bindgen]
+    pub fn set_framing(&mut self, framing: Framing) {
+        self.framing = Some(framing);
+    }
+
+    /// Set the labels attached to the connection.
+    #[wasm_bindgen]
+    pub fn set_tags(&mut self, tags: Vec<String>) {
+        self.tags = tags;
+    }
+
+    /// Set the socket options of TCP connections.
+    #[wasm_bindgen]
+    pub fn set_tcp_options(&mut self, options: &TcpOptions) {
+        self.tcp = Some(options.clone());
+    }
+
+    /// Allow the connection to be made to a port blocked by `Client::set_block_bad_ports`.
+    #[wasm_bindgen]
+    pub fn set_allow_bad_port(&mut self, allow: bool) {
+        self.allow_bad_port = allow;
+    }
+}
+
+impl ConnectionOptions {
+    /// Get the time to wait for the connection to open in ms, if limited.
+    pub fn timeout(&self) -> Option<f64> {
+        self.timeout
+    }
+
+    /// Get the WebSocket subprotocols offered after those of the framing.
+    pub fn subprotocols(&self) -> &[String] {
+        &self.subprotocols
+    }
+
+    /// Get the extra query parameters of the proxy url, including those of the TCP options.
+    pub fn query(&self) -> Vec<(String, String)> {
+        let mut query = self.query.clone();
+        if let Some(tcp) = &self.tcp {
+            query.extend(tcp.to_query());
+        }
+        query
+    }
+
+    /// Get the lowest and highest TLS versions of HTTPS connections, if set.
+    pub fn tls_versions(&self) -> Option<(TLSVersion, TLSVersion)> {
+        self.tls_versions
+    }
+
+    /// Get the framing of the connection, if set.
+    pub fn framing(&self) -> Option<Framing> {
+        self.framing
+    }
+
+    /// Get the labels attached to the connection.
+    pub fn tags(&self) -> &[String] {
+        &self.tags
+    }
+
+    /// Get the socket options of TCP connections, if set.
+    pub fn tcp(&self) -> Option<&TcpOptions> {
+        self.tcp.as_ref()
+    }
+
+    /// Check if the connection may be made to a port blocked by the client.
+    pub fn allow_bad_port(&self) -> bool {
+        self.allow_bad_port
+    }
+}
@@ -0,0 +1,297 @@
+use std::{
+    cell::{Cell, RefCell},
+    collections::BTreeMap,
+    fmt,
+    rc::Rc,
+};
+
+use wasm_bindgen::prelude::*;
+use wasm_bindgen_futures::js_sys::{self, Promise};
+use web_sys::{AddEventListenerOptions, CloseEvent, WebSocket};
+
+use crate::{connection::ConnectionError, console_log, set_timeout, usage::BufferUsage};
+
+/// Close code of connections closed by the client, as sent by `WebSocket.close()`.
+pub const CLOSE_NORMAL: u16 = 1000;
+
+/// Error pending requests and promises of a connection are rejected with once it closes.
+#[wasm_bindgen]
+#[derive(Clone, Debug, PartialEq)]
+pub struct ConnectionClosed {
+    /// Close code of the connection
+    code: u16,
+    /// Reason the connection was closed for, possibly empty
+    reason: String,
+}
+
+impl ConnectionClosed {
+    /// Create the error of a connection closed with the given code and reason.
+    pub(crate) fn new(code: u16, reason: &str) -> Self {
+        Self {
+            code,
+            reason: reason.to_string(),
+        }
+    }
+
+    /// Create the error of a connection from the event of its socket closing.
+    pub(crate) fn from_event(event: &CloseEvent) -> Self {
+        Self::new(event.code(), &event.reason())
+    }
+}
+
+#[wasm_bindgen]
+impl ConnectionClosed {
+    /// Get the close code of the connection, `1000` if it was closed by the client.
+    #[wasm_bindgen]
+    pub fn get_code(&self) -> u16 {
+        self.code
+    }
+
+    /// Get the reason the connection was closed for, possibly empty.
+    #[wasm_bindgen]
+    pub fn get_reason(&self) -> String {
+        self.reason.clone()
+    }
+
+    /// Get a message describing the error.
+    #[wasm_bindgen]
+    pub fn get_message(&self) -> String {
+        match self.reason.is_empty() {
+            true => format!("Connection closed with code {}", self.code),
+            false => format!("Connection closed with code {}: {}", self.code, self.reason),
+        }
+    }
+
+    /// Get a message describing the error, so the error reads well when logged.
+    #[wasm_bindgen(js_name = toString)]
+    pub fn to_js_string(&self) -> String {
+        self.get_message()
+    }
+}
+
+/// Function releasing the resources of a request, called with the error of the connection
+/// closing if it is cancelled, or `None` once it completes.
+type Release = Box<dyn FnOnce(Option<&ConnectionClosed>)>;
+
+/// Function adding the memory held by the buffers of a request to a `BufferUsage`.
+type UsageProbe = Box<dyn Fn(&mut BufferUsage)>;
+
+/// Requests in flight on a connection, cancelled together once it closes.
+///
+/// Requests are cancelled synchronously when the connection is closed or dropped by the
+/// client, or once its socket closes otherwise, e.g. when the remote end closes it.
+#[derive(Clone, Default)]
+pub(crate) struct PendingRequests {
+    /// ID of the next request
+    next_id: Rc<Cell<u32>>,
+    /// Requests in flight, by ID, with what releases them once set
+    requests: Rc<RefCell<BTreeMap<u32, Option<Release>>>>,
+    /// Functions measuring the buffers of the requests in flight, by ID, once set
+    probes: Rc<RefCell<BTreeMap<u32, UsageProbe>>>,
+    /// Error requests were cancelled with, once the connection closed
+    closed: Rc<RefCell<Option<ConnectionClosed>>>,
+    /// Whether new requests are refused, as the client is shutting down
+    refused: Rc<Cell<bool>>,
+    /// Functions called once no request is in flight anymore
+    idle: Rc<RefCell<Vec<js_sys::Function>>>,
+}
+
+impl PendingRequests {
+    /// Track the requests of a connection, cancelling them once its socket closes.
+    ///
+    /// # Arguments
+    ///
+    /// * `socket` - Socket of the connection
+    pub(crate) fn watch(socket: &WebSocket) -> Self {
+        let pending = Self::default();
+        let target = pending.clone();
+        let onclose = Closure::once_into_js(move |event: CloseEvent| {
+            target.cancel_all(ConnectionClosed::from_event(&event));
+        });
+        socket
+            .add_event_listener_with_callback_and_add_event_listener_options(
+                "close",
+                onclose.unchecked_ref(),
+                AddEventListenerOptions::new().once(true),
+            )
+            .unwrap_throw();
+        pending
+    }
+
+    /// Start tracking a request.
+    ///
+    /// Fails if new requests are refused, see `refuse_new`.
+    pub(crate) fn start(&self) -> Result<PendingRequest, ConnectionError> {
+        if self.refused.get() {
+            return Err(ConnectionError {
+                message: "Client is shutting down".to_string(),
+            });
+        }
+        let id = self.next_id.get();
+        self.next_id.set(id.wrapping_add(1));
+        if self.closed.borrow().is_none() {
+            self.requests.borrow_mut().insert(id, None);
+        }
+        Ok(PendingRequest {
+            id,
+            pending: self.clone(),
+        })
+    }
+
+    /// Refuse new requests, letting those in flight complete.
+    pub(crate) fn refuse_new(&self) {
+        self.refused.set(true);
+    }
+
+    /// Wait for no request to be in flight anymore.
+    ///
+    /// # Returns
+    ///
+    /// A promise resolving once the requests in flight completed or were cancelled, right
+    /// away if there are none.
+    pub(crate) fn idle(&self) -> Promise {
+        Promise::new(&mut |resolve, _| match self.len() {
+            0 => {
+                let _ = resolve.call0(&JsValue::null());
+            }
+            _ => self.idle.borrow_mut().push(resolve),
+        })
+    }
+
+    /// Call the functions waiting for no request to be in flight, if none is.
+    fn notify_idle(&self) {
+        if self.len() > 0 {
+            return;
+        }
+        let idle = std::mem::take(&mut *self.idle.borrow_mut());
+        for resolve in idle {
+            let _ = resolve.call0(&JsValue::null());
+        }
+    }
+
+    /// Get the number of requests in flight.
+    pub(crate) fn len(&self) -> usize {
+        self.requests.borrow().len()
+    }
+
+    /// Add the memory held by the buffers of the requests in flight to a `BufferUsage`.
+    pub(crate) fn measure(&self, usage: &mut BufferUsage) {
+        for probe in self.probes.borrow().values() {
+            probe(usage);
+        }
+    }
+
+    /// Cancel every request in flight, unless they were already cancelled.
+    ///
+    /// # Arguments
+    ///
+    /// * `closed` - Error of the connection closing, passed to every request
+    pub(crate) fn cancel_all(&self, closed: ConnectionClosed) {
+        if self.closed.borrow().is_some() {
+            return;
+        }
+        *self.closed.borrow_mut() = Some(closed.clone());
+        // Taken first, as releasing a request may drop handles of this connection
+        let requests = std::mem::take(&mut *self.requests.borrow_mut());
+        self.probes.borrow_mut().clear();
+        if !requests.is_empty() {
+            console_log!(
+                "Cancelling {} pending requests: {}",
+                requests.len(),
+                closed.get_message()
+            );
+        }
+        for release in requests.into_values().flatten() {
+            release(Some(&closed));
+        }
+        self.notify_idle();
+    }
+}
+
+impl fmt::Debug for PendingRequests {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("PendingRequests")
+            .field("requests", &self.len())
+            .field("closed", &self.closed.borrow())
+            .finish()
+    }
+}
+
+/// Request tracked by `PendingRequests`, until it completes or its connection closes.
+#[derive(Clone)]
+pub(crate) struct PendingRequest {
+    /// ID of the request
+    id: u32,
+    /// Requests of the connection
+    pending: PendingRequests,
+}
+
+impl PendingRequest {
+    /// Set what releases the resources of the request, e.g. the listeners of its response.
+    ///
+    /// Any function set before is called as if the request completed. If the connection is
+    /// already closed, `release` is called with its error right away.
+    ///
+    /// # Arguments
+    ///
+    /// * `release` - Function called with the error of the connection closing if the request
+    ///   is cancelled, or with `None` once it completes
+    pub(crate) fn on_release(&self, release: impl FnOnce(Option<&ConnectionClosed>) + 'static) {
+        let closed = self.pending.closed.borrow().clone();
+        if let Some(closed) = closed {
+            release(Some(&closed));
+            return;
+        }
+        let previous = self
+            .pending
+            .requests
+            .borrow_mut()
+            .insert(self.id, Some(Box::new(release)));
+        if let Some(previous) = previous.flatten() {
+            previous(None);
+        }
+    }
+
+    /// Set what measures the buffers of the request, replacing any function set before.
+    ///
+    /// # Arguments
+    ///
+    /// * `probe` - Function adding the memory held by the buffers of the request to a
+    ///   `BufferUsage`, called until the request completes or is cancelled
+    pub(crate) fn set_probe(&self, probe: impl Fn(&mut BufferUsage) + 'static) {
+        if self.pending.closed.borrow().is_some() {
+            return;
+        }
+        self.pending
+            .probes
+            .borrow_mut()
+            .insert(self.id, Box::new(probe));
+    }
+
+    /// Mark the request as complete, releasing its resources.
+    pub(crate) fn finish(&self) {
+        self.pending.probes.borrow_mut().remove(&self.id);
+        let release = self.pending.requests.borrow_mut().remove(&self.id);
+        if let Some(release) = release.flatten() {
+            release(None);
+        }
+        self.pending.notify_idle();
+    }
+}
+
+/// Remove a listener from a socket, then drop it once any call of it in progress returns.
+///
+/// # Arguments
+///
+/// * `socket` - Socket the listener was added to
+/// * `event` - Event the listener was added for
+/// * `listener` - Listener to remove
+pub(crate) fn release_listener<T: ?Sized + 'static>(
+    socket: &WebSocket,
+    event: &str,
+    listener: Closure<T>,
+) {
+    let _ = socket.remove_event_listener_with_callback(event, listener.as_ref().unchecked_ref());
+    // Deferred, as the listener may be releasing itself
+    set_timeout(&Closure::once_into_js(move || drop(listener)), 0.0);
+}
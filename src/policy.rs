@@ -0,0 +1,173 @@
+use wasm_bindgen::prelude::*;
+use wasm_bindgen_futures::js_sys::{self, RegExp};
+
+use crate::{callbacks::HostRules, connection::ConnectionError, SocketCapability};
+
+/// Ports HTTP and HTTPS connections are never made to by browsers, as listed by the "bad port"
+/// definition of the Fetch standard. Their services could be confused by an HTTP request.
+pub const BAD_PORTS: [u16; 82] = [
+    1, 7, 9, 11, 13, 15, 17, 19, 20, 21, 22, 23, 25, 37, 42, 43, 53, 69, 77, 79, 87, 95, 101, 102,
+    103, 104, 109, 110, 111, 113, 115, 117, 119, 123, 135, 137, 139, 143, 161, 179, 389, 427, 465,
+    512, 513, 514, 515, 526, 530, 531, 532, 540, 548, 554, 556, 563, 587, 601, 636, 989, 990, 993,
+    995, 1719, 1720, 1723, 2049, 3659, 4045, 4190, 5060, 5061, 6000, 6566, 6665, 6666, 6667, 6668,
+    6669, 6679, 6697, 10080,
+];
+
+/// Rule matching the addresses of hosts.
+#[derive(Clone, Debug)]
+enum HostRule {
+    /// Pattern where `*` matches any run of characters and `?` any single character,
+    /// compared ignoring ASCII case
+    Glob(String),
+    /// Regular expression, tested as is
+    Regex(RegExp),
+}
+
+impl HostRule {
+    /// Parse a rule, a glob pattern string or a `RegExp`.
+    fn from_js(value: JsValue) -> Result<Self, ConnectionError> {
+        if let Some(pattern) = value.as_string() {
+            return Ok(Self::Glob(pattern.to_ascii_lowercase()));
+        }
+        value
+            .dyn_into::<RegExp>()
+            .map(Self::Regex)
+            .map_err(|value| ConnectionError {
+                message: format!("Invalid host rule: {:?}", value),
+            })
+    }
+
+    /// Check if the rule matches a host, or its address with the port.
+    fn matches(&self, host: &str, addr: &str) -> bool {
+        match self {
+            Self::Glob(pattern) => {
+                glob_match(pattern, &host.to_ascii_lowercase())
+                    || glob_match(pattern, &addr.to_ascii_lowercase())
+            }
+            Self::Regex(regex) => [host, addr].iter().any(|text| {
+                // Global and sticky expressions resume from their last match otherwise
+                regex.set_last_index(0);
+                regex.test(text)
+            }),
+        }
+    }
+}
+
+/// Match a glob pattern, where `*` matches any run of characters and `?` any single one.
+fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    let (mut p, mut t) = (0, 0);
+    // Position of the last `*` and of the text it was tried at, to backtrack to
+    let mut star: Option<(usize, usize)> = None;
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, t));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match star {
+                Some((star_p, star_t)) => {
+                    p = star_p + 1;
+                    t = star_t + 1;
+                    star = Some((star_p, star_t + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+/// Split an address into its host and port, keeping the brackets of IPv6 hosts.
+fn split_host(addr: &str) -> (&str, Option<&str>) {
+    match addr.rsplit_once(':') {
+        Some((host, port)) if port.chars().all(|c| c.is_ascii_digit()) && !host.ends_with(':') => {
+            (host, Some(port))
+        }
+        _ => (addr, None),
+    }
+}
+
+/// Hosts and ports connections may be created to, checked before the proxy is dialed.
+///
+/// A rule matches a host if it matches either its name, e.g. `*.example.com`, or its address
+/// with the port, e.g. `*:443`. An address is rejected if any deny rule matches it, or if there
+/// are allow rules and none of them matches it. HTTP and HTTPS addresses may also be rejected
+/// for their port being one of `BAD_PORTS`.
+#[derive(Clone, Debug, Default)]
+pub struct HostPolicy {
+    /// Rules of which at least one must match, unless empty
+    allow: Vec<HostRule>,
+    /// Rules none of which may match
+    deny: Vec<HostRule>,
+    /// Whether HTTP and HTTPS connections to one of `BAD_PORTS` are rejected
+    block_bad_ports: bool,
+}
+
+impl HostPolicy {
+    /// Parse rules given from JS.
+    fn parse(rules: Option<HostRules>) -> Result<Vec<HostRule>, ConnectionError> {
+        rules.map_or(Ok(Vec::new()), |rules| {
+            js_sys::Array::from(&rules)
+                .iter()
+                .map(HostRule::from_js)
+                .collect()
+        })
+    }
+
+    /// Set the rules of which at least one must match, or `None` to allow every host.
+    pub fn set_allow(&mut self, rules: Option<HostRules>) -> Result<(), ConnectionError> {
+        self.allow = Self::parse(rules)?;
+        Ok(())
+    }
+
+    /// Set the rules none of which may match, or `None` to deny no host.
+    pub fn set_deny(&mut self, rules: Option<HostRules>) -> Result<(), ConnectionError> {
+        self.deny = Self::parse(rules)?;
+        Ok(())
+    }
+
+    /// Set whether HTTP and HTTPS connections to one of `BAD_PORTS` are rejected.
+    pub fn set_block_bad_ports(&mut self, block: bool) {
+        self.block_bad_ports = block;
+    }
+
+    /// Check if connections may be created to an address.
+    ///
+    /// # Arguments
+    ///
+    /// * `protocol` - Protocol of the connections
+    /// * `addr` - Address to connect to, as `host:port`
+    pub fn check(&self, protocol: SocketCapability, addr: &str) -> Result<(), ConnectionError> {
+        let (host, port) = split_host(addr);
+        if self.block_bad_ports
+            && matches!(
+                protocol,
+                SocketCapability::HTTP | SocketCapability::HTTPS(_)
+            )
+        {
+            if let Some(port) = port
+                .and_then(|port| port.parse().ok())
+                .filter(|port| BAD_PORTS.contains(port))
+            {
+                return Err(ConnectionError {
+                    message: format!("Connections to port {} are blocked: {}", port, addr),
+                });
+            }
+        }
+        let matches = |rule: &HostRule| rule.matches(host, addr);
+        if self.deny.iter().any(matches)
+            || (!self.allow.is_empty() && !self.allow.iter().any(matches))
+        {
+            return Err(ConnectionError {
+                message: format!("Host not allowed by policy: {}", addr),
+            });
+        }
+        Ok(())
+    }
+}
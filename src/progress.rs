@@ -0,0 +1,207 @@
+use std::{cell::RefCell, rc::Rc};
+
+use wasm_bindgen::prelude::*;
+use wasm_bindgen_futures::js_sys;
+
+use crate::{tls::HandshakeStage, TLSVersion};
+
+/// What a progress event reports on.
+#[wasm_bindgen]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ProgressDirection {
+    /// Body of a response being received
+    Download,
+    /// Request being sent
+    Upload,
+    /// TLS handshake being performed
+    Handshake,
+}
+
+/// Progress of a transfer, shaped like the DOM `ProgressEvent` so UI code can share one handler.
+#[wasm_bindgen]
+#[derive(Copy, Clone, Debug)]
+pub struct Progress {
+    /// Number of bytes transferred so far
+    loaded: f64,
+    /// Total number of bytes to transfer, 0 if unknown
+    total: f64,
+    /// Whether the total is known
+    length_computable: bool,
+    /// What is being transferred
+    direction: ProgressDirection,
+}
+
+impl Progress {
+    /// Create a new progress of a transfer of known length.
+    ///
+    /// # Arguments
+    ///
+    /// * `direction` - What is being transferred
+    /// * `loaded` - Number of bytes transferred so far
+    /// * `total` - Total number of bytes to transfer
+    pub fn new(direction: ProgressDirection, loaded: usize, total: usize) -> Self {
+        Self {
+            loaded: loaded as f64,
+            total: total as f64,
+            length_computable: true,
+            direction,
+        }
+    }
+
+    /// Create a new progress of a transfer of unknown length.
+    ///
+    /// # Arguments
+    ///
+    /// * `direction` - What is being transferred
+    /// * `loaded` - Number of bytes transferred so far
+    pub fn indeterminate(direction: ProgressDirection, loaded: usize) -> Self {
+        Self {
+            loaded: loaded as f64,
+            total: 0.0,
+            length_computable: false,
+            direction,
+        }
+    }
+}
+
+#[wasm_bindgen]
+impl Progress {
+    /// Get the number of bytes transferred so far.
+    #[wasm_bindgen(getter)]
+    pub fn loaded(&self) -> f64 {
+        self.loaded
+    }
+
+    /// Get the total number of bytes to transfer, 0 if unknown.
+    #[wasm_bindgen(getter)]
+    pub fn total(&self) -> f64 {
+        self.total
+    }
+
+    /// Check if the total number of bytes to transfer is known.
+    #[wasm_bindgen(getter, js_name = lengthComputable)]
+    pub fn length_computable(&self) -> bool {
+        self.length_computable
+    }
+
+    /// Get what is being transferred.
+    #[wasm_bindgen(getter)]
+    pub fn direction(&self) -> ProgressDirection {
+        self.direction
+    }
+}
+
+/// Milestone reached by the TLS handshake of a request.
+#[wasm_bindgen]
+#[derive(Clone, Debug)]
+pub struct HandshakeEvent {
+    /// Milestone reached
+    stage: HandshakeStage,
+    /// TLS version negotiated, once the handshake is complete
+    version: Option<TLSVersion>,
+    /// Name of the cipher suite negotiated, once the handshake is complete
+    cipher_suite: Option<String>,
+    /// Time since the request was sent, in ms
+    elapsed: f64,
+}
+
+impl HandshakeEvent {
+    /// Create a new handshake event.
+    ///
+    /// # Arguments
+    ///
+    /// * `stage` - Milestone reached
+    /// * `version` - TLS version negotiated, if known
+    /// * `cipher_suite` - Name of the cipher suite negotiated, if known
+    /// * `elapsed` - Time since the request was sent, in ms
+    pub fn new(
+        stage: HandshakeStage,
+        version: Option<TLSVersion>,
+        cipher_suite: Option<String>,
+        elapsed: f64,
+    ) -> Self {
+        Self {
+            stage,
+            version,
+            cipher_suite,
+            elapsed,
+        }
+    }
+}
+
+#[wasm_bindgen]
+impl HandshakeEvent {
+    /// Get the milestone reached.
+    #[wasm_bindgen(getter)]
+    pub fn stage(&self) -> HandshakeStage {
+        self.stage
+    }
+
+    /// Get the TLS version negotiated, e.g. `tls1_3`, once the handshake is complete.
+    #[wasm_bindgen(getter)]
+    pub fn version(&self) -> Option<String> {
+        self.version.map(|v| v.to_string())
+    }
+
+    /// Get the name of the cipher suite negotiated, e.g. `TLS13_AES_128_GCM_SHA256`, once the
+    /// handshake is complete.
+    #[wasm_bindgen(getter, js_name = cipherSuite)]
+    pub fn cipher_suite(&self) -> Option<String> {
+        self.cipher_suite.clone()
+    }
+
+    /// Get the time since the request was sent, in ms.
+    #[wasm_bindgen(getter)]
+    pub fn elapsed(&self) -> f64 {
+        self.elapsed
+    }
+}
+
+/// Handle calling the progress callbacks of a connection, shared by all its clones.
+#[derive(Clone, Debug, Default)]
+pub struct ProgressReporter {
+    /// Function called with every progress, if set
+    callback: Rc<RefCell<Option<js_sys::Function>>>,
+    /// Function called with every handshake milestone, if set
+    handshake: Rc<RefCell<Option<js_sys::Function>>>,
+}
+
+impl ProgressReporter {
+    /// Set the function called with every progress, or `None` to stop reporting.
+    pub fn set(&self, callback: Option<js_sys::Function>) {
+        *self.callback.borrow_mut() = callback;
+    }
+
+    /// Set the function called with every handshake milestone, or `None` to stop reporting.
+    pub fn set_handshake(&self, callback: Option<js_sys::Function>) {
+        *self.handshake.borrow_mut() = callback;
+    }
+
+    /// Call the handshake callback, if set.
+    pub fn report_handshake(&self, event: HandshakeEvent) {
+        // Cloned, so the callback may replace itself
+        let callback = self.handshake.borrow().clone();
+        if let Some(callback) = callback {
+            let _ = callback.call1(&JsValue::null(), &JsValue::from(event));
+        }
+    }
+
+    /// Get a function reporting the number of bytes transferred so far and the total.
+    ///
+    /// # Arguments
+    ///
+    /// * `direction` - What is being transferred
+    pub fn sink(&self, direction: ProgressDirection) -> Box<dyn FnMut(usize, usize)> {
+        let reporter = self.clone();
+        Box::new(move |loaded, total| reporter.report(Progress::new(direction, loaded, total)))
+    }
+
+    /// Call the progress callback, if set.
+    pub fn report(&self, progress: Progress) {
+        // Cloned, so the callback may replace itself
+        let callback = self.callback.borrow().clone();
+        if let Some(callback) = callback {
+            let _ = callback.call1(&JsValue::null(), &JsValue::from(progress));
+        }
+    }
+}
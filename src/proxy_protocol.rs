@@ -0,0 +1,199 @@
+use std::net::IpAddr;
+
+use wasm_bindgen::prelude::*;
+
+use crate::connection::ConnectionError;
+
+/// Signature starting every PROXY protocol v2 header.
+const V2_SIGNATURE: [u8; 12] = [
+    0x0D, 0x0A, 0x0D, 0x0A, 0x00, 0x0D, 0x0A, 0x51, 0x55, 0x49, 0x54, 0x0A,
+];
+
+/// A HAProxy PROXY protocol header, sent as the first bytes of a TCP connection.
+#[wasm_bindgen]
+#[derive(Clone, Debug)]
+pub struct ProxyHeader {
+    /// Encoded header
+    bytes: Vec<u8>,
+}
+
+#[wasm_bindgen]
+impl ProxyHeader {
+    /// Create a new PROXY protocol header.
+    ///
+    /// # Arguments
+    ///
+    /// * `version` - Protocol version, 1 (text) or 2 (binary)
+    /// * `source` - Source IP address
+    /// * `source_port` - Source port
+    /// * `destination` - Destination IP address, of the same family as `source`
+    /// * `destination_port` - Destination port
+    #[wasm_bindgen(constructor)]
+    pub fn new(
+        version: u8,
+        source: String,
+        source_port: u16,
+        destination: String,
+        destination_port: u16,
+    ) -> Result<ProxyHeader, ConnectionError> {
+        let parse = |addr: &str| {
+            addr.parse::<IpAddr>().map_err(|_| ConnectionError {
+                message: format!("Invalid IP address: {}", addr),
+            })
+        };
+        let source = parse(&source)?;
+        let destination = parse(&destination)?;
+
+        let bytes = match version {
+            1 => encode_v1(source, source_port, destination, destination_port)?,
+            2 => encode_v2(source, source_port, destination, destination_port)?,
+            _ => {
+                return Err(ConnectionError {
+                    message: format!("Unsupported PROXY protocol version: {}", version),
+                })
+            }
+        };
+
+        Ok(Self { bytes })
+    }
+
+    /// Get the encoded header.
+    #[wasm_bindgen]
+    pub fn get_bytes(&self) -> Vec<u8> {
+        self.bytes.clone()
+    }
+}
+
+impl ProxyHeader {
+    /// Get the encoded header without copying it.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+}
+
+/// Fail if the addresses are not of the same family.
+fn ensure_same_family(source: IpAddr, destination: IpAddr) -> Result<(), ConnectionError> {
+    if source.is_ipv4() != destination.is_ipv4() {
+        return Err(ConnectionError {
+            message: "Source and destination addresses must be of the same family".to_string(),
+        });
+    }
+    Ok(())
+}
+
+/// Encode a text (v1) header.
+fn encode_v1(
+    source: IpAddr,
+    source_port: u16,
+    destination: IpAddr,
+    destination_port: u16,
+) -> Result<Vec<u8>, ConnectionError> {
+    ensure_same_family(source, destination)?;
+    let family = if source.is_ipv4() { "TCP4" } else { "TCP6" };
+    Ok(format!(
+        "PROXY {} {} {} {} {}\r\n",
+        family, source, destination, source_port, destination_port
+    )
+    .into_bytes())
+}
+
+/// Encode a binary (v2) header.
+fn encode_v2(
+    source: IpAddr,
+    source_port: u16,
+    destination: IpAddr,
+    destination_port: u16,
+) -> Result<Vec<u8>, ConnectionError> {
+    ensure_same_family(source, destination)?;
+
+    let mut addresses = Vec::new();
+    let family = match (source, destination) {
+        (IpAddr::V4(source), IpAddr::V4(destination)) => {
+            addresses.extend_from_slice(&source.octets());
+            addresses.extend_from_slice(&destination.octets());
+            // AF_INET, STREAM
+            0x11
+        }
+        (IpAddr::V6(source), IpAddr::V6(destination)) => {
+            addresses.extend_from_slice(&source.octets());
+            addresses.extend_from_slice(&destination.octets());
+            // AF_INET6, STREAM
+            0x21
+        }
+        _ => unreachable!(),
+    };
+    addresses.extend_from_slice(&source_port.to_be_bytes());
+    addresses.extend_from_slice(&destination_port.to_be_bytes());
+
+    let mut bytes = Vec::with_capacity(16 + addresses.len());
+    bytes.extend_from_slice(&V2_SIGNATURE);
+    // Version 2, PROXY command
+    bytes.push(0x21);
+    bytes.push(family);
+    bytes.extend_from_slice(&(addresses.len() as u16).to_be_bytes());
+    bytes.extend_from_slice(&addresses);
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encode(version: u8, source: &str, destination: &str) -> Result<Vec<u8>, String> {
+        ProxyHeader::new(version, source.into(), 56324, destination.into(), 443)
+            .map(|header| header.get_bytes())
+            .map_err(|e| e.message)
+    }
+
+    #[test]
+    fn encodes_v1_headers() {
+        assert_eq!(
+            encode(1, "192.0.2.1", "198.51.100.7").as_deref(),
+            Ok(&b"PROXY TCP4 192.0.2.1 198.51.100.7 56324 443\r\n"[..])
+        );
+        assert_eq!(
+            encode(1, "2001:db8::1", "::1").as_deref(),
+            Ok(&b"PROXY TCP6 2001:db8::1 ::1 56324 443\r\n"[..])
+        );
+    }
+
+    #[test]
+    fn encodes_v2_ipv4_headers() {
+        let mut expected = V2_SIGNATURE.to_vec();
+        expected.extend_from_slice(&[0x21, 0x11, 0x00, 0x0C]);
+        expected.extend_from_slice(&[192, 0, 2, 1, 198, 51, 100, 7]);
+        expected.extend_from_slice(&[0xDC, 0x04, 0x01, 0xBB]);
+        assert_eq!(encode(2, "192.0.2.1", "198.51.100.7"), Ok(expected));
+    }
+
+    #[test]
+    fn encodes_v2_ipv6_headers() {
+        let bytes = encode(2, "2001:db8::1", "::1").unwrap();
+        assert_eq!(bytes.len(), 16 + 36);
+        assert_eq!(&bytes[..12], V2_SIGNATURE);
+        assert_eq!(bytes[12..16], [0x21, 0x21, 0x00, 0x24]);
+        assert_eq!(bytes[16..18], [0x20, 0x01]);
+        assert_eq!(bytes[47], 1);
+        assert_eq!(bytes[48..], [0xDC, 0x04, 0x01, 0xBB]);
+    }
+
+    #[test]
+    fn rejects_invalid_headers() {
+        assert_eq!(
+            encode(1, "192.0.2.1", "::1"),
+            Err("Source and destination addresses must be of the same family".to_string())
+        );
+        assert_eq!(
+            encode(2, "::1", "192.0.2.1"),
+            Err("Source and destination addresses must be of the same family".to_string())
+        );
+        assert_eq!(
+            encode(2, "example.com", "192.0.2.1"),
+            Err("Invalid IP address: example.com".to_string())
+        );
+        assert_eq!(
+            encode(3, "192.0.2.1", "192.0.2.2"),
+            Err("Unsupported PROXY protocol version: 3".to_string())
+        );
+    }
+}
@@ -0,0 +1,576 @@
+use std::collections::HashMap;
+
+use crate::connection::ConnectionError;
+
+/// Static table of QPACK, RFC 9204 Appendix A.
+const STATIC_TABLE: [(&str, &str); 99] = [
+    (":authority", ""),
+    (":path", "/"),
+    ("age", "0"),
+    ("content-disposition", ""),
+    ("content-length", "0"),
+    ("cookie", ""),
+    ("date", ""),
+    ("etag", ""),
+    ("if-modified-since", ""),
+    ("if-none-match", ""),
+    ("last-modified", ""),
+    ("link", ""),
+    ("location", ""),
+    ("referer", ""),
+    ("set-cookie", ""),
+    (":method", "CONNECT"),
+    (":method", "DELETE"),
+    (":method", "GET"),
+    (":method", "HEAD"),
+    (":method", "OPTIONS"),
+    (":method", "POST"),
+    (":method", "PUT"),
+    (":scheme", "http"),
+    (":scheme", "https"),
+    (":status", "103"),
+    (":status", "200"),
+    (":status", "304"),
+    (":status", "404"),
+    (":status", "503"),
+    ("accept", "*/*"),
+    ("accept", "application/dns-message"),
+    ("accept-encoding", "gzip, deflate, br"),
+    ("accept-ranges", "bytes"),
+    ("access-control-allow-headers", "cache-control"),
+    ("access-control-allow-headers", "content-type"),
+    ("access-control-allow-origin", "*"),
+    ("cache-control", "max-age=0"),
+    ("cache-control", "max-age=2592000"),
+    ("cache-control", "max-age=604800"),
+    ("cache-control", "no-cache"),
+    ("cache-control", "no-store"),
+    ("cache-control", "public, max-age=31536000"),
+    ("content-encoding", "br"),
+    ("content-encoding", "gzip"),
+    ("content-type", "application/dns-message"),
+    ("content-type", "application/javascript"),
+    ("content-type", "application/json"),
+    ("content-type", "application/x-www-form-urlencoded"),
+    ("content-type", "image/gif"),
+    ("content-type", "image/jpeg"),
+    ("content-type", "image/png"),
+    ("content-type", "text/css"),
+    ("content-type", "text/html; charset=utf-8"),
+    ("content-type", "text/plain"),
+    ("content-type", "text/plain;charset=utf-8"),
+    ("range", "bytes=0-"),
+    ("strict-transport-security", "max-age=31536000"),
+    (
+        "strict-transport-security",
+        "max-age=31536000; includesubdomains",
+    ),
+    (
+        "strict-transport-security",
+        "max-age=31536000; includesubdomains; preload",
+    ),
+    ("vary", "accept-encoding"),
+    ("vary", "origin"),
+    ("x-content-type-options", "nosniff"),
+    ("x-xss-protection", "1; mode=block"),
+    (":status", "100"),
+    (":status", "204"),
+    (":status", "206"),
+    (":status", "302"),
+    (":status", "400"),
+    (":status", "403"),
+    (":status", "421"),
+    (":status", "425"),
+    (":status", "500"),
+    ("accept-language", ""),
+    ("access-control-allow-credentials", "FALSE"),
+    ("access-control-allow-credentials", "TRUE"),
+    ("access-control-allow-headers", "*"),
+    ("access-control-allow-methods", "get"),
+    ("access-control-allow-methods", "get, post, options"),
+    ("access-control-allow-methods", "options"),
+    ("access-control-expose-headers", "content-length"),
+    ("access-control-request-headers", "content-type"),
+    ("access-control-request-method", "get"),
+    ("access-control-request-method", "post"),
+    ("alt-svc", "clear"),
+    ("authorization", ""),
+    (
+        "content-security-policy",
+        "script-src 'none'; object-src 'none'; base-uri 'none'",
+    ),
+    ("early-data", "1"),
+    ("expect-ct", ""),
+    ("forwarded", ""),
+    ("if-range", ""),
+    ("origin", ""),
+    ("purpose", "prefetch"),
+    ("server", ""),
+    ("timing-allow-origin", "*"),
+    ("upgrade-insecure-requests", "1"),
+    ("user-agent", ""),
+    ("x-forwarded-for", ""),
+    ("x-frame-options", "deny"),
+    ("x-frame-options", "sameorigin"),
+];
+
+/// Codes and bit lengths of the Huffman code of HPACK and QPACK, by symbol, RFC 7541
+/// Appendix B, the last one being the end of string.
+const HUFFMAN_CODES: [(u32, u8); 257] = [
+    (0x1ff8, 13),
+    (0x7fffd8, 23),
+    (0xfffffe2, 28),
+    (0xfffffe3, 28),
+    (0xfffffe4, 28),
+    (0xfffffe5, 28),
+    (0xfffffe6, 28),
+    (0xfffffe7, 28),
+    (0xfffffe8, 28),
+    (0xffffea, 24),
+    (0x3ffffffc, 30),
+    (0xfffffe9, 28),
+    (0xfffffea, 28),
+    (0x3ffffffd, 30),
+    (0xfffffeb, 28),
+    (0xfffffec, 28),
+    (0xfffffed, 28),
+    (0xfffffee, 28),
+    (0xfffffef, 28),
+    (0xffffff0, 28),
+    (0xffffff1, 28),
+    (0xffffff2, 28),
+    (0x3ffffffe, 30),
+    (0xffffff3, 28),
+    (0xffffff4, 28),
+    (0xffffff5, 28),
+    (0xffffff6, 28),
+    (0xffffff7, 28),
+    (0xffffff8, 28),
+    (0xffffff9, 28),
+    (0xffffffa, 28),
+    (0xffffffb, 28),
+    (0x14, 6),
+    (0x3f8, 10),
+    (0x3f9, 10),
+    (0xffa, 12),
+    (0x1ff9, 13),
+    (0x15, 6),
+    (0xf8, 8),
+    (0x7fa, 11),
+    (0x3fa, 10),
+    (0x3fb, 10),
+    (0xf9, 8),
+    (0x7fb, 11),
+    (0xfa, 8),
+    (0x16, 6),
+    (0x17, 6),
+    (0x18, 6),
+    (0x0, 5),
+    (0x1, 5),
+    (0x2, 5),
+    (0x19, 6),
+    (0x1a, 6),
+    (0x1b, 6),
+    (0x1c, 6),
+    (0x1d, 6),
+    (0x1e, 6),
+    (0x1f, 6),
+    (0x5c, 7),
+    (0xfb, 8),
+    (0x7ffc, 15),
+    (0x20, 6),
+    (0xffb, 12),
+    (0x3fc, 10),
+    (0x1ffa, 13),
+    (0x21, 6),
+    (0x5d, 7),
+    (0x5e, 7),
+    (0x5f, 7),
+    (0x60, 7),
+    (0x61, 7),
+    (0x62, 7),
+    (0x63, 7),
+    (0x64, 7),
+    (0x65, 7),
+    (0x66, 7),
+    (0x67, 7),
+    (0x68, 7),
+    (0x69, 7),
+    (0x6a, 7),
+    (0x6b, 7),
+    (0x6c, 7),
+    (0x6d, 7),
+    (0x6e, 7),
+    (0x6f, 7),
+    (0x70, 7),
+    (0x71, 7),
+    (0x72, 7),
+    (0xfc, 8),
+    (0x73, 7),
+    (0xfd, 8),
+    (0x1ffb, 13),
+    (0x7fff0, 19),
+    (0x1ffc, 13),
+    (0x3ffc, 14),
+    (0x22, 6),
+    (0x7ffd, 15),
+    (0x3, 5),
+    (0x23, 6),
+    (0x4, 5),
+    (0x24, 6),
+    (0x5, 5),
+    (0x25, 6),
+    (0x26, 6),
+    (0x27, 6),
+    (0x6, 5),
+    (0x74, 7),
+    (0x75, 7),
+    (0x28, 6),
+    (0x29, 6),
+    (0x2a, 6),
+    (0x7, 5),
+    (0x2b, 6),
+    (0x76, 7),
+    (0x2c, 6),
+    (0x8, 5),
+    (0x9, 5),
+    (0x2d, 6),
+    (0x77, 7),
+    (0x78, 7),
+    (0x79, 7),
+    (0x7a, 7),
+    (0x7b, 7),
+    (0x7ffe, 15),
+    (0x7fc, 11),
+    (0x3ffd, 14),
+    (0x1ffd, 13),
+    (0xffffffc, 28),
+    (0xfffe6, 20),
+    (0x3fffd2, 22),
+    (0xfffe7, 20),
+    (0xfffe8, 20),
+    (0x3fffd3, 22),
+    (0x3fffd4, 22),
+    (0x3fffd5, 22),
+    (0x7fffd9, 23),
+    (0x3fffd6, 22),
+    (0x7fffda, 23),
+    (0x7fffdb, 23),
+    (0x7fffdc, 23),
+    (0x7fffdd, 23),
+    (0x7fffde, 23),
+    (0xffffeb, 24),
+    (0x7fffdf, 23),
+    (0xffffec, 24),
+    (0xffffed, 24),
+    (0x3fffd7, 22),
+    (0x7fffe0, 23),
+    (0xffffee, 24),
+    (0x7fffe1, 23),
+    (0x7fffe2, 23),
+    (0x7fffe3, 23),
+    (0x7fffe4, 23),
+    (0x1fffdc, 21),
+    (0x3fffd8, 22),
+    (0x7fffe5, 23),
+    (0x3fffd9, 22),
+    (0x7fffe6, 23),
+    (0x7fffe7, 23),
+    (0xffffef, 24),
+    (0x3fffda, 22),
+    (0x1fffdd, 21),
+    (0xfffe9, 20),
+    (0x3fffdb, 22),
+    (0x3fffdc, 22),
+    (0x7fffe8, 23),
+    (0x7fffe9, 23),
+    (0x1fffde, 21),
+    (0x7fffea, 23),
+    (0x3fffdd, 22),
+    (0x3fffde, 22),
+    (0xfffff0, 24),
+    (0x1fffdf, 21),
+    (0x3fffdf, 22),
+    (0x7fffeb, 23),
+    (0x7fffec, 23),
+    (0x1fffe0, 21),
+    (0x1fffe1, 21),
+    (0x3fffe0, 22),
+    (0x1fffe2, 21),
+    (0x7fffed, 23),
+    (0x3fffe1, 22),
+    (0x7fffee, 23),
+    (0x7fffef, 23),
+    (0xfffea, 20),
+    (0x3fffe2, 22),
+    (0x3fffe3, 22),
+    (0x3fffe4, 22),
+    (0x7ffff0, 23),
+    (0x3fffe5, 22),
+    (0x3fffe6, 22),
+    (0x7ffff1, 23),
+    (0x3ffffe0, 26),
+    (0x3ffffe1, 26),
+    (0xfffeb, 20),
+    (0x7fff1, 19),
+    (0x3fffe7, 22),
+    (0x7ffff2, 23),
+    (0x3fffe8, 22),
+    (0x1ffffec, 25),
+    (0x3ffffe2, 26),
+    (0x3ffffe3, 26),
+    (0x3ffffe4, 26),
+    (0x7ffffde, 27),
+    (0x7ffffdf, 27),
+    (0x3ffffe5, 26),
+    (0xfffff1, 24),
+    (0x1ffffed, 25),
+    (0x7fff2, 19),
+    (0x1fffe3, 21),
+    (0x3ffffe6, 26),
+    (0x7ffffe0, 27),
+    (0x7ffffe1, 27),
+    (0x3ffffe7, 26),
+    (0x7ffffe2, 27),
+    (0xfffff2, 24),
+    (0x1fffe4, 21),
+    (0x1fffe5, 21),
+    (0x3ffffe8, 26),
+    (0x3ffffe9, 26),
+    (0xffffffd, 28),
+    (0x7ffffe3, 27),
+    (0x7ffffe4, 27),
+    (0x7ffffe5, 27),
+    (0xfffec, 20),
+    (0xfffff3, 24),
+    (0xfffed, 20),
+    (0x1fffe6, 21),
+    (0x3fffe9, 22),
+    (0x1fffe7, 21),
+    (0x1fffe8, 21),
+    (0x7ffff3, 23),
+    (0x3fffea, 22),
+    (0x3fffeb, 22),
+    (0x1ffffee, 25),
+    (0x1ffffef, 25),
+    (0xfffff4, 24),
+    (0xfffff5, 24),
+    (0x3ffffea, 26),
+    (0x7ffff4, 23),
+    (0x3ffffeb, 26),
+    (0x7ffffe6, 27),
+    (0x3ffffec, 26),
+    (0x3ffffed, 26),
+    (0x7ffffe7, 27),
+    (0x7ffffe8, 27),
+    (0x7ffffe9, 27),
+    (0x7ffffea, 27),
+    (0x7ffffeb, 27),
+    (0xffffffe, 28),
+    (0x7ffffec, 27),
+    (0x7ffffed, 27),
+    (0x7ffffee, 27),
+    (0x7ffffef, 27),
+    (0x7fffff0, 27),
+    (0x3ffffee, 26),
+    (0x3fffffff, 30),
+];
+
+/// Symbol standing for the end of a Huffman encoded string, which must not be encoded.
+const HUFFMAN_EOS: u16 = 256;
+
+thread_local! {
+    /// Symbols of the Huffman code by bit length and code, built on first use.
+    static HUFFMAN_SYMBOLS: HashMap<(u8, u32), u16> = HUFFMAN_CODES
+        .iter()
+        .enumerate()
+        .map(|(symbol, &(code, len))| ((len, code), symbol as u16))
+        .collect();
+}
+
+/// Build the error of a malformed field section.
+fn malformed(reason: &str) -> ConnectionError {
+    ConnectionError {
+        message: format!("Malformed QPACK field section: {}", reason),
+    }
+}
+
+/// Encode an integer with an `n` bit prefix, the bits above the prefix of the first byte
+/// being `flags`.
+fn encode_int(out: &mut Vec<u8>, n: u8, flags: u8, value: usize) {
+    let max = (1usize << n) - 1;
+    if value < max {
+        out.push(flags | value as u8);
+        return;
+    }
+    out.push(flags | max as u8);
+    let mut value = value - max;
+    while value >= 0x80 {
+        out.push(0x80 | (value & 0x7F) as u8);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+/// Decode an integer with an `n` bit prefix, starting at `pos` and advancing it.
+fn decode_int(block: &[u8], pos: &mut usize, n: u8) -> Result<usize, ConnectionError> {
+    let max = (1usize << n) - 1;
+    let first = *block
+        .get(*pos)
+        .ok_or_else(|| malformed("truncated integer"))?;
+    *pos += 1;
+    let mut value = first as usize & max;
+    if value < max {
+        return Ok(value);
+    }
+    let mut shift = 0;
+    loop {
+        let byte = *block
+            .get(*pos)
+            .ok_or_else(|| malformed("truncated integer"))?;
+        *pos += 1;
+        if shift > 28 {
+            return Err(malformed("integer too large"));
+        }
+        value += ((byte & 0x7F) as usize) << shift;
+        shift += 7;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+}
+
+/// Encode a string literal without Huffman coding, with an `n` bit length prefix.
+fn encode_string(out: &mut Vec<u8>, n: u8, flags: u8, value: &str) {
+    encode_int(out, n, flags, value.len());
+    out.extend_from_slice(value.as_bytes());
+}
+
+/// Decode a string literal whose Huffman flag is the bit above its `n` bit length prefix.
+fn decode_string(block: &[u8], pos: &mut usize, n: u8) -> Result<String, ConnectionError> {
+    let huffman = block.get(*pos).is_some_and(|b| b & (1 << n) != 0);
+    let len = decode_int(block, pos, n)?;
+    let bytes = block
+        .get(*pos..*pos + len)
+        .ok_or_else(|| malformed("truncated string"))?;
+    *pos += len;
+    let bytes = if huffman {
+        huffman_decode(bytes)?
+    } else {
+        bytes.to_vec()
+    };
+    String::from_utf8(bytes).map_err(|_| malformed("field is not UTF-8"))
+}
+
+/// Decode a Huffman encoded string.
+fn huffman_decode(bytes: &[u8]) -> Result<Vec<u8>, ConnectionError> {
+    HUFFMAN_SYMBOLS.with(|symbols| {
+        let mut decoded = Vec::with_capacity(bytes.len() * 8 / 5);
+        let (mut code, mut len) = (0u32, 0u8);
+        for byte in bytes {
+            for bit in (0..8).rev() {
+                code = code << 1 | u32::from(byte >> bit & 1);
+                len += 1;
+                match symbols.get(&(len, code)) {
+                    Some(&HUFFMAN_EOS) => return Err(malformed("Huffman end of string")),
+                    Some(&symbol) => {
+                        decoded.push(symbol as u8);
+                        (code, len) = (0, 0);
+                    }
+                    None if len >= 30 => return Err(malformed("invalid Huffman code")),
+                    None => {}
+                }
+            }
+        }
+        // Padding is the most significant bits of the end of string, all ones
+        if len > 7 || code != (1 << len) - 1 {
+            return Err(malformed("invalid Huffman padding"));
+        }
+        Ok(decoded)
+    })
+}
+
+/// Encode a field section without referencing the dynamic table, so no encoder stream is
+/// needed.
+///
+/// Fields are referenced from the static table where possible, names being lowercase.
+///
+/// # Arguments
+///
+/// * `fields` - Names and values of the fields, pseudo-headers first
+pub fn encode_field_section(fields: &[(String, String)]) -> Vec<u8> {
+    // Required insert count and delta base, both 0 without dynamic table
+    let mut out = vec![0, 0];
+    for (name, value) in fields {
+        let exact = STATIC_TABLE
+            .iter()
+            .position(|&(n, v)| n == name && v == value);
+        if let Some(index) = exact {
+            // Indexed field line, static table
+            encode_int(&mut out, 6, 0xC0, index);
+            continue;
+        }
+        match STATIC_TABLE.iter().position(|&(n, _)| n == name) {
+            Some(index) => {
+                // Literal field line with name reference, static table
+                encode_int(&mut out, 4, 0x50, index);
+            }
+            None => {
+                // Literal field line with literal name
+                encode_string(&mut out, 3, 0x20, name);
+            }
+        }
+        encode_string(&mut out, 7, 0x00, value);
+    }
+    out
+}
+
+/// Decode a field section that doesn't reference the dynamic table, as peers are told it
+/// has no capacity.
+///
+/// # Returns
+///
+/// The names and values of the fields, in order.
+pub fn decode_field_section(block: &[u8]) -> Result<Vec<(String, String)>, ConnectionError> {
+    let mut pos = 0;
+    if decode_int(block, &mut pos, 8)? != 0 {
+        return Err(malformed("dynamic table reference"));
+    }
+    decode_int(block, &mut pos, 7)?;
+
+    let static_entry = |index: usize| {
+        STATIC_TABLE
+            .get(index)
+            .copied()
+            .ok_or_else(|| malformed("static table index out of range"))
+    };
+    let mut fields = Vec::new();
+    while pos < block.len() {
+        let first = block[pos];
+        if first & 0x80 != 0 {
+            // Indexed field line
+            if first & 0x40 == 0 {
+                return Err(malformed("dynamic table reference"));
+            }
+            let (name, value) = static_entry(decode_int(block, &mut pos, 6)?)?;
+            fields.push((name.to_string(), value.to_string()));
+        } else if first & 0x40 != 0 {
+            // Literal field line with name reference
+            if first & 0x10 == 0 {
+                return Err(malformed("dynamic table reference"));
+            }
+            let (name, _) = static_entry(decode_int(block, &mut pos, 4)?)?;
+            let value = decode_string(block, &mut pos, 7)?;
+            fields.push((name.to_string(), value));
+        } else if first & 0x20 != 0 {
+            // Literal field line with literal name
+            let name = decode_string(block, &mut pos, 3)?;
+            let value = decode_string(block, &mut pos, 7)?;
+            fields.push((name, value));
+        } else {
+            // Field lines with post-base index only reference the dynamic table
+            return Err(malformed("dynamic table reference"));
+        }
+    }
+    Ok(fields)
+}
@@ -0,0 +1,72 @@
+use std::{cell::RefCell, rc::Rc};
+
+use wasm_bindgen::prelude::*;
+use wasm_bindgen_futures::js_sys;
+
+use crate::{clear_interval, connection::Connection, set_interval};
+
+/// Shortest interval between two checks for idle connections, in ms.
+const MIN_CHECK_INTERVAL: f64 = 100.0;
+
+/// Periodic check closing the connections of a client left idle for too long.
+///
+/// The check stops when the reaper is dropped.
+pub struct IdleReaper {
+    /// Function running the check, kept alive as long as the interval
+    _check: Closure<dyn FnMut()>,
+    /// Handle of the interval running the check
+    handle: JsValue,
+}
+
+impl IdleReaper {
+    /// Start checking for idle connections.
+    ///
+    /// # Arguments
+    ///
+    /// * `connections` - Connections of the client
+    /// * `timeout` - Time without any byte sent or received after which a connection is closed, in ms
+    /// * `onreap` - Function called with the ID of every connection closed
+    pub fn start(
+        connections: Rc<RefCell<Vec<Connection>>>,
+        timeout: f64,
+        onreap: Option<js_sys::Function>,
+    ) -> Self {
+        let check: Closure<dyn FnMut()> = Closure::wrap(Box::new(move || {
+            let mut reaped = Vec::new();
+            connections.borrow_mut().retain(|c| {
+                // Connections the server drops sooner are closed before it does
+                let timeout = c
+                    .get_keep_alive_timeout()
+                    .map_or(timeout, |t| t.min(timeout));
+                if c.idle_time() < timeout {
+                    return true;
+                }
+                reaped.push(c.clone());
+                false
+            });
+
+            // Closed once the connections are released, so the callback may use the client
+            for connection in reaped {
+                connection.close();
+                if let Some(onreap) = &onreap {
+                    let id = u64::from(connection.get_id());
+                    let _ = onreap.call1(&JsValue::null(), &JsValue::from(id));
+                }
+            }
+        }));
+        let handle = set_interval(
+            check.as_ref().unchecked_ref(),
+            (timeout / 4.0).max(MIN_CHECK_INTERVAL),
+        );
+        Self {
+            _check: check,
+            handle,
+        }
+    }
+}
+
+impl Drop for IdleReaper {
+    fn drop(&mut self) {
+        clear_interval(&self.handle);
+    }
+}
@@ -0,0 +1,380 @@
+use wasm_bindgen::{convert::TryFromJsValue, prelude::*};
+use wasm_bindgen_futures::js_sys;
+
+use crate::{
+    callbacks::RedirectCallback,
+    connection_apis::http::{
+        HttpConnectionRequest, HttpConnectionResponse, HttpHeader, METHOD_GET, METHOD_HEAD,
+        METHOD_POST,
+    },
+};
+
+/// Default number of redirects followed before giving up, as in the Fetch standard.
+pub const DEFAULT_MAX_REDIRECTS: u32 = 20;
+
+/// Headers describing a request body, dropped when a redirect turns the request into a `GET`.
+const BODY_HEADERS: [&str; 5] = [
+    "Content-Length",
+    "Content-Type",
+    "Content-Encoding",
+    "Content-Language",
+    "Transfer-Encoding",
+];
+
+/// How requests sent by `Client::get`, `post`, `put`, `delete` and `fetch_all` follow
+/// redirects.
+///
+/// Responses with a `301`, `302`, `303`, `307` or `308` code and a `Location` header are
+/// followed over a new connection. As in browsers, a `303` answer, or a `301` or `302` answer
+/// to a `POST`, is followed with a `GET` without body, and the `Authorization` header is
+/// dropped when the redirect leads to another origin.
+#[wasm_bindgen]
+#[derive(Clone)]
+pub struct RedirectPolicy {
+    /// Number of redirects followed before failing
+    max_redirects: u32,
+    /// Callback called before following every redirect
+    onredirect: Option<js_sys::Function>,
+}
+
+#[wasm_bindgen]
+impl RedirectPolicy {
+    /// Create a new redirect policy.
+    ///
+    /// # Arguments
+    ///
+    /// * `max_redirects` - Number of redirects followed before failing, 20 by default
+    #[wasm_bindgen(constructor)]
+    pub fn new(max_redirects: Option<u32>) -> Self {
+        Self {
+            max_redirects: max_redirects.unwrap_or(DEFAULT_MAX_REDIRECTS),
+            onredirect: None,
+        }
+    }
+
+    /// Set the callback called with a `RedirectEntry` before following every redirect.
+    ///
+    /// It may return `false` to deliver the redirect response instead of following it, or an
+    /// `HttpConnectionRequest` to send in place of the request of the entry, e.g. without
+    /// credentials. Returning anything else follows the redirect with the request of the entry.
+    #[wasm_bindgen]
+    pub fn set_onredirect(&mut self, onredirect: Option<RedirectCallback>) {
+        self.onredirect = onredirect.map(Into::into);
+    }
+
+    /// Get the number of redirects followed before failing.
+    #[wasm_bindgen]
+    pub fn get_max_redirects(&self) -> u32 {
+        self.max_redirects
+    }
+}
+
+impl RedirectPolicy {
+    /// Get the request following a response, if it is a redirect to follow.
+    ///
+    /// # Arguments
+    ///
+    /// * `from` - Url the response was received from
+    /// * `request` - Request the response answers
+    /// * `response` - Response to the request
+    ///
+    /// # Returns
+    ///
+    /// The url and request of the redirect, or `None` if the response is final.
+    pub fn follow(
+        &self,
+        from: &str,
+        request: &HttpConnectionRequest,
+        response: &HttpConnectionResponse,
+    ) -> Option<(String, HttpConnectionRequest)> {
+        if !matches!(response.code, 301 | 302 | 303 | 307 | 308) {
+            return None;
+        }
+        let to = resolve_location(from, &response.get_header("Location")?)?;
+
+        let mut next = request.clone();
+        let to_get = match response.code {
+            303 => !next.method.eq_ignore_ascii_case(METHOD_HEAD),
+            301 | 302 => next.method.eq_ignore_ascii_case(METHOD_POST),
+            _ => false,
+        };
+        if to_get {
+            next.method = METHOD_GET.to_string();
+            next.body = None;
+            next.headers.retain(|h| {
+                !BODY_HEADERS
+                    .iter()
+                    .any(|b| h.name().eq_ignore_ascii_case(b))
+            });
+        }
+        let (authority, path) = split_url(&to);
+        next.path = path;
+        if origin(from) != origin(&to) {
+            next.remove_header("Authorization");
+            next.set_header("Host", authority);
+        }
+
+        let onredirect = match &self.onredirect {
+            Some(onredirect) => onredirect,
+            None => return Some((to, next)),
+        };
+        let entry = RedirectEntry {
+            status: response.code,
+            from: from.to_string(),
+            to: to.clone(),
+            headers: response.get_headers(),
+            request: next.clone(),
+        };
+        match onredirect.call1(&JsValue::null(), &entry.into()) {
+            Ok(verdict) if verdict == JsValue::FALSE => None,
+            Ok(verdict) if verdict.is_object() => {
+                let replaced = HttpConnectionRequest::try_from_js_value(verdict).ok();
+                Some((to, replaced.unwrap_or(next)))
+            }
+            _ => Some((to, next)),
+        }
+    }
+}
+
+/// Redirect about to be followed, given to the `onredirect` callback of a `RedirectPolicy`.
+#[wasm_bindgen]
+#[derive(Clone)]
+pub struct RedirectEntry {
+    /// Code of the redirect response
+    status: u16,
+    /// Url the redirect response was received from
+    from: String,
+    /// Url the redirect leads to
+    to: String,
+    /// Headers of the redirect response
+    headers: Vec<HttpHeader>,
+    /// Request about to be sent to `to`
+    request: HttpConnectionRequest,
+}
+
+#[wasm_bindgen]
+impl RedirectEntry {
+    /// Get the code of the redirect response, e.g. `302`.
+    #[wasm_bindgen]
+    pub fn get_status(&self) -> u16 {
+        self.status
+    }
+
+    /// Get the url the redirect response was received from.
+    #[wasm_bindgen]
+    pub fn get_from(&self) -> String {
+        self.from.clone()
+    }
+
+    /// Get the url the redirect leads to.
+    #[wasm_bindgen]
+    pub fn get_to(&self) -> String {
+        self.to.clone()
+    }
+
+    /// Get the headers of the redirect response.
+    #[wasm_bindgen]
+    pub fn get_headers(&self) -> Vec<HttpHeader> {
+        self.headers.clone()
+    }
+
+    /// Get a copy of the request about to be sent to the url the redirect leads to.
+    #[wasm_bindgen]
+    pub fn get_request(&self) -> HttpConnectionRequest {
+        self.request.clone()
+    }
+
+    /// Check if the redirect leads to another origin, i.e. scheme, host or port.
+    #[wasm_bindgen]
+    pub fn is_cross_origin(&self) -> bool {
+        origin(&self.from) != origin(&self.to)
+    }
+}
+
+/// Split an absolute url into its authority and its path, `/` if it has none.
+fn split_url(url: &str) -> (&str, String) {
+    let rest = url.split_once("://").map_or(url, |(_, rest)| rest);
+    match rest.find(['/', '?']) {
+        Some(i) if rest[i..].starts_with('/') => (&rest[..i], rest[i..].to_string()),
+        Some(i) => (&rest[..i], format!("/{}", &rest[i..])),
+        None => (rest, "/".to_string()),
+    }
+}
+
+/// Get the origin of an absolute url, its scheme and authority without the default port.
+pub(crate) fn origin(url: &str) -> String {
+    let scheme = url.split_once("://").map_or("", |(scheme, _)| scheme);
+    let (authority, _) = split_url(url);
+    let authority = authority.to_ascii_lowercase();
+    let default_port = match scheme.to_ascii_lowercase().as_str() {
+        "https" | "wss" => ":443",
+        _ => ":80",
+    };
+    let authority = authority.strip_suffix(default_port).unwrap_or(&authority);
+    format!("{}://{}", scheme.to_ascii_lowercase(), authority)
+}
+
+/// Resolve the value of a `Location` header against the url of its response.
+///
+/// # Returns
+///
+/// The absolute url, without fragment, or `None` if the value is empty.
+fn resolve_location(base: &str, location: &str) -> Option<String> {
+    let location = location.trim();
+    let location = location.split('#').next().unwrap_or(location);
+    if location.is_empty() {
+        return None;
+    }
+    if location.contains("://") {
+        return Some(normalize_path(location));
+    }
+    let scheme = base.split_once("://").map_or("http", |(scheme, _)| scheme);
+    if let Some(rest) = location.strip_prefix("//") {
+        return Some(normalize_path(&format!("{}://{}", scheme, rest)));
+    }
+    let (authority, path) = split_url(base);
+    let path = if location.starts_with('/') {
+        location.to_string()
+    } else if location.starts_with('?') {
+        let path = path.split('?').next().unwrap_or_default();
+        format!("{}{}", path, location)
+    } else {
+        // Relative to the directory of the path, without its query
+        let path = path.split('?').next().unwrap_or_default();
+        let dir = path.rsplit_once('/').map_or("", |(dir, _)| dir);
+        format!("{}/{}", dir, location)
+    };
+    Some(normalize_path(&format!(
+        "{}://{}{}",
+        scheme, authority, path
+    )))
+}
+
+/// Remove the `.` and `..` segments of the path of an absolute url, leaving its query as is.
+fn normalize_path(url: &str) -> String {
+    let (scheme, rest) = match url.split_once("://") {
+        Some(parts) => parts,
+        None => return url.to_string(),
+    };
+    let (authority, rest) = rest.split_at(rest.find(['/', '?']).unwrap_or(rest.len()));
+    let (path, query) = rest.split_at(rest.find('?').unwrap_or(rest.len()));
+    format!(
+        "{}://{}{}{}",
+        scheme,
+        authority,
+        remove_dot_segments(path),
+        query
+    )
+}
+
+/// Remove the `.` and `..` segments of a path, as in RFC 3986 section 5.2.4.
+fn remove_dot_segments(path: &str) -> String {
+    let mut input = path;
+    let mut output = String::with_capacity(path.len());
+    while !input.is_empty() {
+        if let Some(rest) = input
+            .strip_prefix("../")
+            .or_else(|| input.strip_prefix("./"))
+        {
+            input = rest;
+        } else if input.starts_with("/./") {
+            input = &input[2..];
+        } else if input == "/." {
+            input = "/";
+        } else if input.starts_with("/../") || input == "/.." {
+            input = if input == "/.." { "/" } else { &input[3..] };
+            output.truncate(output.rfind('/').unwrap_or(0));
+        } else if input == "." || input == ".." {
+            input = "";
+        } else {
+            let end = input[1..].find('/').map_or(input.len(), |i| i + 1);
+            output.push_str(&input[..end]);
+            input = &input[end..];
+        }
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BASE: &str = "http://a/b/c/d;p?q";
+
+    #[test]
+    fn resolves_references_as_in_rfc_3986() {
+        let cases = [
+            ("g", "http://a/b/c/g"),
+            ("./g", "http://a/b/c/g"),
+            ("g/", "http://a/b/c/g/"),
+            ("/g", "http://a/g"),
+            ("//g", "http://g"),
+            ("?y", "http://a/b/c/d;p?y"),
+            ("g?y", "http://a/b/c/g?y"),
+            ("g#s", "http://a/b/c/g"),
+            (";x", "http://a/b/c/;x"),
+            (".", "http://a/b/c/"),
+            ("./", "http://a/b/c/"),
+            ("..", "http://a/b/"),
+            ("../", "http://a/b/"),
+            ("../g", "http://a/b/g"),
+            ("../..", "http://a/"),
+            ("../../g", "http://a/g"),
+        ];
+        for (location, expected) in cases {
+            assert_eq!(
+                resolve_location(BASE, location).as_deref(),
+                Some(expected),
+                "{}",
+                location
+            );
+        }
+    }
+
+    #[test]
+    fn removes_dot_segments_beyond_the_root() {
+        let cases = [
+            ("../../../g", "http://a/g"),
+            ("/./g", "http://a/g"),
+            ("/../g", "http://a/g"),
+            ("g.", "http://a/b/c/g."),
+            (".g", "http://a/b/c/.g"),
+            ("g..", "http://a/b/c/g.."),
+            ("..g", "http://a/b/c/..g"),
+            ("./../g", "http://a/b/g"),
+            ("g/./h", "http://a/b/c/g/h"),
+            ("g/../h", "http://a/b/c/h"),
+            ("g;x=1/./y", "http://a/b/c/g;x=1/y"),
+            ("g?y/./x", "http://a/b/c/g?y/./x"),
+            ("http://x/a/../b", "http://x/b"),
+            ("//x/./a", "http://x/a"),
+        ];
+        for (location, expected) in cases {
+            assert_eq!(
+                resolve_location(BASE, location).as_deref(),
+                Some(expected),
+                "{}",
+                location
+            );
+        }
+    }
+
+    #[test]
+    fn ignores_empty_locations() {
+        assert_eq!(resolve_location(BASE, ""), None);
+        assert_eq!(resolve_location(BASE, " #top"), None);
+    }
+
+    #[test]
+    fn origins_drop_the_default_port_and_path() {
+        assert_eq!(origin("HTTP://Example.com:80/a?b"), "http://example.com");
+        assert_eq!(origin("https://example.com:443"), "https://example.com");
+        assert_eq!(origin("wss://example.com:443/socket"), "wss://example.com");
+        assert_eq!(origin("https://example.com:80/"), "https://example.com:80");
+        assert_eq!(
+            origin("http://example.com:8080?a"),
+            "http://example.com:8080"
+        );
+        assert_ne!(origin("http://example.com"), origin("https://example.com"));
+    }
+}
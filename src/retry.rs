@@ -0,0 +1,305 @@
+use wasm_bindgen::prelude::*;
+use wasm_bindgen_futures::js_sys;
+
+use crate::{
+    callbacks::RetryCallback,
+    connection_apis::http::{
+        HttpConnectionResponse, METHOD_DELETE, METHOD_GET, METHOD_HEAD, METHOD_OPTIONS, METHOD_PUT,
+        METHOD_TRACE,
+    },
+};
+
+/// Default time to wait before the first retry in ms, doubled with every retry.
+pub const DEFAULT_BASE_DELAY: f64 = 500.0;
+/// Default longest time to wait before a retry in ms.
+pub const DEFAULT_MAX_DELAY: f64 = 30_000.0;
+
+/// Methods retried after any retryable response, as sending them twice has no more effect
+/// than sending them once.
+const IDEMPOTENT_METHODS: [&str; 6] = [
+    METHOD_GET,
+    METHOD_HEAD,
+    METHOD_PUT,
+    METHOD_DELETE,
+    METHOD_OPTIONS,
+    METHOD_TRACE,
+];
+
+/// Abbreviated month names of HTTP-dates.
+const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+/// When and how long to wait before retrying requests sent by `Client::get`, `post`, `put`,
+/// `delete` and `fetch_all`.
+///
+/// Requests answered with `429 Too Many Requests` or `503 Service Unavailable` are retried
+/// whatever their method, as the server did not process them. Requests answered with `408`,
+/// `500`, `502` or `504` are only retried if their method is idempotent.
+///
+/// A `Retry-After` header, in seconds or as an HTTP-date, is honored instead of the computed
+/// backoff. A response asking to wait longer than the maximum delay is not retried.
+#[wasm_bindgen]
+#[derive(Clone)]
+pub struct RetryPolicy {
+    /// Number of retries after the first attempt
+    max_retries: u32,
+    /// Time to wait before the first retry in ms
+    base_delay: f64,
+    /// Longest time to wait before a retry in ms
+    max_delay: f64,
+    /// Callback called before waiting for every retry
+    onretry: Option<js_sys::Function>,
+}
+
+#[wasm_bindgen]
+impl RetryPolicy {
+    /// Create a new retry policy.
+    ///
+    /// # Arguments
+    ///
+    /// * `max_retries` - Number of retries after the first attempt
+    #[wasm_bindgen(constructor)]
+    pub fn new(max_retries: u32) -> Self {
+        Self {
+            max_retries,
+            base_delay: DEFAULT_BASE_DELAY,
+            max_delay: DEFAULT_MAX_DELAY,
+            onretry: None,
+        }
+    }
+
+    /// Set the time to wait before the first retry in ms, doubled with every retry.
+    #[wasm_bindgen]
+    pub fn set_base_delay(&mut self, millis: f64) {
+        self.base_delay = millis;
+    }
+
+    /// Set the longest time to wait before a retry in ms, including those asked by
+    /// `Retry-After`.
+    #[wasm_bindgen]
+    pub fn set_max_delay(&mut self, millis: f64) {
+        self.max_delay = millis;
+    }
+
+    /// Set the callback called with the retry number, wait in ms and response code before
+    /// waiting for every retry.
+    #[wasm_bindgen]
+    pub fn set_onretry(&mut self, onretry: Option<RetryCallback>) {
+        self.onretry = onretry.map(Into::into);
+    }
+
+    /// Get the number of retries after the first attempt.
+    #[wasm_bindgen]
+    pub fn get_max_retries(&self) -> u32 {
+        self.max_retries
+    }
+
+    /// Get the time to wait before the first retry in ms.
+    #[wasm_bindgen]
+    pub fn get_base_delay(&self) -> f64 {
+        self.base_delay
+    }
+
+    /// Get the longest time to wait before a retry in ms.
+    #[wasm_bindgen]
+    pub fn get_max_delay(&self) -> f64 {
+        self.max_delay
+    }
+}
+
+impl RetryPolicy {
+    /// Get the time to wait before retrying a request, if it should be retried.
+    ///
+    /// # Arguments
+    ///
+    /// * `method` - Method of the request
+    /// * `response` - Response to the request
+    /// * `retry` - Number of the retry, starting at 1
+    ///
+    /// # Returns
+    ///
+    /// The time to wait in ms, or `None` if the response is final.
+    pub fn delay(
+        &self,
+        method: &str,
+        response: &HttpConnectionResponse,
+        retry: u32,
+    ) -> Option<f64> {
+        if retry > self.max_retries {
+            return None;
+        }
+        let retryable = match response.code {
+            429 | 503 => true,
+            408 | 500 | 502 | 504 => IDEMPOTENT_METHODS
+                .iter()
+                .any(|m| m.eq_ignore_ascii_case(method)),
+            _ => false,
+        };
+        if !retryable {
+            return None;
+        }
+        match response.get_header("Retry-After") {
+            Some(value) => parse_retry_after(&value).filter(|wait| *wait <= self.max_delay),
+            None => Some((self.base_delay * 2f64.powi(retry as i32 - 1)).min(self.max_delay)),
+        }
+    }
+
+    /// Call the retry callback, if any.
+    ///
+    /// # Arguments
+    ///
+    /// * `retry` - Number of the retry, starting at 1
+    /// * `wait` - Time waited before the retry in ms
+    /// * `code` - Code of the response being retried
+    pub fn notify(&self, retry: u32, wait: f64, code: u16) {
+        if let Some(onretry) = &self.onretry {
+            let _ = onretry.call3(&JsValue::null(), &retry.into(), &wait.into(), &code.into());
+        }
+    }
+}
+
+/// Parse the value of a `Retry-After` header, in seconds or as an HTTP-date.
+///
+/// # Returns
+///
+/// The time to wait from now in ms, or `None` if the value is invalid.
+pub fn parse_retry_after(value: &str) -> Option<f64> {
+    parse_retry_after_at(value, js_sys::Date::now())
+}
+
+/// Parse the value of a `Retry-After` header, in seconds or as an HTTP-date.
+///
+/// # Arguments
+///
+/// * `value` - Value of the header
+/// * `now` - Current time in ms since the Unix epoch
+///
+/// # Returns
+///
+/// The time to wait from `now` in ms, or `None` if the value is invalid.
+fn parse_retry_after_at(value: &str, now: f64) -> Option<f64> {
+    let value = value.trim();
+    if let Ok(seconds) = value.parse::<u32>() {
+        return Some(f64::from(seconds) * 1000.0);
+    }
+    let date = parse_http_date(value)?;
+    Some((date - now).max(0.0))
+}
+
+/// Parse an HTTP-date, in the IMF-fixdate format, e.g. `Sun, 06 Nov 1994 08:49:37 GMT`, or
+/// in the obsolete RFC 850 and asctime formats.
+///
+/// # Returns
+///
+/// The date in ms since the Unix epoch, or `None` if the value is invalid.
+fn parse_http_date(value: &str) -> Option<f64> {
+    let parts: Vec<&str> = value
+        .split([' ', ',', '-'])
+        .filter(|part| !part.is_empty())
+        .collect();
+    let (day, month, year, time) = match parts[..] {
+        // IMF-fixdate, and RFC 850 as in `Sunday, 06-Nov-94 08:49:37 GMT`
+        [_, day, month, year, time, "GMT"] => (day, month, year, time),
+        // asctime, as in `Sun Nov  6 08:49:37 1994`
+        [_, month, day, time, year] => (day, month, year, time),
+        _ => return None,
+    };
+    let day: i64 = day.parse().ok()?;
+    let month = MONTHS.iter().position(|m| *m == month)? as i64 + 1;
+    let year: i64 = match year.parse().ok()? {
+        // Two digit years of RFC 850
+        year @ 0..=69 => 2000 + year,
+        year @ 70..=99 => 1900 + year,
+        year => year,
+    };
+    let mut time = time.split(':').map(|t| t.parse::<i64>().ok());
+    let (hours, minutes, seconds) = (time.next()??, time.next()??, time.next()??);
+    if time.next().is_some()
+        || !(1..=31).contains(&day)
+        || !(0..24).contains(&hours)
+        || !(0..60).contains(&minutes)
+        || !(0..=60).contains(&seconds)
+    {
+        return None;
+    }
+    let days = days_from_civil(year, month, day);
+    Some((((days * 24 + hours) * 60 + minutes) * 60 + seconds) as f64 * 1000.0)
+}
+
+/// Get the number of days between the Unix epoch and a date of the proleptic Gregorian
+/// calendar.
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    // Years starting in March, so that leap days end them
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `Sun, 06 Nov 1994 08:49:37 GMT` in ms since the Unix epoch.
+    const DATE: f64 = 784_111_777_000.0;
+
+    #[test]
+    fn parses_seconds() {
+        assert_eq!(parse_retry_after_at("120", DATE), Some(120_000.0));
+        assert_eq!(parse_retry_after_at(" 0 ", DATE), Some(0.0));
+        assert_eq!(parse_retry_after_at("-1", DATE), None);
+        assert_eq!(parse_retry_after_at("1.5", DATE), None);
+        assert_eq!(parse_retry_after_at("soon", DATE), None);
+        assert_eq!(parse_retry_after_at("", DATE), None);
+    }
+
+    #[test]
+    fn parses_dates() {
+        let value = "Sun, 06 Nov 1994 08:49:37 GMT";
+        assert_eq!(parse_retry_after_at(value, DATE - 5000.0), Some(5000.0));
+        assert_eq!(parse_retry_after_at(value, DATE), Some(0.0));
+        // Dates in the past mean retrying right away
+        assert_eq!(parse_retry_after_at(value, DATE + 5000.0), Some(0.0));
+    }
+
+    #[test]
+    fn parses_every_http_date_format() {
+        let formats = [
+            "Sun, 06 Nov 1994 08:49:37 GMT",
+            "Sunday, 06-Nov-94 08:49:37 GMT",
+            "Sun Nov  6 08:49:37 1994",
+        ];
+        for value in formats {
+            assert_eq!(parse_http_date(value), Some(DATE), "{}", value);
+        }
+        assert_eq!(parse_http_date("Thu, 01 Jan 1970 00:00:00 GMT"), Some(0.0));
+        assert_eq!(
+            parse_http_date("Thu, 29 Feb 2024 23:59:59 GMT"),
+            Some(1_709_251_199_000.0)
+        );
+        assert_eq!(
+            parse_http_date("Wednesday, 01-Jan-25 00:00:00 GMT"),
+            Some(1_735_689_600_000.0)
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_dates() {
+        let cases = [
+            "Sun, 06 Nov 1994 08:49:37 PST",
+            "Sun, 06 Nov 1994 08:49 GMT",
+            "Sun, 06 Nov 1994 24:00:00 GMT",
+            "Sun, 32 Nov 1994 08:49:37 GMT",
+            "Sun, 06 Foo 1994 08:49:37 GMT",
+            "Sun, 06 Nov 1994 08:49:37:00 GMT",
+            "06 Nov 1994 08:49:37 GMT",
+            "1994-11-06T08:49:37Z",
+        ];
+        for value in cases {
+            assert_eq!(parse_http_date(value), None, "{}", value);
+        }
+    }
+}
@@ -0,0 +1,465 @@
+use std::{
+    cell::{Cell, RefCell},
+    collections::HashMap,
+    rc::Rc,
+};
+
+use wasm_bindgen::prelude::*;
+use wasm_bindgen_futures::js_sys::{self, Array};
+use web_sys::{MessageEvent, MessagePort, SharedWorker, SharedWorkerGlobalScope};
+
+use crate::{
+    callbacks::{ConnectionIdCallback, HttpResponseCallback, ReadyCallback, TcpResponseCallback},
+    client::Client,
+    connection::ConnectionError,
+    connection_apis::{
+        http::{HttpConnectionApi, HttpConnectionRequest, HttpConnectionResponse, HttpHeader},
+        https::HttpsConnectionApi,
+        tcp::{TcpConnectionApi, TcpConnectionRequest, TcpConnectionResponse},
+    },
+    worker::{get, get_bytes, message, to_buffer},
+    SocketCapability,
+};
+
+/// Encode headers as an array of `[name, value]` pairs.
+fn encode_headers(headers: &[HttpHeader]) -> Array {
+    headers
+        .iter()
+        .map(|h| Array::of2(&h.name().into(), &h.value().into()))
+        .collect()
+}
+
+/// Decode headers from an array of `[name, value]` pairs.
+fn decode_headers(headers: JsValue) -> Vec<HttpHeader> {
+    Array::from(&headers)
+        .iter()
+        .map(|pair| {
+            let pair = Array::from(&pair);
+            HttpHeader::of(
+                pair.get(0).as_string().unwrap_or_default(),
+                pair.get(1).as_string().unwrap_or_default(),
+            )
+        })
+        .collect()
+}
+
+/// Decode a connection ID, which is sent as a string since it does not fit in a JS number.
+fn decode_id(msg: &JsValue) -> Option<u64> {
+    get(msg, "id").as_string()?.parse().ok()
+}
+
+/// Post a message belonging to a call, transferring its body if there is one.
+fn post_call(port: &MessagePort, call: u32, props: &[(&str, JsValue)]) {
+    let msg = message(&[("call", call.into())]);
+    let mut transfer = Array::new();
+    for (key, value) in props {
+        js_sys::Reflect::set(&msg, &JsValue::from_str(key), value).unwrap_throw();
+        if value.is_instance_of::<js_sys::ArrayBuffer>() {
+            transfer = Array::of1(value);
+        }
+    }
+    port.post_message_with_transferable(&msg, &transfer)
+        .unwrap_throw();
+}
+
+/// Post an error reply to a call.
+fn reply_error(port: &MessagePort, call: u32, error: ConnectionError) {
+    post_call(
+        port,
+        call,
+        &[("kind", "error".into()), ("error", error.message.into())],
+    );
+}
+
+/// API of a connection of the shared client.
+///
+/// Dropping an API closes its connection, so the worker keeps the API of every connection
+/// until a page closes it.
+enum SharedConnection {
+    /// TCP connection
+    Tcp(TcpConnectionApi),
+    /// HTTP connection
+    Http(HttpConnectionApi),
+    /// HTTPS connection
+    Https(HttpsConnectionApi),
+}
+
+impl SharedConnection {
+    /// Get the ID of the connection.
+    fn get_id(&self) -> u64 {
+        match self {
+            Self::Tcp(api) => api.get_id(),
+            Self::Http(api) => api.get_id(),
+            Self::Https(api) => api.get_id(),
+        }
+    }
+}
+
+/// Decode a HTTP request sent by a page.
+fn decode_request(msg: &JsValue) -> HttpConnectionRequest {
+    HttpConnectionRequest::new(
+        get(msg, "method").as_string().unwrap_or_default(),
+        get(msg, "path").as_string().unwrap_or_default(),
+        decode_headers(get(msg, "headers")),
+        get(msg, "body").is_truthy().then(|| get_bytes(msg, "body")),
+    )
+}
+
+/// Create the callback replying to a call with a HTTP response, or with its error.
+fn http_reply(port: &MessagePort, call: u32) -> HttpResponseCallback {
+    let port = port.clone();
+    Closure::once_into_js(
+        move |resp: Option<HttpConnectionResponse>, error: JsValue| match resp {
+            Some(mut resp) => post_call(
+                &port,
+                call,
+                &[
+                    ("kind", "http".into()),
+                    ("code", resp.get_code().into()),
+                    ("headers", encode_headers(&resp.get_headers()).into()),
+                    (
+                        "body",
+                        to_buffer(&resp.take_body().unwrap_or_default()).into(),
+                    ),
+                ],
+            ),
+            None => reply_error(
+                &port,
+                call,
+                ConnectionError {
+                    message: error.as_string().unwrap_or_default(),
+                },
+            ),
+        },
+    )
+    .unchecked_into()
+}
+
+/// Handle a call made by a page over its port.
+///
+/// # Arguments
+///
+/// * `client` - Shared client
+/// * `connections` - APIs of the connections of the shared client, by ID
+/// * `port` - Port of the page
+/// * `msg` - Call made by the page
+fn handle_call(
+    client: &Rc<RefCell<Client>>,
+    connections: &Rc<RefCell<HashMap<u64, SharedConnection>>>,
+    port: &MessagePort,
+    msg: JsValue,
+) {
+    let call = get(&msg, "call").as_f64().unwrap_or_default() as u32;
+    let op = get(&msg, "op").as_string().unwrap_or_default();
+
+    match op.as_str() {
+        "connect" => {
+            let addr = get(&msg, "addr").as_string().unwrap_or_default();
+            let capability = get(&msg, "capability")
+                .as_string()
+                .and_then(|c| SocketCapability::from_string(c.to_lowercase().as_str()));
+
+            let cb_port = port.clone();
+            let id: Rc<Cell<u64>> = Rc::new(Cell::new(0));
+            let cb_id = id.clone();
+            let onready: ReadyCallback = Closure::once_into_js(move || {
+                post_call(
+                    &cb_port,
+                    call,
+                    &[
+                        ("kind", "connected".into()),
+                        ("id", cb_id.get().to_string().into()),
+                    ],
+                );
+            })
+            .unchecked_into();
+
+            let mut client = client.borrow_mut();
+            let created = match capability {
+                Some(SocketCapability::TCP) => client
+                    .create_tcp_connection_with_onready(addr, onready)
+                    .map(|api| api.map(SharedConnection::Tcp)),
+                Some(SocketCapability::HTTP) => client
+                    .create_http_connection_with_onready(addr, onready)
+                    .map(|api| api.map(SharedConnection::Http)),
+                Some(SocketCapability::HTTPS(_)) => client
+                    .create_https_connection_with_onready(addr, onready)
+                    .map(|api| api.map(SharedConnection::Https)),
+                Some(SocketCapability::UDP) | None => Err(ConnectionError {
+                    message: "Unsupported capability".to_string(),
+                }),
+            };
+
+            match created {
+                Ok(Some(created)) => {
+                    id.set(created.get_id());
+                    connections.borrow_mut().insert(created.get_id(), created);
+                }
+                Ok(None) => reply_error(
+                    port,
+                    call,
+                    ConnectionError {
+                        message: "Too many connections".to_string(),
+                    },
+                ),
+                Err(e) => reply_error(port, call, e),
+            }
+        }
+        "send" => {
+            let id = match decode_id(&msg) {
+                Some(id) => id,
+                None => return,
+            };
+            let cb_port = port.clone();
+
+            let result = match connections.borrow().get(&id) {
+                Some(SharedConnection::Tcp(api)) => {
+                    let callback = Closure::once_into_js(move |mut resp: TcpConnectionResponse| {
+                        post_call(
+                            &cb_port,
+                            call,
+                            &[
+                                ("kind", "tcp".into()),
+                                ("body", to_buffer(&resp.take_body()).into()),
+                            ],
+                        );
+                    });
+                    api.send(
+                        TcpConnectionRequest::new(get_bytes(&msg, "body")),
+                        callback.unchecked_into(),
+                    )
+                }
+                Some(SharedConnection::Http(api)) => {
+                    api.send(decode_request(&msg), http_reply(port, call))
+                }
+                Some(SharedConnection::Https(api)) => {
+                    api.send(decode_request(&msg), http_reply(port, call))
+                }
+                None => Err(ConnectionError {
+                    message: format!("Unknown connection: {}", id),
+                }),
+            };
+
+            if let Err(e) = result {
+                reply_error(port, call, e);
+            }
+        }
+        "close" => {
+            if let Some(id) = decode_id(&msg) {
+                connections.borrow_mut().remove(&id);
+                client.borrow_mut().remove_connection(id);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Host a client for all pages connected to the current shared worker.
+///
+/// Pages talk to this client through `SharedClient`, so all tabs share the same
+/// proxy connections. The worker script must initialize this module and then call
+/// this function, e.g.
+///
+/// ```js
+/// import init, { start_shared_client } from "./socketguard.js";
+/// await init();
+/// start_shared_client("ws://localhost:3000");
+/// ```
+///
+/// # Arguments
+///
+/// * `addr` - Base wsproxy url of the shared client
+#[wasm_bindgen]
+pub fn start_shared_client(addr: String) {
+    let scope: SharedWorkerGlobalScope = js_sys::global().unchecked_into();
+    let client = Rc::new(RefCell::new(Client::new(addr)));
+    let connections = Rc::new(RefCell::new(HashMap::new()));
+
+    let connect_callback: Closure<dyn Fn(MessageEvent)> =
+        Closure::wrap(Box::new(move |evt: MessageEvent| {
+            let port: MessagePort = evt.ports().get(0).unchecked_into();
+
+            let cb_client = client.clone();
+            let cb_connections = connections.clone();
+            let cb_port = port.clone();
+            let message_callback: Closure<dyn Fn(MessageEvent)> =
+                Closure::wrap(Box::new(move |evt: MessageEvent| {
+                    handle_call(&cb_client, &cb_connections, &cb_port, evt.data());
+                }));
+
+            port.set_onmessage(Some(message_callback.as_ref().unchecked_ref()));
+            message_callback.forget();
+            port.start();
+        }));
+
+    scope.set_onconnect(Some(connect_callback.as_ref().unchecked_ref()));
+    connect_callback.forget();
+}
+
+/// Stub for a client hosted in a shared worker by `start_shared_client`.
+///
+/// Callbacks are called with the result of the call as first argument, or with `null`
+/// and an error message if the call failed.
+#[wasm_bindgen]
+pub struct SharedClient {
+    /// Port to the shared worker
+    port: MessagePort,
+    /// Callbacks of pending calls
+    calls: Rc<RefCell<HashMap<u32, js_sys::Function>>>,
+    /// ID of the last call
+    last_call: Rc<Cell<u32>>,
+}
+
+#[wasm_bindgen]
+impl SharedClient {
+    /// Create a new stub for the client hosted in the given shared worker.
+    ///
+    /// # Arguments
+    ///
+    /// * `worker` - Shared worker that has called `start_shared_client`
+    #[wasm_bindgen(constructor)]
+    pub fn new(worker: SharedWorker) -> Self {
+        let port = worker.port();
+        let calls: Rc<RefCell<HashMap<u32, js_sys::Function>>> =
+            Rc::new(RefCell::new(HashMap::new()));
+
+        let cb_calls = calls.clone();
+        let message_callback: Closure<dyn Fn(MessageEvent)> =
+            Closure::wrap(Box::new(move |evt: MessageEvent| {
+                let msg = evt.data();
+                let call = get(&msg, "call").as_f64().unwrap_or_default() as u32;
+                let callback = match cb_calls.borrow_mut().remove(&call) {
+                    Some(callback) => callback,
+                    None => return,
+                };
+                let this = JsValue::null();
+
+                let result: JsValue = match get(&msg, "kind").as_string().as_deref() {
+                    Some("connected") => match decode_id(&msg) {
+                        Some(id) => id.into(),
+                        None => JsValue::null(),
+                    },
+                    Some("tcp") => TcpConnectionResponse::new(get_bytes(&msg, "body")).into(),
+                    Some("http") => HttpConnectionResponse::new(
+                        get(&msg, "code").as_f64().unwrap_or_default() as u16,
+                        decode_headers(get(&msg, "headers")),
+                        Some(get_bytes(&msg, "body")),
+                    )
+                    .into(),
+                    _ => {
+                        callback
+                            .call2(&this, &JsValue::null(), &get(&msg, "error"))
+                            .unwrap_throw();
+                        return;
+                    }
+                };
+
+                callback.call1(&this, &result).unwrap_throw();
+            }));
+
+        port.set_onmessage(Some(message_callback.as_ref().unchecked_ref()));
+        message_callback.forget();
+        port.start();
+
+        Self {
+            port,
+            calls,
+            last_call: Rc::new(Cell::new(0)),
+        }
+    }
+
+    /// Create a new connection in the shared client.
+    ///
+    /// # Arguments
+    ///
+    /// * `capability` - Capability of the connection (e.g. `tcp`, `http` or `https_tls1_2`)
+    /// * `addr` - Address to connect to
+    /// * `callback` - Callback to call with the ID of the connection once it is ready
+    #[wasm_bindgen]
+    pub fn create_connection(
+        &self,
+        capability: String,
+        addr: String,
+        callback: ConnectionIdCallback,
+    ) {
+        self.call(
+            callback.into(),
+            &[
+                ("op", "connect".into()),
+                ("capability", capability.into()),
+                ("addr", addr.into()),
+            ],
+        );
+    }
+
+    /// Send data to a TCP connection of the shared client.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - ID of the connection
+    /// * `body` - Data to send
+    /// * `callback` - Callback to call with the `TcpConnectionResponse`
+    #[wasm_bindgen]
+    pub fn send_tcp(&self, id: u64, body: Vec<u8>, callback: TcpResponseCallback) {
+        self.call(
+            callback.into(),
+            &[
+                ("op", "send".into()),
+                ("id", id.to_string().into()),
+                ("body", to_buffer(&body).into()),
+            ],
+        );
+    }
+
+    /// Send a request to a HTTP or HTTPS connection of the shared client.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - ID of the connection
+    /// * `request` - Request to send
+    /// * `callback` - Callback to call with the `HttpConnectionResponse`
+    #[wasm_bindgen]
+    pub fn send_http(
+        &self,
+        id: u64,
+        request: HttpConnectionRequest,
+        callback: HttpResponseCallback,
+    ) {
+        let body = match request.body {
+            Some(body) => to_buffer(&body).into(),
+            None => JsValue::null(),
+        };
+        self.call(
+            callback.into(),
+            &[
+                ("op", "send".into()),
+                ("id", id.to_string().into()),
+                ("method", request.method.into()),
+                ("path", request.path.into()),
+                ("headers", encode_headers(&request.headers).into()),
+                ("body", body),
+            ],
+        );
+    }
+
+    /// Close a connection of the shared client.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - ID of the connection
+    #[wasm_bindgen]
+    pub fn close(&self, id: u64) {
+        let msg = message(&[("op", "close".into()), ("id", id.to_string().into())]);
+        self.port.post_message(&msg).unwrap_throw();
+    }
+}
+
+impl SharedClient {
+    /// Post a call to the shared worker.
+    fn call(&self, callback: js_sys::Function, props: &[(&str, JsValue)]) {
+        let call = self.last_call.get().wrapping_add(1);
+        self.last_call.set(call);
+        self.calls.borrow_mut().insert(call, callback);
+        post_call(&self.port, call, props);
+    }
+}
@@ -0,0 +1,290 @@
+use wasm_bindgen::prelude::*;
+use wasm_bindgen_futures::js_sys::{Array, Object, Reflect, JSON};
+
+use crate::{
+    backoff::HostBackoff,
+    compat::ProxyProfile,
+    conditions::NetworkConditions,
+    connection::ConnectionError,
+    connection_apis::http::TimeoutPolicy,
+    framing::Framing,
+    id::ConnIdScheme,
+    retry::RetryPolicy,
+    tls::TlsOptions,
+    worker::{get_names, names},
+    TLSVersion,
+};
+
+/// Version of the snapshot format, bumped whenever it changes incompatibly.
+const SNAPSHOT_VERSION: u32 = 1;
+
+/// Configuration and warm state of a client, saved by `Client::serialize` and loaded by
+/// `Client::restore`.
+///
+/// Live connections are not part of a snapshot, nor is anything holding a JS function, such
+/// as middlewares, callbacks or the TLS worker. The credentials of the upstream HTTP proxy
+/// are left out as well, so snapshots can be stored without leaking them.
+pub struct ClientSnapshot {
+    /// Base wsproxy url
+    pub addr: String,
+    /// Names of the capabilities
+    pub capabilities: Vec<String>,
+    /// Name of the client, if any
+    pub name: Option<String>,
+    /// How bytes are carried in the frames exchanged with the proxy
+    pub framing: Framing,
+    /// How connection IDs are made
+    pub id_scheme: ConnIdScheme,
+    /// Preset handling of the proxy, if any
+    pub proxy_profile: Option<ProxyProfile>,
+    /// Template of the urls of connections, if saved
+    pub proxy_url_template: Option<String>,
+    /// What happens to connections when a request times out
+    pub timeout_policy: TimeoutPolicy,
+    /// Lowest TLS version to fall back to, if any
+    pub tls_fallback: Option<String>,
+    /// Cipher suites and key exchange groups of HTTPS connections
+    pub tls_options: TlsOptions,
+    /// Capacity of the raw traffic capture, if enabled
+    pub capture_capacity: Option<usize>,
+    /// Upload and download rate limits, in bytes per second
+    pub throttle_limits: (Option<f64>, Option<f64>),
+    /// Simulated network conditions, if any
+    pub network_conditions: Option<NetworkConditions>,
+    /// Maximum number of concurrent connections, if limited
+    pub max_connections: Option<usize>,
+    /// Address of the upstream HTTP proxy, if any
+    pub http_proxy: Option<String>,
+    /// When to retry requests, if ever
+    pub retry: Option<RetryPolicy>,
+    /// Number of redirects followed, if following them
+    pub max_redirects: Option<u32>,
+    /// Pace of the requests to hosts answering with rate limits, if adapted
+    pub backoff: Option<HostBackoff>,
+}
+
+impl ClientSnapshot {
+    /// Serialize this snapshot to JSON.
+    pub fn to_json(&self) -> Result<String, ConnectionError> {
+        let snapshot = Object::new();
+        set(&snapshot, "version", SNAPSHOT_VERSION.into());
+        set(&snapshot, "addr", self.addr.as_str().into());
+        let capabilities: Array = self
+            .capabilities
+            .iter()
+            .map(|c| JsValue::from(c.as_str()))
+            .collect();
+        set(&snapshot, "capabilities", capabilities.into());
+        set(&snapshot, "name", self.name.clone().into());
+        set(&snapshot, "framing", framing_name(self.framing).into());
+        let id_scheme = match self.id_scheme {
+            ConnIdScheme::Compact => "compact",
+            ConnIdScheme::UuidV7 => "uuid_v7",
+        };
+        set(&snapshot, "id_scheme", id_scheme.into());
+        set(
+            &snapshot,
+            "proxy_profile",
+            self.proxy_profile.map(ProxyProfile::name).into(),
+        );
+        set(
+            &snapshot,
+            "proxy_url_template",
+            self.proxy_url_template.clone().into(),
+        );
+        let timeout_policy = match self.timeout_policy {
+            TimeoutPolicy::Close => "close",
+            TimeoutPolicy::Keep => "keep",
+        };
+        set(&snapshot, "timeout_policy", timeout_policy.into());
+        set(&snapshot, "tls_fallback", self.tls_fallback.clone().into());
+        set(
+            &snapshot,
+            "cipher_suites",
+            names(&self.tls_options.cipher_suites),
+        );
+        set(&snapshot, "kx_groups", names(&self.tls_options.kx_groups));
+        set(
+            &snapshot,
+            "tls_min_version",
+            self.tls_options.min_version.map(|v| v.to_string()).into(),
+        );
+        set(
+            &snapshot,
+            "capture_capacity",
+            self.capture_capacity.map(|c| c as f64).into(),
+        );
+        set(&snapshot, "upload_limit", self.throttle_limits.0.into());
+        set(&snapshot, "download_limit", self.throttle_limits.1.into());
+        let conditions = self.network_conditions.map(|conditions| {
+            let object = Object::new();
+            set(&object, "latency", conditions.get_latency().into());
+            set(&object, "jitter", conditions.get_jitter().into());
+            set(&object, "drop_rate", conditions.get_drop_rate().into());
+            JsValue::from(object)
+        });
+        set(&snapshot, "network_conditions", conditions.into());
+        set(
+            &snapshot,
+            "max_connections",
+            self.max_connections.map(|m| m as f64).into(),
+        );
+        set(&snapshot, "http_proxy", self.http_proxy.clone().into());
+        let retry = self.retry.as_ref().map(|retry| {
+            let object = Object::new();
+            set(&object, "max_retries", retry.get_max_retries().into());
+            set(&object, "base_delay", retry.get_base_delay().into());
+            set(&object, "max_delay", retry.get_max_delay().into());
+            JsValue::from(object)
+        });
+        set(&snapshot, "retry", retry.into());
+        set(&snapshot, "max_redirects", self.max_redirects.into());
+        let backoff = self.backoff.as_ref().map(|backoff| {
+            let paces: Array = backoff
+                .paces()
+                .into_iter()
+                .map(|(addr, spacing, next)| {
+                    let object = Object::new();
+                    set(&object, "addr", addr.into());
+                    set(&object, "spacing", spacing.into());
+                    set(&object, "next", next.into());
+                    JsValue::from(object)
+                })
+                .collect();
+            JsValue::from(paces)
+        });
+        set(&snapshot, "backoff", backoff.into());
+
+        JSON::stringify(&snapshot)
+            .map(String::from)
+            .map_err(|e| ConnectionError {
+                message: format!("Failed to serialize client: {:?}", e),
+            })
+    }
+
+    /// Deserialize a snapshot from JSON produced by `to_json`.
+    pub fn from_json(json: &str) -> Result<Self, ConnectionError> {
+        let snapshot = JSON::parse(json).map_err(|e| ConnectionError {
+            message: format!("Invalid client snapshot: {:?}", e),
+        })?;
+        let version = get(&snapshot, "version").as_f64();
+        if version != Some(f64::from(SNAPSHOT_VERSION)) {
+            return Err(ConnectionError {
+                message: format!("Unsupported client snapshot version: {:?}", version),
+            });
+        }
+        let addr = get(&snapshot, "addr")
+            .as_string()
+            .ok_or_else(|| ConnectionError {
+                message: "Missing addr in client snapshot".to_string(),
+            })?;
+        let capabilities = Array::from(&get(&snapshot, "capabilities"))
+            .iter()
+            .filter_map(|c| c.as_string())
+            .collect();
+        let framing = match get(&snapshot, "framing").as_string().as_deref() {
+            Some("base64") => Framing::Base64,
+            Some("negotiate") => Framing::Negotiate,
+            _ => Framing::Binary,
+        };
+        let id_scheme = match get(&snapshot, "id_scheme").as_string().as_deref() {
+            Some("uuid_v7") => ConnIdScheme::UuidV7,
+            _ => ConnIdScheme::Compact,
+        };
+        let timeout_policy = match get(&snapshot, "timeout_policy").as_string().as_deref() {
+            Some("keep") => TimeoutPolicy::Keep,
+            _ => TimeoutPolicy::Close,
+        };
+        let network_conditions = object(&snapshot, "network_conditions").map(|object| {
+            let mut conditions = NetworkConditions::new();
+            conditions.set_latency(get(&object, "latency").as_f64().unwrap_or_default());
+            conditions.set_jitter(get(&object, "jitter").as_f64().unwrap_or_default());
+            conditions.set_drop_rate(get(&object, "drop_rate").as_f64().unwrap_or_default());
+            conditions
+        });
+        let retry = object(&snapshot, "retry").map(|object| {
+            let max_retries = get(&object, "max_retries").as_f64().unwrap_or_default();
+            let mut retry = RetryPolicy::new(max_retries as u32);
+            if let Some(delay) = get(&object, "base_delay").as_f64() {
+                retry.set_base_delay(delay);
+            }
+            if let Some(delay) = get(&object, "max_delay").as_f64() {
+                retry.set_max_delay(delay);
+            }
+            retry
+        });
+        let backoff = object(&snapshot, "backoff").map(|paces| {
+            let backoff = HostBackoff::default();
+            for pace in Array::from(&paces).iter() {
+                let addr = get(&pace, "addr").as_string();
+                let spacing = get(&pace, "spacing").as_f64();
+                let next = get(&pace, "next").as_f64();
+                if let (Some(addr), Some(spacing), Some(next)) = (addr, spacing, next) {
+                    backoff.set_pace(addr, spacing, next);
+                }
+            }
+            backoff
+        });
+
+        Ok(Self {
+            addr,
+            capabilities,
+            name: get(&snapshot, "name").as_string(),
+            framing,
+            id_scheme,
+            proxy_profile: get(&snapshot, "proxy_profile")
+                .as_string()
+                .and_then(|name| ProxyProfile::from_name(&name)),
+            proxy_url_template: get(&snapshot, "proxy_url_template").as_string(),
+            timeout_policy,
+            tls_fallback: get(&snapshot, "tls_fallback").as_string(),
+            tls_options: TlsOptions {
+                cipher_suites: get_names(&snapshot, "cipher_suites"),
+                kx_groups: get_names(&snapshot, "kx_groups"),
+                min_version: get(&snapshot, "tls_min_version")
+                    .as_string()
+                    .and_then(|v| TLSVersion::from_string(&v)),
+            },
+            capture_capacity: get(&snapshot, "capture_capacity")
+                .as_f64()
+                .map(|c| c as usize),
+            throttle_limits: (
+                get(&snapshot, "upload_limit").as_f64(),
+                get(&snapshot, "download_limit").as_f64(),
+            ),
+            network_conditions,
+            max_connections: get(&snapshot, "max_connections")
+                .as_f64()
+                .map(|m| m as usize),
+            http_proxy: get(&snapshot, "http_proxy").as_string(),
+            retry,
+            max_redirects: get(&snapshot, "max_redirects").as_f64().map(|m| m as u32),
+            backoff,
+        })
+    }
+}
+
+/// Get the name of a framing in snapshots.
+fn framing_name(framing: Framing) -> &'static str {
+    match framing {
+        Framing::Binary => "binary",
+        Framing::Base64 => "base64",
+        Framing::Negotiate => "negotiate",
+    }
+}
+
+/// Set a property of a JS object.
+fn set(object: &Object, key: &str, value: JsValue) {
+    Reflect::set(object, &key.into(), &value).unwrap_throw();
+}
+
+/// Get a property of a JS value, `undefined` if missing.
+fn get(value: &JsValue, key: &str) -> JsValue {
+    Reflect::get(value, &key.into()).unwrap_or(JsValue::UNDEFINED)
+}
+
+/// Get a property of a JS value holding an object, if present and not `null`.
+fn object(value: &JsValue, key: &str) -> Option<JsValue> {
+    let value = get(value, key);
+    value.is_object().then_some(value)
+}
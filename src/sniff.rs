@@ -0,0 +1,335 @@
+use wasm_bindgen::prelude::*;
+
+/// Number of bytes at the start of a body the type of the body is sniffed from.
+pub const RESOURCE_HEADER_SIZE: usize = 1445;
+
+/// Bytes ignored before the patterns allowing leading whitespace.
+const WHITESPACE: &[u8] = b"\t\n\x0c\r ";
+
+/// Tags starting an HTML document, matched ignoring ASCII case and followed by a space or `>`.
+const HTML_TAGS: [&[u8]; 17] = [
+    b"<!DOCTYPE HTML",
+    b"<HTML",
+    b"<HEAD",
+    b"<SCRIPT",
+    b"<IFRAME",
+    b"<H1",
+    b"<DIV",
+    b"<FONT",
+    b"<TABLE",
+    b"<A",
+    b"<STYLE",
+    b"<TITLE",
+    b"<B",
+    b"<BODY",
+    b"<BR",
+    b"<P",
+    b"<!--",
+];
+
+/// Byte pattern identifying a type, where the bytes of `mask` select the bits compared.
+struct Pattern {
+    /// Bytes to match
+    pattern: &'static [u8],
+    /// Bits of the bytes compared, as long as `pattern`
+    mask: &'static [u8],
+    /// Whether whitespace before the pattern is ignored
+    skip_whitespace: bool,
+    /// Type identified by the pattern
+    mime: &'static str,
+}
+
+impl Pattern {
+    /// Create a pattern comparing every bit of its bytes.
+    const fn exact(pattern: &'static [u8], mime: &'static str) -> Self {
+        Self {
+            pattern,
+            mask: &[0xFF; 16],
+            skip_whitespace: false,
+            mime,
+        }
+    }
+
+    /// Create a pattern comparing the bits selected by `mask`.
+    const fn masked(pattern: &'static [u8], mask: &'static [u8], mime: &'static str) -> Self {
+        Self {
+            pattern,
+            mask,
+            skip_whitespace: false,
+            mime,
+        }
+    }
+
+    /// Check if a resource header matches the pattern.
+    fn matches(&self, header: &[u8]) -> bool {
+        let start = if self.skip_whitespace {
+            header
+                .iter()
+                .position(|b| !WHITESPACE.contains(b))
+                .unwrap_or(header.len())
+        } else {
+            0
+        };
+        let header = &header[start..];
+        header.len() >= self.pattern.len()
+            && self
+                .pattern
+                .iter()
+                .zip(self.mask)
+                .zip(header)
+                .all(|((p, m), b)| b & m == *p)
+    }
+}
+
+/// Patterns of the types sniffed from the start of documents, in the order they are tried.
+const DOCUMENT_PATTERNS: &[Pattern] = &[
+    Pattern {
+        pattern: b"<?xml",
+        mask: &[0xFF; 5],
+        skip_whitespace: true,
+        mime: "text/xml",
+    },
+    Pattern::exact(b"%PDF-", "application/pdf"),
+    Pattern::exact(b"%!PS-Adobe-", "application/postscript"),
+    Pattern::masked(b"\xFE\xFF\x00\x00", b"\xFF\xFF\x00\x00", "text/plain"),
+    Pattern::masked(b"\xFF\xFE\x00\x00", b"\xFF\xFF\x00\x00", "text/plain"),
+    Pattern::exact(b"\xEF\xBB\xBF", "text/plain"),
+];
+
+/// Patterns of image types.
+const IMAGE_PATTERNS: &[Pattern] = &[
+    Pattern::exact(b"\x00\x00\x01\x00", "image/x-icon"),
+    Pattern::exact(b"\x00\x00\x02\x00", "image/x-icon"),
+    Pattern::exact(b"BM", "image/bmp"),
+    Pattern::exact(b"GIF87a", "image/gif"),
+    Pattern::exact(b"GIF89a", "image/gif"),
+    Pattern::masked(
+        b"RIFF\x00\x00\x00\x00WEBPVP",
+        b"\xFF\xFF\xFF\xFF\x00\x00\x00\x00\xFF\xFF\xFF\xFF\xFF\xFF",
+        "image/webp",
+    ),
+    Pattern::exact(b"\x89PNG\r\n\x1A\n", "image/png"),
+    Pattern::exact(b"\xFF\xD8\xFF", "image/jpeg"),
+];
+
+/// Patterns of audio and video types, besides MP4 and WebM which are parsed.
+const MEDIA_PATTERNS: &[Pattern] = &[
+    Pattern::masked(
+        b"FORM\x00\x00\x00\x00AIFF",
+        b"\xFF\xFF\xFF\xFF\x00\x00\x00\x00\xFF\xFF\xFF\xFF",
+        "audio/aiff",
+    ),
+    Pattern::exact(b"ID3", "audio/mpeg"),
+    Pattern::exact(b"OggS\x00", "application/ogg"),
+    Pattern::exact(b"MThd\x00\x00\x00\x06", "audio/midi"),
+    Pattern::masked(
+        b"RIFF\x00\x00\x00\x00AVI ",
+        b"\xFF\xFF\xFF\xFF\x00\x00\x00\x00\xFF\xFF\xFF\xFF",
+        "video/avi",
+    ),
+    Pattern::masked(
+        b"RIFF\x00\x00\x00\x00WAVE",
+        b"\xFF\xFF\xFF\xFF\x00\x00\x00\x00\xFF\xFF\xFF\xFF",
+        "audio/wave",
+    ),
+];
+
+/// Patterns of archive types.
+const ARCHIVE_PATTERNS: &[Pattern] = &[
+    Pattern::exact(b"\x1F\x8B\x08", "application/x-gzip"),
+    Pattern::exact(b"PK\x03\x04", "application/zip"),
+    Pattern::exact(b"Rar!\x1A\x07\x00", "application/x-rar-compressed"),
+];
+
+/// Sniff the type of a body from its first bytes, following the rules for identifying an
+/// unknown MIME type of the MIME Sniffing standard.
+///
+/// # Arguments
+///
+/// * `header` - First bytes of the body, only the first `RESOURCE_HEADER_SIZE` are used
+///
+/// # Returns
+///
+/// The type of the body, `text/plain` or `application/octet-stream` if no pattern matches.
+pub fn sniff(header: &[u8]) -> &'static str {
+    let header = &header[..header.len().min(RESOURCE_HEADER_SIZE)];
+    if is_html(header) {
+        return "text/html";
+    }
+    let matched = [DOCUMENT_PATTERNS, IMAGE_PATTERNS, MEDIA_PATTERNS]
+        .iter()
+        .flat_map(|patterns| patterns.iter())
+        .find(|pattern| pattern.matches(header));
+    if let Some(pattern) = matched {
+        return pattern.mime;
+    }
+    if is_mp4(header) {
+        return "video/mp4";
+    }
+    if is_webm(header) {
+        return "video/webm";
+    }
+    if let Some(pattern) = ARCHIVE_PATTERNS.iter().find(|p| p.matches(header)) {
+        return pattern.mime;
+    }
+    if header.iter().any(is_binary_byte) {
+        "application/octet-stream"
+    } else {
+        "text/plain"
+    }
+}
+
+/// Sniff the type of a body from its first bytes, as browsers do for responses without a
+/// `Content-Type`.
+///
+/// # Arguments
+///
+/// * `bytes` - First bytes of the body, 1445 are enough
+///
+/// # Returns
+///
+/// The type of the body, e.g. `text/html` or `image/png`, `text/plain` or
+/// `application/octet-stream` if unknown.
+#[wasm_bindgen]
+pub fn sniff_mime_type(bytes: &[u8]) -> String {
+    sniff(bytes).to_string()
+}
+
+/// Check if a resource header starts with an HTML tag or comment, after whitespace.
+fn is_html(header: &[u8]) -> bool {
+    let start = header
+        .iter()
+        .position(|b| !WHITESPACE.contains(b))
+        .unwrap_or(header.len());
+    let header = &header[start..];
+    HTML_TAGS.iter().any(|tag| {
+        header.len() > tag.len()
+            && header[..tag.len()].eq_ignore_ascii_case(tag)
+            && matches!(header[tag.len()], b' ' | b'>')
+    })
+}
+
+/// Check if a resource header is the start of an MP4 file, from its `ftyp` box.
+fn is_mp4(header: &[u8]) -> bool {
+    if header.len() < 12 {
+        return false;
+    }
+    let box_size = u32::from_be_bytes([header[0], header[1], header[2], header[3]]) as usize;
+    if box_size < 12
+        || header.len() < box_size
+        || !box_size.is_multiple_of(4)
+        || &header[4..8] != b"ftyp"
+    {
+        return false;
+    }
+    // Major brand, then the compatible brands after the minor version
+    &header[8..11] == b"mp4"
+        || (16..box_size)
+            .step_by(4)
+            .any(|i| &header[i..i + 3] == b"mp4")
+}
+
+/// Check if a resource header is the start of a WebM file, from the doctype of its EBML header.
+fn is_webm(header: &[u8]) -> bool {
+    if !header.starts_with(b"\x1A\x45\xDF\xA3") {
+        return false;
+    }
+    let mut i = 4;
+    while i < header.len() && i < 38 {
+        if header[i..].starts_with(b"\x42\x82") {
+            // DocType element, followed by the size of its value as a variable length integer
+            i += 2;
+            if i >= header.len() {
+                break;
+            }
+            i += vint_size(header[i]);
+            if i + 4 > header.len() {
+                break;
+            }
+            if &header[i..i + 4] == b"webm" {
+                return true;
+            }
+        }
+        i += 1;
+    }
+    false
+}
+
+/// Get the number of bytes of a variable length integer of EBML from its first byte.
+fn vint_size(first: u8) -> usize {
+    (first.leading_zeros() as usize + 1).min(8)
+}
+
+/// Check if a byte can't appear in text, per the MIME Sniffing standard.
+fn is_binary_byte(byte: &u8) -> bool {
+    matches!(byte, 0x00..=0x08 | 0x0B | 0x0E..=0x1A | 0x1C..=0x1F)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Samples of typed bodies, with the number of leading bytes identifying their type.
+    const SAMPLES: [(&[u8], &str, usize); 8] = [
+        (b"\x89PNG\r\n\x1A\n\0\0\0\rIHDR", "image/png", 8),
+        (b"GIF89a\x01\x00", "image/gif", 6),
+        (b"\xFF\xD8\xFF\xE0", "image/jpeg", 3),
+        (b"RIFF\x24\0\0\0WEBPVP8 ", "image/webp", 14),
+        (b"%PDF-1.7\n", "application/pdf", 5),
+        (b"\x1F\x8B\x08\0", "application/x-gzip", 3),
+        (b"\0\0\0\x18ftypisom\0\0\x02\0isommp41", "video/mp4", 24),
+        (
+            b"\x1A\x45\xDF\xA3\x9F\x42\x86\x81\x01\x42\x82\x84webm",
+            "video/webm",
+            16,
+        ),
+    ];
+
+    #[test]
+    fn sniffs_complete_magic_bytes() {
+        for (sample, mime, _) in SAMPLES {
+            assert_eq!(sniff(sample), mime);
+        }
+    }
+
+    #[test]
+    fn ignores_truncated_magic_bytes() {
+        for (sample, mime, magic) in SAMPLES {
+            for len in 0..magic {
+                assert_ne!(sniff(&sample[..len]), mime, "{} truncated to {}", mime, len);
+            }
+        }
+        assert_eq!(sniff(b"GIF8"), "text/plain");
+        assert_eq!(sniff(b"\x89PNG\r\n\x1A"), "application/octet-stream");
+    }
+
+    #[test]
+    fn ignores_truncated_webm_doctypes() {
+        assert_eq!(
+            sniff(b"\x1A\x45\xDF\xA3\x42\x82"),
+            "application/octet-stream"
+        );
+        assert_eq!(
+            sniff(b"\x1A\x45\xDF\xA3\x42\x82\x84web"),
+            "application/octet-stream"
+        );
+    }
+
+    #[test]
+    fn sniffs_html_after_whitespace() {
+        assert_eq!(sniff(b"\r\n  <!doctype html>"), "text/html");
+        assert_eq!(sniff(b"<p>hi</p>"), "text/html");
+        assert_eq!(sniff(b"<HTML"), "text/plain");
+        assert_eq!(sniff(b"<pre>"), "text/plain");
+    }
+
+    #[test]
+    fn tells_text_from_binary() {
+        assert_eq!(sniff(b""), "text/plain");
+        assert_eq!(sniff("plain text, € included\n".as_bytes()), "text/plain");
+        assert_eq!(sniff(b"text\0with a null"), "application/octet-stream");
+        let mut long = vec![b'a'; RESOURCE_HEADER_SIZE];
+        long.push(0);
+        assert_eq!(sniff(&long), "text/plain");
+    }
+}
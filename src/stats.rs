@@ -0,0 +1,408 @@
+use std::{cell::RefCell, collections::BTreeMap, rc::Rc};
+
+use wasm_bindgen::prelude::*;
+
+use crate::SocketCapability;
+
+/// Get the name statistics of connections of a protocol are grouped under.
+fn protocol_name(protocol: SocketCapability) -> &'static str {
+    match protocol {
+        SocketCapability::TCP => "tcp",
+        SocketCapability::UDP => "udp",
+        SocketCapability::HTTP => "http",
+        SocketCapability::HTTPS(_) => "https",
+    }
+}
+
+/// Number of buckets per power of two of a histogram, bounding the error of its percentiles to
+/// about 6%.
+const SUB_BUCKETS: u64 = 16;
+
+/// Histogram of durations, with buckets growing exponentially like an HDR histogram.
+///
+/// Durations are recorded in µs. Values below `2 * SUB_BUCKETS` each have their own bucket,
+/// then every power of two is split into `SUB_BUCKETS` buckets of equal width.
+#[derive(Clone, Debug, Default)]
+struct LatencyHistogram {
+    /// Number of values recorded in each bucket
+    buckets: Vec<u32>,
+    /// Number of values recorded
+    count: u32,
+    /// Sum of the values recorded, in ms
+    sum: f64,
+    /// Smallest value recorded, in ms
+    min: f64,
+    /// Largest value recorded, in ms
+    max: f64,
+}
+
+impl LatencyHistogram {
+    /// Get the index of the bucket of a value, in µs.
+    fn bucket(value: u64) -> usize {
+        if value < 2 * SUB_BUCKETS {
+            return value as usize;
+        }
+        let shift = value.ilog2() - SUB_BUCKETS.ilog2();
+        (shift as u64 * SUB_BUCKETS + (value >> shift)) as usize
+    }
+
+    /// Get the smallest value of a bucket and its width, in µs.
+    fn bucket_range(index: usize) -> (u64, u64) {
+        let index = index as u64;
+        if index < 2 * SUB_BUCKETS {
+            return (index, 1);
+        }
+        let shift = index / SUB_BUCKETS - 1;
+        ((index - shift * SUB_BUCKETS) << shift, 1 << shift)
+    }
+
+    /// Record a duration.
+    ///
+    /// # Arguments
+    ///
+    /// * `millis` - Duration in ms, ignored if negative or not finite
+    fn record(&mut self, millis: f64) {
+        if !millis.is_finite() || millis < 0.0 {
+            return;
+        }
+        let index = Self::bucket((millis * 1000.0).round() as u64);
+        if self.buckets.len() <= index {
+            self.buckets.resize(index + 1, 0);
+        }
+        self.buckets[index] += 1;
+        if self.count == 0 || millis < self.min {
+            self.min = millis;
+        }
+        self.max = self.max.max(millis);
+        self.count += 1;
+        self.sum += millis;
+    }
+
+    /// Get the value below which a fraction of the values recorded fall, in ms.
+    ///
+    /// The middle of the bucket holding it is returned, within the smallest and largest
+    /// values recorded.
+    ///
+    /// # Arguments
+    ///
+    /// * `quantile` - Fraction of the values, between 0 and 1
+    fn percentile(&self, quantile: f64) -> f64 {
+        let rank = ((quantile * self.count as f64).ceil() as u32).max(1);
+        let mut seen = 0;
+        for (index, &count) in self.buckets.iter().enumerate() {
+            seen += count;
+            if seen >= rank {
+                let (start, width) = Self::bucket_range(index);
+                let middle = (start as f64 + (width - 1) as f64 / 2.0) / 1000.0;
+                return middle.clamp(self.min, self.max);
+            }
+        }
+        self.max
+    }
+
+    /// Summarize the values recorded, if any.
+    fn summary(&self) -> Option<LatencySummary> {
+        (self.count > 0).then(|| LatencySummary {
+            count: self.count,
+            min: self.min,
+            max: self.max,
+            mean: self.sum / self.count as f64,
+            p50: self.percentile(0.50),
+            p95: self.percentile(0.95),
+            p99: self.percentile(0.99),
+        })
+    }
+}
+
+/// Summary of the durations recorded by a histogram, in ms.
+///
+/// Percentiles are approximate, within about 6% of the exact value.
+#[wasm_bindgen]
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct LatencySummary {
+    /// Number of durations recorded
+    count: u32,
+    /// Shortest duration recorded
+    min: f64,
+    /// Longest duration recorded
+    max: f64,
+    /// Average of the durations recorded
+    mean: f64,
+    /// Median duration
+    p50: f64,
+    /// 95th percentile
+    p95: f64,
+    /// 99th percentile
+    p99: f64,
+}
+
+#[wasm_bindgen]
+impl LatencySummary {
+    /// Get the number of durations recorded.
+    #[wasm_bindgen]
+    pub fn get_count(&self) -> u32 {
+        self.count
+    }
+
+    /// Get the shortest duration recorded, in ms.
+    #[wasm_bindgen]
+    pub fn get_min(&self) -> f64 {
+        self.min
+    }
+
+    /// Get the longest duration recorded, in ms.
+    #[wasm_bindgen]
+    pub fn get_max(&self) -> f64 {
+        self.max
+    }
+
+    /// Get the average of the durations recorded, in ms.
+    #[wasm_bindgen]
+    pub fn get_mean(&self) -> f64 {
+        self.mean
+    }
+
+    /// Get the median duration, in ms.
+    #[wasm_bindgen]
+    pub fn get_p50(&self) -> f64 {
+        self.p50
+    }
+
+    /// Get the duration 95% of the durations recorded are below, in ms.
+    #[wasm_bindgen]
+    pub fn get_p95(&self) -> f64 {
+        self.p95
+    }
+
+    /// Get the duration 99% of the durations recorded are below, in ms.
+    #[wasm_bindgen]
+    pub fn get_p99(&self) -> f64 {
+        self.p99
+    }
+}
+
+/// Totals across all connections of a client, as of the time they were taken.
+#[wasm_bindgen]
+#[derive(Clone, Debug, Default)]
+pub struct ClientStats {
+    /// Name of the client, if any
+    client_name: Option<String>,
+    /// Number of connections opened, by tag
+    tagged: BTreeMap<String, u32>,
+    /// Number of connections opened, by protocol
+    opened: BTreeMap<&'static str, u32>,
+    /// Number of connections closed, by protocol
+    closed: BTreeMap<&'static str, u32>,
+    /// Number of bytes received
+    bytes_in: f64,
+    /// Number of bytes sent
+    bytes_out: f64,
+    /// Number of HTTP and HTTPS requests sent
+    requests: u32,
+    /// Number of HTTP and HTTPS responses received
+    responses: u32,
+    /// Sum of the times between sending requests and receiving their response, in ms
+    response_time: f64,
+    /// Times connections took to open, a round trip through the proxy to the remote end
+    connect_times: LatencyHistogram,
+    /// Times between sending requests and receiving the first byte of their response
+    first_byte_times: LatencyHistogram,
+    /// Number of socket, TLS and response parsing errors
+    errors: u32,
+}
+
+#[wasm_bindgen]
+impl ClientStats {
+    /// Get the number of connections opened.
+    ///
+    /// # Arguments
+    ///
+    /// * `protocol` - Protocol of the connections to count, e.g. `tcp` or `https`, or `None` for all
+    #[wasm_bindgen]
+    pub fn get_opened(&self, protocol: Option<String>) -> u32 {
+        Self::count(&self.opened, protocol.as_deref())
+    }
+
+    /// Get the number of connections opened with the given tag.
+    ///
+    /// # Arguments
+    ///
+    /// * `tag` - Tag the connections were created with
+    #[wasm_bindgen]
+    pub fn get_opened_with_tag(&self, tag: &str) -> u32 {
+        self.tagged.get(tag).copied().unwrap_or_default()
+    }
+
+    /// Get the name of the client, if any.
+    #[wasm_bindgen]
+    pub fn get_client_name(&self) -> Option<String> {
+        self.client_name.clone()
+    }
+
+    /// Get the number of connections closed.
+    ///
+    /// # Arguments
+    ///
+    /// * `protocol` - Protocol of the connections to count, e.g. `tcp` or `https`, or `None` for all
+    #[wasm_bindgen]
+    pub fn get_closed(&self, protocol: Option<String>) -> u32 {
+        Self::count(&self.closed, protocol.as_deref())
+    }
+
+    /// Get the number of connections not closed yet.
+    ///
+    /// # Arguments
+    ///
+    /// * `protocol` - Protocol of the connections to count, e.g. `tcp` or `https`, or `None` for all
+    #[wasm_bindgen]
+    pub fn get_open(&self, protocol: Option<String>) -> u32 {
+        let protocol = protocol.as_deref();
+        Self::count(&self.opened, protocol).saturating_sub(Self::count(&self.closed, protocol))
+    }
+
+    /// Get the number of bytes received.
+    #[wasm_bindgen]
+    pub fn get_bytes_in(&self) -> f64 {
+        self.bytes_in
+    }
+
+    /// Get the number of bytes sent.
+    #[wasm_bindgen]
+    pub fn get_bytes_out(&self) -> f64 {
+        self.bytes_out
+    }
+
+    /// Get the number of HTTP and HTTPS requests sent.
+    #[wasm_bindgen]
+    pub fn get_requests(&self) -> u32 {
+        self.requests
+    }
+
+    /// Get the number of socket, TLS and response parsing errors.
+    #[wasm_bindgen]
+    pub fn get_errors(&self) -> u32 {
+        self.errors
+    }
+
+    /// Get the average time between sending a request and receiving its response, in ms, if
+    /// any response was received.
+    #[wasm_bindgen]
+    pub fn get_average_response_time(&self) -> Option<f64> {
+        (self.responses > 0).then(|| self.response_time / self.responses as f64)
+    }
+
+    /// Get the percentiles of the times connections took to open, i.e. the round trip
+    /// through the proxy to the remote end, if any connection opened.
+    #[wasm_bindgen]
+    pub fn get_connect_latency(&self) -> Option<LatencySummary> {
+        self.connect_times.summary()
+    }
+
+    /// Get the percentiles of the times between sending a HTTP or HTTPS request and receiving
+    /// the first byte of its response, if any response was received.
+    #[wasm_bindgen]
+    pub fn get_time_to_first_byte(&self) -> Option<LatencySummary> {
+        self.first_byte_times.summary()
+    }
+}
+
+impl ClientStats {
+    /// Count the connections of a protocol, or of all protocols.
+    fn count(counts: &BTreeMap<&'static str, u32>, protocol: Option<&str>) -> u32 {
+        match protocol {
+            Some(protocol) => counts
+                .get(protocol.to_lowercase().as_str())
+                .copied()
+                .unwrap_or_default(),
+            None => counts.values().sum(),
+        }
+    }
+}
+
+/// Handle recording the statistics of a client, shared by all its connections.
+#[derive(Clone, Debug, Default)]
+pub struct StatsRecorder {
+    /// Totals recorded so far
+    stats: Rc<RefCell<ClientStats>>,
+}
+
+impl StatsRecorder {
+    /// Get a copy of the totals recorded so far.
+    ///
+    /// # Arguments
+    ///
+    /// * `client_name` - Name of the client, if any
+    pub fn snapshot(&self, client_name: Option<String>) -> ClientStats {
+        ClientStats {
+            client_name,
+            ..self.stats.borrow().clone()
+        }
+    }
+
+    /// Record a connection being opened.
+    ///
+    /// # Arguments
+    ///
+    /// * `protocol` - Protocol of the connection
+    /// * `tags` - Tags the connection was created with
+    pub fn record_open(&self, protocol: SocketCapability, tags: &[String]) {
+        let mut stats = self.stats.borrow_mut();
+        *stats.opened.entry(protocol_name(protocol)).or_default() += 1;
+        for tag in tags {
+            *stats.tagged.entry(tag.clone()).or_default() += 1;
+        }
+    }
+
+    /// Record a connection being opened by the remote end.
+    ///
+    /// # Arguments
+    ///
+    /// * `time` - Time the connection took to open, in ms
+    pub fn record_connect(&self, time: f64) {
+        self.stats.borrow_mut().connect_times.record(time);
+    }
+
+    /// Record a connection being closed.
+    pub fn record_close(&self, protocol: SocketCapability) {
+        *self
+            .stats
+            .borrow_mut()
+            .closed
+            .entry(protocol_name(protocol))
+            .or_default() += 1;
+    }
+
+    /// Record bytes received.
+    pub fn record_bytes_in(&self, len: usize) {
+        self.stats.borrow_mut().bytes_in += len as f64;
+    }
+
+    /// Record bytes sent.
+    pub fn record_bytes_out(&self, len: usize) {
+        self.stats.borrow_mut().bytes_out += len as f64;
+    }
+
+    /// Record an HTTP or HTTPS request being sent.
+    pub fn record_request(&self) {
+        self.stats.borrow_mut().requests += 1;
+    }
+
+    /// Record the response of a request being received.
+    ///
+    /// # Arguments
+    ///
+    /// * `time` - Time between sending the request and receiving the response, in ms
+    /// * `first_byte` - Time between sending the request and receiving the first byte of the
+    ///   response, in ms
+    pub fn record_response(&self, time: f64, first_byte: f64) {
+        let mut stats = self.stats.borrow_mut();
+        stats.responses += 1;
+        stats.response_time += time;
+        stats.first_byte_times.record(first_byte);
+    }
+
+    /// Record a socket, TLS or response parsing error.
+    pub fn record_error(&self) {
+        self.stats.borrow_mut().errors += 1;
+    }
+}
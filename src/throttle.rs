@@ -0,0 +1,113 @@
+use std::cell::{Cell, RefCell};
+
+use wasm_bindgen_futures::js_sys;
+
+/// Token bucket limiting the rate of a transfer.
+///
+/// Transfers are never refused: a transfer larger than the tokens available puts the bucket
+/// in debt, and is delayed until the debt is paid back. Since later transfers inherit the
+/// debt, delays preserve the order of transfers.
+#[derive(Debug)]
+struct TokenBucket {
+    /// Refill rate, in bytes per second
+    rate: f64,
+    /// Maximum number of tokens, in bytes
+    burst: f64,
+    /// Tokens available, negative when in debt
+    tokens: f64,
+    /// Time of the last refill, in ms since the epoch
+    last: f64,
+}
+
+impl TokenBucket {
+    /// Create a new full bucket.
+    ///
+    /// # Arguments
+    ///
+    /// * `rate` - Refill rate, in bytes per second
+    fn new(rate: f64) -> Self {
+        // Allow bursts of a tenth of a second of traffic
+        let burst = rate / 10.0;
+        Self {
+            rate,
+            burst,
+            tokens: burst,
+            last: js_sys::Date::now(),
+        }
+    }
+
+    /// Take tokens for a transfer.
+    ///
+    /// # Returns
+    ///
+    /// The delay before the transfer may happen, in ms.
+    fn take(&mut self, bytes: usize) -> f64 {
+        let now = js_sys::Date::now();
+        self.tokens = (self.tokens + (now - self.last) * self.rate / 1000.0).min(self.burst);
+        self.last = now;
+        self.tokens -= bytes as f64;
+        if self.tokens >= 0.0 {
+            0.0
+        } else {
+            -self.tokens * 1000.0 / self.rate
+        }
+    }
+}
+
+/// Upload and download rate limits of a connection.
+#[derive(Debug, Default)]
+pub struct Throttle {
+    /// Limit of the bytes sent, if any
+    upload: RefCell<Option<TokenBucket>>,
+    /// Limit of the bytes received, if any
+    download: RefCell<Option<TokenBucket>>,
+    /// Whether a delayed message is being delivered, so it is not delayed again
+    delivering: Cell<bool>,
+}
+
+impl Throttle {
+    /// Set the rate limits, replacing the previous ones.
+    ///
+    /// # Arguments
+    ///
+    /// * `upload` - Limit of the bytes sent per second, or `None` for no limit
+    /// * `download` - Limit of the bytes received per second, or `None` for no limit
+    pub fn set_limits(&self, upload: Option<f64>, download: Option<f64>) {
+        let bucket = |rate: Option<f64>| rate.filter(|r| *r > 0.0).map(TokenBucket::new);
+        *self.upload.borrow_mut() = bucket(upload);
+        *self.download.borrow_mut() = bucket(download);
+    }
+
+    /// Get the delay before sending the given number of bytes, in ms.
+    pub fn upload_delay(&self, bytes: usize) -> f64 {
+        self.upload
+            .borrow_mut()
+            .as_mut()
+            .map_or(0.0, |bucket| bucket.take(bytes))
+    }
+
+    /// Get the delay before delivering the given number of received bytes, in ms.
+    pub fn download_delay(&self, bytes: usize) -> f64 {
+        self.download
+            .borrow_mut()
+            .as_mut()
+            .map_or(0.0, |bucket| bucket.take(bytes))
+    }
+
+    /// Check if no download limit is set.
+    pub fn is_download_unlimited(&self) -> bool {
+        self.download.borrow().is_none()
+    }
+
+    /// Check if a delayed message is being delivered.
+    pub fn is_delivering(&self) -> bool {
+        self.delivering.get()
+    }
+
+    /// Run `deliver` while marking a delayed message as being delivered.
+    pub fn deliver(&self, deliver: impl FnOnce()) {
+        self.delivering.set(true);
+        deliver();
+        self.delivering.set(false);
+    }
+}
@@ -0,0 +1,216 @@
+use std::{cell::Cell, rc::Rc};
+
+use wasm_bindgen::prelude::*;
+use wasm_bindgen_futures::js_sys;
+use web_sys::{AddEventListenerOptions, WebSocket};
+
+/// Time spent setting up a connection, charged to the first request sent on it.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct ConnectionSetup {
+    /// Time the socket was created at, in ms since the epoch
+    created: f64,
+    /// Time the socket was opened at, in ms since the epoch, once open
+    opened: Rc<Cell<Option<f64>>>,
+    /// Time the creation waited for a free slot of the limiter, in ms
+    queue_wait: Rc<Cell<f64>>,
+    /// Whether the setup was already charged to a request
+    charged: Rc<Cell<bool>>,
+}
+
+impl ConnectionSetup {
+    /// Start timing the setup of a connection, from now until its socket opens.
+    pub(crate) fn start(socket: &WebSocket) -> Self {
+        let setup = Self {
+            created: js_sys::Date::now(),
+            ..Self::default()
+        };
+        let opened = setup.opened.clone();
+        let onopen = Closure::once_into_js(move || opened.set(Some(js_sys::Date::now())));
+        socket
+            .add_event_listener_with_callback_and_add_event_listener_options(
+                "open",
+                onopen.unchecked_ref(),
+                AddEventListenerOptions::new().once(true),
+            )
+            .unwrap_throw();
+        setup
+    }
+
+    /// Set the time the creation of the connection waited for a free slot, in ms.
+    pub(crate) fn set_queue_wait(&self, wait: f64) {
+        self.queue_wait.set(wait);
+    }
+
+    /// Get the time the connection took to open, in ms, once open.
+    pub(crate) fn connect_time(&self) -> Option<f64> {
+        self.opened.get().map(|opened| opened - self.created)
+    }
+
+    /// Take the queue wait and connect time of the connection, in ms, both 0 after the
+    /// first request.
+    fn take(&self) -> (f64, f64) {
+        if self.charged.replace(true) {
+            return (0.0, 0.0);
+        }
+        (self.queue_wait.get(), self.connect_time().unwrap_or(0.0))
+    }
+}
+
+/// Timer of the phases of a request, shared by the callbacks handling its response.
+#[derive(Clone, Debug)]
+pub(crate) struct RequestTimer {
+    /// Time the connection waited for a free slot, in ms
+    queue_wait: f64,
+    /// Time the connection took to open, in ms
+    connect: f64,
+    /// Time the request was sent at, in ms since the epoch
+    started: f64,
+    /// Time the TLS handshake completed at, in ms since the epoch, if it did
+    handshake_end: Rc<Cell<Option<f64>>>,
+    /// Time the first byte of the response was received at, in ms since the epoch
+    first_byte: Rc<Cell<Option<f64>>>,
+}
+
+impl RequestTimer {
+    /// Start timing a request sent now on a connection.
+    ///
+    /// # Arguments
+    ///
+    /// * `setup` - Setup of the connection, charged to this request if it is the first one
+    pub(crate) fn start(setup: &ConnectionSetup) -> Self {
+        let (queue_wait, connect) = setup.take();
+        Self {
+            queue_wait,
+            connect,
+            started: js_sys::Date::now(),
+            handshake_end: Rc::new(Cell::new(None)),
+            first_byte: Rc::new(Cell::new(None)),
+        }
+    }
+
+    /// Mark the TLS handshake of the request as complete.
+    pub(crate) fn handshake_done(&self) {
+        if self.handshake_end.get().is_none() {
+            self.handshake_end.set(Some(js_sys::Date::now()));
+        }
+    }
+
+    /// Mark the first byte of the response as received, if not already.
+    pub(crate) fn first_byte(&self) {
+        if self.first_byte.get().is_none() {
+            self.first_byte.set(Some(js_sys::Date::now()));
+        }
+    }
+
+    /// Get the time since the request was sent, in ms.
+    pub(crate) fn elapsed(&self) -> f64 {
+        js_sys::Date::now() - self.started
+    }
+
+    /// Get the time from sending the request to receiving the first byte of its response, or
+    /// to now if none was received yet, in ms.
+    pub(crate) fn time_to_first_byte(&self) -> f64 {
+        self.first_byte.get().unwrap_or_else(js_sys::Date::now) - self.started
+    }
+
+    /// Get the timing of the request, ending now.
+    pub(crate) fn finish(&self) -> RequestTiming {
+        let end = js_sys::Date::now();
+        RequestTiming {
+            start_time: self.started,
+            queue_wait: self.queue_wait,
+            connect: self.connect,
+            tls_handshake: self.handshake_end.get().map(|t| t - self.started),
+            time_to_first_byte: self.first_byte.get().unwrap_or(end) - self.started,
+            duration: end - self.started,
+        }
+    }
+}
+
+/// Timing of the phases of a request, like a `PerformanceResourceTiming` for tunneled
+/// traffic.
+///
+/// The queue wait and connect time are only charged to the first request sent on a
+/// connection, later requests reusing it report 0. The TLS handshake, time to first byte
+/// and duration are measured from the time the request was sent, as a TLS session is started
+/// with every HTTPS request.
+#[wasm_bindgen]
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct RequestTiming {
+    /// Time the request was sent at, in ms since the epoch
+    start_time: f64,
+    /// Time the connection waited for a free slot, in ms
+    queue_wait: f64,
+    /// Time the connection took to open, in ms
+    connect: f64,
+    /// Time the TLS handshake took, in ms, if the request was sent over HTTPS
+    tls_handshake: Option<f64>,
+    /// Time until the first byte of the response was received, in ms
+    time_to_first_byte: f64,
+    /// Time until the response was complete, in ms
+    duration: f64,
+}
+
+impl RequestTiming {
+    /// Hide the phases of the request, keeping its start time and duration, as browsers do
+    /// for cross-origin resources not allowed by `Timing-Allow-Origin`.
+    pub(crate) fn restricted(self) -> Self {
+        Self {
+            queue_wait: 0.0,
+            connect: 0.0,
+            tls_handshake: None,
+            time_to_first_byte: 0.0,
+            ..self
+        }
+    }
+}
+
+#[wasm_bindgen]
+impl RequestTiming {
+    /// Get the time the request was sent at, in ms since the epoch.
+    #[wasm_bindgen]
+    pub fn get_start_time(&self) -> f64 {
+        self.start_time
+    }
+
+    /// Get the time the connection waited for a free slot before opening, in ms.
+    #[wasm_bindgen]
+    pub fn get_queue_wait(&self) -> f64 {
+        self.queue_wait
+    }
+
+    /// Get the time the connection took to open through the proxy, in ms.
+    #[wasm_bindgen]
+    pub fn get_connect(&self) -> f64 {
+        self.connect
+    }
+
+    /// Get the time the TLS handshake took, in ms, or `None` if the request was not sent
+    /// over HTTPS.
+    #[wasm_bindgen]
+    pub fn get_tls_handshake(&self) -> Option<f64> {
+        self.tls_handshake
+    }
+
+    /// Get the time from sending the request to receiving the first byte of its response,
+    /// including the TLS handshake, in ms.
+    #[wasm_bindgen]
+    pub fn get_time_to_first_byte(&self) -> f64 {
+        self.time_to_first_byte
+    }
+
+    /// Get the time from sending the request to receiving its whole response, in ms.
+    ///
+    /// For streamed responses, this ends once the head is received.
+    #[wasm_bindgen]
+    pub fn get_duration(&self) -> f64 {
+        self.duration
+    }
+
+    /// Get the total time of the request, from queueing its connection to receiving its
+    /// whole response, in ms.
+    #[wasm_bindgen]
+    pub fn get_total(&self) -> f64 {
+        self.queue_wait + self.connect + self.duration
+    }
+}
@@ -0,0 +1,776 @@
+use std::{
+    cell::{Cell, RefCell},
+    collections::{HashMap, VecDeque},
+    io::{ErrorKind, Read, Write},
+    rc::Rc,
+    sync::{Arc, Mutex},
+};
+
+use rustls::{
+    crypto::CryptoProvider,
+    version::{TLS12, TLS13},
+    AlertDescription, ClientConfig, ClientConnection, ProtocolVersion, RootCertStore,
+    SupportedProtocolVersion,
+};
+use rustls_pki_types::{DnsName, IpAddr, ServerName};
+use wasm_bindgen::prelude::*;
+
+use crate::{connection::ConnectionError, worker::TlsWorker, TLSVersion};
+
+/// Milestone of a TLS handshake, reached in this order.
+#[wasm_bindgen]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum HandshakeStage {
+    /// The `ClientHello` was sent to the server
+    ClientHelloSent,
+    /// The `ServerHello` was received, the version and cipher suite are chosen
+    ServerHelloReceived,
+    /// The certificates of the server were verified
+    CertificatesVerified,
+    /// The handshake is complete, application data can be exchanged
+    Complete,
+}
+
+impl HandshakeStage {
+    /// Every stage, in the order they are reached.
+    const ALL: [HandshakeStage; 4] = [
+        HandshakeStage::ClientHelloSent,
+        HandshakeStage::ServerHelloReceived,
+        HandshakeStage::CertificatesVerified,
+        HandshakeStage::Complete,
+    ];
+
+    /// Get the name of this stage, e.g. `client_hello_sent`.
+    pub fn name(self) -> &'static str {
+        match self {
+            HandshakeStage::ClientHelloSent => "client_hello_sent",
+            HandshakeStage::ServerHelloReceived => "server_hello_received",
+            HandshakeStage::CertificatesVerified => "certificates_verified",
+            HandshakeStage::Complete => "complete",
+        }
+    }
+
+    /// Parse the name of a stage, as returned by `name`.
+    pub fn from_name(name: &str) -> Option<HandshakeStage> {
+        Self::ALL.into_iter().find(|stage| stage.name() == name)
+    }
+}
+
+/// Output of a single step of the TLS state machine.
+#[derive(Default, Debug)]
+pub struct TlsOutput {
+    /// Ciphertext that must be sent to the remote end
+    pub tls: Vec<u8>,
+    /// Decrypted application data received from the remote end
+    pub plaintext: Vec<u8>,
+    /// Whether the handshake is still in progress
+    pub handshaking: bool,
+    /// TLS version negotiated, once the handshake is complete
+    pub version: Option<TLSVersion>,
+    /// Handshake milestones reached during this step, in order
+    pub stages: Vec<HandshakeStage>,
+    /// Name of the cipher suite negotiated, once the handshake is complete
+    pub cipher_suite: Option<String>,
+    /// Lines of the key log written during this step
+    #[cfg(feature = "keylog")]
+    pub key_log: Vec<String>,
+}
+
+/// Start of the message of errors caused by the server rejecting the offered TLS versions.
+const VERSION_REJECTED: &str = "TLS version rejected by the server";
+
+/// Check if an error was caused by the server rejecting the offered TLS versions.
+pub fn is_version_rejection(error: &ConnectionError) -> bool {
+    error.message.starts_with(VERSION_REJECTED)
+}
+
+/// Versions the HTTPS connections of a client fell back to, by address, so new connections
+/// to a server rejecting the versions first offered offer the lower one instead.
+#[derive(Clone, Debug, Default)]
+pub struct TlsDowngrades(Rc<RefCell<HashMap<String, TLSVersion>>>);
+
+impl TlsDowngrades {
+    /// Get the version connections to an address fell back to, if any.
+    pub fn get(&self, addr: &str) -> Option<TLSVersion> {
+        self.0.borrow().get(addr).copied()
+    }
+
+    /// Record the version connections to an address fall back to.
+    pub fn record(&self, addr: &str, version: TLSVersion) {
+        self.0.borrow_mut().insert(addr.to_string(), version);
+    }
+}
+
+/// Handle tracking the TLS versions of a HTTPS connection, shared by all its clones.
+#[derive(Clone, Debug)]
+pub struct TlsVersions {
+    /// Lowest version offered
+    min: TLSVersion,
+    /// Highest version offered
+    max: TLSVersion,
+    /// Lower versions to fall back to, the highest first, if the server rejects the offered ones
+    fallback: Rc<[TLSVersion]>,
+    /// Where the version fallen back to is recorded for new connections, with the address of
+    /// this one
+    downgrades: Option<(TlsDowngrades, String)>,
+    /// Whether the server rejected the offered versions, new connections falling back
+    fell_back: Rc<Cell<bool>>,
+    /// Version negotiated by the last completed handshake
+    negotiated: Rc<Cell<Option<TLSVersion>>>,
+}
+
+impl TlsVersions {
+    /// Create new TLS versions of a connection.
+    ///
+    /// # Arguments
+    ///
+    /// * `min` - Lowest version to offer
+    /// * `max` - Highest version to offer
+    /// * `fallback` - Versions the client allows falling back to, in any order
+    pub fn new(min: TLSVersion, max: TLSVersion, fallback: &[TLSVersion]) -> Self {
+        let mut fallback: Vec<TLSVersion> = fallback.iter().copied().filter(|v| *v < min).collect();
+        fallback.sort_by(|a, b| b.cmp(a));
+        Self {
+            min,
+            max,
+            fallback: fallback.into(),
+            downgrades: None,
+            fell_back: Rc::new(Cell::new(false)),
+            negotiated: Rc::new(Cell::new(None)),
+        }
+    }
+
+    /// Offer the version previous connections to an address fell back to, if allowed, and
+    /// record the version this connection falls back to.
+    ///
+    /// # Arguments
+    ///
+    /// * `downgrades` - Versions the connections of the client fell back to
+    /// * `addr` - Address of the connection
+    pub fn with_downgrades(mut self, downgrades: &TlsDowngrades, addr: &str) -> Self {
+        if let Some(version) = downgrades.get(addr).filter(|v| self.fallback.contains(v)) {
+            self.min = version;
+            self.max = version;
+            self.fallback = self
+                .fallback
+                .iter()
+                .copied()
+                .filter(|v| *v < version)
+                .collect();
+        }
+        self.downgrades = Some((downgrades.clone(), addr.to_string()));
+        self
+    }
+
+    /// Get the lowest and highest versions offered.
+    pub fn range(&self) -> (TLSVersion, TLSVersion) {
+        (self.min, self.max)
+    }
+
+    /// Fall back to the next lower version the client allows on new connections, after the
+    /// server rejected the offered ones.
+    ///
+    /// The handshake is not retried on this connection, as the server may still send records
+    /// of the rejected one.
+    ///
+    /// # Returns
+    ///
+    /// The version new connections offer, or `None` if there is no lower version to fall back
+    /// to.
+    pub fn fall_back(&self) -> Option<TLSVersion> {
+        let version = self.fallback.first().copied()?;
+        if let Some((downgrades, addr)) = &self.downgrades {
+            downgrades.record(addr, version);
+        }
+        self.fell_back.set(true);
+        Some(version)
+    }
+
+    /// Check if the server rejected the offered versions, new connections falling back to a
+    /// lower one.
+    pub fn fell_back(&self) -> bool {
+        self.fell_back.get()
+    }
+
+    /// Get the version negotiated by the last completed handshake, if any.
+    pub fn negotiated(&self) -> Option<TLSVersion> {
+        self.negotiated.get()
+    }
+
+    /// Record the version negotiated by a completed handshake.
+    pub fn set_negotiated(&self, version: TLSVersion) {
+        self.negotiated.set(Some(version));
+    }
+}
+
+/// Cipher suites, key exchange groups and lowest version HTTPS connections are restricted to.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TlsOptions {
+    /// Names of the cipher suites to offer, e.g. `TLS13_AES_256_GCM_SHA384`, or `None` for
+    /// every suite of the crypto provider
+    pub cipher_suites: Option<Vec<String>>,
+    /// Names of the key exchange groups to offer, e.g. `X25519`, or `None` for every group of
+    /// the crypto provider
+    pub kx_groups: Option<Vec<String>>,
+    /// Lowest TLS version ever offered, whatever the version range of the connection, if
+    /// restricted by a profile
+    pub min_version: Option<TLSVersion>,
+}
+
+/// Preset TLS settings of a client, see `Client::set_tls_profile`.
+#[wasm_bindgen]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TlsProfile {
+    /// TLS 1.3 only, with AEAD cipher suites and modern key exchange groups, never falling
+    /// back to a lower version
+    Strict,
+    /// Every supported version, cipher suite and key exchange group, falling back to lower
+    /// versions when the server rejects the offered ones
+    Compat,
+}
+
+/// Cipher suites of the strict profile.
+const STRICT_CIPHER_SUITES: [&str; 3] = [
+    "TLS13_AES_256_GCM_SHA384",
+    "TLS13_AES_128_GCM_SHA256",
+    "TLS13_CHACHA20_POLY1305_SHA256",
+];
+
+/// Key exchange groups of the strict profile.
+const STRICT_KX_GROUPS: [&str; 3] = ["X25519", "secp384r1", "secp256r1"];
+
+impl TlsProfile {
+    /// Get the cipher suites, key exchange groups and lowest version of this profile.
+    pub fn options(self) -> TlsOptions {
+        let names = |names: &[&str]| Some(names.iter().map(|n| n.to_string()).collect());
+        match self {
+            TlsProfile::Strict => TlsOptions {
+                cipher_suites: names(&STRICT_CIPHER_SUITES),
+                kx_groups: names(&STRICT_KX_GROUPS),
+                min_version: Some(TLSVersion::TLSv1_3),
+            },
+            TlsProfile::Compat => TlsOptions::default(),
+        }
+    }
+
+    /// Get the lowest TLS version connections of this profile may fall back to, if any.
+    pub fn fallback_floor(self) -> Option<TLSVersion> {
+        match self {
+            TlsProfile::Strict => None,
+            // The lowest version implemented by rustls
+            TlsProfile::Compat => Some(TLSVersion::TLSv1_2),
+        }
+    }
+}
+
+/// Get the names of the cipher suites of the crypto provider, in order of preference.
+pub fn cipher_suite_names() -> Vec<String> {
+    crypto_provider()
+        .cipher_suites
+        .iter()
+        .map(|suite| format!("{:?}", suite.suite()))
+        .collect()
+}
+
+/// Get the names of the key exchange groups of the crypto provider, in order of preference.
+pub fn kx_group_names() -> Vec<String> {
+    crypto_provider()
+        .kx_groups
+        .iter()
+        .map(|group| format!("{:?}", group.name()))
+        .collect()
+}
+
+/// Check that names are known to the crypto provider, and that at least one is given.
+///
+/// # Arguments
+///
+/// * `kind` - What the names are of, for error messages
+/// * `names` - Names to check
+/// * `known` - Names known to the crypto provider
+pub fn check_names(kind: &str, names: &[String], known: &[String]) -> Result<(), ConnectionError> {
+    if names.is_empty() {
+        return Err(ConnectionError {
+            message: format!("No TLS {} given", kind),
+        });
+    }
+    match names
+        .iter()
+        .find(|name| !known.iter().any(|k| k.eq_ignore_ascii_case(name)))
+    {
+        Some(name) => Err(ConnectionError {
+            message: format!("Unsupported TLS {}: {}", kind, name),
+        }),
+        None => Ok(()),
+    }
+}
+
+/// Keep the items of the crypto provider whose name is allowed, if restricted.
+fn restrict<T>(items: &mut Vec<T>, allowed: &Option<Vec<String>>, name: impl Fn(&T) -> String) {
+    if let Some(allowed) = allowed {
+        items.retain(|item| {
+            let name = name(item);
+            allowed.iter().any(|a| a.eq_ignore_ascii_case(&name))
+        });
+    }
+}
+
+#[cfg(not(any(feature = "ring", feature = "aws-lc-rs")))]
+compile_error!("either the `ring` or the `aws-lc-rs` feature must be enabled");
+
+/// Get the crypto provider selected at compile time.
+#[cfg(feature = "ring")]
+pub fn crypto_provider() -> Arc<CryptoProvider> {
+    Arc::new(rustls::crypto::ring::default_provider())
+}
+
+/// Get the crypto provider selected at compile time.
+#[cfg(all(feature = "aws-lc-rs", not(feature = "ring")))]
+pub fn crypto_provider() -> Arc<CryptoProvider> {
+    Arc::new(rustls::crypto::aws_lc_rs::default_provider())
+}
+
+/// Get the protocol versions supported by rustls within a range of TLS versions.
+///
+/// # Arguments
+///
+/// * `min` - Lowest TLS version to offer
+/// * `max` - Highest TLS version to offer
+pub fn supported_versions(
+    min: TLSVersion,
+    max: TLSVersion,
+) -> Result<Vec<&'static SupportedProtocolVersion>, ConnectionError> {
+    // TLS 1.0 and 1.1 are not implemented by rustls
+    let versions: Vec<&'static SupportedProtocolVersion> =
+        [(TLSVersion::TLSv1_2, &TLS12), (TLSVersion::TLSv1_3, &TLS13)]
+            .into_iter()
+            .filter(|(version, _)| (min..=max).contains(version))
+            .map(|(_, protocol_version)| protocol_version)
+            .collect();
+    if versions.is_empty() {
+        return Err(ConnectionError {
+            message: if min == max {
+                format!("Unsupported TLS version: {:?}", min)
+            } else {
+                format!("Unsupported TLS version range: {:?} to {:?}", min, max)
+            },
+        });
+    }
+    Ok(versions)
+}
+
+/// Build a TLS client config for the given range of protocol versions.
+///
+/// # Arguments
+///
+/// * `min` - Lowest TLS version to offer
+/// * `max` - Highest TLS version to offer
+/// * `options` - Cipher suites and key exchange groups to restrict the config to
+pub fn client_config(
+    min: TLSVersion,
+    max: TLSVersion,
+    options: &TlsOptions,
+) -> Result<Arc<ClientConfig>, ConnectionError> {
+    let root_store = RootCertStore {
+        roots: webpki_roots::TLS_SERVER_ROOTS.into(),
+    };
+
+    let versions = supported_versions(min, max)?;
+
+    let mut provider = (*crypto_provider()).clone();
+    restrict(
+        &mut provider.cipher_suites,
+        &options.cipher_suites,
+        |suite| format!("{:?}", suite.suite()),
+    );
+    restrict(&mut provider.kx_groups, &options.kx_groups, |group| {
+        format!("{:?}", group.name())
+    });
+
+    // Fails if no cipher suite is left for the versions offered
+    #[allow(unused_mut)]
+    let mut config = ClientConfig::builder_with_provider(Arc::new(provider))
+        .with_protocol_versions(&versions)
+        .map_err(|e| ConnectionError {
+            message: format!("TLS error: {}", e),
+        })?
+        .with_root_certificates(root_store)
+        .with_no_client_auth();
+    #[cfg(feature = "keylog")]
+    {
+        config.key_log = Arc::new(crate::keylog::NssKeyLog);
+    }
+    Ok(Arc::new(config))
+}
+
+/// Determine the TLS server name of an address.
+///
+/// # Arguments
+///
+/// * `addr` - Address of the connection, with or without a port
+pub fn server_name(addr: &str) -> Result<ServerName<'static>, ConnectionError> {
+    let host = addr.split(':').next().unwrap_or(addr).to_string();
+
+    // Determine if the server name is an IP address or a domain name
+    if let Ok(ip) = IpAddr::try_from(host.as_str()) {
+        return Ok(ServerName::IpAddress(ip));
+    }
+
+    DnsName::try_from(host)
+        .map(ServerName::DnsName)
+        .map_err(|e| ConnectionError {
+            message: format!("Invalid server name: {}", e),
+        })
+}
+
+/// Capacity kept by the incoming ciphertext buffer once drained, enough for one full record.
+const INCOMING_CAPACITY: usize = 16 * 1024 + 256;
+
+/// A TLS client session, independent of the transport carrying its records.
+pub struct TlsSession {
+    /// Underlying rustls connection
+    conn: ClientConnection,
+    /// Ciphertext received but not yet accepted by rustls
+    incoming: VecDeque<u8>,
+    /// Last handshake milestone reached, if any
+    stage: Option<HandshakeStage>,
+}
+
+impl TlsSession {
+    /// Create a new TLS session.
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - TLS client config
+    /// * `server_name` - Name of the server to verify
+    pub fn new(
+        config: Arc<ClientConfig>,
+        server_name: ServerName<'static>,
+    ) -> Result<Self, ConnectionError> {
+        let mut conn = ClientConnection::new(config, server_name).map_err(|e| ConnectionError {
+            message: format!("TLS error: {}", e),
+        })?;
+        // Requests are written whole, possibly before the handshake completes, so larger
+        // than the 64 KiB rustls buffers by default
+        conn.set_buffer_limit(None);
+        Ok(Self {
+            conn,
+            incoming: VecDeque::with_capacity(INCOMING_CAPACITY),
+            stage: None,
+        })
+    }
+
+    /// Queue plaintext to be encrypted and collect the resulting records.
+    ///
+    /// # Arguments
+    ///
+    /// * `plaintext` - Application data to send
+    pub fn write(&mut self, plaintext: &[u8]) -> Result<TlsOutput, ConnectionError> {
+        self.conn
+            .writer()
+            .write_all(plaintext)
+            .map_err(|e| ConnectionError {
+                message: format!("TLS write error: {}", e),
+            })?;
+        let mut output = self.flush()?;
+        self.finish(&mut output);
+        Ok(output)
+    }
+
+    /// Process ciphertext received from the remote end.
+    ///
+    /// Ciphertext that rustls can't accept yet is kept and processed on the next read.
+    ///
+    /// # Arguments
+    ///
+    /// * `ciphertext` - TLS records received from the transport
+    pub fn read(&mut self, ciphertext: &[u8]) -> Result<TlsOutput, ConnectionError> {
+        let mut output = TlsOutput::default();
+
+        self.incoming.extend(ciphertext);
+
+        while !self.incoming.is_empty() {
+            let (mut front, _) = self.incoming.as_slices();
+            let read = match self.conn.read_tls(&mut front) {
+                Ok(read) => read,
+                // The buffers of rustls are full, keep the rest for the next read
+                Err(e) if e.kind() == ErrorKind::Other => 0,
+                Err(e) => {
+                    return Err(ConnectionError {
+                        message: format!("TLS read error: {}", e),
+                    })
+                }
+            };
+            self.incoming.drain(..read);
+            let received = output.plaintext.len();
+
+            self.conn.process_new_packets().map_err(|e| {
+                let message = match e {
+                    rustls::Error::AlertReceived(AlertDescription::ProtocolVersion)
+                    | rustls::Error::PeerIncompatible(_) => format!("{}: {}", VERSION_REJECTED, e),
+                    e => format!("TLS error: {}", e),
+                };
+                ConnectionError { message }
+            })?;
+
+            let mut buf = [0u8; 4096];
+            loop {
+                match self.conn.reader().read(&mut buf) {
+                    Ok(0) => break,
+                    Ok(n) => output.plaintext.extend_from_slice(&buf[..n]),
+                    Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                    Err(e) => {
+                        return Err(ConnectionError {
+                            message: format!("TLS read error: {}", e),
+                        })
+                    }
+                }
+            }
+
+            // Stop once rustls neither accepts ciphertext nor yields plaintext
+            if read == 0 && output.plaintext.len() == received {
+                break;
+            }
+        }
+
+        if self.incoming.is_empty() {
+            self.incoming.shrink_to(INCOMING_CAPACITY);
+        }
+
+        output.tls = self.flush()?.tls;
+        self.finish(&mut output);
+        Ok(output)
+    }
+
+    /// Get the number of bytes held by the buffer of ciphertext not yet accepted by rustls.
+    pub fn buffered(&self) -> usize {
+        self.incoming.capacity()
+    }
+
+    /// Fill in the handshake state of the output of a step.
+    fn finish(&mut self, output: &mut TlsOutput) {
+        #[cfg(feature = "keylog")]
+        {
+            output.key_log = crate::keylog::take_lines();
+        }
+        output.handshaking = self.conn.is_handshaking();
+        output.version = self.version();
+        output.cipher_suite = self
+            .version()
+            .and(self.conn.negotiated_cipher_suite())
+            .map(|suite| format!("{:?}", suite.suite()));
+
+        for stage in HandshakeStage::ALL {
+            if self.stage.is_some_and(|reached| reached >= stage) {
+                continue;
+            }
+            let reached = match stage {
+                HandshakeStage::ClientHelloSent => !output.tls.is_empty(),
+                HandshakeStage::ServerHelloReceived => self.conn.protocol_version().is_some(),
+                HandshakeStage::CertificatesVerified => self.conn.peer_certificates().is_some(),
+                HandshakeStage::Complete => !output.handshaking,
+            };
+            if reached {
+                self.stage = Some(stage);
+                output.stages.push(stage);
+            }
+        }
+    }
+
+    /// Get the TLS version negotiated, once the handshake is complete.
+    fn version(&self) -> Option<TLSVersion> {
+        if self.conn.is_handshaking() {
+            return None;
+        }
+        match self.conn.protocol_version()? {
+            ProtocolVersion::TLSv1_0 => Some(TLSVersion::TLSv1_0),
+            ProtocolVersion::TLSv1_1 => Some(TLSVersion::TLSv1_1),
+            ProtocolVersion::TLSv1_2 => Some(TLSVersion::TLSv1_2),
+            ProtocolVersion::TLSv1_3 => Some(TLSVersion::TLSv1_3),
+            _ => None,
+        }
+    }
+
+    /// Collect any pending outgoing records.
+    fn flush(&mut self) -> Result<TlsOutput, ConnectionError> {
+        let mut output = TlsOutput::default();
+        while self.conn.wants_write() {
+            self.conn
+                .write_tls(&mut output.tls)
+                .map_err(|e| ConnectionError {
+                    message: format!("TLS write error: {}", e),
+                })?;
+        }
+        Ok(output)
+    }
+}
+
+/// Callback receiving the output of a TLS engine.
+pub type TlsOutputCallback = Box<dyn Fn(Result<TlsOutput, ConnectionError>)>;
+
+/// Where the TLS state machine of a connection runs.
+pub enum TlsEngine {
+    /// On the calling thread
+    Inline {
+        /// TLS session
+        session: Arc<Mutex<TlsSession>>,
+        /// Callback receiving output of the session
+        output: TlsOutputCallback,
+    },
+    /// In a dedicated Web Worker
+    Worker {
+        /// Handle of the worker
+        worker: TlsWorker,
+        /// ID of the session inside the worker
+        id: u32,
+    },
+}
+
+impl TlsEngine {
+    /// Start a TLS session for the given address.
+    ///
+    /// # Arguments
+    ///
+    /// * `worker` - Worker to run the session in, or `None` to run it inline
+    /// * `min` - Lowest TLS version to offer
+    /// * `max` - Highest TLS version to offer
+    /// * `options` - Cipher suites and key exchange groups to offer
+    /// * `addr` - Address of the connection
+    /// * `output` - Callback receiving the output of the session
+    pub fn start(
+        worker: Option<TlsWorker>,
+        min: TLSVersion,
+        max: TLSVersion,
+        options: &TlsOptions,
+        addr: &str,
+        output: TlsOutputCallback,
+    ) -> Result<Self, ConnectionError> {
+        let min = match options.min_version {
+            Some(lowest) if lowest > max => {
+                return Err(ConnectionError {
+                    message: format!(
+                        "TLS version {:?} is below the lowest allowed by the TLS profile, {:?}",
+                        max, lowest
+                    ),
+                })
+            }
+            Some(lowest) => min.max(lowest),
+            None => min,
+        };
+        match worker {
+            Some(worker) => {
+                let id = worker.open(min, max, options, addr, output)?;
+                Ok(TlsEngine::Worker { worker, id })
+            }
+            None => {
+                let config = client_config(min, max, options)?;
+                let session = TlsSession::new(config, server_name(addr)?)?;
+                Ok(TlsEngine::Inline {
+                    session: Arc::new(Mutex::new(session)),
+                    output,
+                })
+            }
+        }
+    }
+
+    /// Encrypt plaintext and send the resulting records to the output callback.
+    ///
+    /// # Arguments
+    ///
+    /// * `plaintext` - Application data to send
+    pub fn write(&self, plaintext: Vec<u8>) {
+        match self {
+            TlsEngine::Inline { session, output } => {
+                let result = session.lock().unwrap_throw().write(&plaintext);
+                output(result);
+            }
+            TlsEngine::Worker { worker, id } => worker.write(*id, plaintext),
+        }
+    }
+
+    /// Decrypt ciphertext and send the resulting data to the output callback.
+    ///
+    /// # Arguments
+    ///
+    /// * `ciphertext` - TLS records received from the transport
+    pub fn read(&self, ciphertext: Vec<u8>) {
+        match self {
+            TlsEngine::Inline { session, output } => {
+                let result = session.lock().unwrap_throw().read(&ciphertext);
+                output(result);
+            }
+            TlsEngine::Worker { worker, id } => worker.read(*id, ciphertext),
+        }
+    }
+
+    /// Get the number of bytes buffered by the session, or `None` if it runs in a worker or
+    /// is busy.
+    pub fn buffered(&self) -> Option<usize> {
+        match self {
+            TlsEngine::Inline { session, .. } => session.try_lock().ok().map(|s| s.buffered()),
+            TlsEngine::Worker { .. } => None,
+        }
+    }
+}
+
+impl Drop for TlsEngine {
+    fn drop(&mut self) {
+        if let TlsEngine::Worker { worker, id } = self {
+            worker.close(*id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn new_connections_fall_back_after_a_rejection() {
+        let downgrades = TlsDowngrades::default();
+        let fallback = [TLSVersion::TLSv1_2];
+        let versions = || {
+            TlsVersions::new(TLSVersion::TLSv1_3, TLSVersion::TLSv1_3, &fallback)
+                .with_downgrades(&downgrades, "example.com:443")
+        };
+
+        let rejected = versions();
+        assert_eq!(rejected.fall_back(), Some(TLSVersion::TLSv1_2));
+        assert!(rejected.fell_back());
+        // The rejected connection itself keeps offering the same versions
+        assert_eq!(rejected.range(), (TLSVersion::TLSv1_3, TLSVersion::TLSv1_3));
+
+        let next = versions();
+        assert_eq!(next.range(), (TLSVersion::TLSv1_2, TLSVersion::TLSv1_2));
+        assert_eq!(next.fall_back(), None);
+        assert!(!next.fell_back());
+
+        let other = TlsVersions::new(TLSVersion::TLSv1_3, TLSVersion::TLSv1_3, &fallback)
+            .with_downgrades(&downgrades, "example.org:443");
+        assert_eq!(other.range(), (TLSVersion::TLSv1_3, TLSVersion::TLSv1_3));
+    }
+
+    #[test]
+    fn writes_requests_larger_than_the_rustls_buffers() {
+        let config = client_config(
+            TLSVersion::TLSv1_2,
+            TLSVersion::TLSv1_3,
+            &TlsOptions::default(),
+        )
+        .unwrap();
+        let mut session = TlsSession::new(config, server_name("example.com:443").unwrap()).unwrap();
+        // Held until the handshake completes, only the ClientHello being sent
+        let output = session.write(&vec![0; 256 * 1024]).unwrap();
+        assert!(output.handshaking);
+        assert!(output.tls.len() < 4096);
+    }
+
+    #[test]
+    fn downgrades_stay_within_the_allowed_versions() {
+        let downgrades = TlsDowngrades::default();
+        downgrades.record("example.com:443", TLSVersion::TLSv1_2);
+        let strict = TlsVersions::new(TLSVersion::TLSv1_3, TLSVersion::TLSv1_3, &[])
+            .with_downgrades(&downgrades, "example.com:443");
+        assert_eq!(strict.range(), (TLSVersion::TLSv1_3, TLSVersion::TLSv1_3));
+    }
+}
@@ -0,0 +1,127 @@
+use wasm_bindgen::prelude::*;
+
+/// Memory held by the buffers of a client at one point, to detect leaks in long-running
+/// apps or decide when to trim its connections.
+///
+/// Sizes are the capacity of the buffers, not only the bytes they hold, as that is what
+/// stays allocated. Buffers held by JS, e.g. the chunks of streamed bodies not read yet, are
+/// not included.
+#[wasm_bindgen]
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct BufferUsage {
+    /// Number of responses being assembled
+    assemblers: usize,
+    /// Bytes held by the assemblers of responses
+    assembler_bytes: usize,
+    /// Number of TLS sessions in progress
+    tls_sessions: usize,
+    /// Bytes of ciphertext buffered by the TLS sessions run on this thread
+    tls_bytes: usize,
+    /// Bytes of payload kept by the raw traffic captures of connections
+    capture_bytes: usize,
+    /// Number of connections of the client
+    connections: usize,
+    /// Number of open connections without a request in flight
+    idle_connections: usize,
+    /// Number of connections opened by `preconnect` and not handed out yet
+    preconnected: usize,
+}
+
+impl BufferUsage {
+    /// Count the assembler of a response being received.
+    ///
+    /// # Arguments
+    ///
+    /// * `bytes` - Bytes held by the assembler
+    pub(crate) fn add_assembler(&mut self, bytes: usize) {
+        self.assemblers += 1;
+        self.assembler_bytes += bytes;
+    }
+
+    /// Count a TLS session in progress.
+    ///
+    /// # Arguments
+    ///
+    /// * `bytes` - Bytes buffered by the session, or `None` if it runs in a worker
+    pub(crate) fn add_tls_session(&mut self, bytes: Option<usize>) {
+        self.tls_sessions += 1;
+        self.tls_bytes += bytes.unwrap_or(0);
+    }
+
+    /// Count a connection of the client.
+    ///
+    /// # Arguments
+    ///
+    /// * `idle` - Whether the connection is open without a request in flight
+    /// * `capture_bytes` - Bytes kept by the capture of the connection, if captured
+    pub(crate) fn add_connection(&mut self, idle: bool, capture_bytes: usize) {
+        self.connections += 1;
+        self.idle_connections += usize::from(idle);
+        self.capture_bytes += capture_bytes;
+    }
+
+    /// Set the number of connections opened by `preconnect` and not handed out yet.
+    pub(crate) fn set_preconnected(&mut self, preconnected: usize) {
+        self.preconnected = preconnected;
+    }
+}
+
+#[wasm_bindgen]
+impl BufferUsage {
+    /// Get the number of responses being assembled.
+    #[wasm_bindgen]
+    pub fn get_assemblers(&self) -> usize {
+        self.assemblers
+    }
+
+    /// Get the bytes held by the assemblers of responses: heads being parsed and bodies
+    /// being buffered.
+    #[wasm_bindgen]
+    pub fn get_assembler_bytes(&self) -> usize {
+        self.assembler_bytes
+    }
+
+    /// Get the number of TLS sessions in progress, including those run in a worker.
+    #[wasm_bindgen]
+    pub fn get_tls_sessions(&self) -> usize {
+        self.tls_sessions
+    }
+
+    /// Get the bytes of ciphertext buffered by TLS sessions until whole records are
+    /// received. Sessions run in a worker hold their buffers there, and are not included.
+    #[wasm_bindgen]
+    pub fn get_tls_bytes(&self) -> usize {
+        self.tls_bytes
+    }
+
+    /// Get the bytes of payload kept by the raw traffic captures of connections.
+    #[wasm_bindgen]
+    pub fn get_capture_bytes(&self) -> usize {
+        self.capture_bytes
+    }
+
+    /// Get the number of connections of the client, in any state.
+    #[wasm_bindgen]
+    pub fn get_connections(&self) -> usize {
+        self.connections
+    }
+
+    /// Get the number of open connections without a request in flight, which may be closed
+    /// to trim the client.
+    #[wasm_bindgen]
+    pub fn get_idle_connections(&self) -> usize {
+        self.idle_connections
+    }
+
+    /// Get the number of connections opened by `preconnect` and not handed out yet.
+    #[wasm_bindgen]
+    pub fn get_preconnected(&self) -> usize {
+        self.preconnected
+    }
+
+    /// Get the bytes held by all the buffers above.
+    #[wasm_bindgen]
+    pub fn get_total_bytes(&self) -> usize {
+        self.assembler_bytes + self.tls_bytes + self.capture_bytes
+    }
+}
@@ -0,0 +1,882 @@
+use wasm_bindgen::prelude::*;
+
+use crate::connection::ConnectionError;
+
+/// Default limit of the size of a message, and of a single frame, in bytes.
+pub const DEFAULT_MAX_MESSAGE_SIZE: usize = 16 * 1024 * 1024;
+/// Largest payload of a control frame, in bytes.
+const MAX_CONTROL_PAYLOAD: usize = 125;
+
+/// Close codes of RFC 6455 used by the codec.
+pub mod close_code {
+    /// The peer violated the protocol
+    pub const PROTOCOL_ERROR: u16 = 1002;
+    /// A message isn't consistent with its type, e.g. text that isn't UTF-8
+    pub const INVALID_PAYLOAD: u16 = 1007;
+    /// A message is too big to process
+    pub const MESSAGE_TOO_BIG: u16 = 1009;
+}
+
+/// Check if a close code may be sent in a close frame, per RFC 6455.
+///
+/// 1004 to 1006 and 1015 are reserved for reporting a close without such a frame.
+pub fn is_valid_close_code(code: u16) -> bool {
+    matches!(code, 1000..=1003 | 1007..=1014 | 3000..=4999)
+}
+
+/// Type of a frame.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Opcode {
+    Continuation = 0x0,
+    Text = 0x1,
+    Binary = 0x2,
+    Close = 0x8,
+    Ping = 0x9,
+    Pong = 0xA,
+}
+
+impl Opcode {
+    /// Parse the opcode of a frame, `None` if reserved.
+    pub fn from_u8(opcode: u8) -> Option<Self> {
+        match opcode {
+            0x0 => Some(Opcode::Continuation),
+            0x1 => Some(Opcode::Text),
+            0x2 => Some(Opcode::Binary),
+            0x8 => Some(Opcode::Close),
+            0x9 => Some(Opcode::Ping),
+            0xA => Some(Opcode::Pong),
+            _ => None,
+        }
+    }
+
+    /// Check if frames of this type are control frames, which can't be fragmented.
+    pub fn is_control(self) -> bool {
+        (self as u8) & 0x8 != 0
+    }
+}
+
+/// Side of the connection, which decides the masking of frames.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Role {
+    /// Masks the frames it sends, and expects unmasked frames
+    Client,
+    /// Sends unmasked frames, and expects masked frames
+    Server,
+}
+
+/// Violation of the protocol by the peer, with the code to close the connection with.
+#[derive(Clone, Debug)]
+pub struct CodecError {
+    /// Close code describing the violation
+    pub close_code: u16,
+    /// Description of the violation
+    pub message: String,
+}
+
+impl CodecError {
+    fn new(close_code: u16, message: &str) -> Self {
+        Self {
+            close_code,
+            message: message.to_string(),
+        }
+    }
+}
+
+impl From<CodecError> for ConnectionError {
+    fn from(e: CodecError) -> Self {
+        ConnectionError {
+            message: format!("WebSocket protocol error ({}): {}", e.close_code, e.message),
+        }
+    }
+}
+
+/// A single WebSocket frame.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Frame {
+    /// Whether this is the last frame of its message
+    pub fin: bool,
+    /// Type of the frame
+    pub opcode: Opcode,
+    /// Unmasked payload
+    pub payload: Vec<u8>,
+}
+
+impl Frame {
+    /// Create a new frame ending its message.
+    pub fn new(opcode: Opcode, payload: Vec<u8>) -> Self {
+        Self {
+            fin: true,
+            opcode,
+            payload,
+        }
+    }
+
+    /// Create a new close frame.
+    ///
+    /// # Arguments
+    ///
+    /// * `close` - Close code and reason, or `None` for a close frame without payload
+    pub fn close(close: Option<(u16, &str)>) -> Result<Self, CodecError> {
+        let mut payload = Vec::new();
+        if let Some((code, reason)) = close {
+            if !is_valid_close_code(code) {
+                return Err(CodecError::new(
+                    close_code::PROTOCOL_ERROR,
+                    &format!("Invalid close code: {}", code),
+                ));
+            }
+            payload.extend_from_slice(&code.to_be_bytes());
+            payload.extend_from_slice(reason.as_bytes());
+        }
+        if payload.len() > MAX_CONTROL_PAYLOAD {
+            return Err(CodecError::new(
+                close_code::PROTOCOL_ERROR,
+                "Close reason is too long",
+            ));
+        }
+        Ok(Self::new(Opcode::Close, payload))
+    }
+
+    /// Encode the frame.
+    ///
+    /// # Arguments
+    ///
+    /// * `mask` - Masking key, required for frames sent by clients
+    pub fn encode(&self, mask: Option<[u8; 4]>) -> Vec<u8> {
+        let len = self.payload.len();
+        let mut bytes = Vec::with_capacity(len + 14);
+        bytes.push(u8::from(self.fin) << 7 | self.opcode as u8);
+        let mask_bit = if mask.is_some() { 0x80 } else { 0 };
+        if len < 126 {
+            bytes.push(mask_bit | len as u8);
+        } else if len <= u16::MAX as usize {
+            bytes.push(mask_bit | 126);
+            bytes.extend_from_slice(&(len as u16).to_be_bytes());
+        } else {
+            bytes.push(mask_bit | 127);
+            bytes.extend_from_slice(&(len as u64).to_be_bytes());
+        }
+        let start = bytes.len();
+        match mask {
+            Some(mask) => {
+                bytes.extend_from_slice(&mask);
+                bytes.extend_from_slice(&self.payload);
+                apply_mask(&mut bytes[start + 4..], mask);
+            }
+            None => bytes.extend_from_slice(&self.payload),
+        }
+        bytes
+    }
+}
+
+/// Mask or unmask a payload in place, the operation being its own inverse.
+pub fn apply_mask(payload: &mut [u8], mask: [u8; 4]) {
+    for (i, byte) in payload.iter_mut().enumerate() {
+        *byte ^= mask[i % 4];
+    }
+}
+
+/// Generate a masking key for a frame sent by a client.
+///
+/// Keys must be unpredictable to the application, per RFC 6455 section 5.3, so they come
+/// from `crypto.getRandomValues` rather than `Math.random`.
+pub fn generate_mask() -> [u8; 4] {
+    let mut mask = [0; 4];
+    getrandom::getrandom(&mut mask).unwrap_throw();
+    mask
+}
+
+/// Split a message into frames whose payload is at most `max_frame_size` bytes.
+///
+/// Control frames can't be fragmented and are returned as a single frame.
+pub fn fragment(opcode: Opcode, payload: &[u8], max_frame_size: usize) -> Vec<Frame> {
+    if opcode.is_control() || payload.len() <= max_frame_size || max_frame_size == 0 {
+        return vec![Frame::new(opcode, payload.to_vec())];
+    }
+    let count = payload.len().div_ceil(max_frame_size);
+    payload
+        .chunks(max_frame_size)
+        .enumerate()
+        .map(|(i, chunk)| Frame {
+            fin: i + 1 == count,
+            opcode: if i == 0 { opcode } else { Opcode::Continuation },
+            payload: chunk.to_vec(),
+        })
+        .collect()
+}
+
+/// Decoder of the frames of a byte stream, buffering partial frames.
+pub struct FrameDecoder {
+    /// Side of the connection decoding the frames
+    role: Role,
+    /// Largest payload of a frame accepted, in bytes
+    max_frame_size: usize,
+    /// Bytes received and not decoded yet
+    buffer: Vec<u8>,
+}
+
+impl FrameDecoder {
+    /// Create a new frame decoder.
+    ///
+    /// # Arguments
+    ///
+    /// * `role` - Side of the connection decoding the frames
+    /// * `max_frame_size` - Largest payload of a frame accepted, in bytes
+    pub fn new(role: Role, max_frame_size: usize) -> Self {
+        Self {
+            role,
+            max_frame_size,
+            buffer: Vec::new(),
+        }
+    }
+
+    /// Add received bytes to decode.
+    pub fn push(&mut self, bytes: &[u8]) {
+        self.buffer.extend_from_slice(bytes);
+    }
+
+    /// Decode the next frame of the received bytes.
+    ///
+    /// # Returns
+    ///
+    /// The frame, `None` if it isn't fully received yet, or the violation of the protocol.
+    pub fn next_frame(&mut self) -> Result<Option<Frame>, CodecError> {
+        let buffer = &self.buffer;
+        if buffer.len() < 2 {
+            return Ok(None);
+        }
+        let fin = buffer[0] & 0x80 != 0;
+        if buffer[0] & 0x70 != 0 {
+            return Err(CodecError::new(
+                close_code::PROTOCOL_ERROR,
+                "Reserved bits set without a negotiated extension",
+            ));
+        }
+        let opcode = Opcode::from_u8(buffer[0] & 0x0F)
+            .ok_or_else(|| CodecError::new(close_code::PROTOCOL_ERROR, "Reserved opcode"))?;
+        let masked = buffer[1] & 0x80 != 0;
+        if masked != (self.role == Role::Server) {
+            return Err(CodecError::new(
+                close_code::PROTOCOL_ERROR,
+                if masked {
+                    "Frame from the server is masked"
+                } else {
+                    "Frame from the client is not masked"
+                },
+            ));
+        }
+
+        let (len, mut header) = match buffer[1] & 0x7F {
+            126 if buffer.len() >= 4 => (u16::from_be_bytes([buffer[2], buffer[3]]) as u64, 4),
+            127 if buffer.len() >= 10 => {
+                let mut len = [0; 8];
+                len.copy_from_slice(&buffer[2..10]);
+                (u64::from_be_bytes(len), 10)
+            }
+            126 | 127 => return Ok(None),
+            len => (len as u64, 2),
+        };
+        if opcode.is_control() && (!fin || len > MAX_CONTROL_PAYLOAD as u64) {
+            return Err(CodecError::new(
+                close_code::PROTOCOL_ERROR,
+                "Control frame is fragmented or longer than 125 bytes",
+            ));
+        }
+        if len > self.max_frame_size as u64 {
+            return Err(CodecError::new(
+                close_code::MESSAGE_TOO_BIG,
+                "Frame is too big",
+            ));
+        }
+        let len = len as usize;
+
+        let mask = if masked {
+            if buffer.len() < header + 4 {
+                return Ok(None);
+            }
+            let mut mask = [0; 4];
+            mask.copy_from_slice(&buffer[header..header + 4]);
+            header += 4;
+            Some(mask)
+        } else {
+            None
+        };
+        if buffer.len() < header + len {
+            return Ok(None);
+        }
+
+        let mut payload: Vec<u8> = self.buffer.drain(..header + len).skip(header).collect();
+        if let Some(mask) = mask {
+            apply_mask(&mut payload, mask);
+        }
+        Ok(Some(Frame {
+            fin,
+            opcode,
+            payload,
+        }))
+    }
+}
+
+/// A complete message, reassembled from its frames.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Message {
+    Text(String),
+    Binary(Vec<u8>),
+    Ping(Vec<u8>),
+    Pong(Vec<u8>),
+    /// Close code and reason, `None` if the close frame had no payload
+    Close(Option<(u16, String)>),
+}
+
+/// Reassembler of fragmented messages, passing control frames through as they arrive.
+pub struct MessageAssembler {
+    /// Largest message accepted, in bytes
+    max_message_size: usize,
+    /// Type and payload received so far of the fragmented message in progress
+    partial: Option<(Opcode, Vec<u8>)>,
+}
+
+impl MessageAssembler {
+    /// Create a new message assembler.
+    ///
+    /// # Arguments
+    ///
+    /// * `max_message_size` - Largest message accepted, in bytes
+    pub fn new(max_message_size: usize) -> Self {
+        Self {
+            max_message_size,
+            partial: None,
+        }
+    }
+
+    /// Add a decoded frame.
+    ///
+    /// # Returns
+    ///
+    /// The message completed by the frame if any, or the violation of the protocol.
+    pub fn push(&mut self, frame: Frame) -> Result<Option<Message>, CodecError> {
+        match frame.opcode {
+            Opcode::Ping => Ok(Some(Message::Ping(frame.payload))),
+            Opcode::Pong => Ok(Some(Message::Pong(frame.payload))),
+            Opcode::Close => parse_close(&frame.payload).map(|close| Some(Message::Close(close))),
+            Opcode::Text | Opcode::Binary => {
+                if self.partial.is_some() {
+                    return Err(CodecError::new(
+                        close_code::PROTOCOL_ERROR,
+                        "New message before the end of a fragmented message",
+                    ));
+                }
+                if frame.fin {
+                    return to_message(frame.opcode, frame.payload).map(Some);
+                }
+                self.partial = Some((frame.opcode, frame.payload));
+                Ok(None)
+            }
+            Opcode::Continuation => {
+                let (opcode, mut payload) = self.partial.take().ok_or_else(|| {
+                    CodecError::new(
+                        close_code::PROTOCOL_ERROR,
+                        "Continuation frame without a fragmented message",
+                    )
+                })?;
+                if payload.len() + frame.payload.len() > self.max_message_size {
+                    return Err(CodecError::new(
+                        close_code::MESSAGE_TOO_BIG,
+                        "Message is too big",
+                    ));
+                }
+                payload.extend_from_slice(&frame.payload);
+                if frame.fin {
+                    return to_message(opcode, payload).map(Some);
+                }
+                self.partial = Some((opcode, payload));
+                Ok(None)
+            }
+        }
+    }
+}
+
+/// Convert the payload of a complete data message to a message.
+fn to_message(opcode: Opcode, payload: Vec<u8>) -> Result<Message, CodecError> {
+    match opcode {
+        Opcode::Text => String::from_utf8(payload)
+            .map(Message::Text)
+            .map_err(|_| CodecError::new(close_code::INVALID_PAYLOAD, "Text is not UTF-8")),
+        _ => Ok(Message::Binary(payload)),
+    }
+}
+
+/// Parse the payload of a close frame.
+fn parse_close(payload: &[u8]) -> Result<Option<(u16, String)>, CodecError> {
+    match payload {
+        [] => Ok(None),
+        [_] => Err(CodecError::new(
+            close_code::PROTOCOL_ERROR,
+            "Close frame payload of a single byte",
+        )),
+        [high, low, reason @ ..] => {
+            let code = u16::from_be_bytes([*high, *low]);
+            if !is_valid_close_code(code) {
+                return Err(CodecError::new(
+                    close_code::PROTOCOL_ERROR,
+                    &format!("Invalid close code: {}", code),
+                ));
+            }
+            let reason = String::from_utf8(reason.to_vec()).map_err(|_| {
+                CodecError::new(close_code::INVALID_PAYLOAD, "Close reason is not UTF-8")
+            })?;
+            Ok(Some((code, reason)))
+        }
+    }
+}
+
+/// A message decoded by a `WebSocketCodec`.
+#[wasm_bindgen]
+#[derive(Clone, Debug)]
+pub struct WebSocketMessage {
+    /// Decoded message
+    message: Message,
+}
+
+#[wasm_bindgen]
+impl WebSocketMessage {
+    /// Get the type of the message: `text`, `binary`, `ping`, `pong` or `close`.
+    #[wasm_bindgen]
+    pub fn get_kind(&self) -> String {
+        match self.message {
+            Message::Text(_) => "text",
+            Message::Binary(_) => "binary",
+            Message::Ping(_) => "ping",
+            Message::Pong(_) => "pong",
+            Message::Close(_) => "close",
+        }
+        .to_string()
+    }
+
+    /// Get the payload of the message, the reason of close messages.
+    #[wasm_bindgen]
+    pub fn get_data(&self) -> Vec<u8> {
+        match &self.message {
+            Message::Text(text) => text.as_bytes().to_vec(),
+            Message::Binary(data) | Message::Ping(data) | Message::Pong(data) => data.clone(),
+            Message::Close(close) => close
+                .as_ref()
+                .map(|(_, reason)| reason.as_bytes().to_vec())
+                .unwrap_or_default(),
+        }
+    }
+
+    /// Get the text of text messages.
+    #[wasm_bindgen]
+    pub fn get_text(&self) -> Option<String> {
+        match &self.message {
+            Message::Text(text) => Some(text.clone()),
+            _ => None,
+        }
+    }
+
+    /// Get the close code of close messages, if the peer sent one.
+    #[wasm_bindgen]
+    pub fn get_close_code(&self) -> Option<u16> {
+        match &self.message {
+            Message::Close(close) => close.as_ref().map(|(code, _)| *code),
+            _ => None,
+        }
+    }
+
+    /// Get the close reason of close messages, if the peer sent one.
+    #[wasm_bindgen]
+    pub fn get_close_reason(&self) -> Option<String> {
+        match &self.message {
+            Message::Close(close) => close.as_ref().map(|(_, reason)| reason.clone()),
+            _ => None,
+        }
+    }
+}
+
+impl WebSocketMessage {
+    /// Get the decoded message.
+    pub fn message(&self) -> &Message {
+        &self.message
+    }
+}
+
+/// RFC 6455 frame codec, to speak WebSocket over a raw connection, e.g. a TCP connection or
+/// a tunnel, once the opening handshake is done.
+///
+/// Frames are masked when encoding as a client, fragmented messages are reassembled and
+/// violations of the protocol are reported with the close code to close with.
+#[wasm_bindgen]
+pub struct WebSocketCodec {
+    /// Side of the connection
+    role: Role,
+    /// Decoder of the received bytes
+    decoder: FrameDecoder,
+    /// Reassembler of the decoded frames
+    assembler: MessageAssembler,
+}
+
+impl WebSocketCodec {
+    /// Create a new codec.
+    ///
+    /// # Arguments
+    ///
+    /// * `role` - Side of the connection
+    /// * `max_message_size` - Largest message accepted, in bytes
+    pub fn with_role(role: Role, max_message_size: usize) -> Self {
+        Self {
+            role,
+            decoder: FrameDecoder::new(role, max_message_size),
+            assembler: MessageAssembler::new(max_message_size),
+        }
+    }
+
+    /// Encode a frame, masked if this codec is the client.
+    pub fn encode_frame(&self, frame: &Frame) -> Vec<u8> {
+        let mask = match self.role {
+            Role::Client => Some(generate_mask()),
+            Role::Server => None,
+        };
+        frame.encode(mask)
+    }
+
+    /// Decode the messages completed by received bytes.
+    pub fn decode_messages(&mut self, bytes: &[u8]) -> Result<Vec<Message>, CodecError> {
+        self.decoder.push(bytes);
+        let mut messages = Vec::new();
+        while let Some(frame) = self.decoder.next_frame()? {
+            messages.extend(self.assembler.push(frame)?);
+        }
+        Ok(messages)
+    }
+
+    /// Encode a control frame, checking the length of its payload.
+    fn encode_control(&self, opcode: Opcode, data: &[u8]) -> Result<Vec<u8>, ConnectionError> {
+        if data.len() > MAX_CONTROL_PAYLOAD {
+            return Err(ConnectionError {
+                message: "Control frame payload is longer than 125 bytes".to_string(),
+            });
+        }
+        Ok(self.encode_frame(&Frame::new(opcode, data.to_vec())))
+    }
+}
+
+#[wasm_bindgen]
+impl WebSocketCodec {
+    /// Create a new codec.
+    /// # Arguments
+    /// * `client` - Whether this side is the client, which masks the frames it sends
+    /// * `max_message_size` - Largest message accepted in bytes, 16 MiB if not given
+    #[wasm_bindgen(constructor)]
+    pub fn new(client: bool, max_message_size: Option<usize>) -> WebSocketCodec {
+        let role = if client { Role::Client } else { Role::Server };
+        Self::with_role(role, max_message_size.unwrap_or(DEFAULT_MAX_MESSAGE_SIZE))
+    }
+
+    /// Encode a text message as a single frame.
+    #[wasm_bindgen]
+    pub fn encode_text(&self, text: &str) -> Vec<u8> {
+        self.encode_frame(&Frame::new(Opcode::Text, text.as_bytes().to_vec()))
+    }
+
+    /// Encode a binary message, split into frames of at most `max_frame_size` bytes if given.
+    #[wasm_bindgen]
+    pub fn encode_binary(&self, data: &[u8], max_frame_size: Option<usize>) -> Vec<u8> {
+        fragment(Opcode::Binary, data, max_frame_size.unwrap_or(0))
+            .iter()
+            .flat_map(|frame| self.encode_frame(frame))
+            .collect()
+    }
+
+    /// Encode a ping, with at most 125 bytes of data.
+    #[wasm_bindgen]
+    pub fn encode_ping(&self, data: &[u8]) -> Result<Vec<u8>, ConnectionError> {
+        self.encode_control(Opcode::Ping, data)
+    }
+
+    /// Encode a pong, echoing the data of the ping it answers.
+    #[wasm_bindgen]
+    pub fn encode_pong(&self, data: &[u8]) -> Result<Vec<u8>, ConnectionError> {
+        self.encode_control(Opcode::Pong, data)
+    }
+
+    /// Encode a close frame.
+    /// # Arguments
+    /// * `code` - Close code, none sent if not given
+    /// * `reason` - Close reason, sent with the code, at most 123 bytes
+    #[wasm_bindgen]
+    pub fn encode_close(
+        &self,
+        code: Option<u16>,
+        reason: Option<String>,
+    ) -> Result<Vec<u8>, ConnectionError> {
+        let reason = reason.unwrap_or_default();
+        let frame = Frame::close(code.map(|code| (code, reason.as_str())))?;
+        Ok(self.encode_frame(&frame))
+    }
+
+    /// Decode received bytes, buffering incomplete frames until the rest is received.
+    /// # Returns
+    /// The messages completed by the bytes, or an error carrying the close code to close the
+    /// connection with if the peer violated the protocol.
+    #[wasm_bindgen]
+    pub fn decode(&mut self, bytes: &[u8]) -> Result<Vec<WebSocketMessage>, ConnectionError> {
+        Ok(self
+            .decode_messages(bytes)?
+            .into_iter()
+            .map(|message| WebSocketMessage { message })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MASK: [u8; 4] = [0x37, 0xfa, 0x21, 0x3d];
+
+    /// Decode all the frames of a byte stream pushed at once.
+    fn decode_all(role: Role, bytes: &[u8]) -> Result<Vec<Frame>, CodecError> {
+        let mut decoder = FrameDecoder::new(role, DEFAULT_MAX_MESSAGE_SIZE);
+        decoder.push(bytes);
+        let mut frames = Vec::new();
+        while let Some(frame) = decoder.next_frame()? {
+            frames.push(frame);
+        }
+        Ok(frames)
+    }
+
+    /// Get the close code of the violation found decoding a byte stream as a client.
+    fn client_error(bytes: &[u8]) -> u16 {
+        decode_all(Role::Client, bytes).unwrap_err().close_code
+    }
+
+    #[test]
+    fn encodes_rfc_examples() {
+        // Examples of RFC 6455 section 5.7
+        let hello = Frame::new(Opcode::Text, b"Hello".to_vec());
+        assert_eq!(hello.encode(None), b"\x81\x05Hello");
+        assert_eq!(
+            hello.encode(Some(MASK)),
+            [0x81, 0x85, 0x37, 0xfa, 0x21, 0x3d, 0x7f, 0x9f, 0x4d, 0x51, 0x58]
+        );
+        let ping = Frame::new(Opcode::Ping, b"Hello".to_vec());
+        assert_eq!(ping.encode(None), b"\x89\x05Hello");
+    }
+
+    #[test]
+    fn encodes_extended_lengths() {
+        let medium = Frame::new(Opcode::Binary, vec![0; 256]).encode(None);
+        assert_eq!(medium[..4], [0x82, 126, 0x01, 0x00]);
+        assert_eq!(medium.len(), 4 + 256);
+
+        let large = Frame::new(Opcode::Binary, vec![0; 65536]).encode(None);
+        assert_eq!(large[..10], [0x82, 127, 0, 0, 0, 0, 0, 0x01, 0x00, 0x00]);
+        assert_eq!(large.len(), 10 + 65536);
+
+        let limit = Frame::new(Opcode::Binary, vec![0; 125]).encode(None);
+        assert_eq!(limit[..2], [0x82, 125]);
+    }
+
+    #[test]
+    fn masking_is_its_own_inverse() {
+        let mut payload = b"masked payload".to_vec();
+        apply_mask(&mut payload, MASK);
+        assert_ne!(payload, b"masked payload");
+        apply_mask(&mut payload, MASK);
+        assert_eq!(payload, b"masked payload");
+    }
+
+    #[test]
+    fn generates_masks() {
+        // Equal keys every time would be a broken source, not bad luck
+        let masks: Vec<[u8; 4]> = (0..8).map(|_| generate_mask()).collect();
+        assert!(masks.iter().any(|mask| *mask != masks[0]));
+    }
+
+    #[test]
+    fn decodes_frames_split_anywhere() {
+        let frames = [
+            Frame::new(Opcode::Text, b"first".to_vec()),
+            Frame::new(Opcode::Binary, vec![7; 300]),
+            Frame::new(Opcode::Pong, Vec::new()),
+        ];
+        let bytes: Vec<u8> = frames.iter().flat_map(|f| f.encode(Some(MASK))).collect();
+        for size in [1, 2, 3, 5, 7, 64] {
+            let mut decoder = FrameDecoder::new(Role::Server, DEFAULT_MAX_MESSAGE_SIZE);
+            let mut decoded = Vec::new();
+            for chunk in bytes.chunks(size) {
+                decoder.push(chunk);
+                while let Some(frame) = decoder.next_frame().unwrap() {
+                    decoded.push(frame);
+                }
+            }
+            assert_eq!(decoded, frames);
+        }
+    }
+
+    #[test]
+    fn rejects_wrong_masking() {
+        let frame = Frame::new(Opcode::Text, b"hi".to_vec());
+        let masked = decode_all(Role::Client, &frame.encode(Some(MASK)));
+        assert_eq!(masked.unwrap_err().close_code, close_code::PROTOCOL_ERROR);
+        let unmasked = decode_all(Role::Server, &frame.encode(None));
+        assert_eq!(unmasked.unwrap_err().close_code, close_code::PROTOCOL_ERROR);
+    }
+
+    #[test]
+    fn rejects_reserved_bits_and_opcodes() {
+        assert_eq!(client_error(b"\xc1\x00"), close_code::PROTOCOL_ERROR);
+        assert_eq!(client_error(b"\x83\x00"), close_code::PROTOCOL_ERROR);
+        assert_eq!(client_error(b"\x8b\x00"), close_code::PROTOCOL_ERROR);
+    }
+
+    #[test]
+    fn rejects_invalid_control_frames() {
+        // Fragmented ping
+        assert_eq!(client_error(b"\x09\x00"), close_code::PROTOCOL_ERROR);
+        // Ping of 126 bytes
+        let mut long = vec![0x89, 126, 0, 126];
+        long.extend_from_slice(&[0; 126]);
+        assert_eq!(client_error(&long), close_code::PROTOCOL_ERROR);
+    }
+
+    #[test]
+    fn rejects_frames_over_the_limit() {
+        let mut decoder = FrameDecoder::new(Role::Client, 4);
+        decoder.push(&Frame::new(Opcode::Binary, vec![0; 5]).encode(None));
+        let error = decoder.next_frame().unwrap_err();
+        assert_eq!(error.close_code, close_code::MESSAGE_TOO_BIG);
+    }
+
+    #[test]
+    fn fragments_data_but_not_control_frames() {
+        let frames = fragment(Opcode::Text, b"abcdefg", 3);
+        let shape: Vec<_> = frames.iter().map(|f| (f.fin, f.opcode)).collect();
+        assert_eq!(
+            shape,
+            [
+                (false, Opcode::Text),
+                (false, Opcode::Continuation),
+                (true, Opcode::Continuation)
+            ]
+        );
+        assert_eq!(frames[2].payload, b"g");
+
+        assert_eq!(fragment(Opcode::Ping, b"abcdefg", 3).len(), 1);
+        assert_eq!(fragment(Opcode::Binary, b"abc", 0).len(), 1);
+    }
+
+    #[test]
+    fn reassembles_fragmented_messages_around_control_frames() {
+        let mut assembler = MessageAssembler::new(DEFAULT_MAX_MESSAGE_SIZE);
+        let mut frames = fragment(Opcode::Text, "héllo wörld".as_bytes(), 4);
+        frames.insert(1, Frame::new(Opcode::Ping, b"p".to_vec()));
+        let messages: Vec<Message> = frames
+            .into_iter()
+            .filter_map(|frame| assembler.push(frame).unwrap())
+            .collect();
+        assert_eq!(
+            messages,
+            [
+                Message::Ping(b"p".to_vec()),
+                Message::Text("héllo wörld".to_string())
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_fragmentation() {
+        let mut assembler = MessageAssembler::new(DEFAULT_MAX_MESSAGE_SIZE);
+        let continuation = Frame::new(Opcode::Continuation, b"x".to_vec());
+        let error = assembler.push(continuation).unwrap_err();
+        assert_eq!(error.close_code, close_code::PROTOCOL_ERROR);
+
+        let mut first = Frame::new(Opcode::Binary, b"x".to_vec());
+        first.fin = false;
+        assembler.push(first.clone()).unwrap();
+        let error = assembler.push(first).unwrap_err();
+        assert_eq!(error.close_code, close_code::PROTOCOL_ERROR);
+    }
+
+    #[test]
+    fn rejects_oversized_and_invalid_messages() {
+        let mut assembler = MessageAssembler::new(4);
+        let mut frames = fragment(Opcode::Binary, b"abcdef", 3).into_iter();
+        assert_eq!(assembler.push(frames.next().unwrap()).unwrap(), None);
+        let error = assembler.push(frames.next().unwrap()).unwrap_err();
+        assert_eq!(error.close_code, close_code::MESSAGE_TOO_BIG);
+
+        let invalid = Frame::new(Opcode::Text, vec![0xff, 0xfe]);
+        let error = MessageAssembler::new(4).push(invalid).unwrap_err();
+        assert_eq!(error.close_code, close_code::INVALID_PAYLOAD);
+    }
+
+    #[test]
+    fn parses_close_payloads() {
+        assert_eq!(parse_close(&[]).unwrap(), None);
+        assert_eq!(
+            parse_close(b"\x03\xe8bye").unwrap(),
+            Some((1000, "bye".to_string()))
+        );
+        assert_eq!(
+            parse_close(b"\x0f\xa0").unwrap(),
+            Some((4000, String::new()))
+        );
+        assert_eq!(
+            parse_close(&[0x03]).unwrap_err().close_code,
+            close_code::PROTOCOL_ERROR
+        );
+        assert_eq!(
+            parse_close(b"\x03\xe8\xff").unwrap_err().close_code,
+            close_code::INVALID_PAYLOAD
+        );
+    }
+
+    #[test]
+    fn validates_close_codes() {
+        for code in [1000, 1001, 1002, 1003, 1007, 1011, 1014, 3000, 4999] {
+            assert!(is_valid_close_code(code), "{}", code);
+            assert!(parse_close(&code.to_be_bytes()).is_ok(), "{}", code);
+        }
+        // Reserved for closes without a close frame, or never assigned
+        for code in [0, 999, 1004, 1005, 1006, 1015, 1016, 2999, 5000, u16::MAX] {
+            assert!(!is_valid_close_code(code), "{}", code);
+            let error = parse_close(&code.to_be_bytes()).unwrap_err();
+            assert_eq!(error.close_code, close_code::PROTOCOL_ERROR);
+        }
+    }
+
+    #[test]
+    fn encodes_close_frames() {
+        let frame = Frame::close(Some((1000, "done"))).unwrap();
+        assert_eq!(frame.encode(None), b"\x88\x06\x03\xe8done");
+        assert_eq!(Frame::close(None).unwrap().payload, b"");
+        assert!(Frame::close(Some((1005, ""))).is_err());
+        assert!(Frame::close(Some((1000, &"x".repeat(124)))).is_err());
+        assert!(Frame::close(Some((1000, &"x".repeat(123)))).is_ok());
+    }
+
+    #[test]
+    fn codec_round_trips_between_client_and_server() {
+        let client = WebSocketCodec::with_role(Role::Client, DEFAULT_MAX_MESSAGE_SIZE);
+        let mut server = WebSocketCodec::with_role(Role::Server, DEFAULT_MAX_MESSAGE_SIZE);
+        let mut bytes = client.encode_text("hi");
+        bytes.extend(client.encode_binary(&[1, 2, 3, 4, 5], Some(2)));
+        bytes.extend(
+            client
+                .encode_close(Some(1001), Some("away".into()))
+                .unwrap(),
+        );
+        // Client frames are masked
+        assert_eq!(bytes[1] & 0x80, 0x80);
+        assert_eq!(
+            server.decode_messages(&bytes).unwrap(),
+            [
+                Message::Text("hi".to_string()),
+                Message::Binary(vec![1, 2, 3, 4, 5]),
+                Message::Close(Some((1001, "away".to_string())))
+            ]
+        );
+    }
+}
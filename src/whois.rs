@@ -0,0 +1,129 @@
+use std::{cell::Cell, rc::Rc};
+
+use wasm_bindgen::prelude::*;
+use wasm_bindgen_futures::JsFuture;
+
+use crate::{
+    batch::settle_on,
+    connection::ConnectionError,
+    connection_apis::tcp::{read_to_close, TcpDialer},
+    console_log, set_timeout,
+};
+
+/// WHOIS server of IANA, referring queries to the server of their TLD or address range.
+pub const IANA_WHOIS_SERVER: &str = "whois.iana.org";
+/// Default port of WHOIS servers.
+pub const WHOIS_PORT: u16 = 43;
+/// Number of referrals followed after the query to IANA, to the registry then the registrar.
+const MAX_REFERRALS: usize = 2;
+/// Time a server has to send its whole response, in ms.
+const WHOIS_TIMEOUT: f64 = 15_000.0;
+/// Keys of the lines referring a query to another server, in lowercase.
+const REFERRAL_KEYS: [&str; 4] = ["refer", "whois", "registrar whois server", "referralserver"];
+
+/// Find the server a response refers the query to, if any.
+///
+/// IANA refers with `refer:`, thin registries with `Registrar WHOIS Server:`, and address
+/// registries with `ReferralServer: whois://server`.
+fn referral(response: &str) -> Option<String> {
+    response.lines().find_map(|line| {
+        let (key, value) = line.trim().split_once(':')?;
+        if !REFERRAL_KEYS.contains(&key.trim().to_ascii_lowercase().as_str()) {
+            return None;
+        }
+        let value = value.trim();
+        // Other schemes, e.g. `rwhois://`, are other protocols
+        let server = match value.split_once("://") {
+            Some((scheme, server)) if scheme.eq_ignore_ascii_case("whois") => server,
+            Some(_) => return None,
+            None => value,
+        };
+        let server = server.trim_end_matches('/');
+        if server.is_empty() || server.contains(char::is_whitespace) {
+            return None;
+        }
+        Some(server.to_ascii_lowercase())
+    })
+}
+
+/// Get the address of a WHOIS server, port 43 if it has none.
+fn server_addr(server: &str) -> String {
+    match server.rsplit_once(':') {
+        Some((host, _)) if !host.contains(':') || host.ends_with(']') => server.to_string(),
+        _ => format!("{}:{}", server, WHOIS_PORT),
+    }
+}
+
+/// Send a query to a WHOIS server and read its response, which ends with the connection.
+async fn query_server(dialer: &TcpDialer, server: &str, query: &str) -> Result<String, JsValue> {
+    let connection = dialer.open(server_addr(server))?;
+    let socket = connection.socket().clone();
+
+    let timed_out = Rc::new(Cell::new(false));
+    {
+        let timed_out = timed_out.clone();
+        let socket = socket.clone();
+        let ontimeout = Closure::once_into_js(move || {
+            timed_out.set(true);
+            let _ = socket.close();
+        });
+        set_timeout(&ontimeout, WHOIS_TIMEOUT);
+    }
+
+    let opened = settle_on(&socket, Some("open"), "WHOIS server failed to open");
+    JsFuture::from(opened)
+        .await
+        .map_err(|e| match timed_out.get() {
+            true => JsValue::from(timeout_error(server)),
+            false => e,
+        })?;
+    // Listened to before the query is sent, as the server may answer and close right away
+    let response = read_to_close(&socket);
+    connection
+        .connection
+        .sender()
+        .send(format!("{}\r\n", query).as_bytes())?;
+    let response = response.await?;
+    if timed_out.get() {
+        return Err(timeout_error(server).into());
+    }
+    Ok(String::from_utf8_lossy(&response).into_owned())
+}
+
+/// Get the error of a server that did not send its whole response in time.
+fn timeout_error(server: &str) -> ConnectionError {
+    ConnectionError {
+        message: format!("WHOIS server {} timed out", server),
+    }
+}
+
+/// Look up a domain or address, following referrals from IANA.
+///
+/// # Arguments
+///
+/// * `dialer` - Handles of the client to open connections with
+/// * `query` - Domain or address to look up, without line breaks
+///
+/// # Returns
+///
+/// The response of the last server answering, as a string. A referral that fails is logged,
+/// and the response referring to it returned instead.
+pub(crate) async fn whois(dialer: TcpDialer, query: String) -> Result<JsValue, JsValue> {
+    let mut servers = vec![IANA_WHOIS_SERVER.to_string()];
+    let mut response = query_server(&dialer, IANA_WHOIS_SERVER, &query).await?;
+    while servers.len() <= MAX_REFERRALS {
+        let server = match referral(&response) {
+            Some(server) if !servers.contains(&server) => server,
+            _ => break,
+        };
+        match query_server(&dialer, &server, &query).await {
+            Ok(referred) => response = referred,
+            Err(e) => {
+                console_log!("WHOIS referral to {} failed: {:?}", server, e);
+                break;
+            }
+        }
+        servers.push(server);
+    }
+    Ok(response.into())
+}
@@ -0,0 +1,334 @@
+use std::{
+    cell::{Cell, RefCell},
+    collections::HashMap,
+    rc::Rc,
+    sync::{Arc, Mutex},
+};
+
+use wasm_bindgen::prelude::*;
+use wasm_bindgen_futures::js_sys::{self, ArrayBuffer, Object, Reflect, Uint8Array};
+use web_sys::{DedicatedWorkerGlobalScope, MessageEvent, Worker};
+
+use crate::{
+    connection::ConnectionError,
+    tls::{
+        client_config, server_name, HandshakeStage, TlsOptions, TlsOutput, TlsOutputCallback,
+        TlsSession,
+    },
+    SocketCapability, TLSVersion,
+};
+
+/// Copy bytes into a fresh `ArrayBuffer` that can be transferred to another context.
+pub(crate) fn to_buffer(bytes: &[u8]) -> ArrayBuffer {
+    Uint8Array::from(bytes).buffer()
+}
+
+/// Read a property of a message.
+pub(crate) fn get(msg: &JsValue, key: &str) -> JsValue {
+    Reflect::get(msg, &JsValue::from_str(key)).unwrap_or(JsValue::UNDEFINED)
+}
+
+/// Read a byte buffer property of a message.
+pub(crate) fn get_bytes(msg: &JsValue, key: &str) -> Vec<u8> {
+    get(msg, key)
+        .dyn_into::<ArrayBuffer>()
+        .map(|buffer| Uint8Array::new(&buffer).to_vec())
+        .unwrap_or_default()
+}
+
+/// Convert names to a JS array, or `null` if not given.
+pub(crate) fn names(names: &Option<Vec<String>>) -> JsValue {
+    match names {
+        Some(names) => names
+            .iter()
+            .map(|name| JsValue::from(name.as_str()))
+            .collect::<js_sys::Array>()
+            .into(),
+        None => JsValue::NULL,
+    }
+}
+
+/// Read a property of a message holding names, `None` if not an array.
+pub(crate) fn get_names(msg: &JsValue, key: &str) -> Option<Vec<String>> {
+    let value = get(msg, key);
+    js_sys::Array::is_array(&value).then(|| {
+        js_sys::Array::from(&value)
+            .iter()
+            .filter_map(|name| name.as_string())
+            .collect()
+    })
+}
+
+/// Build a message object from its properties.
+pub(crate) fn message(props: &[(&str, JsValue)]) -> Object {
+    let msg = Object::new();
+    for (key, value) in props {
+        Reflect::set(&msg, &JsValue::from_str(key), value).unwrap_throw();
+    }
+    msg
+}
+
+/// Handle to a Web Worker running TLS sessions on behalf of the main thread.
+///
+/// Ciphertext and plaintext are exchanged with the worker as transferred `ArrayBuffer`s,
+/// so no additional copies are made when crossing the thread boundary.
+#[derive(Clone)]
+pub struct TlsWorker {
+    /// Worker running `start_tls_worker`
+    worker: Worker,
+    /// Output callbacks of the sessions running in the worker
+    sessions: Rc<RefCell<HashMap<u32, Rc<TlsOutputCallback>>>>,
+    /// ID of the last session
+    last_id: Rc<Cell<u32>>,
+}
+
+impl TlsWorker {
+    /// Create a new handle for the given worker.
+    ///
+    /// # Arguments
+    ///
+    /// * `worker` - Worker that has called `start_tls_worker`
+    pub fn new(worker: Worker) -> Self {
+        let sessions: Rc<RefCell<HashMap<u32, Rc<TlsOutputCallback>>>> =
+            Rc::new(RefCell::new(HashMap::new()));
+
+        let cb_sessions = sessions.clone();
+        let message_callback: Closure<dyn Fn(MessageEvent)> =
+            Closure::wrap(Box::new(move |evt: MessageEvent| {
+                let msg = evt.data();
+                let id = get(&msg, "id").as_f64().unwrap_or_default() as u32;
+
+                // Release the lock before calling back, the callback may start new sessions
+                let output = match cb_sessions.borrow().get(&id) {
+                    Some(output) => output.clone(),
+                    None => return,
+                };
+
+                if let Some(error) = get(&msg, "error").as_string() {
+                    output(Err(ConnectionError { message: error }));
+                } else {
+                    output(Ok(TlsOutput {
+                        tls: get_bytes(&msg, "tls"),
+                        plaintext: get_bytes(&msg, "plaintext"),
+                        handshaking: get(&msg, "handshaking").is_truthy(),
+                        version: get(&msg, "version")
+                            .as_string()
+                            .and_then(|v| TLSVersion::from_string(&v)),
+                        stages: js_sys::Array::from(&get(&msg, "stages"))
+                            .iter()
+                            .filter_map(|s| HandshakeStage::from_name(&s.as_string()?))
+                            .collect(),
+                        cipher_suite: get(&msg, "cipherSuite").as_string(),
+                        #[cfg(feature = "keylog")]
+                        key_log: js_sys::Array::from(&get(&msg, "keyLog"))
+                            .iter()
+                            .filter_map(|line| line.as_string())
+                            .collect(),
+                    }));
+                }
+            }));
+
+        worker.set_onmessage(Some(message_callback.as_ref().unchecked_ref()));
+        message_callback.forget();
+
+        Self {
+            worker,
+            sessions,
+            last_id: Rc::new(Cell::new(0)),
+        }
+    }
+
+    /// Open a new TLS session in the worker.
+    ///
+    /// # Arguments
+    ///
+    /// * `min` - Lowest TLS version to offer
+    /// * `max` - Highest TLS version to offer
+    /// * `options` - Cipher suites and key exchange groups to offer
+    /// * `addr` - Address of the connection
+    /// * `output` - Callback receiving the output of the session
+    pub fn open(
+        &self,
+        min: TLSVersion,
+        max: TLSVersion,
+        options: &TlsOptions,
+        addr: &str,
+        output: TlsOutputCallback,
+    ) -> Result<u32, ConnectionError> {
+        let id = self.last_id.get().wrapping_add(1);
+        self.last_id.set(id);
+
+        self.sessions.borrow_mut().insert(id, Rc::new(output));
+
+        self.worker
+            .post_message(&message(&[
+                ("id", id.into()),
+                ("kind", "open".into()),
+                (
+                    "capability",
+                    SocketCapability::HTTPS(max).to_string().into(),
+                ),
+                (
+                    "minCapability",
+                    SocketCapability::HTTPS(min).to_string().into(),
+                ),
+                ("cipherSuites", names(&options.cipher_suites)),
+                ("kxGroups", names(&options.kx_groups)),
+                ("addr", addr.into()),
+            ]))
+            .map_err(|_| ConnectionError {
+                message: "Failed to post message to TLS worker".to_string(),
+            })?;
+
+        Ok(id)
+    }
+
+    /// Send plaintext to be encrypted by a session.
+    pub fn write(&self, id: u32, plaintext: Vec<u8>) {
+        self.post_data(id, "write", &plaintext);
+    }
+
+    /// Send ciphertext to be decrypted by a session.
+    pub fn read(&self, id: u32, ciphertext: Vec<u8>) {
+        self.post_data(id, "read", &ciphertext);
+    }
+
+    /// Close a session.
+    pub fn close(&self, id: u32) {
+        self.sessions.borrow_mut().remove(&id);
+        let _ = self
+            .worker
+            .post_message(&message(&[("id", id.into()), ("kind", "close".into())]));
+    }
+
+    /// Post a data message to the worker, transferring its buffer.
+    fn post_data(&self, id: u32, kind: &str, bytes: &[u8]) {
+        let buffer = to_buffer(bytes);
+        let msg = message(&[
+            ("id", id.into()),
+            ("kind", kind.into()),
+            ("data", buffer.clone().into()),
+        ]);
+        self.worker
+            .post_message_with_transfer(&msg, &js_sys::Array::of1(&buffer))
+            .unwrap_throw();
+    }
+}
+
+/// Run TLS sessions in the current dedicated worker.
+///
+/// The worker script must initialize this module and then call this function, e.g.
+///
+/// ```js
+/// import init, { start_tls_worker } from "./socketguard.js";
+/// await init();
+/// start_tls_worker();
+/// ```
+#[wasm_bindgen]
+pub fn start_tls_worker() {
+    let scope: DedicatedWorkerGlobalScope = js_sys::global().unchecked_into();
+
+    let sessions: Arc<Mutex<HashMap<u32, TlsSession>>> = Arc::new(Mutex::new(HashMap::new()));
+
+    let cb_scope = scope.clone();
+    let message_callback: Closure<dyn Fn(MessageEvent)> =
+        Closure::wrap(Box::new(move |evt: MessageEvent| {
+            let msg = evt.data();
+            let id = get(&msg, "id").as_f64().unwrap_or_default() as u32;
+            let kind = get(&msg, "kind").as_string().unwrap_or_default();
+
+            let mut sessions = sessions.lock().unwrap_throw();
+
+            let result = match kind.as_str() {
+                "open" => {
+                    let version = |key| match get(&msg, key)
+                        .as_string()
+                        .and_then(|c| SocketCapability::from_string(&c))
+                    {
+                        Some(SocketCapability::HTTPS(version)) => Some(version),
+                        _ => None,
+                    };
+                    let max = version("capability").unwrap_or(TLSVersion::TLSv1_2);
+                    let min = version("minCapability").unwrap_or(max);
+                    let addr = get(&msg, "addr").as_string().unwrap_or_default();
+                    let options = TlsOptions {
+                        cipher_suites: get_names(&msg, "cipherSuites"),
+                        kx_groups: get_names(&msg, "kxGroups"),
+                        // Already applied to the version range
+                        min_version: None,
+                    };
+                    client_config(min, max, &options)
+                        .and_then(|config| TlsSession::new(config, server_name(&addr)?))
+                        .map(|session| {
+                            sessions.insert(id, session);
+                        })
+                        .err()
+                        .map(Err)
+                }
+                "write" | "read" => match sessions.get_mut(&id) {
+                    Some(session) => {
+                        let data = get_bytes(&msg, "data");
+                        Some(if kind == "write" {
+                            session.write(&data)
+                        } else {
+                            session.read(&data)
+                        })
+                    }
+                    None => Some(Err(ConnectionError {
+                        message: format!("Unknown TLS session: {}", id),
+                    })),
+                },
+                "close" => {
+                    sessions.remove(&id);
+                    None
+                }
+                _ => None,
+            };
+
+            let reply = match result {
+                Some(Ok(output)) => {
+                    let tls = to_buffer(&output.tls);
+                    let plaintext = to_buffer(&output.plaintext);
+                    let transfer = js_sys::Array::of2(&tls, &plaintext);
+                    let msg = message(&[
+                        ("id", id.into()),
+                        ("tls", tls.into()),
+                        ("plaintext", plaintext.into()),
+                        ("handshaking", output.handshaking.into()),
+                        ("version", output.version.map(|v| v.to_string()).into()),
+                        (
+                            "stages",
+                            output
+                                .stages
+                                .iter()
+                                .map(|s| JsValue::from(s.name()))
+                                .collect::<js_sys::Array>()
+                                .into(),
+                        ),
+                        ("cipherSuite", output.cipher_suite.into()),
+                        #[cfg(feature = "keylog")]
+                        (
+                            "keyLog",
+                            output
+                                .key_log
+                                .iter()
+                                .map(|line| JsValue::from(line.as_str()))
+                                .collect::<js_sys::Array>()
+                                .into(),
+                        ),
+                    ]);
+                    cb_scope.post_message_with_transfer(&msg, &transfer)
+                }
+                Some(Err(e)) => {
+                    sessions.remove(&id);
+                    cb_scope
+                        .post_message(&message(&[("id", id.into()), ("error", e.message.into())]))
+                }
+                None => Ok(()),
+            };
+            reply.unwrap_throw();
+        }));
+
+    scope.set_onmessage(Some(message_callback.as_ref().unchecked_ref()));
+    message_callback.forget();
+}
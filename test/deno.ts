@@ -0,0 +1,17 @@
+// Run the client under Deno, against the wsproxy started by `npm start`:
+//
+//   deno run --allow-net --allow-read test/deno.ts
+import init, { Client, get_runtime } from "../pkg/socketguard.js";
+
+await init(
+  await Deno.readFile(new URL("../pkg/socketguard_bg.wasm", import.meta.url))
+);
+console.log("runtime", get_runtime());
+
+const client = new Client("ws://localhost:3000");
+const resp = await client.get("http://neverssl.com/online/", {
+  "User-Agent": "SocketGuard/0.1.0",
+  Accept: "*/*",
+});
+console.log("status", resp.get_code());
+console.log("body", resp.text());
@@ -1,5 +1,6 @@
 import express from "express";
 import http from "http";
+import net from "net";
 const app = express();
 import { dirname } from "path";
 import { fileURLToPath } from "url";
@@ -24,3 +25,35 @@ const wsProxyConfig = {
 
 let wsProxy = new Server(wsProxyConfig);
 wsProxy.listen();
+
+// Local targets of public/integration.html and public/bench.html, reached through the wsproxy
+net.createServer((socket) => socket.pipe(socket)).listen(4242);
+
+http
+  .createServer((req, res) => {
+    if (req.url === "/content-length") {
+      res.setHeader("Content-Length", 5);
+      res.end("hello");
+    } else if (req.url === "/chunked") {
+      // Without a Content-Length, node sends the body chunked
+      res.write("hel");
+      setTimeout(() => res.end("lo"), 50);
+    } else if (req.url?.startsWith("/bytes/")) {
+      // Body of the given size, for public/bench.html
+      const size = Number(req.url.slice("/bytes/".length)) || 0;
+      res.setHeader("Content-Length", size);
+      res.end(Buffer.alloc(size, 120));
+    } else if (req.url === "/echo" && req.method === "POST") {
+      const chunks: Buffer[] = [];
+      req.on("data", (chunk) => chunks.push(chunk));
+      req.on("end", () => {
+        const body = Buffer.concat(chunks);
+        res.setHeader("Content-Length", body.length);
+        res.end(body);
+      });
+    } else {
+      res.statusCode = 404;
+      res.end();
+    }
+  })
+  .listen(8080);
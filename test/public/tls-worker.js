@@ -0,0 +1,4 @@
+import init, { start_tls_worker } from "./dist/socketguard.js";
+
+await init();
+start_tls_worker();